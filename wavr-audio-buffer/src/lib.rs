@@ -193,6 +193,22 @@ impl AudioBuffer {
         }
     }
 
+    /// Resizes every channel to the given sample size, zeroing the new samples. This does not
+    /// allocate when shrinking, or when growing back within the largest size the buffer had.
+    pub fn resize(&mut self, buffer_size: usize) {
+        for ch in &mut self.audio_data {
+            ch.resize(buffer_size, 0.0);
+        }
+        self.buffer_size = buffer_size;
+    }
+
+    /// Sets every sample of the buffer to zero.
+    pub fn clear(&mut self) {
+        for ch in &mut self.audio_data {
+            ch.iter_mut().for_each(|v| *v = 0.0);
+        }
+    }
+
     /// Copy an interleaved slice at the given sample position. The slice is
     /// assumed to contain as many channels as the buffer. The slice sample size
     /// and the position must fit so that the slice can be fully copied into the
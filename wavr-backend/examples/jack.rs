@@ -0,0 +1,37 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+
+use std::time::Duration;
+
+use wavr_backend::{shared_engine, Backend, JackBackend};
+use wavr_engine::AudioEngine;
+
+fn main() {
+    let engine = shared_engine(AudioEngine::new(48000, 2));
+    let mut stream = JackBackend::new("wavr", 2)
+        .with_port_names(&["in_left", "in_right"], &["out_left", "out_right"])
+        .expect("mismatched port names")
+        .with_auto_connect(true)
+        .start(engine.clone())
+        .expect("cannot connect to the JACK server");
+    println!(
+        "Running at {} Hz, {} frames: {:?} -> {:?}",
+        stream.sample_rate(),
+        stream.buffer_size(),
+        stream.input_ports(),
+        stream.output_ports()
+    );
+
+    loop {
+        std::thread::sleep(Duration::from_millis(500));
+        for event in stream.poll_events() {
+            println!("{:?}", event);
+        }
+        let engine = engine.lock().unwrap();
+        let context = engine.get_context();
+        println!("{:?} at {:?}", context.state, context.timestamp());
+    }
+}
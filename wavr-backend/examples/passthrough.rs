@@ -0,0 +1,44 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+
+use std::time::Duration;
+
+use wavr_backend::{shared_engine, Backend, BackendEvent, CpalBackend, StreamSettings};
+use wavr_engine::{AudioContextState, AudioEngine};
+
+fn main() {
+    let mut backend = CpalBackend::new(StreamSettings {
+        duplex: true,
+        ..StreamSettings::default()
+    });
+    for device in backend.devices().expect("cannot enumerate devices") {
+        println!(
+            "{}: {} in, {} out, {:?}",
+            device.name, device.input_channels, device.output_channels, device.sample_rates
+        );
+    }
+
+    let engine = shared_engine(AudioEngine::new(48000, 2));
+    let mut stream = backend.start(engine.clone()).expect("cannot start stream");
+    println!("Running with {:?}", stream.config());
+    engine
+        .lock()
+        .unwrap()
+        .set_context_state(AudioContextState::Playing);
+
+    loop {
+        std::thread::sleep(Duration::from_millis(100));
+        for event in stream.poll_events() {
+            match event {
+                BackendEvent::Xrun => eprintln!("xrun"),
+                event => println!("{:?}", event),
+            }
+        }
+        if let Some(data) = engine.lock().unwrap().get_rack().get_output_meter_data() {
+            println!("{}", data.loudness);
+        }
+    }
+}
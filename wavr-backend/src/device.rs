@@ -0,0 +1,437 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! # The Device Backend
+//!
+//! This backend drives the engine from the system audio devices through `cpal`. It handles device
+//! enumeration, sample format conversion, duplex input and output, xrun reporting and recovering
+//! from a device being unplugged while the stream is running.
+//!
+//! The data callbacks do not allocate: xruns are counted in an atomic counter, and the duplex
+//! input queue and the engine buffer are allocated when the streams are built. Blocks longer than
+//! the engine buffer are processed in several parts.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{
+    BufferSize, Device, Host, Sample, SampleFormat, SampleRate, Stream, StreamConfig, StreamError,
+    SupportedStreamConfig,
+};
+use wavr_audio_buffer::AudioBuffer;
+
+use crate::{Backend, BackendError, BackendEvent, SharedEngine, StreamSettings};
+
+/// Number of pending events kept by the stream. Further events are dropped until
+/// `CpalStream::poll_events` is called.
+const EVENT_CAPACITY: usize = 64;
+
+/// Number of frames the engine buffer is allocated for, unless the stream buffer size is larger.
+const MAX_BUFFER_FRAMES: usize = 8192;
+
+/// Information about an audio device available on the system.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceInfo {
+    /// Name of the device, as used in [`StreamSettings`](struct.StreamSettings.html).
+    pub name: String,
+    /// Maximum number of input channels, or 0 if the device has no inputs.
+    pub input_channels: u16,
+    /// Maximum number of output channels, or 0 if the device has no outputs.
+    pub output_channels: u16,
+    /// Supported sample rate ranges, in Hertz.
+    pub sample_rates: Vec<(u32, u32)>,
+    /// Default sample rate of the device, if any.
+    pub default_sample_rate: Option<u32>,
+}
+
+/// Backend driving the engine from the system audio devices.
+pub struct CpalBackend {
+    host: Host,
+    settings: StreamSettings,
+}
+
+/// Handle on the running device streams. Dropping it stops the streams.
+pub struct CpalStream {
+    host: Host,
+    settings: StreamSettings,
+    engine: SharedEngine,
+    streams: Vec<Stream>,
+    config: StreamConfig,
+    lost: Arc<AtomicBool>,
+    xruns: Arc<AtomicUsize>,
+    reported_xruns: usize,
+    events_tx: SyncSender<BackendEvent>,
+    events: Receiver<BackendEvent>,
+}
+
+/// Interleaved input samples, written by the input stream and read by the output stream.
+type InputQueue = Arc<Mutex<VecDeque<f64>>>;
+
+impl CpalBackend {
+    /// Create a new backend on the default host, using the given stream settings.
+    pub fn new(settings: StreamSettings) -> Self {
+        Self {
+            host: cpal::default_host(),
+            settings,
+        }
+    }
+
+    /// Lists the audio devices available on the host.
+    pub fn devices(&self) -> Result<Vec<DeviceInfo>, BackendError> {
+        let devices = self
+            .host
+            .devices()
+            .map_err(|err| BackendError::Stream(err.to_string()))?;
+        Ok(devices.filter_map(|device| device_info(&device)).collect())
+    }
+}
+
+impl Backend for CpalBackend {
+    type Stream = CpalStream;
+
+    fn start(&mut self, engine: SharedEngine) -> Result<Self::Stream, BackendError> {
+        let host = cpal::host_from_id(self.host.id())
+            .map_err(|err| BackendError::Stream(err.to_string()))?;
+        let (events_tx, events) = sync_channel(EVENT_CAPACITY);
+        let mut stream = CpalStream {
+            host,
+            settings: self.settings.clone(),
+            engine,
+            streams: vec![],
+            config: StreamConfig {
+                channels: 0,
+                sample_rate: SampleRate(0),
+                buffer_size: BufferSize::Default,
+            },
+            lost: Arc::new(AtomicBool::new(false)),
+            xruns: Arc::new(AtomicUsize::new(0)),
+            reported_xruns: 0,
+            events_tx,
+            events,
+        };
+        stream.build()?;
+        Ok(stream)
+    }
+}
+
+impl CpalStream {
+    /// Returns the negotiated stream configuration.
+    pub fn config(&self) -> &StreamConfig {
+        &self.config
+    }
+
+    /// Returns the number of xruns since the stream started.
+    pub fn xruns(&self) -> usize {
+        self.xruns.load(Ordering::Relaxed)
+    }
+
+    /// Drains the pending events. A single `Xrun` event is reported when xruns happened since the
+    /// last call; `xruns` returns their count. If the device has been lost, this tries to rebuild
+    /// the streams on the default devices, reporting `DeviceRecovered` on success.
+    pub fn poll_events(&mut self) -> Vec<BackendEvent> {
+        let mut events = vec![];
+        let xruns = self.xruns();
+        if xruns != self.reported_xruns {
+            self.reported_xruns = xruns;
+            events.push(BackendEvent::Xrun);
+        }
+        if self.lost.swap(false, Ordering::AcqRel) {
+            self.streams.clear();
+            self.settings.input_device = None;
+            self.settings.output_device = None;
+            match self.build() {
+                Ok(name) => events.push(BackendEvent::DeviceRecovered(name)),
+                Err(err) => {
+                    events.push(BackendEvent::Error(err.to_string()));
+                    self.lost.store(true, Ordering::Release);
+                }
+            }
+        }
+        events.extend(self.events.try_iter());
+        events
+    }
+
+    /// Pauses the device streams.
+    pub fn pause(&self) -> Result<(), BackendError> {
+        for stream in &self.streams {
+            stream
+                .pause()
+                .map_err(|err| BackendError::Stream(err.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Resumes the device streams.
+    pub fn play(&self) -> Result<(), BackendError> {
+        for stream in &self.streams {
+            stream
+                .play()
+                .map_err(|err| BackendError::Stream(err.to_string()))?;
+        }
+        Ok(())
+    }
+
+    fn build(&mut self) -> Result<String, BackendError> {
+        let output = find_device(&self.host, self.settings.output_device.as_deref(), false)?;
+        let supported = select_config(&output, &self.settings)?;
+        let sample_format = supported.sample_format();
+        let mut config = supported.config();
+        if let Some(buffer_size) = self.settings.buffer_size {
+            config.buffer_size = BufferSize::Fixed(buffer_size);
+        }
+
+        self.engine
+            .lock()
+            .map_err(|_| BackendError::Stream("engine mutex poisoned".to_string()))?
+            .configure(config.sample_rate.0 as u64, config.channels as u8);
+
+        let input_queue = if self.settings.duplex {
+            let input = find_device(&self.host, self.settings.input_device.as_deref(), true)?;
+            let queue = InputQueue::default();
+            let input_config = input
+                .default_input_config()
+                .map_err(|err| BackendError::UnsupportedConfig(err.to_string()))?;
+            let input_stream_config = StreamConfig {
+                channels: input_config.channels(),
+                ..config.clone()
+            };
+            let stream = match input_config.sample_format() {
+                SampleFormat::I16 => {
+                    self.build_input::<i16>(&input, &input_stream_config, &config, queue.clone())
+                }
+                SampleFormat::U16 => {
+                    self.build_input::<u16>(&input, &input_stream_config, &config, queue.clone())
+                }
+                SampleFormat::F32 => {
+                    self.build_input::<f32>(&input, &input_stream_config, &config, queue.clone())
+                }
+            }?;
+            self.streams.push(stream);
+            Some(queue)
+        } else {
+            None
+        };
+
+        let stream = match sample_format {
+            SampleFormat::I16 => self.build_output::<i16>(&output, &config, input_queue),
+            SampleFormat::U16 => self.build_output::<u16>(&output, &config, input_queue),
+            SampleFormat::F32 => self.build_output::<f32>(&output, &config, input_queue),
+        }?;
+        self.streams.push(stream);
+        self.config = config;
+        self.play()?;
+        output
+            .name()
+            .map_err(|err| BackendError::Stream(err.to_string()))
+    }
+
+    fn build_input<S: Sample>(
+        &self,
+        device: &Device,
+        config: &StreamConfig,
+        output_config: &StreamConfig,
+        queue: InputQueue,
+    ) -> Result<Stream, BackendError> {
+        let xruns = self.xruns.clone();
+        let input_channels = config.channels as usize;
+        let output_channels = output_config.channels as usize;
+        // Keep at most a few blocks of latency between the input and output streams
+        let capacity_frames = 4 * buffer_frames(output_config);
+        let capacity = output_channels * capacity_frames;
+        if let Ok(mut queue) = queue.lock() {
+            queue.reserve(capacity);
+        }
+        device
+            .build_input_stream(
+                config,
+                move |data: &[S], _| {
+                    let mut queue = match queue.try_lock() {
+                        Ok(queue) => queue,
+                        Err(_) => {
+                            xruns.fetch_add(1, Ordering::Relaxed);
+                            return;
+                        }
+                    };
+                    // Make room for the block without growing the queue past its capacity,
+                    // dropping the oldest samples first
+                    let frames = data.len() / input_channels;
+                    let skipped = frames.saturating_sub(capacity_frames);
+                    let needed = (frames - skipped) * output_channels;
+                    if queue.len() + needed > capacity {
+                        let overflow = (queue.len() + needed - capacity).min(queue.len());
+                        queue.drain(..overflow);
+                        xruns.fetch_add(1, Ordering::Relaxed);
+                    }
+                    // Remap the input channels onto the engine channels, dropping extra
+                    // channels and filling missing ones with silence
+                    for frame in data.chunks(input_channels).skip(skipped) {
+                        queue.extend(
+                            (0..output_channels)
+                                .map(|ch| frame.get(ch).map(|s| s.to_f32() as f64).unwrap_or(0.0)),
+                        );
+                    }
+                },
+                self.error_callback(),
+            )
+            .map_err(|err| BackendError::Stream(err.to_string()))
+    }
+
+    fn build_output<S: Sample>(
+        &self,
+        device: &Device,
+        config: &StreamConfig,
+        input: Option<InputQueue>,
+    ) -> Result<Stream, BackendError> {
+        let xruns = self.xruns.clone();
+        let engine = self.engine.clone();
+        let channels = config.channels as usize;
+        let max_frames = buffer_frames(config).max(MAX_BUFFER_FRAMES);
+        let mut buffer = AudioBuffer::zeroed(channels, max_frames);
+        device
+            .build_output_stream(
+                config,
+                move |data: &mut [S], _| {
+                    for block in data.chunks_mut(channels * max_frames) {
+                        let frames = block.len() / channels;
+                        buffer.resize(frames);
+                        buffer.clear();
+                        if let Some(input) = &input {
+                            if let Ok(mut queue) = input.try_lock() {
+                                if queue.len() < block.len() {
+                                    xruns.fetch_add(1, Ordering::Relaxed);
+                                }
+                                let available = queue.len().min(block.len());
+                                for (i, sample) in queue.drain(..available).enumerate() {
+                                    buffer[(i % channels, i / channels)] = sample;
+                                }
+                            }
+                        }
+
+                        match engine.try_lock() {
+                            Ok(mut engine) => engine.fill_buffer(&mut buffer),
+                            Err(_) => {
+                                buffer.clear();
+                                xruns.fetch_add(1, Ordering::Relaxed);
+                            }
+                        }
+
+                        for (i, dst) in block.iter_mut().enumerate() {
+                            *dst = S::from(&(buffer[(i % channels, i / channels)] as f32));
+                        }
+                    }
+                },
+                self.error_callback(),
+            )
+            .map_err(|err| BackendError::Stream(err.to_string()))
+    }
+
+    fn error_callback(&self) -> impl FnMut(StreamError) + Send + 'static {
+        let events = self.events_tx.clone();
+        let lost = self.lost.clone();
+        move |err| match err {
+            StreamError::DeviceNotAvailable => {
+                if !lost.swap(true, Ordering::AcqRel) {
+                    let _ = events.try_send(BackendEvent::DeviceLost);
+                }
+            }
+            StreamError::BackendSpecific { err } => {
+                let _ = events.try_send(BackendEvent::Error(err.to_string()));
+            }
+        }
+    }
+}
+
+fn device_info(device: &Device) -> Option<DeviceInfo> {
+    let name = device.name().ok()?;
+    let mut sample_rates = vec![];
+    let mut input_channels = 0;
+    let mut output_channels = 0;
+    if let Ok(configs) = device.supported_input_configs() {
+        for config in configs {
+            input_channels = input_channels.max(config.channels());
+            sample_rates.push((config.min_sample_rate().0, config.max_sample_rate().0));
+        }
+    }
+    if let Ok(configs) = device.supported_output_configs() {
+        for config in configs {
+            output_channels = output_channels.max(config.channels());
+            sample_rates.push((config.min_sample_rate().0, config.max_sample_rate().0));
+        }
+    }
+    sample_rates.sort_unstable();
+    sample_rates.dedup();
+    let default_sample_rate = device
+        .default_output_config()
+        .or_else(|_| device.default_input_config())
+        .map(|config| config.sample_rate().0)
+        .ok();
+
+    Some(DeviceInfo {
+        name,
+        input_channels,
+        output_channels,
+        sample_rates,
+        default_sample_rate,
+    })
+}
+
+fn find_device(host: &Host, name: Option<&str>, input: bool) -> Result<Device, BackendError> {
+    match name {
+        None if input => host.default_input_device(),
+        None => host.default_output_device(),
+        Some(name) => {
+            let mut devices = if input {
+                host.input_devices()
+                    .map_err(|err| BackendError::Stream(err.to_string()))?
+                    .collect::<Vec<_>>()
+            } else {
+                host.output_devices()
+                    .map_err(|err| BackendError::Stream(err.to_string()))?
+                    .collect::<Vec<_>>()
+            };
+            let position = devices
+                .iter()
+                .position(|d| d.name().map(|n| n == name).unwrap_or(false));
+            position.map(|i| devices.swap_remove(i))
+        }
+    }
+    .ok_or_else(|| BackendError::DeviceNotFound(name.map(String::from)))
+}
+
+fn select_config(
+    device: &Device,
+    settings: &StreamSettings,
+) -> Result<SupportedStreamConfig, BackendError> {
+    let default = device
+        .default_output_config()
+        .map_err(|err| BackendError::UnsupportedConfig(err.to_string()))?;
+    if settings.sample_rate.is_none() && settings.channels.is_none() {
+        return Ok(default);
+    }
+
+    let sample_rate = SampleRate(settings.sample_rate.unwrap_or(default.sample_rate().0));
+    let channels = settings.channels.unwrap_or_else(|| default.channels());
+    device
+        .supported_output_configs()
+        .map_err(|err| BackendError::UnsupportedConfig(err.to_string()))?
+        .filter(|c| c.channels() == channels)
+        .filter(|c| c.min_sample_rate() <= sample_rate && sample_rate <= c.max_sample_rate())
+        .max_by_key(|c| c.sample_format() == SampleFormat::F32)
+        .map(|c| c.with_sample_rate(sample_rate))
+        .ok_or_else(|| {
+            BackendError::UnsupportedConfig(format!("{} Hz, {} channels", sample_rate.0, channels))
+        })
+}
+
+fn buffer_frames(config: &StreamConfig) -> usize {
+    match config.buffer_size {
+        BufferSize::Fixed(frames) => frames as usize,
+        // Assume a generous default block size when the host does not tell
+        BufferSize::Default => 4096,
+    }
+}
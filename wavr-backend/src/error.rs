@@ -0,0 +1,34 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Errors returned by the audio backends.
+
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// Error type for the audio backends.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BackendError {
+    /// No device matched the requested name, or no default device is available.
+    DeviceNotFound(Option<String>),
+    /// The device does not support the requested configuration.
+    UnsupportedConfig(String),
+    /// The backend failed to build or start the stream.
+    Stream(String),
+}
+
+impl Display for BackendError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            BackendError::DeviceNotFound(Some(name)) => write!(f, "device not found: {}", name),
+            BackendError::DeviceNotFound(None) => write!(f, "no default device available"),
+            BackendError::UnsupportedConfig(msg) => write!(f, "unsupported configuration: {}", msg),
+            BackendError::Stream(msg) => write!(f, "stream error: {}", msg),
+        }
+    }
+}
+
+impl Error for BackendError {}
@@ -0,0 +1,338 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! # The JACK Backend
+//!
+//! This backend registers the engine as a JACK client (which also works with PipeWire's JACK
+//! implementation), with one named input and output port per channel. The rack is re-prepared
+//! whenever the server changes its sample rate or buffer size, and the audio context can follow
+//! the JACK transport, so that the engine plays, stops and locates along with the other clients.
+//!
+//! The process callback does not allocate: the audio buffer is allocated for up to
+//! `MAX_BUFFER_SIZE` frames (or the server buffer size if larger) on start, and xruns are counted
+//! in an atomic counter.
+//!
+//! The backend can be exercised headless against a dummy server, ie. `jackd -d dummy -r 48000`.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::Arc;
+
+use ::jack::{
+    AsyncClient, AudioIn, AudioOut, Client, ClientOptions, ClientStatus, Control, Frames,
+    NotificationHandler, Port, PortFlags, ProcessHandler, ProcessScope, TransportState,
+};
+use wavr_audio_buffer::AudioBuffer;
+use wavr_engine::AudioContextState;
+
+use crate::{Backend, BackendError, BackendEvent, SharedEngine};
+
+/// Number of frames the audio buffer is allocated for on start. Larger server buffer sizes
+/// allocate in the buffer size callback.
+pub const MAX_BUFFER_SIZE: usize = 8192;
+const EVENT_CAPACITY: usize = 64;
+
+/// Backend registering the engine as a JACK client.
+#[derive(Clone, Debug)]
+pub struct JackBackend {
+    client_name: String,
+    input_names: Vec<String>,
+    output_names: Vec<String>,
+    transport_sync: bool,
+    auto_connect: bool,
+}
+
+/// Handle on the running JACK client. Dropping it deactivates the client.
+pub struct JackStream {
+    client: AsyncClient<Notifications, Processor>,
+    xruns: Arc<AtomicUsize>,
+    reported_xruns: usize,
+    events: Receiver<BackendEvent>,
+}
+
+struct Notifications {
+    engine: SharedEngine,
+    channels: u8,
+    xruns: Arc<AtomicUsize>,
+    events: SyncSender<BackendEvent>,
+}
+
+struct Processor {
+    engine: SharedEngine,
+    inputs: Vec<Port<AudioIn>>,
+    outputs: Vec<Port<AudioOut>>,
+    buffer: AudioBuffer,
+    transport_sync: bool,
+    rolling: bool,
+    xruns: Arc<AtomicUsize>,
+}
+
+impl JackBackend {
+    /// Create a new JACK backend with the given client name and channel count. Ports are named
+    /// `in_1`, `out_1`, `in_2`, `out_2`, etc.
+    pub fn new(client_name: &str, channels: u8) -> Self {
+        Self {
+            client_name: client_name.to_string(),
+            input_names: (1..=channels).map(|i| format!("in_{}", i)).collect(),
+            output_names: (1..=channels).map(|i| format!("out_{}", i)).collect(),
+            transport_sync: true,
+            auto_connect: false,
+        }
+    }
+
+    /// Renames the ports, one name per channel. Both lists must have the same, non-zero length,
+    /// which replaces the channel count given at creation.
+    pub fn with_port_names<S: AsRef<str>>(
+        mut self,
+        inputs: &[S],
+        outputs: &[S],
+    ) -> Result<Self, BackendError> {
+        if inputs.len() != outputs.len() || outputs.is_empty() {
+            return Err(BackendError::UnsupportedConfig(format!(
+                "{} input and {} output port names",
+                inputs.len(),
+                outputs.len()
+            )));
+        }
+        self.input_names = inputs.iter().map(|s| s.as_ref().to_string()).collect();
+        self.output_names = outputs.iter().map(|s| s.as_ref().to_string()).collect();
+        Ok(self)
+    }
+
+    /// Sets whether the audio context follows the JACK transport. When enabled (the default),
+    /// a paused context starts playing when the transport starts rolling, and a playing context
+    /// is paused when the transport stops. Monitoring and offline contexts are left alone. The
+    /// position is set from the transport frame.
+    pub fn with_transport_sync(mut self, transport_sync: bool) -> Self {
+        self.transport_sync = transport_sync;
+        self
+    }
+
+    /// Sets whether the ports are connected to the system capture and playback ports on start.
+    pub fn with_auto_connect(mut self, auto_connect: bool) -> Self {
+        self.auto_connect = auto_connect;
+        self
+    }
+
+    fn channels(&self) -> u8 {
+        self.output_names.len() as u8
+    }
+}
+
+impl Backend for JackBackend {
+    type Stream = JackStream;
+
+    fn start(&mut self, engine: SharedEngine) -> Result<Self::Stream, BackendError> {
+        let (client, _status) = Client::new(&self.client_name, ClientOptions::NO_START_SERVER)
+            .map_err(|err| BackendError::Stream(err.to_string()))?;
+        let inputs = self
+            .input_names
+            .iter()
+            .map(|name| client.register_port(name, AudioIn))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| BackendError::Stream(err.to_string()))?;
+        let outputs = self
+            .output_names
+            .iter()
+            .map(|name| client.register_port(name, AudioOut))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| BackendError::Stream(err.to_string()))?;
+
+        let channels = self.channels();
+        engine
+            .lock()
+            .map_err(|_| BackendError::Stream("engine mutex poisoned".to_string()))?
+            .configure(client.sample_rate() as u64, channels);
+
+        let (tx, events) = sync_channel(EVENT_CAPACITY);
+        let xruns = Arc::new(AtomicUsize::new(0));
+        let notifications = Notifications {
+            engine: engine.clone(),
+            channels,
+            xruns: xruns.clone(),
+            events: tx,
+        };
+        let buffer_size = client.buffer_size() as usize;
+        let mut buffer = AudioBuffer::zeroed(channels as usize, buffer_size.max(MAX_BUFFER_SIZE));
+        buffer.resize(buffer_size);
+        let processor = Processor {
+            engine,
+            buffer,
+            inputs,
+            outputs,
+            transport_sync: self.transport_sync,
+            rolling: false,
+            xruns: xruns.clone(),
+        };
+        let client = client
+            .activate_async(notifications, processor)
+            .map_err(|err| BackendError::Stream(err.to_string()))?;
+        let stream = JackStream {
+            client,
+            xruns,
+            reported_xruns: 0,
+            events,
+        };
+        if self.auto_connect {
+            stream.connect_system_ports()?;
+        }
+        Ok(stream)
+    }
+}
+
+impl JackStream {
+    /// Returns the full names of the input ports, as used to connect them.
+    pub fn input_ports(&self) -> Vec<String> {
+        self.client
+            .as_client()
+            .ports(Some(&self.own_ports_pattern()), None, PortFlags::IS_INPUT)
+    }
+
+    /// Returns the full names of the output ports, as used to connect them.
+    pub fn output_ports(&self) -> Vec<String> {
+        self.client
+            .as_client()
+            .ports(Some(&self.own_ports_pattern()), None, PortFlags::IS_OUTPUT)
+    }
+
+    /// Connects the ports to the system capture and playback ports, channel by channel.
+    pub fn connect_system_ports(&self) -> Result<(), BackendError> {
+        let client = self.client.as_client();
+        let capture = client.ports(Some("^system:capture_"), None, PortFlags::IS_OUTPUT);
+        let playback = client.ports(Some("^system:playback_"), None, PortFlags::IS_INPUT);
+        for (src, dst) in capture.iter().zip(self.input_ports()) {
+            client
+                .connect_ports_by_name(src, &dst)
+                .map_err(|err| BackendError::Stream(err.to_string()))?;
+        }
+        for (src, dst) in self.output_ports().iter().zip(&playback) {
+            client
+                .connect_ports_by_name(src, dst)
+                .map_err(|err| BackendError::Stream(err.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the current sample rate of the JACK server.
+    pub fn sample_rate(&self) -> usize {
+        self.client.as_client().sample_rate()
+    }
+
+    /// Returns the current buffer size of the JACK server.
+    pub fn buffer_size(&self) -> Frames {
+        self.client.as_client().buffer_size()
+    }
+
+    /// Returns the number of xruns since the client started, as reported by the server or caused
+    /// by the engine being locked during the process callback.
+    pub fn xruns(&self) -> usize {
+        self.xruns.load(Ordering::Relaxed)
+    }
+
+    /// Drains the pending events. A single `Xrun` event is reported when xruns happened since the
+    /// last call; `xruns` returns their count.
+    pub fn poll_events(&mut self) -> Vec<BackendEvent> {
+        let mut events = vec![];
+        let xruns = self.xruns();
+        if xruns != self.reported_xruns {
+            self.reported_xruns = xruns;
+            events.push(BackendEvent::Xrun);
+        }
+        events.extend(self.events.try_iter());
+        events
+    }
+
+    fn own_ports_pattern(&self) -> String {
+        format!("^{}:", self.client.as_client().name())
+    }
+}
+
+impl NotificationHandler for Notifications {
+    fn shutdown(&mut self, _status: ClientStatus, reason: &str) {
+        let _ = self
+            .events
+            .try_send(BackendEvent::Error(reason.to_string()));
+        let _ = self.events.try_send(BackendEvent::DeviceLost);
+    }
+
+    fn sample_rate(&mut self, _: &Client, srate: Frames) -> Control {
+        match self.engine.lock() {
+            Ok(mut engine) => {
+                engine.configure(srate as u64, self.channels);
+                Control::Continue
+            }
+            Err(_) => Control::Quit,
+        }
+    }
+
+    fn xrun(&mut self, _: &Client) -> Control {
+        self.xruns.fetch_add(1, Ordering::Relaxed);
+        Control::Continue
+    }
+}
+
+impl ProcessHandler for Processor {
+    fn process(&mut self, client: &Client, scope: &ProcessScope) -> Control {
+        let frames = scope.n_frames() as usize;
+        if self.buffer.buffer_size() != frames {
+            // The buffer size callback has not run yet for this size
+            self.buffer.resize(frames);
+        }
+        for (i, port) in self.inputs.iter().enumerate() {
+            let channel = &mut self.buffer[i];
+            for (dst, src) in channel.iter_mut().zip(port.as_slice(scope)) {
+                *dst = *src as f64;
+            }
+        }
+
+        match self.engine.try_lock() {
+            Ok(mut engine) => {
+                if self.transport_sync {
+                    if let Ok(transport) = client.transport().query() {
+                        engine.set_position(transport.pos.frame() as usize);
+                        // Only follow the transport when it starts or stops, so that the state
+                        // set on the engine in the meantime is kept
+                        let rolling = transport.state == TransportState::Rolling;
+                        let state = engine.get_context().state;
+                        match (self.rolling, rolling, state) {
+                            (false, true, AudioContextState::Paused) => {
+                                engine.set_context_state(AudioContextState::Playing)
+                            }
+                            (true, false, AudioContextState::Playing) => {
+                                engine.set_context_state(AudioContextState::Paused)
+                            }
+                            _ => {}
+                        }
+                        self.rolling = rolling;
+                    }
+                }
+                engine.fill_buffer(&mut self.buffer);
+            }
+            Err(_) => {
+                self.buffer.clear();
+                self.xruns.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        for (i, port) in self.outputs.iter_mut().enumerate() {
+            for (dst, src) in port.as_mut_slice(scope).iter_mut().zip(&self.buffer[i]) {
+                *dst = *src as f32;
+            }
+        }
+        Control::Continue
+    }
+
+    fn buffer_size(&mut self, client: &Client, size: Frames) -> Control {
+        self.buffer.resize(size as usize);
+        // Prepare the rack again for the new block size, as for a new sample rate
+        match self.engine.lock() {
+            Ok(mut engine) => {
+                engine.configure(client.sample_rate() as u64, self.buffer.channels() as u8);
+                Control::Continue
+            }
+            Err(_) => Control::Quit,
+        }
+    }
+}
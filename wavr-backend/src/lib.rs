@@ -0,0 +1,130 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! # The Wavr Audio Backends
+//!
+//! This crate connects the [`AudioEngine`](wavr_engine::AudioEngine) to the outside world. The
+//! `device` module drives the engine from the system audio devices through `cpal` (enabled by the
+//! default `cpal` feature), the `jack` module registers it as a JACK client (enabled by the `jack`
+//! feature), and the `null` module drives it from a timer, which is useful for running the engine
+//! headless (ie. in CI tests).
+//!
+//! ## Running the engine on the default devices
+//!
+//! ```rust,no_run
+//! # #[cfg(not(feature = "cpal"))]
+//! # fn main() {}
+//! # #[cfg(feature = "cpal")]
+//! # fn main() -> Result<(), wavr_backend::BackendError> {
+//! # use wavr_backend::{shared_engine, Backend, CpalBackend, StreamSettings};
+//! # use wavr_engine::{AudioContextState, AudioEngine};
+//! let engine = shared_engine(AudioEngine::new(48000, 2));
+//! let mut backend = CpalBackend::new(StreamSettings::default());
+//! let mut stream = backend.start(engine.clone())?;
+//! engine.lock().unwrap().set_context_state(AudioContextState::Playing);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## Running the engine as a JACK client
+//!
+//! This needs the `jack` feature.
+//!
+//! ```rust,no_run
+//! # #[cfg(not(feature = "jack"))]
+//! # fn main() {}
+//! # #[cfg(feature = "jack")]
+//! # fn main() -> Result<(), wavr_backend::BackendError> {
+//! # use wavr_backend::{shared_engine, Backend, JackBackend};
+//! # use wavr_engine::AudioEngine;
+//! let engine = shared_engine(AudioEngine::new(48000, 2));
+//! let mut stream = JackBackend::new("wavr", 2)
+//!     .with_auto_connect(true)
+//!     .start(engine.clone())?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## Running the engine headless
+//!
+//! ```rust
+//! # use std::time::Duration;
+//! # use wavr_backend::{shared_engine, Backend, BackendError, NullBackend};
+//! # use wavr_engine::AudioEngine;
+//! # fn main() -> Result<(), BackendError> {
+//! let engine = shared_engine(AudioEngine::new(48000, 2));
+//! let mut stream = NullBackend::new(48000, 2, 512).start(engine.clone())?;
+//! std::thread::sleep(Duration::from_millis(100));
+//! stream.stop();
+//! assert!(stream.blocks_processed() > 0);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "jack")]
+pub use crate::jack::*;
+#[cfg(feature = "cpal")]
+pub use device::*;
+pub use error::*;
+pub use null::*;
+use wavr_engine::AudioEngine;
+
+#[cfg(feature = "cpal")]
+pub mod device;
+pub mod error;
+#[cfg(feature = "jack")]
+pub mod jack;
+pub mod null;
+
+/// Audio engine shared between the application and the audio thread of a backend.
+pub type SharedEngine = Arc<Mutex<AudioEngine>>;
+
+/// Wraps the audio engine to share it with a backend.
+pub fn shared_engine(engine: AudioEngine) -> SharedEngine {
+    Arc::new(Mutex::new(engine))
+}
+
+/// Events reported by a running backend stream.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BackendEvent {
+    /// The audio thread could not keep up and a block was dropped or filled with silence.
+    Xrun,
+    /// The audio device is no longer available (ie. it has been unplugged).
+    DeviceLost,
+    /// The stream has been rebuilt on a new device after the previous one was lost.
+    DeviceRecovered(String),
+    /// The backend reported an error which did not stop the stream.
+    Error(String),
+}
+
+/// Stream settings requested from the backend. Fields left to `None` use the device defaults.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StreamSettings {
+    /// Name of the input device, or `None` for the default input device.
+    pub input_device: Option<String>,
+    /// Name of the output device, or `None` for the default output device.
+    pub output_device: Option<String>,
+    /// Sample rate in Hertz.
+    pub sample_rate: Option<u32>,
+    /// Size of the audio blocks in samples per channel.
+    pub buffer_size: Option<u32>,
+    /// Number of channels of the engine.
+    pub channels: Option<u16>,
+    /// Whether to open an input stream alongside the output stream. When disabled, the engine is
+    /// fed with silence.
+    pub duplex: bool,
+}
+
+/// Trait implemented by audio backends, starting a stream driving the shared engine.
+pub trait Backend {
+    /// Handle on the running stream. Dropping it stops the stream.
+    type Stream;
+
+    /// Starts driving the engine. The engine is reconfigured to the negotiated sample rate and
+    /// channel count before the first block is processed.
+    fn start(&mut self, engine: SharedEngine) -> Result<Self::Stream, BackendError>;
+}
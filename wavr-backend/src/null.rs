@@ -0,0 +1,181 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! # The Null Backend
+//!
+//! This backend drives the engine from a timer thread instead of an audio device. The engine is fed
+//! with silence, and the processed blocks can optionally be sent to a channel for inspection. It
+//! allows running the engine headless, ie. in CI tests where no audio device is available.
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use wavr_audio_buffer::AudioBuffer;
+
+use crate::{Backend, BackendError, BackendEvent, SharedEngine};
+
+/// Backend driven by a timer, processing one block every `buffer_size / sample_rate` seconds.
+#[derive(Clone, Debug)]
+pub struct NullBackend {
+    sample_rate: u32,
+    channels: u16,
+    buffer_size: usize,
+    realtime: bool,
+    output: Option<SyncSender<AudioBuffer>>,
+}
+
+/// Handle on a running null backend stream. Dropping it stops the timer thread.
+#[derive(Debug)]
+pub struct NullStream {
+    running: Arc<AtomicBool>,
+    blocks: Arc<AtomicUsize>,
+    events: Receiver<BackendEvent>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl NullBackend {
+    /// Create a new null backend with the given sample rate, channel count and buffer size.
+    pub fn new(sample_rate: u32, channels: u16, buffer_size: usize) -> Self {
+        Self {
+            sample_rate,
+            channels,
+            buffer_size,
+            realtime: true,
+            output: None,
+        }
+    }
+
+    /// Sends every processed block to the given channel. Blocks are dropped if the channel is
+    /// full, which is reported as an xrun.
+    pub fn with_output(mut self, output: SyncSender<AudioBuffer>) -> Self {
+        self.output = Some(output);
+        self
+    }
+
+    /// Processes blocks as fast as possible instead of following the wall clock.
+    pub fn faster_than_realtime(mut self) -> Self {
+        self.realtime = false;
+        self
+    }
+
+    /// Returns the duration of a single block.
+    pub fn block_duration(&self) -> Duration {
+        Duration::from_secs_f64(self.buffer_size as f64 / self.sample_rate as f64)
+    }
+}
+
+impl Backend for NullBackend {
+    type Stream = NullStream;
+
+    fn start(&mut self, engine: SharedEngine) -> Result<Self::Stream, BackendError> {
+        if self.sample_rate == 0 || self.channels == 0 || self.buffer_size == 0 {
+            return Err(BackendError::UnsupportedConfig(format!(
+                "{} Hz, {} channels, {} samples",
+                self.sample_rate, self.channels, self.buffer_size
+            )));
+        }
+
+        engine
+            .lock()
+            .map_err(|_| BackendError::Stream("engine mutex poisoned".to_string()))?
+            .configure(self.sample_rate as u64, self.channels as u8);
+
+        let (tx, events) = channel();
+        let running = Arc::new(AtomicBool::new(true));
+        let blocks = Arc::new(AtomicUsize::new(0));
+        let handle = std::thread::spawn({
+            let running = running.clone();
+            let blocks = blocks.clone();
+            let this = self.clone();
+            move || this.run(engine, running, blocks, tx)
+        });
+
+        Ok(NullStream {
+            running,
+            blocks,
+            events,
+            handle: Some(handle),
+        })
+    }
+}
+
+impl NullBackend {
+    fn run(
+        self,
+        engine: SharedEngine,
+        running: Arc<AtomicBool>,
+        blocks: Arc<AtomicUsize>,
+        events: Sender<BackendEvent>,
+    ) {
+        let block_duration = self.block_duration();
+        let mut deadline = Instant::now();
+        while running.load(Ordering::Acquire) {
+            let mut buffer = AudioBuffer::zeroed(self.channels as usize, self.buffer_size);
+            match engine.lock() {
+                Ok(mut engine) => engine.fill_buffer(&mut buffer),
+                Err(_) => {
+                    let _ = events.send(BackendEvent::Error("engine mutex poisoned".to_string()));
+                    return;
+                }
+            }
+            blocks.fetch_add(1, Ordering::AcqRel);
+
+            if let Some(output) = &self.output {
+                match output.try_send(buffer) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(_)) => {
+                        let _ = events.send(BackendEvent::Xrun);
+                    }
+                    Err(TrySendError::Disconnected(_)) => return,
+                }
+            }
+
+            if self.realtime {
+                deadline += block_duration;
+                let now = Instant::now();
+                if now > deadline {
+                    let _ = events.send(BackendEvent::Xrun);
+                    deadline = now;
+                } else {
+                    std::thread::sleep(deadline - now);
+                }
+            }
+        }
+    }
+}
+
+impl NullStream {
+    /// Stops the timer thread and waits for it to finish processing the current block.
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::Release);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+
+    /// Returns whether the timer thread is still running.
+    pub fn is_running(&self) -> bool {
+        self.handle.is_some() && self.running.load(Ordering::Acquire)
+    }
+
+    /// Returns the number of blocks processed since the stream started.
+    pub fn blocks_processed(&self) -> usize {
+        self.blocks.load(Ordering::Acquire)
+    }
+
+    /// Returns the next pending event, if any.
+    pub fn try_recv_event(&self) -> Option<BackendEvent> {
+        self.events.try_recv().ok()
+    }
+}
+
+impl Drop for NullStream {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
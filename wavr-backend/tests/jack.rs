@@ -0,0 +1,125 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Runs the engine on a JACK server, ie. a dummy one started with `jackd -d dummy -r 48000`,
+//! checking that the audio context follows the transport and that the server notifications prepare
+//! the rack. Skipped when no server is running.
+#![cfg(feature = "jack")]
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use jack::{Client, ClientOptions};
+use wavr_backend::{shared_engine, Backend, JackBackend, SharedEngine};
+use wavr_engine::buffer::AudioBuffer;
+use wavr_engine::{AudioContext, AudioContextState, AudioEngine, Effect};
+
+/// Effect recording the contexts it is prepared with.
+struct PrepareRecorder(Arc<Mutex<Vec<AudioContext>>>);
+
+impl Effect for PrepareRecorder {
+    fn prepare(&mut self, context: &AudioContext) {
+        self.0.lock().unwrap().push(*context);
+    }
+
+    fn process(&mut self, _: &AudioContext, _: &mut AudioBuffer) {}
+}
+
+/// Waits for the condition to hold, for at most a second.
+fn wait_for(condition: impl Fn() -> bool) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(1) {
+        if condition() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    false
+}
+
+/// Waits for the engine to reach the given state, for at most a second.
+fn wait_for_state(engine: &SharedEngine, state: AudioContextState) -> bool {
+    wait_for(|| engine.lock().unwrap().get_context().state == state)
+}
+
+#[test]
+fn jack_backend_follows_transport() {
+    let control = match Client::new("wavr_test_transport", ClientOptions::NO_START_SERVER) {
+        Ok((client, _)) => client,
+        Err(err) => {
+            eprintln!("skipping, no JACK server available: {}", err);
+            return;
+        }
+    };
+    let transport = control.transport();
+    transport.stop().unwrap();
+
+    let mut engine = AudioEngine::new(48000, 2);
+    engine.set_context_state(AudioContextState::Monitoring);
+    let engine = shared_engine(engine);
+    let mut stream = JackBackend::new("wavr_test", 2)
+        .start(engine.clone())
+        .unwrap();
+    assert_eq!(stream.input_ports().len(), 2);
+    assert_eq!(stream.output_ports().len(), 2);
+
+    // A stopped transport leaves a monitoring engine alone
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(
+        engine.lock().unwrap().get_context().state,
+        AudioContextState::Monitoring
+    );
+
+    engine
+        .lock()
+        .unwrap()
+        .set_context_state(AudioContextState::Paused);
+    transport.start().unwrap();
+    assert!(wait_for_state(&engine, AudioContextState::Playing));
+    transport.stop().unwrap();
+    assert!(wait_for_state(&engine, AudioContextState::Paused));
+
+    let _ = stream.poll_events();
+    assert!(JackBackend::new("wavr_test", 2)
+        .with_port_names(&["in"], &["out_left", "out_right"])
+        .is_err());
+}
+
+#[test]
+fn jack_notifications_prepare_rack() {
+    let control = match Client::new("wavr_test_prepare", ClientOptions::NO_START_SERVER) {
+        Ok((client, _)) => client,
+        Err(err) => {
+            eprintln!("skipping, no JACK server available: {}", err);
+            return;
+        }
+    };
+    let sample_rate = control.sample_rate() as u64;
+    let buffer_size = control.buffer_size();
+
+    let prepared = Arc::new(Mutex::new(vec![]));
+    let mut engine = AudioEngine::new(sample_rate, 2);
+    engine
+        .get_rack_mut()
+        .push_effect(PrepareRecorder(prepared.clone()));
+    let engine = shared_engine(engine);
+    let _stream = JackBackend::new("wavr_test", 2)
+        .start(engine.clone())
+        .unwrap();
+
+    // Pushing the effect and starting prepare it once each, then the server reports its sample
+    // rate when the notifications are registered
+    assert!(wait_for(|| prepared.lock().unwrap().len() >= 3));
+
+    let count = prepared.lock().unwrap().len();
+    control.set_buffer_size(2 * buffer_size).unwrap();
+    assert!(wait_for(|| prepared.lock().unwrap().len() > count));
+    control.set_buffer_size(buffer_size).unwrap();
+
+    for context in prepared.lock().unwrap().iter() {
+        assert_eq!(context.sample_rate, sample_rate);
+        assert_eq!(context.channel_count, 2);
+    }
+}
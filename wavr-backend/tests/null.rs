@@ -0,0 +1,83 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Runs the engine headless on the null backend, checking that the effects are called from the
+//! timer thread and that the state transitions fade the output in and out.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::Arc;
+use std::time::Duration;
+
+use wavr_audio_buffer::AudioBuffer;
+use wavr_backend::{shared_engine, Backend, NullBackend};
+use wavr_engine::{AudioContext, AudioContextState, AudioEngine, Effect};
+
+const SAMPLE_RATE: u32 = 48000;
+const BUFFER_SIZE: usize = 64;
+const MAX_BLOCKS: usize = 200;
+
+/// Effect outputting a constant 1.0, counting its calls.
+struct Constant(Arc<AtomicUsize>);
+
+impl Effect for Constant {
+    fn process(&mut self, _context: &AudioContext, buffer: &mut AudioBuffer) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        for ch in 0..buffer.channels() {
+            buffer[ch].iter_mut().for_each(|s| *s = 1.0);
+        }
+    }
+}
+
+/// Receives blocks until one satisfies the predicate, returning the samples of the first channel
+/// of all the received blocks.
+fn receive_until(output: &Receiver<AudioBuffer>, done: impl Fn(&[f64]) -> bool) -> Vec<f64> {
+    let mut samples = vec![];
+    for _ in 0..MAX_BLOCKS {
+        let block = output
+            .recv_timeout(Duration::from_secs(1))
+            .expect("no block received");
+        samples.extend_from_slice(&block[0]);
+        if done(&block[0]) {
+            return samples;
+        }
+    }
+    panic!("condition not reached after {} blocks", MAX_BLOCKS);
+}
+
+#[test]
+fn null_backend_processes_and_fades() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut engine = AudioEngine::new(SAMPLE_RATE as u64, 2);
+    engine.get_rack_mut().push_effect(Constant(calls.clone()));
+    engine.set_fade_duration(Duration::from_millis(10));
+    engine.set_context_state(AudioContextState::Playing);
+    let engine = shared_engine(engine);
+
+    let (tx, output) = sync_channel(MAX_BLOCKS);
+    let mut stream = NullBackend::new(SAMPLE_RATE, 2, BUFFER_SIZE)
+        .with_output(tx)
+        .start(engine.clone())
+        .unwrap();
+
+    let fade_in = receive_until(&output, |block| block.iter().all(|&s| s == 1.0));
+    assert!(fade_in[0] < 0.1, "output starts at {}", fade_in[0]);
+    assert!(fade_in.windows(2).all(|w| w[1] >= w[0]));
+    assert!(fade_in.iter().any(|&s| s > 0.0 && s < 1.0));
+
+    engine
+        .lock()
+        .unwrap()
+        .set_context_state(AudioContextState::Paused);
+    let fade_out = receive_until(&output, |block| block.iter().all(|&s| s == 0.0));
+    let start = fade_out.iter().position(|&s| s < 1.0).unwrap();
+    assert!(fade_out[start..].windows(2).all(|w| w[1] <= w[0]));
+    assert!(fade_out.iter().any(|&s| s > 0.0 && s < 1.0));
+
+    stream.stop();
+    assert!(!stream.is_running());
+    assert!(stream.blocks_processed() > 0);
+    assert!(calls.load(Ordering::Relaxed) > 0);
+}
@@ -0,0 +1,49 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+
+use std::f64::consts::PI;
+
+use wavr_audio_buffer::AudioBuffer;
+use wavr_effects::{normalize, NormalizationTarget};
+
+const SAMPLE_RATE: u32 = 48000;
+
+/// Ten seconds of a stereo program alternating between a quiet and a loud section, with sharp
+/// transients.
+fn program() -> AudioBuffer {
+    let length = 10 * SAMPLE_RATE as usize;
+    let mut buffer = AudioBuffer::zeroed(2, length);
+    for i in 0..length {
+        let t = i as f64 / SAMPLE_RATE as f64;
+        let level = if (t as usize) % 4 < 2 { 0.05 } else { 0.3 };
+        let click = if i % 12000 == 0 { 0.9 } else { 0.0 };
+        buffer[(0, i)] = level * (2.0 * PI * 220.0 * t).sin() + click;
+        buffer[(1, i)] = level * (2.0 * PI * 330.0 * t).sin() - click;
+    }
+    buffer
+}
+
+fn main() -> Result<(), wavr_meter::Error> {
+    let targets = [
+        ("EBU R 128", NormalizationTarget::ebu_r128()),
+        ("ATSC A/85", NormalizationTarget::atsc_a85()),
+        ("Streaming", NormalizationTarget::streaming()),
+    ];
+    for (name, target) in targets.iter() {
+        let mut buffer = program();
+        let report = normalize(&mut buffer, SAMPLE_RATE, *target)?;
+        println!("{} (gain {:+.2} dB)", name, report.gain_db);
+        println!(
+            "  before: {:.2} LUFS, LRA {:.2} LU, {:.2} dBTP",
+            report.before.loudness.0, report.before.range.0, report.before.true_peak.0
+        );
+        println!(
+            "  after:  {:.2} LUFS, LRA {:.2} LU, {:.2} dBTP",
+            report.after.loudness.0, report.after.range.0, report.after.true_peak.0
+        );
+    }
+    Ok(())
+}
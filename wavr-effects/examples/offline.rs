@@ -0,0 +1,49 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+
+use wavr_effects::{BiquadFilter, BiquadType, Compressor, Delay, Gain, Reverb};
+use wavr_engine::{AudioContextState, AudioEngine};
+
+const CHANNELS: u8 = 2;
+const SAMPLE_RATE: u64 = 48000;
+const BLOCK_SIZE: usize = 512;
+
+fn main() {
+    let mut engine = AudioEngine::new(SAMPLE_RATE, CHANNELS);
+    {
+        let rack = engine.get_rack_mut();
+        rack.push_effect(BiquadFilter::new(BiquadType::HighPass, 80.0, 0.707));
+        rack.push_effect(Compressor::new(-18.0, 4.0));
+        rack.push_effect(Delay::new(250.0));
+        rack.push_effect(Reverb::new(0.8));
+        rack.push_effect(Gain::new(-6.0));
+    }
+    engine.configure(SAMPLE_RATE, CHANNELS);
+    engine.set_context_state(AudioContextState::Offline);
+
+    // Render two seconds of a decaying 440 Hz burst
+    let mut phase = 0.0f64;
+    for block in 0..(2 * SAMPLE_RATE as usize / BLOCK_SIZE) {
+        let mut data = vec![0.0; BLOCK_SIZE * CHANNELS as usize];
+        if block < 10 {
+            for frame in data.chunks_mut(CHANNELS as usize) {
+                let sample = 0.5 * phase.sin();
+                frame.iter_mut().for_each(|s| *s = sample);
+                phase += 2.0 * std::f64::consts::PI * 440.0 / SAMPLE_RATE as f64;
+            }
+        }
+        engine.fill_interleaved(&mut data);
+        if block % 10 == 0 {
+            if let Some(meter) = engine.get_rack().get_output_meter_data() {
+                println!(
+                    "{:>5} ms: {:?}",
+                    block * BLOCK_SIZE * 1000 / SAMPLE_RATE as usize,
+                    meter
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,293 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! # Automatic gain control
+//!
+//! Real-time loudness-compensated gain control for live programs. The input is measured with EBU
+//! short-term and momentary loudness meters, and the gain is steered slowly towards the one
+//! bringing the short-term loudness to the target:
+//!
+//! - when the momentary loudness falls below the gate, the program is considered silent and the
+//!   gain is held, so that pauses don't pump the gain up,
+//! - when the momentary loudness exceeds the target by more than the headroom, the gain is pulled
+//!   down without waiting for the short-term loudness to catch up,
+//! - the gain moves no faster than the boost and cut rates, and stays within the gain range.
+//!
+//! The loudness meters are built when the effect is prepared. Until then, or if they cannot be
+//! built for the context, the gain is held at its current value.
+//!
+//! The gain can be frozen, either on the effect or through a [`FreezeControl`] handle usable from
+//! another thread while the effect is owned by the rack:
+//!
+//! ```rust
+//! # use wavr_effects::AutomaticGainControl;
+//! # use wavr_engine::AudioEngine;
+//! # let mut engine = AudioEngine::new(48000, 2);
+//! let agc = AutomaticGainControl::new(-16.0);
+//! let freeze = agc.freeze_control();
+//! engine.get_rack_mut().push_effect(agc);
+//! // Later, during an ad break
+//! freeze.set_frozen(true);
+//! ```
+//!
+//! [`FreezeControl`]: struct.FreezeControl.html
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use wavr_audio_buffer::AudioBuffer;
+use wavr_engine::{AudioContext, Effect};
+use wavr_meter::decibel::{Decibel, Gain};
+use wavr_meter::ebu::modes::{Momentary, Short};
+use wavr_meter::EBUMeter;
+
+/// Number of frames the interleaving buffer is allocated for. Longer blocks grow it on the audio
+/// thread.
+const SCRATCH_FRAMES: usize = 8192;
+
+/// Handle to the freeze state of an automatic gain control, usable from any thread.
+#[derive(Clone, Debug, Default)]
+pub struct FreezeControl {
+    frozen: Arc<AtomicBool>,
+}
+
+/// Automatic gain control, steering the gain towards a target short-term loudness.
+#[derive(Debug)]
+pub struct AutomaticGainControl {
+    target_lufs: f64,
+    gate_lufs: f64,
+    headroom_db: f64,
+    min_gain_db: f64,
+    max_gain_db: f64,
+    boost_rate_db: f64,
+    cut_rate_db: f64,
+    sample_rate: f64,
+    channels: usize,
+    momentary: Option<EBUMeter<Momentary>>,
+    short: Option<EBUMeter<Short>>,
+    /// Interleaved copy of the block, fed to the meters.
+    interleaved: Vec<f64>,
+    gain_db: f64,
+    freeze: FreezeControl,
+}
+
+impl FreezeControl {
+    /// Returns whether the gain is frozen.
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.load(Ordering::Relaxed)
+    }
+
+    /// Freezes the gain at its current value, or releases it.
+    pub fn set_frozen(&self, frozen: bool) {
+        self.frozen.store(frozen, Ordering::Relaxed);
+    }
+}
+
+impl AutomaticGainControl {
+    /// Create a new AGC towards the given short-term loudness in LUFS. The gain starts at 0 dB
+    /// and stays within ±12 dB, rising at most 3 dB/s and falling at most 6 dB/s. The gate is set
+    /// at -50 LUFS, and the momentary headroom at 6 LU.
+    pub fn new(target_lufs: f64) -> Self {
+        Self {
+            target_lufs,
+            gate_lufs: -50.0,
+            headroom_db: 6.0,
+            min_gain_db: -12.0,
+            max_gain_db: 12.0,
+            boost_rate_db: 3.0,
+            cut_rate_db: 6.0,
+            sample_rate: 48000.0,
+            channels: 0,
+            momentary: None,
+            short: None,
+            interleaved: Vec::new(),
+            gain_db: 0.0,
+            freeze: FreezeControl::default(),
+        }
+    }
+
+    /// Returns the target short-term loudness in LUFS.
+    pub fn target_lufs(&self) -> f64 {
+        self.target_lufs
+    }
+
+    /// Sets the target short-term loudness in LUFS.
+    pub fn set_target_lufs(&mut self, target_lufs: f64) {
+        self.target_lufs = target_lufs;
+    }
+
+    /// Returns the gate threshold in LUFS.
+    pub fn gate_lufs(&self) -> f64 {
+        self.gate_lufs
+    }
+
+    /// Sets the gate threshold in LUFS. The gain is held while the momentary loudness of the input
+    /// is below it.
+    pub fn set_gate_lufs(&mut self, gate_lufs: f64) {
+        self.gate_lufs = gate_lufs;
+    }
+
+    /// Returns the momentary headroom in LU.
+    pub fn headroom_db(&self) -> f64 {
+        self.headroom_db
+    }
+
+    /// Sets how far the momentary loudness of the output may exceed the target before the gain is
+    /// pulled down, in LU.
+    pub fn set_headroom_db(&mut self, headroom_db: f64) {
+        self.headroom_db = headroom_db.max(0.0);
+    }
+
+    /// Returns the lowest gain of the AGC, in decibels.
+    pub fn min_gain_db(&self) -> f64 {
+        self.min_gain_db
+    }
+
+    /// Sets the lowest gain of the AGC, in decibels.
+    pub fn set_min_gain_db(&mut self, min_gain_db: f64) {
+        self.min_gain_db = min_gain_db.min(0.0);
+    }
+
+    /// Returns the highest gain of the AGC, in decibels.
+    pub fn max_gain_db(&self) -> f64 {
+        self.max_gain_db
+    }
+
+    /// Sets the highest gain of the AGC, in decibels.
+    pub fn set_max_gain_db(&mut self, max_gain_db: f64) {
+        self.max_gain_db = max_gain_db.max(0.0);
+    }
+
+    /// Returns the highest rate at which the gain rises, in decibels per second.
+    pub fn boost_rate_db(&self) -> f64 {
+        self.boost_rate_db
+    }
+
+    /// Sets the highest rate at which the gain rises, in decibels per second.
+    pub fn set_boost_rate_db(&mut self, boost_rate_db: f64) {
+        self.boost_rate_db = boost_rate_db.max(0.0);
+    }
+
+    /// Returns the highest rate at which the gain falls, in decibels per second.
+    pub fn cut_rate_db(&self) -> f64 {
+        self.cut_rate_db
+    }
+
+    /// Sets the highest rate at which the gain falls, in decibels per second.
+    pub fn set_cut_rate_db(&mut self, cut_rate_db: f64) {
+        self.cut_rate_db = cut_rate_db.max(0.0);
+    }
+
+    /// Returns whether the gain is frozen.
+    pub fn is_frozen(&self) -> bool {
+        self.freeze.is_frozen()
+    }
+
+    /// Freezes the gain at its current value, or releases it.
+    pub fn set_frozen(&mut self, frozen: bool) {
+        self.freeze.set_frozen(frozen);
+    }
+
+    /// Returns a handle to the freeze state, which can be changed from another thread.
+    pub fn freeze_control(&self) -> FreezeControl {
+        self.freeze.clone()
+    }
+
+    /// Returns the gain applied at the end of the last processed block, in decibels.
+    pub fn gain_db(&self) -> f64 {
+        self.gain_db
+    }
+
+    /// Returns the gain the AGC is moving towards, given the loudness of the input.
+    fn desired_gain_db(&self, momentary: f64, short: f64) -> f64 {
+        let mut gain = if short.is_finite() {
+            self.target_lufs - short
+        } else {
+            self.gain_db
+        };
+        let fast_limit = self.target_lufs + self.headroom_db - momentary;
+        if fast_limit < gain {
+            gain = fast_limit;
+        }
+        gain.clamp(self.min_gain_db, self.max_gain_db)
+    }
+
+    /// Measures the momentary and short-term loudness of the input, in LUFS. Returns `None` when
+    /// the meters are not available for the block.
+    fn measure(&mut self, context: &AudioContext, buffer: &AudioBuffer) -> Option<(f64, f64)> {
+        if self.sample_rate != context.sample_rate as f64 || self.channels != buffer.channels() {
+            return None;
+        }
+        let (momentary, short) = match (&mut self.momentary, &mut self.short) {
+            (Some(momentary), Some(short)) => (momentary, short),
+            _ => return None,
+        };
+        self.interleaved.clear();
+        for i in 0..buffer.buffer_size() {
+            for ch in 0..buffer.channels() {
+                self.interleaved.push(buffer[(ch, i)]);
+            }
+        }
+        momentary.add_samples(&self.interleaved).ok()?;
+        short.add_samples(&self.interleaved).ok()?;
+        let Decibel(momentary) = momentary.get_loudness().ok()?;
+        let Decibel(short) = short.get_loudness().ok()?;
+        Some((momentary, short))
+    }
+
+    fn allocate(&mut self, channels: usize) {
+        self.channels = channels;
+        self.momentary = EBUMeter::new(channels as u32, self.sample_rate as u32).ok();
+        self.short = EBUMeter::new(channels as u32, self.sample_rate as u32).ok();
+        self.interleaved = Vec::with_capacity(channels * SCRATCH_FRAMES);
+    }
+}
+
+impl Effect for AutomaticGainControl {
+    fn prepare(&mut self, context: &AudioContext) {
+        self.sample_rate = context.sample_rate as f64;
+        self.allocate(context.channel_count as usize);
+    }
+
+    /// Clears the loudness history. The gain is kept, so that the program doesn't jump back to
+    /// unity gain.
+    fn reset(&mut self) {
+        // A meter which cannot be reset is dropped, holding the gain as when it cannot be created
+        if let Some(Err(_)) = self.momentary.as_mut().map(EBUMeter::reset) {
+            self.momentary = None;
+        }
+        if let Some(Err(_)) = self.short.as_mut().map(EBUMeter::reset) {
+            self.short = None;
+        }
+    }
+
+    /// Applies the gain, updating it from the loudness of the block. When the loudness cannot be
+    /// measured, the gain is held.
+    fn process(&mut self, context: &AudioContext, buffer: &mut AudioBuffer) {
+        let start = self.gain_db;
+        if let Some((momentary, short)) = self.measure(context, buffer) {
+            let gated = momentary.is_nan() || momentary < self.gate_lufs;
+            if !self.is_frozen() && !gated {
+                let desired = self.desired_gain_db(momentary, short);
+                let seconds = buffer.buffer_size() as f64 / self.sample_rate;
+                self.gain_db = if desired > self.gain_db {
+                    desired.min(self.gain_db + self.boost_rate_db * seconds)
+                } else {
+                    desired.max(self.gain_db - self.cut_rate_db * seconds)
+                };
+            }
+        }
+
+        // Ramp linearly over the block, which stays within the rate limits
+        let size = buffer.buffer_size();
+        let step = (self.gain_db - start) / size.max(1) as f64;
+        for i in 0..size {
+            let gain = Gain(start + step * (i + 1) as f64).to_linear();
+            for ch in 0..buffer.channels() {
+                buffer[(ch, i)] *= gain;
+            }
+        }
+    }
+}
@@ -0,0 +1,312 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! # Biquad filters
+//!
+//! Second-order IIR filters, with coefficients from Robert Bristow-Johnson's "Audio EQ Cookbook".
+//! The filters are implemented in the transposed direct form II, which behaves well when the
+//! coefficients change while processing.
+
+use std::f64::consts::PI;
+
+use smallvec::SmallVec;
+use wavr_audio_buffer::AudioBuffer;
+use wavr_engine::{AudioContext, Effect};
+
+/// Shape of a biquad filter.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BiquadType {
+    /// Low-pass filter, -12 dB/oct above the cutoff frequency.
+    LowPass,
+    /// High-pass filter, -12 dB/oct below the cutoff frequency.
+    HighPass,
+    /// Band-pass filter with a 0 dB peak gain.
+    BandPass,
+    /// Notch (band-reject) filter.
+    Notch,
+    /// Peaking (bell) filter, using the gain parameter.
+    Peak,
+    /// Low shelf filter, using the gain parameter.
+    LowShelf,
+    /// High shelf filter, using the gain parameter.
+    HighShelf,
+    /// All-pass filter, only affecting the phase.
+    AllPass,
+}
+
+/// Normalized biquad coefficients (`a0` is 1).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BiquadCoefficients {
+    pub b0: f64,
+    pub b1: f64,
+    pub b2: f64,
+    pub a1: f64,
+    pub a2: f64,
+}
+
+/// Memory of a single-channel biquad filter.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct BiquadState {
+    s1: f64,
+    s2: f64,
+}
+
+/// Biquad filter effect, applying the same filter to all channels.
+#[derive(Clone, Debug)]
+pub struct BiquadFilter {
+    kind: BiquadType,
+    frequency: f64,
+    q: f64,
+    gain_db: f64,
+    sample_rate: f64,
+    coefficients: BiquadCoefficients,
+    states: SmallVec<[BiquadState; 16]>,
+}
+
+impl BiquadCoefficients {
+    /// Coefficients of a filter letting the signal through unchanged.
+    pub fn identity() -> Self {
+        Self {
+            b0: 1.0,
+            b1: 0.0,
+            b2: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+        }
+    }
+
+    /// Compute the coefficients of a filter of the given type. The frequency is in Hertz and is
+    /// clamped below the Nyquist frequency. The gain, in decibels, is only used by the peak and
+    /// shelf filters.
+    pub fn new(kind: BiquadType, sample_rate: f64, frequency: f64, q: f64, gain_db: f64) -> Self {
+        let frequency = frequency.clamp(1.0, sample_rate * 0.499);
+        let q = q.max(1e-3);
+        let w0 = 2.0 * PI * frequency / sample_rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q);
+        let a = 10f64.powf(gain_db / 40.0);
+
+        let (b0, b1, b2, a0, a1, a2) = match kind {
+            BiquadType::LowPass => (
+                (1.0 - cos) / 2.0,
+                1.0 - cos,
+                (1.0 - cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            BiquadType::HighPass => (
+                (1.0 + cos) / 2.0,
+                -(1.0 + cos),
+                (1.0 + cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            BiquadType::BandPass => (alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            BiquadType::Notch => (1.0, -2.0 * cos, 1.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            BiquadType::Peak => (
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ),
+            BiquadType::LowShelf => {
+                let sqrt = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) - (a - 1.0) * cos + sqrt),
+                    2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                    a * ((a + 1.0) - (a - 1.0) * cos - sqrt),
+                    (a + 1.0) + (a - 1.0) * cos + sqrt,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                    (a + 1.0) + (a - 1.0) * cos - sqrt,
+                )
+            }
+            BiquadType::HighShelf => {
+                let sqrt = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) + (a - 1.0) * cos + sqrt),
+                    -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                    a * ((a + 1.0) + (a - 1.0) * cos - sqrt),
+                    (a + 1.0) - (a - 1.0) * cos + sqrt,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                    (a + 1.0) - (a - 1.0) * cos - sqrt,
+                )
+            }
+            BiquadType::AllPass => (
+                1.0 - alpha,
+                -2.0 * cos,
+                1.0 + alpha,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+        };
+
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    /// Returns the complex frequency response of the filter at the given frequency, as a
+    /// `(real, imaginary)` pair.
+    pub fn complex_response(&self, frequency: f64, sample_rate: f64) -> (f64, f64) {
+        let w = 2.0 * PI * frequency / sample_rate;
+        let (sin1, cos1) = w.sin_cos();
+        let (sin2, cos2) = (2.0 * w).sin_cos();
+        let num_re = self.b0 + self.b1 * cos1 + self.b2 * cos2;
+        let num_im = -(self.b1 * sin1 + self.b2 * sin2);
+        let den_re = 1.0 + self.a1 * cos1 + self.a2 * cos2;
+        let den_im = -(self.a1 * sin1 + self.a2 * sin2);
+        let den_norm = den_re * den_re + den_im * den_im;
+        (
+            (num_re * den_re + num_im * den_im) / den_norm,
+            (num_im * den_re - num_re * den_im) / den_norm,
+        )
+    }
+
+    /// Returns the magnitude (as a linear gain factor) and phase (in radians) of the filter at the
+    /// given frequency.
+    pub fn response(&self, frequency: f64, sample_rate: f64) -> (f64, f64) {
+        let (re, im) = self.complex_response(frequency, sample_rate);
+        (re.hypot(im), im.atan2(re))
+    }
+}
+
+impl Default for BiquadCoefficients {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl BiquadState {
+    /// Filters a single sample.
+    #[inline]
+    pub fn process(&mut self, c: &BiquadCoefficients, x: f64) -> f64 {
+        let y = c.b0 * x + self.s1;
+        self.s1 = c.b1 * x - c.a1 * y + self.s2;
+        self.s2 = c.b2 * x - c.a2 * y;
+        y
+    }
+
+    /// Clears the filter memory.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl BiquadFilter {
+    /// Create a new filter. For the peak and shelf filters, use
+    /// [`with_gain_db`](#method.with_gain_db) to set the gain.
+    pub fn new(kind: BiquadType, frequency: f64, q: f64) -> Self {
+        let mut this = Self {
+            kind,
+            frequency,
+            q,
+            gain_db: 0.0,
+            sample_rate: 48000.0,
+            coefficients: BiquadCoefficients::identity(),
+            states: SmallVec::new(),
+        };
+        this.update();
+        this
+    }
+
+    /// Sets the gain of the filter in decibels.
+    pub fn with_gain_db(mut self, gain_db: f64) -> Self {
+        self.set_gain_db(gain_db);
+        self
+    }
+
+    /// Returns the filter type.
+    pub fn kind(&self) -> BiquadType {
+        self.kind
+    }
+
+    /// Sets the filter type.
+    pub fn set_kind(&mut self, kind: BiquadType) {
+        self.kind = kind;
+        self.update();
+    }
+
+    /// Returns the frequency in Hertz.
+    pub fn frequency(&self) -> f64 {
+        self.frequency
+    }
+
+    /// Sets the frequency in Hertz.
+    pub fn set_frequency(&mut self, frequency: f64) {
+        self.frequency = frequency;
+        self.update();
+    }
+
+    /// Returns the quality factor.
+    pub fn q(&self) -> f64 {
+        self.q
+    }
+
+    /// Sets the quality factor.
+    pub fn set_q(&mut self, q: f64) {
+        self.q = q;
+        self.update();
+    }
+
+    /// Returns the gain in decibels.
+    pub fn gain_db(&self) -> f64 {
+        self.gain_db
+    }
+
+    /// Sets the gain in decibels.
+    pub fn set_gain_db(&mut self, gain_db: f64) {
+        self.gain_db = gain_db;
+        self.update();
+    }
+
+    /// Returns the current coefficients of the filter.
+    pub fn coefficients(&self) -> &BiquadCoefficients {
+        &self.coefficients
+    }
+
+    fn update(&mut self) {
+        self.coefficients = BiquadCoefficients::new(
+            self.kind,
+            self.sample_rate,
+            self.frequency,
+            self.q,
+            self.gain_db,
+        );
+    }
+}
+
+impl Effect for BiquadFilter {
+    fn prepare(&mut self, context: &AudioContext) {
+        self.sample_rate = context.sample_rate as f64;
+        self.states = SmallVec::from_elem(BiquadState::default(), context.channel_count as usize);
+        self.update();
+    }
+
+    fn reset(&mut self) {
+        self.states.iter_mut().for_each(BiquadState::reset);
+    }
+
+    fn process(&mut self, context: &AudioContext, buffer: &mut AudioBuffer) {
+        if self.sample_rate != context.sample_rate as f64 || self.states.len() < buffer.channels() {
+            self.prepare(context);
+            self.states
+                .resize(buffer.channels(), BiquadState::default());
+        }
+        for (ch, state) in self.states.iter_mut().enumerate().take(buffer.channels()) {
+            for sample in &mut buffer[ch] {
+                *sample = state.process(&self.coefficients, *sample);
+            }
+        }
+    }
+}
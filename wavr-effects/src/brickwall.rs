@@ -0,0 +1,245 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! # Brickwall true peak limiter
+//!
+//! Lookahead limiter guaranteeing that the inter-sample peaks of its output stay under the
+//! ceiling, as measured by `wavr_meter`'s true peak meters.
+//!
+//! The input is measured with the same oversampling interpolator as the meters. For every sample,
+//! the gain needed to bring its true peak under the ceiling is computed, and held over the
+//! lookahead window and the span of the interpolation filter. The held gain is released smoothly,
+//! then averaged over the lookahead window, so that the gain ramps down before the peak reaches
+//! the output and never exceeds the required gain around it.
+
+use smallvec::SmallVec;
+use wavr_audio_buffer::AudioBuffer;
+use wavr_engine::{AudioContext, Effect};
+use wavr_meter::decibel::DbFs;
+use wavr_meter::TruePeak;
+
+use crate::dynamics::GainReductionMeter;
+use crate::util::{time_coefficient, SlidingMinimum};
+
+/// Default ceiling, in dBTP, as required by EBU R 128 deliverables.
+pub const DEFAULT_TRUE_PEAK_CEILING: f64 = -1.0;
+
+/// Half-width of the neighbourhood over which the required gain is held, in samples. This covers
+/// the main lobes of the true peak interpolation filter.
+const NEIGHBOURHOOD: usize = 8;
+/// Margin below the ceiling absorbing the interpolation of the gain changes, in decibels.
+const SAFETY_MARGIN_DB: f64 = 0.05;
+
+/// Brickwall true peak limiter. All channels are limited with the same gain.
+#[derive(Clone, Debug)]
+pub struct TruePeakLimiter {
+    ceiling_db: f64,
+    release_ms: f64,
+    lookahead_ms: f64,
+    sample_rate: f64,
+    channels: usize,
+    needs_prepare: bool,
+    release: f64,
+    detector: TruePeak,
+    /// Sliding minimum of the required gains.
+    minimum: SlidingMinimum,
+    envelope: f64,
+    /// Ring buffer of the released gains, averaged over the lookahead window.
+    average: Vec<f64>,
+    average_sum: f64,
+    average_pos: usize,
+    delay_lines: SmallVec<[Vec<f64>; 16]>,
+    delay_pos: usize,
+    gain_reduction: GainReductionMeter,
+}
+
+impl TruePeakLimiter {
+    /// Create a new limiter with the default -1 dBTP ceiling, a 1.5 ms lookahead and a 100 ms
+    /// release.
+    pub fn new() -> Self {
+        Self::with_ceiling(DEFAULT_TRUE_PEAK_CEILING)
+    }
+
+    /// Create a new limiter with the given ceiling in dBTP.
+    pub fn with_ceiling(ceiling_db: f64) -> Self {
+        Self {
+            ceiling_db,
+            release_ms: 100.0,
+            lookahead_ms: 1.5,
+            sample_rate: 48000.0,
+            channels: 0,
+            needs_prepare: true,
+            release: 0.0,
+            detector: TruePeak::new(0, 48000),
+            minimum: SlidingMinimum::new(1),
+            envelope: 1.0,
+            average: Vec::new(),
+            average_sum: 0.0,
+            average_pos: 0,
+            delay_lines: SmallVec::new(),
+            delay_pos: 0,
+            gain_reduction: GainReductionMeter::default(),
+        }
+    }
+
+    /// Returns the ceiling in dBTP.
+    pub fn ceiling_db(&self) -> f64 {
+        self.ceiling_db
+    }
+
+    /// Sets the ceiling in dBTP.
+    pub fn set_ceiling_db(&mut self, ceiling_db: f64) {
+        self.ceiling_db = ceiling_db;
+    }
+
+    /// Returns the release time in milliseconds.
+    pub fn release_ms(&self) -> f64 {
+        self.release_ms
+    }
+
+    /// Sets the release time in milliseconds.
+    pub fn set_release_ms(&mut self, release_ms: f64) {
+        self.release_ms = release_ms.max(0.0);
+        self.release = time_coefficient(self.release_ms, self.sample_rate);
+    }
+
+    /// Returns the lookahead time in milliseconds.
+    pub fn lookahead_ms(&self) -> f64 {
+        self.lookahead_ms
+    }
+
+    /// Sets the lookahead time in milliseconds, over which the gain ramps down before a peak.
+    pub fn set_lookahead_ms(&mut self, lookahead_ms: f64) {
+        self.lookahead_ms = lookahead_ms.max(0.0);
+        self.needs_prepare = true;
+    }
+
+    /// Returns the highest gain reduction of the last processed block, in decibels.
+    pub fn gain_reduction_db(&self) -> f64 {
+        self.gain_reduction.gain_reduction_db()
+    }
+
+    /// Returns a handle to the gain reduction, which can be read from another thread.
+    pub fn gain_reduction_meter(&self) -> GainReductionMeter {
+        self.gain_reduction.clone()
+    }
+
+    /// Returns the length of the lookahead window, in samples.
+    fn lookahead_samples(&self) -> usize {
+        ((self.lookahead_ms * 1e-3 * self.sample_rate).round() as usize).max(1)
+    }
+
+    fn allocate(&mut self, channels: usize) {
+        let lookahead = self.lookahead_samples();
+        let delay = self.latency();
+        self.channels = channels;
+        self.release = time_coefficient(self.release_ms, self.sample_rate);
+        self.detector = TruePeak::new(channels, self.sample_rate as u32);
+        self.minimum = SlidingMinimum::new(2 * NEIGHBOURHOOD + lookahead + 1);
+        self.average = vec![1.0; lookahead];
+        self.delay_lines = SmallVec::from_elem(vec![0.0; delay], channels);
+        self.clear();
+        self.needs_prepare = false;
+    }
+
+    /// Clears the detector, gains and delay lines, keeping their storage.
+    fn clear(&mut self) {
+        self.detector.reset();
+        self.minimum.reset();
+        self.envelope = 1.0;
+        self.average.iter_mut().for_each(|g| *g = 1.0);
+        self.average_sum = self.average.len() as f64;
+        self.average_pos = 0;
+        for line in &mut self.delay_lines {
+            line.iter_mut().for_each(|s| *s = 0.0);
+        }
+        self.delay_pos = 0;
+    }
+
+    /// Pushes a gain into the moving average, and returns the average over the lookahead window.
+    #[inline]
+    fn smooth(&mut self, gain: f64) -> f64 {
+        let len = self.average.len();
+        self.average_sum += gain - self.average[self.average_pos];
+        self.average[self.average_pos] = gain;
+        self.average_pos += 1;
+        if self.average_pos == len {
+            // Recompute the sum once per cycle to avoid accumulating rounding errors
+            self.average_pos = 0;
+            self.average_sum = self.average.iter().sum();
+        }
+        self.average_sum / len as f64
+    }
+}
+
+impl Default for TruePeakLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Effect for TruePeakLimiter {
+    fn prepare(&mut self, context: &AudioContext) {
+        self.sample_rate = context.sample_rate as f64;
+        self.allocate(context.channel_count as usize);
+    }
+
+    fn reset(&mut self) {
+        self.clear();
+        self.gain_reduction.set(0.0);
+    }
+
+    /// The lookahead, plus the span of the interpolation filter and the delay of the true peak
+    /// detector.
+    fn latency(&self) -> usize {
+        self.lookahead_samples() + NEIGHBOURHOOD + TruePeak::latency_at(self.sample_rate as u32)
+    }
+
+    fn process(&mut self, context: &AudioContext, buffer: &mut AudioBuffer) {
+        if self.needs_prepare
+            || self.sample_rate != context.sample_rate as f64
+            || self.channels != buffer.channels()
+        {
+            self.sample_rate = context.sample_rate as f64;
+            self.allocate(buffer.channels());
+        }
+        let ceiling = DbFs(self.ceiling_db - SAFETY_MARGIN_DB).to_amplitude();
+        let delay = self.delay_lines.first().map(Vec::len).unwrap_or(0);
+        if delay == 0 {
+            // Without channels, there is nothing to delay nor limit
+            return;
+        }
+        let mut frame: SmallVec<[f64; 16]> = SmallVec::from_elem(0.0, self.channels);
+        let mut min_gain = 1.0f64;
+
+        for i in 0..buffer.buffer_size() {
+            for (ch, sample) in frame.iter_mut().enumerate() {
+                *sample = buffer[(ch, i)];
+            }
+            let mut peak = 0.0f64;
+            self.detector
+                .process_frame(&frame, |_, p| peak = peak.max(p));
+            let required = if peak > ceiling { ceiling / peak } else { 1.0 };
+
+            let held = self.minimum.push(required);
+            self.envelope = if held < self.envelope {
+                held
+            } else {
+                held + self.release * (self.envelope - held)
+            };
+            let gain = self.smooth(self.envelope);
+            min_gain = min_gain.min(gain);
+
+            for (ch, &sample) in frame.iter().enumerate() {
+                let line = &mut self.delay_lines[ch];
+                let delayed = line[self.delay_pos];
+                line[self.delay_pos] = sample;
+                buffer[(ch, i)] = delayed * gain;
+            }
+            self.delay_pos = (self.delay_pos + 1) % delay;
+        }
+        self.gain_reduction.set(-20.0 * min_gain.log10());
+    }
+}
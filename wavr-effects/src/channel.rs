@@ -0,0 +1,100 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! # Channel utilities
+//!
+//! Polarity inversion and channel swapping.
+
+use smallvec::SmallVec;
+use wavr_audio_buffer::AudioBuffer;
+use wavr_engine::{AudioContext, Effect};
+
+/// Inverts the polarity of the selected channels.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Polarity {
+    inverted: SmallVec<[bool; 16]>,
+    /// Whether the channels not present in `inverted` are inverted.
+    all: bool,
+}
+
+/// Swaps two channels of the buffer (ie. left and right).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ChannelSwap {
+    first: usize,
+    second: usize,
+}
+
+impl Polarity {
+    /// Create a polarity effect inverting all channels.
+    pub fn all() -> Self {
+        Self {
+            inverted: SmallVec::new(),
+            all: true,
+        }
+    }
+
+    /// Create a polarity effect inverting only the given channels.
+    pub fn channels(channels: &[usize]) -> Self {
+        let mut this = Self::default();
+        for &channel in channels {
+            this.set_inverted(channel, true);
+        }
+        this
+    }
+
+    /// Returns whether the given channel is inverted.
+    pub fn is_inverted(&self, channel: usize) -> bool {
+        self.inverted.get(channel).cloned().unwrap_or(self.all)
+    }
+
+    /// Sets whether the given channel is inverted.
+    pub fn set_inverted(&mut self, channel: usize, inverted: bool) {
+        if self.inverted.len() <= channel {
+            self.inverted.resize(channel + 1, self.all);
+        }
+        self.inverted[channel] = inverted;
+    }
+}
+
+impl Effect for Polarity {
+    fn process(&mut self, _context: &AudioContext, buffer: &mut AudioBuffer) {
+        for ch in (0..buffer.channels()).filter(|&ch| self.is_inverted(ch)) {
+            buffer[ch].iter_mut().for_each(|s| *s = -*s);
+        }
+    }
+}
+
+impl ChannelSwap {
+    /// Create an effect swapping the two given channels.
+    pub fn new(first: usize, second: usize) -> Self {
+        Self { first, second }
+    }
+
+    /// Create an effect swapping the left and right channels.
+    pub fn stereo() -> Self {
+        Self::new(0, 1)
+    }
+
+    /// Returns the swapped channels.
+    pub fn channels(&self) -> (usize, usize) {
+        (self.first, self.second)
+    }
+}
+
+impl Effect for ChannelSwap {
+    fn process(&mut self, _context: &AudioContext, buffer: &mut AudioBuffer) {
+        if self.first == self.second
+            || self.first >= buffer.channels()
+            || self.second >= buffer.channels()
+        {
+            return;
+        }
+        for i in 0..buffer.buffer_size() {
+            let first = buffer[(self.first, i)];
+            buffer[(self.first, i)] = buffer[(self.second, i)];
+            buffer[(self.second, i)] = first;
+        }
+    }
+}
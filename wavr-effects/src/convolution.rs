@@ -0,0 +1,876 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! # Convolution
+//!
+//! Partitioned FFT convolution with impulse responses, for convolution reverbs and cabinet
+//! simulation.
+//!
+//! The impulse response is split into partitions, each convolved with the input in the frequency
+//! domain (uniformly partitioned overlap-save). With uniform partitioning, all partitions have the
+//! size of the first block; non-uniform partitioning uses short partitions at the head of the
+//! impulse response to keep the latency low, and longer ones for its tail to keep the cost of long
+//! reverbs down. In both cases the latency of the effect is the size of the first block.
+//!
+//! Impulse responses recorded at another sample rate are resampled when the effect is prepared.
+//! They can be swapped while the effect is running, through a [`ConvolutionHandle`]: the new
+//! impulse response is prepared on the calling thread, and crossfaded with the previous one on
+//! the audio thread.
+//!
+//! Impulse responses are never prepared while processing: until the effect is prepared for the
+//! sample rate and channel count of the context, the audio passes through unchanged. The memory
+//! of the replaced impulse responses is sent back from the audio thread, and released by the
+//! handles and the effect outside of processing.
+//!
+//! ```rust
+//! # use wavr_effects::{Convolution, ImpulseResponse};
+//! # use wavr_engine::AudioEngine;
+//! # let mut engine = AudioEngine::new(48000, 2);
+//! # let room = vec![1.0, 0.5, 0.25];
+//! let ir = ImpulseResponse::new(44100, vec![room]).unwrap();
+//! let convolution = Convolution::new(ir);
+//! let handle = convolution.handle();
+//! engine.get_rack_mut().push_effect(convolution);
+//! // Later, on the UI thread
+//! let hall = ImpulseResponse::new(48000, vec![vec![1.0, 0.8, 0.6, 0.4]]).unwrap();
+//! handle.load(hall);
+//! ```
+//!
+//! [`ConvolutionHandle`]: struct.ConvolutionHandle.html
+
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fmt::{self, Display, Formatter};
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use smallvec::SmallVec;
+use wavr_audio_buffer::AudioBuffer;
+use wavr_engine::{AudioContext, Effect};
+
+use crate::util::Smoothed;
+
+/// Number of partitions of each size in non-uniform partitioning, before doubling the size.
+const PARTITIONS_PER_SIZE: usize = 2;
+/// Duration of the crossfade between impulse responses, in milliseconds.
+const SWAP_FADE_MS: f64 = 50.0;
+/// Half-width of the resampling filter, in zero crossings.
+const RESAMPLING_HALF_WIDTH: f64 = 32.0;
+/// Number of released kernels and impulse responses the audio thread can send back before the
+/// handles or the effect release them. Beyond that, they are dropped on the audio thread.
+const GARBAGE_CAPACITY: usize = 16;
+
+/// Errors raised when creating an impulse response.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImpulseResponseError {
+    /// The impulse response has no samples.
+    Empty,
+    /// The impulse response doesn't have 1 (mono), 2 (stereo) or 4 (true stereo) channels.
+    UnsupportedChannelCount(usize),
+    /// The channels of the impulse response don't have the same length.
+    MismatchedLengths,
+    /// The sample rate is zero.
+    InvalidSampleRate,
+}
+
+/// Channel layout of an impulse response.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ImpulseResponseLayout {
+    /// A single response, applied to every channel.
+    Mono,
+    /// One response per channel: the first applies to the even channels, the second to the odd
+    /// ones.
+    Stereo,
+    /// Four responses for each pair of channels, in the order left to left, left to right, right
+    /// to left and right to right.
+    TrueStereo,
+}
+
+/// Impulse response, with its sample rate.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImpulseResponse {
+    sample_rate: u32,
+    channels: Vec<Vec<f64>>,
+}
+
+/// Partitioning of the impulse response.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Partitioning {
+    /// All partitions have the same size, in samples.
+    Uniform {
+        /// Size of the partitions, which is also the latency.
+        block_size: usize,
+    },
+    /// Partitions start at the block size and double in size up to the maximum size.
+    NonUniform {
+        /// Size of the first partitions, which is also the latency.
+        block_size: usize,
+        /// Size of the longest partitions.
+        max_block_size: usize,
+    },
+}
+
+/// Range of the impulse response convolved by one partition size.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct SegmentLayout {
+    /// Start of the range in the impulse response.
+    offset: usize,
+    /// Length of the range.
+    length: usize,
+    /// Size of the partitions.
+    block_size: usize,
+    /// Zeros inserted before the range, compensating the difference between the latency of the
+    /// partitions and the latency of the effect.
+    padding: usize,
+}
+
+/// Spectra of the partitions of one segment of an impulse response channel.
+struct SegmentSpectra {
+    block_size: usize,
+    fft: Arc<dyn Fft<f64>>,
+    ifft: Arc<dyn Fft<f64>>,
+    partitions: Vec<Vec<Complex<f64>>>,
+}
+
+/// Convolution state of one segment.
+struct SegmentState {
+    spectra: Arc<SegmentSpectra>,
+    /// Previous and current input blocks.
+    input: Vec<f64>,
+    output: Vec<f64>,
+    position: usize,
+    /// Frequency-domain delay line of the input spectra.
+    history: Vec<Vec<Complex<f64>>>,
+    history_pos: usize,
+    buffer: Vec<Complex<f64>>,
+    accumulator: Vec<Complex<f64>>,
+    scratch: Vec<Complex<f64>>,
+}
+
+/// Convolution of one input channel into one output channel.
+struct Convolver {
+    segments: Vec<SegmentState>,
+}
+
+/// Configuration an impulse response is prepared for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct KernelConfig {
+    sample_rate: u64,
+    channels: usize,
+    partitioning: Partitioning,
+}
+
+/// Prepared impulse response, with the convolution state of all channel routes.
+struct Kernel {
+    config: KernelConfig,
+    /// Routes as `(input, output, convolver)`.
+    routes: Vec<(usize, usize, Convolver)>,
+}
+
+/// Impulse response loaded through a handle, with its kernel.
+struct Load {
+    impulse_response: ImpulseResponse,
+    kernel: Option<Kernel>,
+}
+
+/// Memory released by the audio thread, only held until it is dropped.
+#[allow(dead_code)]
+enum Garbage {
+    ImpulseResponse(ImpulseResponse),
+    Kernel(Kernel),
+    Load(Load),
+}
+
+/// Previous kernel, fading out after an impulse response swap.
+struct Fade {
+    kernel: Kernel,
+    remaining: usize,
+    length: usize,
+}
+
+/// State shared between the effect and its handles. The audio thread never locks it.
+struct Shared {
+    /// Configuration the effect is prepared for. Kept locked while preparing impulse responses,
+    /// so that the effect is not prepared again in the meantime.
+    config: Mutex<Option<KernelConfig>>,
+    garbage: Mutex<Receiver<Garbage>>,
+}
+
+/// Handle loading impulse responses into a running [`Convolution`](struct.Convolution.html)
+/// from another thread.
+pub struct ConvolutionHandle {
+    sender: Sender<Load>,
+    shared: Arc<Shared>,
+}
+
+/// Convolution effect.
+pub struct Convolution {
+    impulse_response: ImpulseResponse,
+    partitioning: Partitioning,
+    mix: f64,
+    mix_smoothed: Smoothed,
+    sample_rate: u64,
+    channels: usize,
+    kernel: Option<Kernel>,
+    fade: Option<Fade>,
+    dry_lines: SmallVec<[Vec<f64>; 16]>,
+    dry_pos: usize,
+    sender: Sender<Load>,
+    receiver: Receiver<Load>,
+    garbage: SyncSender<Garbage>,
+    shared: Arc<Shared>,
+}
+
+impl Display for ImpulseResponseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ImpulseResponseError::Empty => write!(f, "empty impulse response"),
+            ImpulseResponseError::UnsupportedChannelCount(count) => write!(
+                f,
+                "unsupported impulse response channel count: {} (expected 1, 2 or 4)",
+                count
+            ),
+            ImpulseResponseError::MismatchedLengths => {
+                write!(f, "impulse response channels have different lengths")
+            }
+            ImpulseResponseError::InvalidSampleRate => {
+                write!(f, "invalid impulse response sample rate")
+            }
+        }
+    }
+}
+
+impl Error for ImpulseResponseError {}
+
+impl fmt::Debug for ConvolutionHandle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConvolutionHandle").finish_non_exhaustive()
+    }
+}
+
+impl fmt::Debug for Convolution {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Convolution")
+            .field("layout", &self.impulse_response.layout())
+            .field("length", &self.impulse_response.len())
+            .field("partitioning", &self.partitioning)
+            .field("mix", &self.mix)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ImpulseResponse {
+    /// Create a new impulse response from its channels. There must be 1 (mono), 2 (stereo) or 4
+    /// (true stereo) channels of the same, non-zero length.
+    pub fn new(sample_rate: u32, channels: Vec<Vec<f64>>) -> Result<Self, ImpulseResponseError> {
+        if sample_rate == 0 {
+            return Err(ImpulseResponseError::InvalidSampleRate);
+        }
+        match channels.len() {
+            1 | 2 | 4 => {}
+            count => return Err(ImpulseResponseError::UnsupportedChannelCount(count)),
+        }
+        let length = channels[0].len();
+        if length == 0 {
+            return Err(ImpulseResponseError::Empty);
+        }
+        if channels.iter().any(|ch| ch.len() != length) {
+            return Err(ImpulseResponseError::MismatchedLengths);
+        }
+        Ok(Self {
+            sample_rate,
+            channels,
+        })
+    }
+
+    /// Create a new impulse response from the channels of a buffer.
+    pub fn from_buffer(
+        sample_rate: u32,
+        buffer: &AudioBuffer,
+    ) -> Result<Self, ImpulseResponseError> {
+        let channels = (0..buffer.channels())
+            .map(|ch| buffer[ch].to_vec())
+            .collect();
+        Self::new(sample_rate, channels)
+    }
+
+    /// Returns the sample rate of the impulse response.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Returns the channel layout of the impulse response.
+    pub fn layout(&self) -> ImpulseResponseLayout {
+        match self.channels.len() {
+            1 => ImpulseResponseLayout::Mono,
+            2 => ImpulseResponseLayout::Stereo,
+            _ => ImpulseResponseLayout::TrueStereo,
+        }
+    }
+
+    /// Returns the number of channels of the impulse response.
+    pub fn channel_count(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Returns the length of the impulse response, in samples.
+    pub fn len(&self) -> usize {
+        self.channels[0].len()
+    }
+
+    /// Returns whether the impulse response is empty. This is never the case for a valid impulse
+    /// response.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the samples of one channel of the impulse response.
+    pub fn channel(&self, channel: usize) -> &[f64] {
+        &self.channels[channel]
+    }
+
+    /// Returns the impulse response resampled to the given sample rate, with a band-limited
+    /// interpolator. The samples are scaled by the ratio of the sample rates, so that the
+    /// frequency response of the convolution is kept.
+    pub fn resampled(&self, sample_rate: u32) -> Self {
+        if sample_rate == self.sample_rate || sample_rate == 0 {
+            return self.clone();
+        }
+        let ratio = sample_rate as f64 / self.sample_rate as f64;
+        let cutoff = ratio.min(1.0);
+        let half_width = RESAMPLING_HALF_WIDTH / cutoff;
+        // Keep the ringing of the interpolator after the last sample
+        let length = ((self.len() as f64 + half_width) * ratio).ceil() as usize;
+        let channels = self
+            .channels
+            .iter()
+            .map(|input| {
+                (0..length)
+                    .map(|n| {
+                        let t = n as f64 / ratio;
+                        let first = (t - half_width).ceil().max(0.0) as usize;
+                        let last = ((t + half_width).floor() as usize).min(input.len() - 1);
+                        let sum: f64 = (first..=last)
+                            .map(|k| {
+                                let x = t - k as f64;
+                                input[k] * cutoff * sinc(cutoff * x) * blackman(x / half_width)
+                            })
+                            .sum();
+                        sum / ratio
+                    })
+                    .collect()
+            })
+            .collect();
+        Self {
+            sample_rate,
+            channels,
+        }
+    }
+}
+
+/// Normalized sinc function.
+#[inline]
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-12 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Blackman window, centered on 0 and spanning [-1, 1].
+#[inline]
+fn blackman(x: f64) -> f64 {
+    if x.abs() >= 1.0 {
+        0.0
+    } else {
+        let phase = PI * (x + 1.0);
+        0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos()
+    }
+}
+
+impl Partitioning {
+    /// Returns the latency of the convolution with this partitioning, in samples.
+    pub fn latency(&self) -> usize {
+        match *self {
+            Partitioning::Uniform { block_size } => block_size.max(1),
+            Partitioning::NonUniform { block_size, .. } => block_size.max(1),
+        }
+    }
+
+    /// Splits an impulse response of the given length into segments of equally sized
+    /// partitions.
+    fn segments(&self, length: usize) -> Vec<SegmentLayout> {
+        let head = self.latency();
+        let max_block_size = match *self {
+            Partitioning::Uniform { .. } => head,
+            Partitioning::NonUniform { max_block_size, .. } => max_block_size.max(head),
+        };
+        let mut segments = Vec::new();
+        let mut offset = 0;
+        let mut block_size = head;
+        while offset < length {
+            // A partition can only start once its block is buffered, which constrains its size
+            let doubled = (2 * block_size).min(max_block_size);
+            if offset > 0 && offset + head >= doubled {
+                block_size = doubled;
+            }
+            let padding = offset + head - block_size;
+            let capacity = if block_size == max_block_size {
+                usize::MAX
+            } else {
+                PARTITIONS_PER_SIZE * block_size - padding
+            };
+            let segment_length = capacity.min(length - offset);
+            segments.push(SegmentLayout {
+                offset,
+                length: segment_length,
+                block_size,
+                padding,
+            });
+            offset += segment_length;
+        }
+        segments
+    }
+}
+
+impl SegmentSpectra {
+    fn new(planner: &mut FftPlanner<f64>, layout: SegmentLayout, ir: &[f64]) -> Self {
+        let block_size = layout.block_size;
+        let size = 2 * block_size;
+        let fft = planner.plan_fft_forward(size);
+        let ifft = planner.plan_fft_inverse(size);
+        let mut scratch = vec![Complex::default(); fft.get_inplace_scratch_len()];
+
+        let mut padded = vec![0.0; layout.padding];
+        padded.extend_from_slice(&ir[layout.offset..layout.offset + layout.length]);
+        let partitions = padded
+            .chunks(block_size)
+            .map(|chunk| {
+                let mut spectrum = vec![Complex::default(); size];
+                for (bin, &sample) in spectrum.iter_mut().zip(chunk) {
+                    bin.re = sample;
+                }
+                fft.process_with_scratch(&mut spectrum, &mut scratch);
+                spectrum
+            })
+            .collect();
+        Self {
+            block_size,
+            fft,
+            ifft,
+            partitions,
+        }
+    }
+}
+
+impl SegmentState {
+    fn new(spectra: Arc<SegmentSpectra>) -> Self {
+        let block_size = spectra.block_size;
+        let size = 2 * block_size;
+        let scratch_len = spectra
+            .fft
+            .get_inplace_scratch_len()
+            .max(spectra.ifft.get_inplace_scratch_len());
+        Self {
+            input: vec![0.0; size],
+            output: vec![0.0; block_size],
+            position: 0,
+            history: vec![vec![Complex::default(); size]; spectra.partitions.len()],
+            history_pos: 0,
+            buffer: vec![Complex::default(); size],
+            accumulator: vec![Complex::default(); size],
+            scratch: vec![Complex::default(); scratch_len],
+            spectra,
+        }
+    }
+
+    /// Pushes one input sample and returns the output sample, delayed by the block size.
+    #[inline]
+    fn process(&mut self, sample: f64) -> f64 {
+        let block_size = self.spectra.block_size;
+        let output = self.output[self.position];
+        self.input[block_size + self.position] = sample;
+        self.position += 1;
+        if self.position == block_size {
+            self.position = 0;
+            self.convolve_block();
+        }
+        output
+    }
+
+    /// Convolves the buffered input block with all partitions (overlap-save).
+    fn convolve_block(&mut self) {
+        let block_size = self.spectra.block_size;
+        let size = 2 * block_size;
+        let count = self.history.len();
+
+        for (bin, &sample) in self.buffer.iter_mut().zip(&self.input) {
+            *bin = Complex::new(sample, 0.0);
+        }
+        self.spectra
+            .fft
+            .process_with_scratch(&mut self.buffer, &mut self.scratch);
+        self.history[self.history_pos].copy_from_slice(&self.buffer);
+
+        self.accumulator
+            .iter_mut()
+            .for_each(|bin| *bin = Complex::default());
+        for (k, partition) in self.spectra.partitions.iter().enumerate() {
+            let spectrum = &self.history[(self.history_pos + count - k) % count];
+            for ((acc, &x), &h) in self.accumulator.iter_mut().zip(spectrum).zip(partition) {
+                *acc += x * h;
+            }
+        }
+        self.history_pos = (self.history_pos + 1) % count;
+
+        self.spectra
+            .ifft
+            .process_with_scratch(&mut self.accumulator, &mut self.scratch);
+        let scale = 1.0 / size as f64;
+        for (out, bin) in self.output.iter_mut().zip(&self.accumulator[block_size..]) {
+            *out = bin.re * scale;
+        }
+        self.input.copy_within(block_size.., 0);
+    }
+}
+
+impl Convolver {
+    fn new(spectra: &[Arc<SegmentSpectra>]) -> Self {
+        Self {
+            segments: spectra.iter().cloned().map(SegmentState::new).collect(),
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, sample: f64) -> f64 {
+        self.segments.iter_mut().map(|s| s.process(sample)).sum()
+    }
+}
+
+impl Kernel {
+    /// Prepares the impulse response for the sample rate, channel count and partitioning.
+    fn new(impulse_response: &ImpulseResponse, config: KernelConfig) -> Self {
+        let KernelConfig {
+            sample_rate,
+            channels,
+            partitioning,
+        } = config;
+        let ir = impulse_response.resampled(sample_rate as u32);
+        let layouts = partitioning.segments(ir.len());
+        let mut planner = FftPlanner::new();
+        let spectra: Vec<Vec<Arc<SegmentSpectra>>> = ir
+            .channels
+            .iter()
+            .map(|channel| {
+                layouts
+                    .iter()
+                    .map(|&layout| Arc::new(SegmentSpectra::new(&mut planner, layout, channel)))
+                    .collect()
+            })
+            .collect();
+
+        let mut routes = Vec::new();
+        match ir.layout() {
+            ImpulseResponseLayout::Mono => {
+                for ch in 0..channels {
+                    routes.push((ch, ch, Convolver::new(&spectra[0])));
+                }
+            }
+            ImpulseResponseLayout::Stereo => {
+                for ch in 0..channels {
+                    routes.push((ch, ch, Convolver::new(&spectra[ch % 2])));
+                }
+            }
+            ImpulseResponseLayout::TrueStereo => {
+                for left in (0..channels).step_by(2) {
+                    let right = left + 1;
+                    routes.push((left, left, Convolver::new(&spectra[0])));
+                    if right < channels {
+                        routes.push((left, right, Convolver::new(&spectra[1])));
+                        routes.push((right, left, Convolver::new(&spectra[2])));
+                        routes.push((right, right, Convolver::new(&spectra[3])));
+                    }
+                }
+            }
+        }
+        Self { config, routes }
+    }
+
+    /// Convolves one frame, accumulating the result into `output`.
+    #[inline]
+    fn process_frame(&mut self, input: &[f64], output: &mut [f64], gain: f64) {
+        for (from, to, convolver) in &mut self.routes {
+            output[*to] += convolver.process(input[*from]) * gain;
+        }
+    }
+}
+
+impl Shared {
+    fn config(&self) -> MutexGuard<'_, Option<KernelConfig>> {
+        // The configuration is only ever replaced as a whole, so it is valid even if poisoned
+        self.config.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Drops the memory released by the audio thread.
+    fn collect_garbage(&self) {
+        if let Ok(garbage) = self.garbage.lock() {
+            garbage.try_iter().for_each(drop);
+        }
+    }
+}
+
+impl ConvolutionHandle {
+    /// Loads a new impulse response into the convolution, which crossfades to it. The impulse
+    /// response is resampled and transformed on the calling thread when the convolution has
+    /// already been prepared, and otherwise when it is prepared.
+    pub fn load(&self, impulse_response: ImpulseResponse) {
+        self.shared.collect_garbage();
+        let config = self.shared.config();
+        let kernel = config.map(|config| Kernel::new(&impulse_response, config));
+        // The effect may have been dropped, in which case there is nothing to load into
+        let _ = self.sender.send(Load {
+            impulse_response,
+            kernel,
+        });
+    }
+}
+
+impl Convolution {
+    /// Create a new convolution with the impulse response, fully wet, with non-uniform
+    /// partitioning from 128 to 8192 samples.
+    pub fn new(impulse_response: ImpulseResponse) -> Self {
+        Self::with_partitioning(
+            impulse_response,
+            Partitioning::NonUniform {
+                block_size: 128,
+                max_block_size: 8192,
+            },
+        )
+    }
+
+    /// Create a new convolution with the impulse response and partitioning.
+    pub fn with_partitioning(
+        impulse_response: ImpulseResponse,
+        partitioning: Partitioning,
+    ) -> Self {
+        let (sender, receiver) = channel();
+        let (garbage, garbage_receiver) = sync_channel(GARBAGE_CAPACITY);
+        Self {
+            impulse_response,
+            partitioning,
+            mix: 1.0,
+            mix_smoothed: Smoothed::new(1.0),
+            sample_rate: 0,
+            channels: 0,
+            kernel: None,
+            fade: None,
+            dry_lines: SmallVec::new(),
+            dry_pos: 0,
+            sender,
+            receiver,
+            garbage,
+            shared: Arc::new(Shared {
+                config: Mutex::new(None),
+                garbage: Mutex::new(garbage_receiver),
+            }),
+        }
+    }
+
+    /// Returns the current impulse response.
+    pub fn impulse_response(&self) -> &ImpulseResponse {
+        &self.impulse_response
+    }
+
+    /// Sets the impulse response. The effect crossfades to it when it's prepared, after
+    /// preparing it on the calling thread; prefer loading it through a
+    /// [`ConvolutionHandle`](struct.ConvolutionHandle.html) to avoid holding the effect (and the
+    /// engine) meanwhile.
+    pub fn set_impulse_response(&mut self, impulse_response: ImpulseResponse) {
+        self.shared.collect_garbage();
+        self.impulse_response = impulse_response;
+        if let Some(kernel) = &self.kernel {
+            let kernel = Kernel::new(&self.impulse_response, kernel.config);
+            self.swap_kernel(kernel);
+        }
+    }
+
+    /// Returns the partitioning of the impulse response.
+    pub fn partitioning(&self) -> Partitioning {
+        self.partitioning
+    }
+
+    /// Sets the partitioning of the impulse response. As this changes the latency, the
+    /// convolution restarts without crossfading.
+    pub fn set_partitioning(&mut self, partitioning: Partitioning) {
+        self.partitioning = partitioning;
+        if self.kernel.is_some() {
+            let channels = self.channels;
+            self.allocate(channels);
+        }
+    }
+
+    /// Returns the wet/dry mix, between 0 (dry) and 1 (wet).
+    pub fn mix(&self) -> f64 {
+        self.mix
+    }
+
+    /// Sets the wet/dry mix, between 0 (dry) and 1 (wet).
+    pub fn set_mix(&mut self, mix: f64) {
+        self.mix = mix.clamp(0.0, 1.0);
+        self.mix_smoothed.set(self.mix);
+    }
+
+    /// Returns a handle loading impulse responses from another thread.
+    pub fn handle(&self) -> ConvolutionHandle {
+        ConvolutionHandle {
+            sender: self.sender.clone(),
+            shared: self.shared.clone(),
+        }
+    }
+
+    fn allocate(&mut self, channels: usize) {
+        let shared = self.shared.clone();
+        let mut config = shared.config();
+        shared.collect_garbage();
+        // Impulse responses loaded until now were prepared for the previous configuration
+        if let Some(load) = self.receiver.try_iter().last() {
+            self.impulse_response = load.impulse_response;
+        }
+        let kernel_config = KernelConfig {
+            sample_rate: self.sample_rate,
+            channels,
+            partitioning: self.partitioning,
+        };
+        *config = Some(kernel_config);
+
+        self.channels = channels;
+        self.mix_smoothed.set_sample_rate(self.sample_rate as f64);
+        self.mix_smoothed.reset();
+        self.kernel = Some(Kernel::new(&self.impulse_response, kernel_config));
+        self.fade = None;
+        self.dry_lines = SmallVec::from_elem(vec![0.0; self.partitioning.latency()], channels);
+        self.dry_pos = 0;
+    }
+
+    /// Sends memory back to the handles for releasing. If the queue is full, the memory is
+    /// released on the current thread.
+    fn discard(&self, garbage: Garbage) {
+        let _ = self.garbage.try_send(garbage);
+    }
+
+    /// Starts crossfading from the current kernel to the new one.
+    fn swap_kernel(&mut self, kernel: Kernel) {
+        if let Some(previous) = self.kernel.replace(kernel) {
+            let length = ((SWAP_FADE_MS * 1e-3 * self.sample_rate as f64) as usize).max(1);
+            let fade = self.fade.replace(Fade {
+                kernel: previous,
+                remaining: length,
+                length,
+            });
+            if let Some(fade) = fade {
+                self.discard(Garbage::Kernel(fade.kernel));
+            }
+        }
+    }
+
+    /// Applies the impulse responses loaded through handles, keeping only the latest one.
+    fn receive(&mut self) {
+        let mut latest = None;
+        while let Ok(load) = self.receiver.try_recv() {
+            if let Some(previous) = latest.replace(load) {
+                self.discard(Garbage::Load(previous));
+            }
+        }
+        if let Some(load) = latest {
+            let current = self.kernel.as_ref().map(|kernel| kernel.config);
+            match load.kernel {
+                Some(kernel) if Some(kernel.config) == current => {
+                    let previous =
+                        std::mem::replace(&mut self.impulse_response, load.impulse_response);
+                    self.discard(Garbage::ImpulseResponse(previous));
+                    self.swap_kernel(kernel);
+                }
+                // Loads are prepared with the configuration locked, and taken by `allocate`
+                // when it changes: this is not reachable
+                _ => self.discard(Garbage::Load(load)),
+            }
+        }
+    }
+}
+
+impl Effect for Convolution {
+    fn prepare(&mut self, context: &AudioContext) {
+        self.sample_rate = context.sample_rate;
+        self.allocate(context.channel_count as usize);
+    }
+
+    fn reset(&mut self) {
+        if self.kernel.is_some() {
+            let channels = self.channels;
+            self.allocate(channels);
+        }
+    }
+
+    fn latency(&self) -> usize {
+        self.partitioning.latency()
+    }
+
+    /// Convolves the buffer. The audio passes through unchanged when the effect is not prepared
+    /// for the sample rate and channel count of the context.
+    fn process(&mut self, context: &AudioContext, buffer: &mut AudioBuffer) {
+        let prepared = self.kernel.as_ref().map(|kernel| kernel.config);
+        let config = KernelConfig {
+            sample_rate: context.sample_rate,
+            channels: buffer.channels(),
+            partitioning: self.partitioning,
+        };
+        if prepared != Some(config) {
+            return;
+        }
+        self.receive();
+
+        let channels = self.channels;
+        let latency = self.partitioning.latency();
+        let mut input: SmallVec<[f64; 16]> = SmallVec::from_elem(0.0, channels);
+        let mut wet: SmallVec<[f64; 16]> = SmallVec::from_elem(0.0, channels);
+        let kernel = match &mut self.kernel {
+            Some(kernel) => kernel,
+            None => return,
+        };
+
+        for i in 0..buffer.buffer_size() {
+            for (ch, sample) in input.iter_mut().enumerate() {
+                *sample = buffer[(ch, i)];
+            }
+            wet.iter_mut().for_each(|s| *s = 0.0);
+            match &mut self.fade {
+                Some(fade) => {
+                    let previous = fade.remaining as f64 / fade.length as f64;
+                    fade.kernel.process_frame(&input, &mut wet, previous);
+                    kernel.process_frame(&input, &mut wet, 1.0 - previous);
+                    fade.remaining -= 1;
+                    if fade.remaining == 0 {
+                        if let Some(fade) = self.fade.take() {
+                            let _ = self.garbage.try_send(Garbage::Kernel(fade.kernel));
+                        }
+                    }
+                }
+                None => kernel.process_frame(&input, &mut wet, 1.0),
+            }
+
+            let mix = self.mix_smoothed.next();
+            for ch in 0..channels {
+                let line = &mut self.dry_lines[ch];
+                let dry = line[self.dry_pos];
+                line[self.dry_pos] = input[ch];
+                buffer[(ch, i)] = dry * (1.0 - mix) + wet[ch] * mix;
+            }
+            self.dry_pos = (self.dry_pos + 1) % latency;
+        }
+    }
+}
@@ -0,0 +1,136 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! # Delay
+//!
+//! Feedback delay line, with a dry/wet mix.
+
+use smallvec::SmallVec;
+use wavr_audio_buffer::AudioBuffer;
+use wavr_engine::{AudioContext, Effect};
+
+use crate::util::Smoothed;
+
+/// Longest delay time supported by the [`Delay`](struct.Delay.html) effect, in milliseconds.
+pub const MAX_DELAY_MS: f64 = 2000.0;
+
+/// Feedback delay, applied independently on each channel.
+#[derive(Clone, Debug)]
+pub struct Delay {
+    time_ms: f64,
+    feedback: f64,
+    mix: f64,
+    sample_rate: f64,
+    delay_samples: Smoothed,
+    lines: SmallVec<[Vec<f64>; 16]>,
+    write_pos: usize,
+}
+
+impl Delay {
+    /// Create a new delay with the given time in milliseconds. Defaults to a 0.4 feedback and an
+    /// equal dry/wet mix.
+    pub fn new(time_ms: f64) -> Self {
+        let time_ms = time_ms.clamp(0.0, MAX_DELAY_MS);
+        Self {
+            time_ms,
+            feedback: 0.4,
+            mix: 0.5,
+            sample_rate: 48000.0,
+            delay_samples: Smoothed::new(time_ms * 48.0),
+            lines: SmallVec::new(),
+            write_pos: 0,
+        }
+    }
+
+    /// Returns the delay time in milliseconds.
+    pub fn time_ms(&self) -> f64 {
+        self.time_ms
+    }
+
+    /// Sets the delay time in milliseconds, up to [`MAX_DELAY_MS`](constant.MAX_DELAY_MS.html).
+    pub fn set_time_ms(&mut self, time_ms: f64) {
+        self.time_ms = time_ms.clamp(0.0, MAX_DELAY_MS);
+        self.delay_samples
+            .set(self.time_ms * 1e-3 * self.sample_rate);
+    }
+
+    /// Returns the feedback amount, from 0 to 1.
+    pub fn feedback(&self) -> f64 {
+        self.feedback
+    }
+
+    /// Sets the feedback amount, from 0 to 1 (excluded).
+    pub fn set_feedback(&mut self, feedback: f64) {
+        self.feedback = feedback.clamp(0.0, 0.999);
+    }
+
+    /// Returns the dry/wet mix, from 0 (dry) to 1 (wet).
+    pub fn mix(&self) -> f64 {
+        self.mix
+    }
+
+    /// Sets the dry/wet mix, from 0 (dry) to 1 (wet).
+    pub fn set_mix(&mut self, mix: f64) {
+        self.mix = mix.clamp(0.0, 1.0);
+    }
+
+    /// Returns the length of the delay lines, fitting the longest delay at the current sample rate.
+    fn line_length(&self) -> usize {
+        (MAX_DELAY_MS * 1e-3 * self.sample_rate).ceil() as usize + 2
+    }
+
+    /// Reads the delay line at a fractional position behind the write position.
+    #[inline]
+    fn read(line: &[f64], write_pos: usize, delay: f64) -> f64 {
+        let len = line.len();
+        let whole = delay.floor();
+        let frac = delay - whole;
+        let i0 = (write_pos + len - whole as usize % len) % len;
+        let i1 = (i0 + len - 1) % len;
+        line[i0] * (1.0 - frac) + line[i1] * frac
+    }
+}
+
+impl Effect for Delay {
+    fn prepare(&mut self, context: &AudioContext) {
+        self.sample_rate = context.sample_rate as f64;
+        let len = self.line_length();
+        self.lines = SmallVec::from_elem(vec![0.0; len], context.channel_count as usize);
+        self.write_pos = 0;
+        self.delay_samples.set_sample_rate(self.sample_rate);
+        self.delay_samples
+            .set(self.time_ms * 1e-3 * self.sample_rate);
+        self.delay_samples.reset();
+    }
+
+    fn reset(&mut self) {
+        self.lines
+            .iter_mut()
+            .for_each(|line| line.iter_mut().for_each(|s| *s = 0.0));
+        self.delay_samples.reset();
+    }
+
+    fn process(&mut self, context: &AudioContext, buffer: &mut AudioBuffer) {
+        if self.sample_rate != context.sample_rate as f64 || self.lines.len() < buffer.channels() {
+            self.prepare(context);
+            self.lines
+                .resize(buffer.channels(), vec![0.0; self.line_length()]);
+        }
+        let len = self.line_length();
+
+        for i in 0..buffer.buffer_size() {
+            // Never read the sample being written, which would bypass the delay
+            let delay = self.delay_samples.next().max(1.0);
+            for ch in 0..buffer.channels() {
+                let line = &mut self.lines[ch];
+                let dry = buffer[(ch, i)];
+                let wet = Self::read(line, self.write_pos, delay);
+                line[self.write_pos] = dry + wet * self.feedback;
+                buffer[(ch, i)] = dry * (1.0 - self.mix) + wet * self.mix;
+            }
+            self.write_pos = (self.write_pos + 1) % len;
+        }
+    }
+}
@@ -0,0 +1,836 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! # Dynamics processors
+//!
+//! Compressor, limiter, expander and gate, all built on a shared [`DynamicsCore`]. The core
+//! handles level detection (peak, RMS or true peak), stereo linking, the attack, hold and release
+//! ballistics, lookahead and gain reduction metering; each processor only provides its static
+//! gain curve.
+//!
+//! With lookahead, the gain is held at its lowest value over the lookahead window and averaged
+//! over the same window, so that it ramps down before a transient reaches the output and back up
+//! after it, instead of stepping. The buffers are allocated for [`MAX_LOOKAHEAD_MS`] when the
+//! core is prepared, so that the lookahead and detector can be changed while processing.
+//!
+//! The gain reduction of the last processed block is published through a
+//! [`GainReductionMeter`], which can be cloned and read from the UI thread while the effect is
+//! owned by the rack:
+//!
+//! ```rust
+//! # use wavr_effects::Compressor;
+//! # use wavr_engine::AudioEngine;
+//! # let mut engine = AudioEngine::new(48000, 2);
+//! let compressor = Compressor::new(-18.0, 4.0);
+//! let meter = compressor.core().gain_reduction_meter();
+//! engine.get_rack_mut().push_effect(compressor);
+//! // Later, on the UI thread
+//! println!("GR: {:.1} dB", meter.gain_reduction_db());
+//! ```
+//!
+//! [`DynamicsCore`]: struct.DynamicsCore.html
+//! [`GainReductionMeter`]: struct.GainReductionMeter.html
+//! [`MAX_LOOKAHEAD_MS`]: constant.MAX_LOOKAHEAD_MS.html
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use smallvec::SmallVec;
+use wavr_audio_buffer::AudioBuffer;
+use wavr_engine::{AudioContext, Effect};
+use wavr_meter::decibel::{DbFs, Gain};
+use wavr_meter::TruePeak;
+
+use crate::util::{time_coefficient, MovingAverage, SlidingMinimum};
+
+/// Maximum lookahead of the dynamics processors, in milliseconds.
+pub const MAX_LOOKAHEAD_MS: f64 = 20.0;
+
+/// Lowest level considered by the detectors, in decibels.
+const FLOOR_DB: f64 = -120.0;
+
+/// Level detection method.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Detector {
+    /// Absolute value of the samples.
+    Peak,
+    /// Root mean square level over the RMS window.
+    Rms,
+    /// Inter-sample peak level, oversampled the same way as `wavr_meter`'s true peak meters.
+    TruePeak,
+}
+
+/// How the levels of the channels are combined to compute the gain.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum StereoLink {
+    /// The loudest channel drives the gain of all channels, preserving the stereo image.
+    Linked,
+    /// The average level of the channels drives the gain of all channels.
+    Average,
+    /// Each channel is processed independently.
+    Unlinked,
+}
+
+/// Handle to the gain reduction of a dynamics processor, readable from any thread.
+#[derive(Clone, Debug, Default)]
+pub struct GainReductionMeter {
+    value: Arc<AtomicU64>,
+}
+
+/// Envelope follower state of one detection channel.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Envelope {
+    level_db: f64,
+    hold: usize,
+}
+
+/// Shared detection, ballistics and gain application of the dynamics processors.
+#[derive(Clone, Debug)]
+pub struct DynamicsCore {
+    detector: Detector,
+    link: StereoLink,
+    rms_window_ms: f64,
+    attack_ms: f64,
+    release_ms: f64,
+    hold_ms: f64,
+    lookahead_ms: f64,
+    makeup_db: f64,
+    sample_rate: f64,
+    channels: usize,
+    /// Set when the lookahead or detector changed, to update the buffers before processing.
+    needs_update: bool,
+    attack: f64,
+    release: f64,
+    rms_coefficient: f64,
+    hold_samples: usize,
+    delay_samples: usize,
+    envelopes: SmallVec<[Envelope; 16]>,
+    /// Minimum of the gains over the lookahead window, per detection channel.
+    holds: SmallVec<[SlidingMinimum; 16]>,
+    /// Mean of the held gains over the lookahead window, per detection channel.
+    averages: SmallVec<[MovingAverage; 16]>,
+    mean_squares: SmallVec<[f64; 16]>,
+    true_peak: TruePeak,
+    delay_lines: SmallVec<[Vec<f64>; 16]>,
+    delay_pos: usize,
+    gain_reduction: GainReductionMeter,
+}
+
+/// Downward compressor, reducing the level above the threshold by the ratio.
+#[derive(Clone, Debug)]
+pub struct Compressor {
+    threshold_db: f64,
+    ratio: f64,
+    knee_db: f64,
+    core: DynamicsCore,
+}
+
+/// Peak limiter, keeping the level under the ceiling. This is a compressor with an infinite ratio
+/// and an instantaneous attack; add lookahead to ramp the gain down before the transients reach
+/// the output rather than stepping it.
+#[derive(Clone, Debug)]
+pub struct Limiter {
+    ceiling_db: f64,
+    core: DynamicsCore,
+}
+
+/// Downward expander, reducing the level below the threshold by the ratio. The attenuation is
+/// bounded by the range.
+#[derive(Clone, Debug)]
+pub struct Expander {
+    threshold_db: f64,
+    ratio: f64,
+    knee_db: f64,
+    range_db: f64,
+    core: DynamicsCore,
+}
+
+/// Noise gate, closing when the level falls below the threshold. This is a steep expander with a
+/// hold time, keeping the gate open between close transients.
+#[derive(Clone, Debug)]
+pub struct Gate {
+    threshold_db: f64,
+    range_db: f64,
+    core: DynamicsCore,
+}
+
+impl GainReductionMeter {
+    /// Returns the highest gain reduction of the last processed block, in decibels. The value is
+    /// positive when the processor attenuates the signal.
+    pub fn gain_reduction_db(&self) -> f64 {
+        f64::from_bits(self.value.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn set(&self, gain_reduction_db: f64) {
+        self.value
+            .store(gain_reduction_db.to_bits(), Ordering::Relaxed);
+    }
+}
+
+impl Envelope {
+    fn new() -> Self {
+        Self {
+            level_db: FLOOR_DB,
+            hold: 0,
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, input_db: f64, attack: f64, release: f64, hold: usize) -> f64 {
+        if input_db >= self.level_db {
+            self.level_db = input_db + attack * (self.level_db - input_db);
+            self.hold = hold;
+        } else if self.hold > 0 {
+            self.hold -= 1;
+        } else {
+            self.level_db = input_db + release * (self.level_db - input_db);
+        }
+        self.level_db
+    }
+}
+
+/// Gain of a downward compression curve with a soft knee, in decibels.
+fn compression_gain(level_db: f64, threshold_db: f64, ratio: f64, knee_db: f64) -> f64 {
+    let over = level_db - threshold_db;
+    let slope = 1.0 / ratio - 1.0;
+    if 2.0 * over < -knee_db {
+        0.0
+    } else if 2.0 * over.abs() <= knee_db && knee_db > 0.0 {
+        slope * (over + knee_db / 2.0).powi(2) / (2.0 * knee_db)
+    } else {
+        slope * over
+    }
+}
+
+/// Gain of a downward expansion curve with a soft knee, in decibels, bounded by the range.
+fn expansion_gain(
+    level_db: f64,
+    threshold_db: f64,
+    ratio: f64,
+    knee_db: f64,
+    range_db: f64,
+) -> f64 {
+    let under = level_db - threshold_db;
+    let slope = ratio - 1.0;
+    let gain = if 2.0 * under > knee_db {
+        0.0
+    } else if 2.0 * under.abs() <= knee_db && knee_db > 0.0 {
+        -slope * (under - knee_db / 2.0).powi(2) / (2.0 * knee_db)
+    } else {
+        slope * under
+    };
+    gain.max(-range_db)
+}
+
+impl DynamicsCore {
+    /// Create a new dynamics core with the given attack and release times in milliseconds. The
+    /// core defaults to linked peak detection, without hold nor lookahead.
+    pub fn new(attack_ms: f64, release_ms: f64) -> Self {
+        Self {
+            detector: Detector::Peak,
+            link: StereoLink::Linked,
+            rms_window_ms: 10.0,
+            attack_ms: attack_ms.max(0.0),
+            release_ms: release_ms.max(0.0),
+            hold_ms: 0.0,
+            lookahead_ms: 0.0,
+            makeup_db: 0.0,
+            sample_rate: 48000.0,
+            channels: 0,
+            needs_update: true,
+            attack: 0.0,
+            release: 0.0,
+            rms_coefficient: 0.0,
+            hold_samples: 0,
+            delay_samples: 0,
+            envelopes: SmallVec::new(),
+            holds: SmallVec::new(),
+            averages: SmallVec::new(),
+            mean_squares: SmallVec::new(),
+            true_peak: TruePeak::new(0, 48000),
+            delay_lines: SmallVec::new(),
+            delay_pos: 0,
+            gain_reduction: GainReductionMeter::default(),
+        }
+    }
+
+    /// Returns the level detection method.
+    pub fn detector(&self) -> Detector {
+        self.detector
+    }
+
+    /// Sets the level detection method.
+    pub fn set_detector(&mut self, detector: Detector) {
+        if detector != self.detector {
+            self.detector = detector;
+            self.needs_update = true;
+        }
+    }
+
+    /// Returns the stereo linking mode.
+    pub fn link(&self) -> StereoLink {
+        self.link
+    }
+
+    /// Sets the stereo linking mode.
+    pub fn set_link(&mut self, link: StereoLink) {
+        self.link = link;
+    }
+
+    /// Returns the RMS window length in milliseconds.
+    pub fn rms_window_ms(&self) -> f64 {
+        self.rms_window_ms
+    }
+
+    /// Sets the RMS window length in milliseconds. Only used by the RMS detector.
+    pub fn set_rms_window_ms(&mut self, rms_window_ms: f64) {
+        self.rms_window_ms = rms_window_ms.max(0.0);
+        self.update_coefficients();
+    }
+
+    /// Returns the attack time in milliseconds.
+    pub fn attack_ms(&self) -> f64 {
+        self.attack_ms
+    }
+
+    /// Sets the attack time in milliseconds. A zero attack follows rising levels instantly.
+    pub fn set_attack_ms(&mut self, attack_ms: f64) {
+        self.attack_ms = attack_ms.max(0.0);
+        self.update_coefficients();
+    }
+
+    /// Returns the release time in milliseconds.
+    pub fn release_ms(&self) -> f64 {
+        self.release_ms
+    }
+
+    /// Sets the release time in milliseconds.
+    pub fn set_release_ms(&mut self, release_ms: f64) {
+        self.release_ms = release_ms.max(0.0);
+        self.update_coefficients();
+    }
+
+    /// Returns the hold time in milliseconds.
+    pub fn hold_ms(&self) -> f64 {
+        self.hold_ms
+    }
+
+    /// Sets the hold time in milliseconds, during which the detected level is held before
+    /// releasing.
+    pub fn set_hold_ms(&mut self, hold_ms: f64) {
+        self.hold_ms = hold_ms.max(0.0);
+        self.update_coefficients();
+    }
+
+    /// Returns the lookahead time in milliseconds.
+    pub fn lookahead_ms(&self) -> f64 {
+        self.lookahead_ms
+    }
+
+    /// Sets the lookahead time in milliseconds, up to
+    /// [`MAX_LOOKAHEAD_MS`](constant.MAX_LOOKAHEAD_MS.html). The audio is delayed by this amount,
+    /// and the gain ramps down over the lookahead window, so that it is reduced by the time the
+    /// transients reach the output; see [`latency`](#method.latency).
+    pub fn set_lookahead_ms(&mut self, lookahead_ms: f64) {
+        let lookahead_ms = lookahead_ms.clamp(0.0, MAX_LOOKAHEAD_MS);
+        if lookahead_ms != self.lookahead_ms {
+            self.lookahead_ms = lookahead_ms;
+            self.needs_update = true;
+        }
+    }
+
+    /// Returns the gain applied after the gain reduction, in decibels.
+    pub fn makeup_db(&self) -> f64 {
+        self.makeup_db
+    }
+
+    /// Sets the gain applied after the gain reduction, in decibels.
+    pub fn set_makeup_db(&mut self, makeup_db: f64) {
+        self.makeup_db = makeup_db;
+    }
+
+    /// Returns the highest gain reduction of the last processed block, in decibels.
+    pub fn gain_reduction_db(&self) -> f64 {
+        self.gain_reduction.gain_reduction_db()
+    }
+
+    /// Returns a handle to the gain reduction, which can be read from another thread.
+    pub fn gain_reduction_meter(&self) -> GainReductionMeter {
+        self.gain_reduction.clone()
+    }
+
+    /// Returns the latency introduced by the core, in samples: the lookahead, plus the delay of
+    /// the true peak interpolator when it is used.
+    pub fn latency(&self) -> usize {
+        let detector_latency = match self.detector {
+            Detector::TruePeak => TruePeak::latency_at(self.sample_rate as u32),
+            _ => 0,
+        };
+        self.lookahead_samples() + detector_latency
+    }
+
+    /// Prepares the core for the sample rate and channel count of the context.
+    pub fn prepare(&mut self, context: &AudioContext) {
+        self.sample_rate = context.sample_rate as f64;
+        self.allocate(context.channel_count as usize);
+    }
+
+    /// Clears the detectors, envelopes and lookahead buffers.
+    pub fn reset(&mut self) {
+        self.envelopes.iter_mut().for_each(|e| *e = Envelope::new());
+        self.holds.iter_mut().for_each(SlidingMinimum::reset);
+        self.averages.iter_mut().for_each(MovingAverage::reset);
+        self.mean_squares.iter_mut().for_each(|ms| *ms = 0.0);
+        self.true_peak.reset();
+        for line in &mut self.delay_lines {
+            line.iter_mut().for_each(|s| *s = 0.0);
+        }
+        self.delay_pos = 0;
+        self.gain_reduction.set(0.0);
+    }
+
+    /// Processes the buffer, applying the gain returned by `gain_computer` for the detected level.
+    /// Both are in decibels, and the gain should be negative or zero; the makeup gain is added
+    /// afterwards.
+    ///
+    /// The buffers are reallocated here if the sample rate or channel count differ from the ones
+    /// the core was prepared for.
+    pub fn process<F: Fn(f64) -> f64>(
+        &mut self,
+        context: &AudioContext,
+        buffer: &mut AudioBuffer,
+        gain_computer: F,
+    ) {
+        if self.sample_rate != context.sample_rate as f64 || self.channels != buffer.channels() {
+            self.sample_rate = context.sample_rate as f64;
+            self.allocate(buffer.channels());
+        } else if self.needs_update {
+            self.update();
+        }
+        let channels = self.channels;
+        let mut frame: SmallVec<[f64; 16]> = SmallVec::from_elem(0.0, channels);
+        let mut levels: SmallVec<[f64; 16]> = SmallVec::from_elem(0.0, channels);
+        let mut gains: SmallVec<[f64; 16]> = SmallVec::from_elem(0.0, channels);
+        let mut max_reduction = 0.0f64;
+
+        for i in 0..buffer.buffer_size() {
+            for (ch, sample) in frame.iter_mut().enumerate() {
+                *sample = buffer[(ch, i)];
+            }
+            self.detect(&frame, &mut levels);
+
+            match self.link {
+                StereoLink::Unlinked => {
+                    for ch in 0..channels {
+                        gains[ch] = self.gain(ch, levels[ch], &gain_computer);
+                    }
+                }
+                StereoLink::Linked | StereoLink::Average => {
+                    let level = if self.link == StereoLink::Linked {
+                        levels.iter().cloned().fold(0.0, f64::max)
+                    } else {
+                        levels.iter().sum::<f64>() / channels.max(1) as f64
+                    };
+                    let gain = self.gain(0, level, &gain_computer);
+                    gains.iter_mut().for_each(|g| *g = gain);
+                }
+            }
+
+            for ch in 0..channels {
+                max_reduction = max_reduction.min(gains[ch]);
+                let delayed = self.delay(ch, frame[ch]);
+                buffer[(ch, i)] = delayed * Gain(gains[ch] + self.makeup_db).to_linear();
+            }
+            if self.delay_samples > 0 {
+                self.delay_pos = (self.delay_pos + 1) % self.delay_samples;
+            }
+        }
+        self.gain_reduction.set(-max_reduction);
+    }
+
+    /// Computes the levels of a frame, as linear values.
+    #[inline]
+    fn detect(&mut self, frame: &[f64], levels: &mut [f64]) {
+        match self.detector {
+            Detector::Peak => {
+                for (level, sample) in levels.iter_mut().zip(frame) {
+                    *level = sample.abs();
+                }
+            }
+            Detector::Rms => {
+                let coeff = self.rms_coefficient;
+                for ((level, ms), sample) in
+                    levels.iter_mut().zip(&mut self.mean_squares).zip(frame)
+                {
+                    *ms = sample * sample + coeff * (*ms - sample * sample);
+                    *level = ms.sqrt();
+                }
+            }
+            Detector::TruePeak => {
+                self.true_peak
+                    .process_frame(frame, |ch, peak| levels[ch] = peak);
+            }
+        }
+    }
+
+    /// Runs the envelope follower of the given detection channel, and returns the gain in
+    /// decibels, held and averaged over the lookahead window.
+    #[inline]
+    fn gain<F: Fn(f64) -> f64>(&mut self, index: usize, level: f64, gain_computer: &F) -> f64 {
+        let level_db = DbFs::from_amplitude(level).0.max(FLOOR_DB);
+        let envelope =
+            self.envelopes[index].process(level_db, self.attack, self.release, self.hold_samples);
+        let held = self.holds[index].push(gain_computer(envelope).min(0.0));
+        self.averages[index].push(held)
+    }
+
+    #[inline]
+    fn delay(&mut self, channel: usize, sample: f64) -> f64 {
+        if self.delay_samples == 0 {
+            return sample;
+        }
+        let line = &mut self.delay_lines[channel][..self.delay_samples];
+        let delayed = line[self.delay_pos];
+        line[self.delay_pos] = sample;
+        delayed
+    }
+
+    fn update_coefficients(&mut self) {
+        self.attack = time_coefficient(self.attack_ms, self.sample_rate);
+        self.release = time_coefficient(self.release_ms, self.sample_rate);
+        self.rms_coefficient = time_coefficient(self.rms_window_ms, self.sample_rate);
+        self.hold_samples = (self.hold_ms * 1e-3 * self.sample_rate).round() as usize;
+    }
+
+    fn lookahead_samples(&self) -> usize {
+        (self.lookahead_ms * 1e-3 * self.sample_rate).round() as usize
+    }
+
+    /// Allocates the buffers for the maximum lookahead and the true peak detector, whatever the
+    /// current settings.
+    fn allocate(&mut self, channels: usize) {
+        let max_lookahead = (MAX_LOOKAHEAD_MS * 1e-3 * self.sample_rate).round() as usize;
+        let max_delay = max_lookahead + TruePeak::latency_at(self.sample_rate as u32);
+        self.channels = channels;
+        self.envelopes = SmallVec::from_elem(Envelope::new(), channels);
+        self.holds = SmallVec::from_elem(SlidingMinimum::new(max_lookahead + 1), channels);
+        self.averages = SmallVec::from_elem(MovingAverage::new(max_lookahead + 1), channels);
+        self.mean_squares = SmallVec::from_elem(0.0, channels);
+        self.true_peak = TruePeak::new(channels, self.sample_rate as u32);
+        self.delay_lines = SmallVec::from_elem(vec![0.0; max_delay], channels);
+        self.update();
+    }
+
+    /// Applies the lookahead and detector settings to the allocated buffers, and clears them.
+    fn update(&mut self) {
+        self.update_coefficients();
+        // The audio is delayed by the lookahead and the detector latency, while the levels are
+        // only delayed by the latter: holding the gains over the lookahead covers the delayed
+        // sample, and so does each of the held gains averaged over the same window.
+        let window = self.lookahead_samples() + 1;
+        self.holds.iter_mut().for_each(|h| h.set_window(window));
+        self.averages.iter_mut().for_each(|a| a.set_window(window));
+        self.delay_samples = self.latency();
+        self.reset();
+        self.needs_update = false;
+    }
+}
+
+impl Compressor {
+    /// Create a new compressor with the given threshold in decibels and ratio. Defaults to a 10 ms
+    /// attack, 100 ms release, 6 dB knee and no makeup gain.
+    pub fn new(threshold_db: f64, ratio: f64) -> Self {
+        Self {
+            threshold_db,
+            ratio: ratio.max(1.0),
+            knee_db: 6.0,
+            core: DynamicsCore::new(10.0, 100.0),
+        }
+    }
+
+    /// Returns the threshold in decibels.
+    pub fn threshold_db(&self) -> f64 {
+        self.threshold_db
+    }
+
+    /// Sets the threshold in decibels.
+    pub fn set_threshold_db(&mut self, threshold_db: f64) {
+        self.threshold_db = threshold_db;
+    }
+
+    /// Returns the compression ratio.
+    pub fn ratio(&self) -> f64 {
+        self.ratio
+    }
+
+    /// Sets the compression ratio.
+    pub fn set_ratio(&mut self, ratio: f64) {
+        self.ratio = ratio.max(1.0);
+    }
+
+    /// Returns the knee width in decibels.
+    pub fn knee_db(&self) -> f64 {
+        self.knee_db
+    }
+
+    /// Sets the knee width in decibels. A zero knee is a hard knee.
+    pub fn set_knee_db(&mut self, knee_db: f64) {
+        self.knee_db = knee_db.max(0.0);
+    }
+
+    /// Returns the dynamics core, holding the detector, ballistics and makeup settings.
+    pub fn core(&self) -> &DynamicsCore {
+        &self.core
+    }
+
+    /// Returns the dynamics core mutably.
+    pub fn core_mut(&mut self) -> &mut DynamicsCore {
+        &mut self.core
+    }
+
+    /// Returns the output level of the static compression curve for the given input level, both
+    /// in decibels.
+    pub fn curve(&self, input_db: f64) -> f64 {
+        input_db + compression_gain(input_db, self.threshold_db, self.ratio, self.knee_db)
+    }
+}
+
+impl Effect for Compressor {
+    fn prepare(&mut self, context: &AudioContext) {
+        self.core.prepare(context);
+    }
+
+    fn reset(&mut self) {
+        self.core.reset();
+    }
+
+    fn latency(&self) -> usize {
+        self.core.latency()
+    }
+
+    fn process(&mut self, context: &AudioContext, buffer: &mut AudioBuffer) {
+        let (threshold, ratio, knee) = (self.threshold_db, self.ratio, self.knee_db);
+        self.core.process(context, buffer, |level| {
+            compression_gain(level, threshold, ratio, knee)
+        });
+    }
+}
+
+impl Limiter {
+    /// Create a new limiter with the given ceiling in decibels. Defaults to an instantaneous attack
+    /// and a 50 ms release.
+    pub fn new(ceiling_db: f64) -> Self {
+        Self {
+            ceiling_db,
+            core: DynamicsCore::new(0.0, 50.0),
+        }
+    }
+
+    /// Returns the ceiling in decibels.
+    pub fn ceiling_db(&self) -> f64 {
+        self.ceiling_db
+    }
+
+    /// Sets the ceiling in decibels.
+    pub fn set_ceiling_db(&mut self, ceiling_db: f64) {
+        self.ceiling_db = ceiling_db;
+    }
+
+    /// Returns the dynamics core, holding the detector, ballistics and makeup settings.
+    pub fn core(&self) -> &DynamicsCore {
+        &self.core
+    }
+
+    /// Returns the dynamics core mutably.
+    pub fn core_mut(&mut self) -> &mut DynamicsCore {
+        &mut self.core
+    }
+}
+
+impl Effect for Limiter {
+    fn prepare(&mut self, context: &AudioContext) {
+        self.core.prepare(context);
+    }
+
+    fn reset(&mut self) {
+        self.core.reset();
+    }
+
+    fn latency(&self) -> usize {
+        self.core.latency()
+    }
+
+    fn process(&mut self, context: &AudioContext, buffer: &mut AudioBuffer) {
+        let ceiling = self.ceiling_db;
+        self.core.process(context, buffer, |level| ceiling - level);
+    }
+}
+
+impl Expander {
+    /// Create a new expander with the given threshold in decibels and ratio. Defaults to a 1 ms
+    /// attack, 100 ms release, 6 dB knee and 40 dB range.
+    pub fn new(threshold_db: f64, ratio: f64) -> Self {
+        Self {
+            threshold_db,
+            ratio: ratio.max(1.0),
+            knee_db: 6.0,
+            range_db: 40.0,
+            core: DynamicsCore::new(1.0, 100.0),
+        }
+    }
+
+    /// Returns the threshold in decibels.
+    pub fn threshold_db(&self) -> f64 {
+        self.threshold_db
+    }
+
+    /// Sets the threshold in decibels.
+    pub fn set_threshold_db(&mut self, threshold_db: f64) {
+        self.threshold_db = threshold_db;
+    }
+
+    /// Returns the expansion ratio.
+    pub fn ratio(&self) -> f64 {
+        self.ratio
+    }
+
+    /// Sets the expansion ratio.
+    pub fn set_ratio(&mut self, ratio: f64) {
+        self.ratio = ratio.max(1.0);
+    }
+
+    /// Returns the knee width in decibels.
+    pub fn knee_db(&self) -> f64 {
+        self.knee_db
+    }
+
+    /// Sets the knee width in decibels. A zero knee is a hard knee.
+    pub fn set_knee_db(&mut self, knee_db: f64) {
+        self.knee_db = knee_db.max(0.0);
+    }
+
+    /// Returns the maximum attenuation in decibels.
+    pub fn range_db(&self) -> f64 {
+        self.range_db
+    }
+
+    /// Sets the maximum attenuation in decibels.
+    pub fn set_range_db(&mut self, range_db: f64) {
+        self.range_db = range_db.abs();
+    }
+
+    /// Returns the dynamics core, holding the detector, ballistics and makeup settings.
+    pub fn core(&self) -> &DynamicsCore {
+        &self.core
+    }
+
+    /// Returns the dynamics core mutably.
+    pub fn core_mut(&mut self) -> &mut DynamicsCore {
+        &mut self.core
+    }
+
+    /// Returns the output level of the static expansion curve for the given input level, both in
+    /// decibels.
+    pub fn curve(&self, input_db: f64) -> f64 {
+        input_db
+            + expansion_gain(
+                input_db,
+                self.threshold_db,
+                self.ratio,
+                self.knee_db,
+                self.range_db,
+            )
+    }
+}
+
+impl Effect for Expander {
+    fn prepare(&mut self, context: &AudioContext) {
+        self.core.prepare(context);
+    }
+
+    fn reset(&mut self) {
+        self.core.reset();
+    }
+
+    fn latency(&self) -> usize {
+        self.core.latency()
+    }
+
+    fn process(&mut self, context: &AudioContext, buffer: &mut AudioBuffer) {
+        let (threshold, ratio, knee, range) =
+            (self.threshold_db, self.ratio, self.knee_db, self.range_db);
+        self.core.process(context, buffer, |level| {
+            expansion_gain(level, threshold, ratio, knee, range)
+        });
+    }
+}
+
+impl Gate {
+    /// Ratio of the expansion below the threshold, steep enough to act as a gate.
+    const RATIO: f64 = 20.0;
+
+    /// Create a new gate with the given threshold in decibels. Defaults to an 80 dB range, a
+    /// 0.5 ms attack, 50 ms hold and 100 ms release.
+    pub fn new(threshold_db: f64) -> Self {
+        let mut core = DynamicsCore::new(0.5, 100.0);
+        core.set_hold_ms(50.0);
+        Self {
+            threshold_db,
+            range_db: 80.0,
+            core,
+        }
+    }
+
+    /// Returns the threshold in decibels.
+    pub fn threshold_db(&self) -> f64 {
+        self.threshold_db
+    }
+
+    /// Sets the threshold in decibels.
+    pub fn set_threshold_db(&mut self, threshold_db: f64) {
+        self.threshold_db = threshold_db;
+    }
+
+    /// Returns the maximum attenuation in decibels.
+    pub fn range_db(&self) -> f64 {
+        self.range_db
+    }
+
+    /// Sets the maximum attenuation in decibels.
+    pub fn set_range_db(&mut self, range_db: f64) {
+        self.range_db = range_db.abs();
+    }
+
+    /// Returns the dynamics core, holding the detector, ballistics and makeup settings.
+    pub fn core(&self) -> &DynamicsCore {
+        &self.core
+    }
+
+    /// Returns the dynamics core mutably.
+    pub fn core_mut(&mut self) -> &mut DynamicsCore {
+        &mut self.core
+    }
+}
+
+impl Effect for Gate {
+    fn prepare(&mut self, context: &AudioContext) {
+        self.core.prepare(context);
+    }
+
+    fn reset(&mut self) {
+        self.core.reset();
+    }
+
+    fn latency(&self) -> usize {
+        self.core.latency()
+    }
+
+    fn process(&mut self, context: &AudioContext, buffer: &mut AudioBuffer) {
+        let (threshold, range) = (self.threshold_db, self.range_db);
+        self.core.process(context, buffer, |level| {
+            expansion_gain(level, threshold, Self::RATIO, 0.0, range)
+        });
+    }
+}
@@ -0,0 +1,294 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! # Parametric equalizer
+//!
+//! Multi-band equalizer made of biquad filters in series. Parameter changes are ramped, and the
+//! coefficients recomputed along the ramp, so that bands can be tweaked while playing without
+//! clicks. Enabling or disabling a band crossfades between the filtered and unfiltered signal.
+
+use smallvec::SmallVec;
+use wavr_audio_buffer::AudioBuffer;
+use wavr_engine::{AudioContext, Effect};
+use wavr_meter::decibel::Gain;
+
+use crate::biquad::{BiquadCoefficients, BiquadState, BiquadType};
+use crate::util::Smoothed;
+
+/// Number of samples between coefficient updates while parameters are ramping.
+const UPDATE_INTERVAL: usize = 16;
+
+/// Parameters of a single equalizer band.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EqBand {
+    /// Filter type of the band.
+    pub kind: BiquadType,
+    /// Center or cutoff frequency, in Hertz.
+    pub frequency: f64,
+    /// Quality factor.
+    pub q: f64,
+    /// Gain in decibels, used by the peak and shelf filters.
+    pub gain_db: f64,
+    /// Whether the band is processed.
+    pub enabled: bool,
+}
+
+/// Running state of a band: smoothed parameters, current coefficients and filter memories.
+#[derive(Clone, Debug)]
+struct BandProcessor {
+    band: EqBand,
+    log_frequency: Smoothed,
+    q: Smoothed,
+    gain_db: Smoothed,
+    mix: Smoothed,
+    coefficients: BiquadCoefficients,
+    states: SmallVec<[BiquadState; 16]>,
+}
+
+/// Parametric equalizer effect, applying the same bands to all channels.
+#[derive(Clone, Debug)]
+pub struct ParametricEq {
+    bands: SmallVec<[BandProcessor; 8]>,
+    sample_rate: f64,
+    channels: usize,
+}
+
+impl EqBand {
+    /// Create a new enabled band.
+    pub fn new(kind: BiquadType, frequency: f64, q: f64, gain_db: f64) -> Self {
+        Self {
+            kind,
+            frequency,
+            q,
+            gain_db,
+            enabled: true,
+        }
+    }
+
+    /// Returns the coefficients of the band at the given sample rate.
+    pub fn coefficients(&self, sample_rate: f64) -> BiquadCoefficients {
+        BiquadCoefficients::new(self.kind, sample_rate, self.frequency, self.q, self.gain_db)
+    }
+}
+
+impl BandProcessor {
+    fn new(band: EqBand, sample_rate: f64, channels: usize) -> Self {
+        let mut this = Self {
+            band,
+            log_frequency: Smoothed::new(band.frequency.max(1.0).log2()),
+            q: Smoothed::new(band.q),
+            gain_db: Smoothed::new(band.gain_db),
+            mix: Smoothed::new(if band.enabled { 1.0 } else { 0.0 }),
+            coefficients: band.coefficients(sample_rate),
+            states: SmallVec::from_elem(BiquadState::default(), channels),
+        };
+        this.set_sample_rate(sample_rate);
+        this
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.log_frequency.set_sample_rate(sample_rate);
+        self.q.set_sample_rate(sample_rate);
+        self.gain_db.set_sample_rate(sample_rate);
+        self.mix.set_sample_rate(sample_rate);
+        self.coefficients = self.band.coefficients(sample_rate);
+    }
+
+    fn set(&mut self, band: EqBand, sample_rate: f64) {
+        let kind_changed = band.kind != self.band.kind;
+        self.band = band;
+        self.log_frequency.set(band.frequency.max(1.0).log2());
+        self.q.set(band.q);
+        self.gain_db.set(band.gain_db);
+        self.mix.set(if band.enabled { 1.0 } else { 0.0 });
+        if kind_changed {
+            // Filters of different types cannot be interpolated, jump to the new shape
+            self.log_frequency.reset();
+            self.q.reset();
+            self.gain_db.reset();
+            self.coefficients = band.coefficients(sample_rate);
+        }
+    }
+
+    fn is_ramping(&self) -> bool {
+        self.log_frequency.is_ramping() || self.q.is_ramping() || self.gain_db.is_ramping()
+    }
+
+    /// Advances the parameter ramps by `samples` and recomputes the coefficients.
+    fn update(&mut self, samples: usize, sample_rate: f64) {
+        let (mut log_frequency, mut q, mut gain_db) = (0.0, 0.0, 0.0);
+        for _ in 0..samples {
+            log_frequency = self.log_frequency.next();
+            q = self.q.next();
+            gain_db = self.gain_db.next();
+        }
+        self.coefficients = BiquadCoefficients::new(
+            self.band.kind,
+            sample_rate,
+            log_frequency.exp2(),
+            q,
+            gain_db,
+        );
+    }
+
+    fn reset(&mut self) {
+        self.states.iter_mut().for_each(BiquadState::reset);
+        self.log_frequency.reset();
+        self.q.reset();
+        self.gain_db.reset();
+        self.mix.reset();
+    }
+
+    fn process(&mut self, buffer: &mut AudioBuffer, sample_rate: f64) {
+        if !self.band.enabled && !self.mix.is_ramping() {
+            return;
+        }
+        let size = buffer.buffer_size();
+        let mut start = 0;
+        while start < size {
+            let end = (start + UPDATE_INTERVAL).min(size);
+            if self.is_ramping() {
+                self.update(end - start, sample_rate);
+            }
+            let mix_start = self.mix.current();
+            for _ in start..end {
+                self.mix.next();
+            }
+            let mix_step = (self.mix.current() - mix_start) / (end - start) as f64;
+            for (ch, state) in self.states.iter_mut().enumerate() {
+                let mut mix = mix_start;
+                for i in start..end {
+                    mix += mix_step;
+                    let dry = buffer[(ch, i)];
+                    let wet = state.process(&self.coefficients, dry);
+                    buffer[(ch, i)] = dry + mix * (wet - dry);
+                }
+            }
+            start = end;
+        }
+    }
+}
+
+impl ParametricEq {
+    /// Create a new equalizer without any band.
+    pub fn new() -> Self {
+        Self {
+            bands: SmallVec::new(),
+            sample_rate: 48000.0,
+            channels: 0,
+        }
+    }
+
+    /// Adds a band to the equalizer.
+    pub fn with_band(mut self, band: EqBand) -> Self {
+        self.add_band(band);
+        self
+    }
+
+    /// Returns the number of bands.
+    pub fn band_count(&self) -> usize {
+        self.bands.len()
+    }
+
+    /// Returns the parameters of the band at the given index.
+    pub fn band(&self, index: usize) -> Option<EqBand> {
+        self.bands.get(index).map(|b| b.band)
+    }
+
+    /// Returns the parameters of all bands.
+    pub fn bands(&self) -> impl Iterator<Item = EqBand> + '_ {
+        self.bands.iter().map(|b| b.band)
+    }
+
+    /// Adds a band at the end of the chain, and returns its index.
+    pub fn add_band(&mut self, band: EqBand) -> usize {
+        self.bands
+            .push(BandProcessor::new(band, self.sample_rate, self.channels));
+        self.bands.len() - 1
+    }
+
+    /// Sets the parameters of the band at the given index. Changes are ramped, except for changes
+    /// of the filter type which apply immediately.
+    pub fn set_band(&mut self, index: usize, band: EqBand) {
+        let sample_rate = self.sample_rate;
+        if let Some(processor) = self.bands.get_mut(index) {
+            processor.set(band, sample_rate);
+        }
+    }
+
+    /// Enables or disables the band at the given index.
+    pub fn set_band_enabled(&mut self, index: usize, enabled: bool) {
+        if let Some(band) = self.band(index) {
+            self.set_band(index, EqBand { enabled, ..band });
+        }
+    }
+
+    /// Removes the band at the given index.
+    pub fn remove_band(&mut self, index: usize) {
+        if index < self.bands.len() {
+            self.bands.remove(index);
+        }
+    }
+
+    /// Returns the magnitude (as a linear gain factor) and phase (in radians) of the equalizer at
+    /// the given frequency, taking the target parameters of all enabled bands into account.
+    pub fn response(&self, frequency: f64) -> (f64, f64) {
+        self.bands
+            .iter()
+            .filter(|b| b.band.enabled)
+            .map(|b| {
+                b.band
+                    .coefficients(self.sample_rate)
+                    .response(frequency, self.sample_rate)
+            })
+            .fold((1.0, 0.0), |(mag, phase), (m, p)| (mag * m, phase + p))
+    }
+
+    /// Returns the magnitude of the equalizer in decibels at the given frequency. See
+    /// [`response`](#method.response).
+    pub fn magnitude_db(&self, frequency: f64) -> f64 {
+        Gain::from_linear(self.response(frequency).0).0
+    }
+
+    /// Returns the response of the equalizer at each of the given frequencies. Useful to draw the
+    /// curve of the equalizer.
+    pub fn response_curve(&self, frequencies: &[f64]) -> Vec<(f64, f64)> {
+        frequencies.iter().map(|&f| self.response(f)).collect()
+    }
+}
+
+impl Default for ParametricEq {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Effect for ParametricEq {
+    fn prepare(&mut self, context: &AudioContext) {
+        self.sample_rate = context.sample_rate as f64;
+        self.channels = context.channel_count as usize;
+        for band in &mut self.bands {
+            band.states = SmallVec::from_elem(BiquadState::default(), self.channels);
+            band.set_sample_rate(self.sample_rate);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.bands.iter_mut().for_each(BandProcessor::reset);
+    }
+
+    fn process(&mut self, context: &AudioContext, buffer: &mut AudioBuffer) {
+        if self.sample_rate != context.sample_rate as f64 || self.channels != buffer.channels() {
+            self.prepare(context);
+            self.channels = buffer.channels();
+            for band in &mut self.bands {
+                band.states.resize(self.channels, BiquadState::default());
+            }
+        }
+        for band in &mut self.bands {
+            band.process(buffer, self.sample_rate);
+        }
+    }
+}
@@ -0,0 +1,207 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! # Gain, trim and pan
+//!
+//! Level and stereo placement effects. Every change of parameter is ramped over a few milliseconds
+//! to avoid zipper noise.
+
+use std::f64::consts::FRAC_PI_4;
+
+use smallvec::SmallVec;
+use wavr_audio_buffer::AudioBuffer;
+use wavr_engine::{AudioContext, Effect};
+use wavr_meter::decibel;
+
+use crate::util::Smoothed;
+
+/// Applies the same gain, in decibels, to all channels.
+#[derive(Clone, Debug)]
+pub struct Gain {
+    gain_db: f64,
+    gain: Smoothed,
+}
+
+/// Applies an individual gain, in decibels, to each channel. Channels without a trim value are
+/// left untouched.
+#[derive(Clone, Debug, Default)]
+pub struct Trim {
+    trims_db: SmallVec<[f64; 16]>,
+    gains: SmallVec<[Smoothed; 16]>,
+    sample_rate: f64,
+}
+
+/// Pan law used by the [`Pan`](struct.Pan.html) effect, setting the level of the center position.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PanLaw {
+    /// Both channels at -3 dB in the center, keeping the perceived loudness constant.
+    ConstantPower,
+    /// Both channels at -6 dB in the center, keeping the mono sum constant.
+    Linear,
+    /// Both channels at 0 dB in the center, only attenuating the opposite channel (ie. a balance
+    /// control).
+    Balance,
+}
+
+/// Pans a stereo signal. Only the first two channels are affected.
+#[derive(Clone, Debug)]
+pub struct Pan {
+    pan: f64,
+    law: PanLaw,
+    position: Smoothed,
+}
+
+impl Gain {
+    /// Create a new gain effect with the given gain in decibels.
+    pub fn new(gain_db: f64) -> Self {
+        Self {
+            gain_db,
+            gain: Smoothed::new(decibel::Gain(gain_db).to_linear()),
+        }
+    }
+
+    /// Returns the gain in decibels.
+    pub fn gain_db(&self) -> f64 {
+        self.gain_db
+    }
+
+    /// Sets the gain in decibels.
+    pub fn set_gain_db(&mut self, gain_db: f64) {
+        self.gain_db = gain_db;
+        self.gain.set(decibel::Gain(gain_db).to_linear());
+    }
+}
+
+impl Effect for Gain {
+    fn prepare(&mut self, context: &AudioContext) {
+        self.gain.set_sample_rate(context.sample_rate as f64);
+    }
+
+    fn reset(&mut self) {
+        self.gain.reset();
+    }
+
+    fn process(&mut self, _context: &AudioContext, buffer: &mut AudioBuffer) {
+        for i in 0..buffer.buffer_size() {
+            let gain = self.gain.next();
+            for ch in 0..buffer.channels() {
+                buffer[(ch, i)] *= gain;
+            }
+        }
+    }
+}
+
+impl Trim {
+    /// Create a new trim effect, with all channels at 0 dB.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the trim of the given channel in decibels.
+    pub fn trim_db(&self, channel: usize) -> f64 {
+        self.trims_db.get(channel).cloned().unwrap_or(0.0)
+    }
+
+    /// Sets the trim of the given channel in decibels.
+    pub fn set_trim_db(&mut self, channel: usize, trim_db: f64) {
+        while self.trims_db.len() <= channel {
+            self.trims_db.push(0.0);
+            let mut gain = Smoothed::new(1.0);
+            if self.sample_rate > 0.0 {
+                gain.set_sample_rate(self.sample_rate);
+            }
+            self.gains.push(gain);
+        }
+        self.trims_db[channel] = trim_db;
+        self.gains[channel].set(decibel::Gain(trim_db).to_linear());
+    }
+}
+
+impl Effect for Trim {
+    fn prepare(&mut self, context: &AudioContext) {
+        self.sample_rate = context.sample_rate as f64;
+        for gain in &mut self.gains {
+            gain.set_sample_rate(self.sample_rate);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.gains.iter_mut().for_each(Smoothed::reset);
+    }
+
+    fn process(&mut self, _context: &AudioContext, buffer: &mut AudioBuffer) {
+        for (ch, gain) in self.gains.iter_mut().enumerate().take(buffer.channels()) {
+            for sample in &mut buffer[ch] {
+                *sample *= gain.next();
+            }
+        }
+    }
+}
+
+impl Pan {
+    /// Create a new pan effect with the given position, from -1 (left) to 1 (right).
+    pub fn new(pan: f64, law: PanLaw) -> Self {
+        let pan = pan.clamp(-1.0, 1.0);
+        Self {
+            pan,
+            law,
+            position: Smoothed::new(pan),
+        }
+    }
+
+    /// Returns the pan position, from -1 (left) to 1 (right).
+    pub fn pan(&self) -> f64 {
+        self.pan
+    }
+
+    /// Sets the pan position, from -1 (left) to 1 (right).
+    pub fn set_pan(&mut self, pan: f64) {
+        self.pan = pan.clamp(-1.0, 1.0);
+        self.position.set(self.pan);
+    }
+
+    /// Returns the pan law.
+    pub fn law(&self) -> PanLaw {
+        self.law
+    }
+
+    /// Sets the pan law.
+    pub fn set_law(&mut self, law: PanLaw) {
+        self.law = law;
+    }
+
+    /// Returns the left and right gains for the given position.
+    pub fn gains(law: PanLaw, pan: f64) -> (f64, f64) {
+        match law {
+            PanLaw::ConstantPower => {
+                let angle = (pan + 1.0) * FRAC_PI_4;
+                (angle.cos(), angle.sin())
+            }
+            PanLaw::Linear => ((1.0 - pan) / 2.0, (1.0 + pan) / 2.0),
+            PanLaw::Balance => ((1.0 - pan).min(1.0), (1.0 + pan).min(1.0)),
+        }
+    }
+}
+
+impl Effect for Pan {
+    fn prepare(&mut self, context: &AudioContext) {
+        self.position.set_sample_rate(context.sample_rate as f64);
+    }
+
+    fn reset(&mut self) {
+        self.position.reset();
+    }
+
+    fn process(&mut self, _context: &AudioContext, buffer: &mut AudioBuffer) {
+        if buffer.channels() < 2 {
+            return;
+        }
+        for i in 0..buffer.buffer_size() {
+            let (left, right) = Self::gains(self.law, self.position.next());
+            buffer[(0, i)] *= left;
+            buffer[(1, i)] *= right;
+        }
+    }
+}
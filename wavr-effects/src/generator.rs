@@ -0,0 +1,684 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! # Generators
+//!
+//! Oscillators and test signals: band-limited waveforms, colored noise, sweeps, impulses and
+//! broadcast alignment tones.
+//!
+//! All generators implement [`Generator`], which can fill buffers directly (ie. to feed test
+//! signals to meters and effects), and [`Effect`](wavr_engine::Effect), replacing the input of
+//! the rack with the generated signal:
+//!
+//! ```rust
+//! # use wavr_effects::{Generator, Oscillator, TestTone, Waveform};
+//! # use wavr_engine::AudioEngine;
+//! // One second of the EBU R 68 alignment tone
+//! let tone = TestTone::ebu_r68().render(48000, 2, 48000);
+//!
+//! let mut engine = AudioEngine::new(48000, 2);
+//! engine.get_rack_mut().push_effect(Oscillator::new(Waveform::Saw, 110.0, -12.0));
+//! ```
+//!
+//! [`Generator`]: trait.Generator.html
+
+use std::f64::consts::PI;
+
+use smallvec::SmallVec;
+use wavr_audio_buffer::AudioBuffer;
+use wavr_engine::{AudioContext, Effect};
+use wavr_meter::decibel::DbFs;
+
+/// Source of an audio signal, generated one frame at a time.
+pub trait Generator: Send {
+    /// Generates the next frame, writing one sample per channel.
+    fn next_frame(&mut self, sample_rate: f64, frame: &mut [f64]);
+
+    /// Restarts the signal from its beginning.
+    fn restart(&mut self);
+
+    /// Fills the buffer with the next frames of the signal.
+    fn fill(&mut self, sample_rate: u64, buffer: &mut AudioBuffer) {
+        let mut frame: SmallVec<[f64; 16]> = SmallVec::from_elem(0.0, buffer.channels());
+        for i in 0..buffer.buffer_size() {
+            self.next_frame(sample_rate as f64, &mut frame);
+            for (ch, &sample) in frame.iter().enumerate() {
+                buffer[(ch, i)] = sample;
+            }
+        }
+    }
+
+    /// Renders the next `length` frames of the signal into a new buffer.
+    fn render(&mut self, sample_rate: u64, channels: usize, length: usize) -> AudioBuffer {
+        let mut buffer = AudioBuffer::zeroed(channels, length);
+        self.fill(sample_rate, &mut buffer);
+        buffer
+    }
+}
+
+/// Implements `Effect` for generators, replacing the contents of the buffer.
+macro_rules! generator_effect {
+    ($($generator:ty),*) => {
+        $(
+            impl Effect for $generator {
+                fn reset(&mut self) {
+                    self.restart();
+                }
+
+                fn process(&mut self, context: &AudioContext, buffer: &mut AudioBuffer) {
+                    self.fill(context.sample_rate, buffer);
+                }
+            }
+        )*
+    };
+}
+
+generator_effect!(Oscillator, Noise, Sweep, Impulse, TestTone);
+
+/// Waveform of an [`Oscillator`](struct.Oscillator.html).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Waveform {
+    /// Pure sine wave.
+    Sine,
+    /// Rising sawtooth wave.
+    Saw,
+    /// Square wave with a 50% duty cycle.
+    Square,
+    /// Triangle wave.
+    Triangle,
+}
+
+/// Band-limited oscillator. Saw and square waveforms are corrected with polynomial band-limited
+/// steps (PolyBLEP), and the triangle with their integrated counterpart (PolyBLAMP), keeping the
+/// aliasing low without oversampling. All channels receive the same signal.
+#[derive(Clone, Debug)]
+pub struct Oscillator {
+    waveform: Waveform,
+    frequency: f64,
+    level_db: f64,
+    amplitude: f64,
+    phase: f64,
+}
+
+/// Color of a [`Noise`](struct.Noise.html) generator.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum NoiseColor {
+    /// Flat spectrum.
+    White,
+    /// Spectrum falling 3 dB per octave, with equal energy per octave.
+    Pink,
+    /// Spectrum falling 6 dB per octave.
+    Brown,
+}
+
+/// Per-channel state of a noise generator.
+#[derive(Copy, Clone, Debug, Default)]
+struct NoiseState {
+    random: u64,
+    filter: [f64; 7],
+}
+
+/// Noise generator. Each channel receives an independent, deterministic noise sequence.
+#[derive(Clone, Debug)]
+pub struct Noise {
+    color: NoiseColor,
+    level_db: f64,
+    amplitude: f64,
+    seed: u64,
+    states: SmallVec<[NoiseState; 16]>,
+}
+
+/// Frequency progression of a [`Sweep`](struct.Sweep.html).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SweepKind {
+    /// The frequency increases linearly with time.
+    Linear,
+    /// The frequency increases exponentially with time, spending the same time on each octave.
+    Logarithmic,
+}
+
+/// Sine sweep (chirp) between two frequencies. The sweep is followed by silence, unless it
+/// repeats.
+#[derive(Clone, Debug)]
+pub struct Sweep {
+    kind: SweepKind,
+    start_hz: f64,
+    end_hz: f64,
+    duration_s: f64,
+    level_db: f64,
+    amplitude: f64,
+    repeat: bool,
+    phase: f64,
+    position: usize,
+}
+
+/// Unit impulses (single-sample clicks), either once or periodically.
+#[derive(Clone, Debug)]
+pub struct Impulse {
+    level_db: f64,
+    amplitude: f64,
+    period_s: Option<f64>,
+    position: usize,
+}
+
+/// Stereo identification pattern of a [`TestTone`](struct.TestTone.html), interrupting the tone
+/// to tell the channels apart.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum StereoIdent {
+    /// Continuous tone on all channels.
+    None,
+    /// EBU stereo ident (EBU Tech 3304): the left channel is interrupted for 250 ms every 3
+    /// seconds.
+    Ebu,
+    /// BBC GLITS: over a 4 second cycle, the left channel is interrupted once and the right
+    /// channel twice, for 250 ms each.
+    Glits,
+}
+
+/// Sine test tone, with optional stereo identification.
+#[derive(Clone, Debug)]
+pub struct TestTone {
+    frequency: f64,
+    level_db: f64,
+    amplitude: f64,
+    ident: StereoIdent,
+    phase: f64,
+    position: usize,
+}
+
+/// Advances a normalized phase by one sample, wrapping it in [0, 1).
+#[inline]
+fn advance(phase: &mut f64, increment: f64) {
+    *phase += increment;
+    *phase -= phase.floor();
+}
+
+/// Residual of a band-limited step of height 2 at phase 0, over one sample on each side.
+#[inline]
+fn poly_blep(t: f64, dt: f64) -> f64 {
+    if t < dt {
+        let x = t / dt;
+        2.0 * x - x * x - 1.0
+    } else if t > 1.0 - dt {
+        let x = (t - 1.0) / dt;
+        x * x + 2.0 * x + 1.0
+    } else {
+        0.0
+    }
+}
+
+/// Residual of a band-limited change of slope of 2 per sample at phase 0, over one sample on
+/// each side. This is the integral of `poly_blep`.
+#[inline]
+fn poly_blamp(t: f64, dt: f64) -> f64 {
+    if t < dt {
+        (1.0 - t / dt).powi(3) / 3.0
+    } else if t > 1.0 - dt {
+        ((t - 1.0) / dt + 1.0).powi(3) / 3.0
+    } else {
+        0.0
+    }
+}
+
+impl Oscillator {
+    /// Create a new oscillator with the given waveform, frequency in Hertz, and peak level in
+    /// dBFS.
+    pub fn new(waveform: Waveform, frequency: f64, level_db: f64) -> Self {
+        Self {
+            waveform,
+            frequency,
+            level_db,
+            amplitude: DbFs(level_db).to_amplitude(),
+            phase: 0.0,
+        }
+    }
+
+    /// Returns the waveform.
+    pub fn waveform(&self) -> Waveform {
+        self.waveform
+    }
+
+    /// Sets the waveform. The phase is kept, so that the change is continuous in time.
+    pub fn set_waveform(&mut self, waveform: Waveform) {
+        self.waveform = waveform;
+    }
+
+    /// Returns the frequency in Hertz.
+    pub fn frequency(&self) -> f64 {
+        self.frequency
+    }
+
+    /// Sets the frequency in Hertz.
+    pub fn set_frequency(&mut self, frequency: f64) {
+        self.frequency = frequency;
+    }
+
+    /// Returns the peak level in dBFS.
+    pub fn level_db(&self) -> f64 {
+        self.level_db
+    }
+
+    /// Sets the peak level in dBFS.
+    pub fn set_level_db(&mut self, level_db: f64) {
+        self.level_db = level_db;
+        self.amplitude = DbFs(level_db).to_amplitude();
+    }
+
+    /// Returns the next sample of the waveform at the given sample rate.
+    #[inline]
+    fn next_sample(&mut self, sample_rate: f64) -> f64 {
+        let t = self.phase;
+        let dt = (self.frequency / sample_rate).abs().min(0.5);
+        let value = match self.waveform {
+            Waveform::Sine => (2.0 * PI * t).sin(),
+            Waveform::Saw => 2.0 * t - 1.0 - poly_blep(t, dt),
+            Waveform::Square => {
+                let naive = if t < 0.5 { 1.0 } else { -1.0 };
+                naive + poly_blep(t, dt) - poly_blep((t + 0.5).fract(), dt)
+            }
+            Waveform::Triangle => {
+                let naive = 1.0 - 4.0 * (t - 0.5).abs();
+                naive + 4.0 * dt * (poly_blamp(t, dt) - poly_blamp((t + 0.5).fract(), dt))
+            }
+        };
+        advance(&mut self.phase, self.frequency / sample_rate);
+        value * self.amplitude
+    }
+}
+
+impl Generator for Oscillator {
+    fn next_frame(&mut self, sample_rate: f64, frame: &mut [f64]) {
+        let sample = self.next_sample(sample_rate);
+        frame.iter_mut().for_each(|s| *s = sample);
+    }
+
+    fn restart(&mut self) {
+        self.phase = 0.0;
+    }
+}
+
+impl NoiseState {
+    fn new(seed: u64) -> Self {
+        Self {
+            // xorshift generators must not start from zero
+            random: seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1,
+            filter: [0.0; 7],
+        }
+    }
+
+    /// Returns uniform white noise in [-1, 1).
+    #[inline]
+    fn white(&mut self) -> f64 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+        (self.random >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+    }
+}
+
+impl Noise {
+    /// RMS level of uniform white noise in [-1, 1].
+    const WHITE_RMS: f64 = 0.577_350_269_189_625_8;
+    /// RMS level of the pink noise filter output for uniform white noise in [-1, 1].
+    const PINK_RMS: f64 = 1.745;
+    /// RMS level of the brown noise integrator output for uniform white noise in [-1, 1].
+    const BROWN_RMS: f64 = 0.0573;
+
+    /// Create a new noise generator with the given color and RMS level in dBFS.
+    pub fn new(color: NoiseColor, level_db: f64) -> Self {
+        Self {
+            color,
+            level_db,
+            amplitude: DbFs(level_db).to_amplitude(),
+            seed: 0x5eed,
+            states: SmallVec::new(),
+        }
+    }
+
+    /// Returns the color of the noise.
+    pub fn color(&self) -> NoiseColor {
+        self.color
+    }
+
+    /// Sets the color of the noise.
+    pub fn set_color(&mut self, color: NoiseColor) {
+        self.color = color;
+        self.states.iter_mut().for_each(|s| s.filter = [0.0; 7]);
+    }
+
+    /// Returns the RMS level in dBFS.
+    pub fn level_db(&self) -> f64 {
+        self.level_db
+    }
+
+    /// Sets the RMS level in dBFS.
+    pub fn set_level_db(&mut self, level_db: f64) {
+        self.level_db = level_db;
+        self.amplitude = DbFs(level_db).to_amplitude();
+    }
+
+    /// Returns the seed of the noise sequences.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Sets the seed of the noise sequences, and restarts them.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.restart();
+    }
+
+    /// Returns the next sample of one channel, normalized to a unit RMS level.
+    #[inline]
+    fn next_sample(color: NoiseColor, state: &mut NoiseState) -> f64 {
+        let white = state.white();
+        match color {
+            NoiseColor::White => white / Self::WHITE_RMS,
+            NoiseColor::Pink => {
+                // Paul Kellet's refined pink noise filter
+                let b = &mut state.filter;
+                b[0] = 0.99886 * b[0] + white * 0.055_517_9;
+                b[1] = 0.99332 * b[1] + white * 0.075_075_9;
+                b[2] = 0.96900 * b[2] + white * 0.153_852;
+                b[3] = 0.86650 * b[3] + white * 0.310_485_6;
+                b[4] = 0.55000 * b[4] + white * 0.532_952_2;
+                b[5] = -0.7616 * b[5] - white * 0.016_898;
+                let pink = b[..6].iter().sum::<f64>() + b[6] + white * 0.5362;
+                b[6] = white * 0.115_926;
+                pink / Self::PINK_RMS
+            }
+            NoiseColor::Brown => {
+                let b = &mut state.filter[0];
+                *b = (*b + 0.02 * white) / 1.02;
+                *b / Self::BROWN_RMS
+            }
+        }
+    }
+}
+
+impl Generator for Noise {
+    fn next_frame(&mut self, _sample_rate: f64, frame: &mut [f64]) {
+        while self.states.len() < frame.len() {
+            let channel = self.states.len() as u64;
+            self.states.push(NoiseState::new(self.seed + channel));
+        }
+        for (sample, state) in frame.iter_mut().zip(&mut self.states) {
+            *sample = Self::next_sample(self.color, state) * self.amplitude;
+        }
+    }
+
+    fn restart(&mut self) {
+        let seed = self.seed;
+        for (channel, state) in self.states.iter_mut().enumerate() {
+            *state = NoiseState::new(seed + channel as u64);
+        }
+    }
+}
+
+impl Sweep {
+    /// Create a new sweep from `start_hz` to `end_hz` over `duration_s` seconds, with the given
+    /// peak level in dBFS.
+    pub fn new(
+        kind: SweepKind,
+        start_hz: f64,
+        end_hz: f64,
+        duration_s: f64,
+        level_db: f64,
+    ) -> Self {
+        Self {
+            kind,
+            start_hz,
+            end_hz,
+            duration_s,
+            level_db,
+            amplitude: DbFs(level_db).to_amplitude(),
+            repeat: false,
+            phase: 0.0,
+            position: 0,
+        }
+    }
+
+    /// Returns the frequency progression of the sweep.
+    pub fn kind(&self) -> SweepKind {
+        self.kind
+    }
+
+    /// Returns the start frequency in Hertz.
+    pub fn start_hz(&self) -> f64 {
+        self.start_hz
+    }
+
+    /// Returns the end frequency in Hertz.
+    pub fn end_hz(&self) -> f64 {
+        self.end_hz
+    }
+
+    /// Returns the duration of the sweep in seconds.
+    pub fn duration_s(&self) -> f64 {
+        self.duration_s
+    }
+
+    /// Returns the peak level in dBFS.
+    pub fn level_db(&self) -> f64 {
+        self.level_db
+    }
+
+    /// Sets the peak level in dBFS.
+    pub fn set_level_db(&mut self, level_db: f64) {
+        self.level_db = level_db;
+        self.amplitude = DbFs(level_db).to_amplitude();
+    }
+
+    /// Returns whether the sweep restarts when it ends.
+    pub fn repeat(&self) -> bool {
+        self.repeat
+    }
+
+    /// Sets whether the sweep restarts when it ends, instead of falling silent.
+    pub fn set_repeat(&mut self, repeat: bool) {
+        self.repeat = repeat;
+    }
+
+    /// Returns the instantaneous frequency of the sweep after `time` seconds.
+    pub fn frequency_at(&self, time: f64) -> f64 {
+        let progress = (time / self.duration_s).clamp(0.0, 1.0);
+        match self.kind {
+            SweepKind::Linear => self.start_hz + (self.end_hz - self.start_hz) * progress,
+            SweepKind::Logarithmic => self.start_hz * (self.end_hz / self.start_hz).powf(progress),
+        }
+    }
+}
+
+impl Generator for Sweep {
+    fn next_frame(&mut self, sample_rate: f64, frame: &mut [f64]) {
+        let length = (self.duration_s * sample_rate).round() as usize;
+        if self.repeat && self.position >= length {
+            self.restart();
+        }
+        let sample = if self.position < length {
+            let value = (2.0 * PI * self.phase).sin() * self.amplitude;
+            let frequency = self.frequency_at(self.position as f64 / sample_rate);
+            advance(&mut self.phase, frequency / sample_rate);
+            value
+        } else {
+            0.0
+        };
+        self.position += 1;
+        frame.iter_mut().for_each(|s| *s = sample);
+    }
+
+    fn restart(&mut self) {
+        self.phase = 0.0;
+        self.position = 0;
+    }
+}
+
+impl Impulse {
+    /// Create a single impulse at the start of the signal, with the given level in dBFS.
+    pub fn new(level_db: f64) -> Self {
+        Self {
+            level_db,
+            amplitude: DbFs(level_db).to_amplitude(),
+            period_s: None,
+            position: 0,
+        }
+    }
+
+    /// Create a train of impulses, one every `period_s` seconds, with the given level in dBFS.
+    pub fn periodic(period_s: f64, level_db: f64) -> Self {
+        Self {
+            period_s: Some(period_s),
+            ..Self::new(level_db)
+        }
+    }
+
+    /// Returns the level in dBFS.
+    pub fn level_db(&self) -> f64 {
+        self.level_db
+    }
+
+    /// Sets the level in dBFS.
+    pub fn set_level_db(&mut self, level_db: f64) {
+        self.level_db = level_db;
+        self.amplitude = DbFs(level_db).to_amplitude();
+    }
+
+    /// Returns the period between impulses in seconds, if the impulses repeat.
+    pub fn period_s(&self) -> Option<f64> {
+        self.period_s
+    }
+
+    /// Sets the period between impulses in seconds, or `None` for a single impulse.
+    pub fn set_period_s(&mut self, period_s: Option<f64>) {
+        self.period_s = period_s;
+    }
+}
+
+impl Generator for Impulse {
+    fn next_frame(&mut self, sample_rate: f64, frame: &mut [f64]) {
+        if let Some(period) = self.period_s {
+            // Count the position within the period, firing at its start
+            self.position %= ((period * sample_rate).round() as usize).max(1);
+        }
+        let fire = self.position == 0;
+        self.position += 1;
+        let sample = if fire { self.amplitude } else { 0.0 };
+        frame.iter_mut().for_each(|s| *s = sample);
+    }
+
+    fn restart(&mut self) {
+        self.position = 0;
+    }
+}
+
+impl TestTone {
+    /// Length of the interruptions of the stereo idents, in seconds.
+    const IDENT_GAP_S: f64 = 0.25;
+
+    /// Create a new continuous test tone with the given frequency in Hertz and peak level in dBFS.
+    pub fn new(frequency: f64, level_db: f64) -> Self {
+        Self {
+            frequency,
+            level_db,
+            amplitude: DbFs(level_db).to_amplitude(),
+            ident: StereoIdent::None,
+            phase: 0.0,
+            position: 0,
+        }
+    }
+
+    /// EBU R 68 alignment tone: 1 kHz at -18 dBFS.
+    pub fn ebu_r68() -> Self {
+        Self::new(1000.0, -18.0)
+    }
+
+    /// SMPTE RP 155 alignment tone: 1 kHz at -20 dBFS.
+    pub fn smpte_rp155() -> Self {
+        Self::new(1000.0, -20.0)
+    }
+
+    /// EBU stereo ident: EBU R 68 alignment tone with the left channel interrupted every 3
+    /// seconds.
+    pub fn ebu_stereo_ident() -> Self {
+        Self::ebu_r68().with_ident(StereoIdent::Ebu)
+    }
+
+    /// BBC GLITS: EBU R 68 alignment tone with the left channel interrupted once and the right
+    /// channel twice every 4 seconds.
+    pub fn glits() -> Self {
+        Self::ebu_r68().with_ident(StereoIdent::Glits)
+    }
+
+    /// Sets the stereo identification pattern.
+    pub fn with_ident(self, ident: StereoIdent) -> Self {
+        Self { ident, ..self }
+    }
+
+    /// Returns the frequency in Hertz.
+    pub fn frequency(&self) -> f64 {
+        self.frequency
+    }
+
+    /// Sets the frequency in Hertz.
+    pub fn set_frequency(&mut self, frequency: f64) {
+        self.frequency = frequency;
+    }
+
+    /// Returns the peak level in dBFS.
+    pub fn level_db(&self) -> f64 {
+        self.level_db
+    }
+
+    /// Sets the peak level in dBFS.
+    pub fn set_level_db(&mut self, level_db: f64) {
+        self.level_db = level_db;
+        self.amplitude = DbFs(level_db).to_amplitude();
+    }
+
+    /// Returns the stereo identification pattern.
+    pub fn ident(&self) -> StereoIdent {
+        self.ident
+    }
+
+    /// Sets the stereo identification pattern.
+    pub fn set_ident(&mut self, ident: StereoIdent) {
+        self.ident = ident;
+    }
+
+    /// Returns whether the channel is interrupted at the given time, in seconds.
+    fn is_interrupted(&self, channel: usize, time: f64) -> bool {
+        let within = |start: f64, cycle: f64| {
+            let t = time % cycle;
+            t >= start && t < start + Self::IDENT_GAP_S
+        };
+        match (self.ident, channel) {
+            (StereoIdent::Ebu, 0) => within(0.0, 3.0),
+            (StereoIdent::Glits, 0) => within(0.0, 4.0),
+            (StereoIdent::Glits, 1) => within(0.5, 4.0) || within(1.0, 4.0),
+            _ => false,
+        }
+    }
+}
+
+impl Generator for TestTone {
+    fn next_frame(&mut self, sample_rate: f64, frame: &mut [f64]) {
+        let sample = (2.0 * PI * self.phase).sin() * self.amplitude;
+        let time = self.position as f64 / sample_rate;
+        for (channel, s) in frame.iter_mut().enumerate() {
+            *s = if self.is_interrupted(channel, time) {
+                0.0
+            } else {
+                sample
+            };
+        }
+        advance(&mut self.phase, self.frequency / sample_rate);
+        self.position += 1;
+    }
+
+    fn restart(&mut self) {
+        self.phase = 0.0;
+        self.position = 0;
+    }
+}
@@ -0,0 +1,64 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! # The Wavr Audio Effects
+//!
+//! This crate implements the core audio processors of Wavr Audio as
+//! [`Effect`](wavr_engine::Effect)s, ready to be pushed onto the rack:
+//!
+//! - `generator`: band-limited oscillators, noise, sweeps, impulses and test tones,
+//! - `gain`: gain, trim and stereo pan,
+//! - `channel`: polarity inversion and channel swap,
+//! - `biquad`: biquad filters (peak, shelves, high- and low-pass, notch),
+//! - `eq`: multi-band parametric equalizer, with frequency response queries,
+//! - `dynamics`: compressor, limiter, expander and gate on a shared dynamics core,
+//! - `brickwall`: brickwall true peak limiter,
+//! - `normalize`: two-pass offline loudness normalization,
+//! - `agc`: real-time loudness-driven automatic gain control,
+//! - `delay`: feedback delay,
+//! - `convolution`: partitioned FFT convolution with impulse responses,
+//! - `reverb`: algorithmic reverb.
+//!
+//! Effects allocate their per-channel state from the audio context, and can be reset to clear their
+//! internal memories without touching their parameters.
+//!
+//! ## Usage
+//!
+//! ```rust
+//! # use wavr_effects::*;
+//! # use wavr_engine::AudioEngine;
+//! let mut engine = AudioEngine::new(48000, 2);
+//! let rack = engine.get_rack_mut();
+//! rack.push_effect(BiquadFilter::new(BiquadType::HighPass, 80.0, 0.707));
+//! rack.push_effect(Compressor::new(-18.0, 4.0));
+//! rack.push_effect(Gain::new(-3.0));
+//! ```
+
+pub use agc::*;
+pub use biquad::*;
+pub use brickwall::*;
+pub use channel::*;
+pub use convolution::*;
+pub use delay::*;
+pub use dynamics::*;
+pub use eq::*;
+pub use gain::*;
+pub use generator::*;
+pub use normalize::*;
+pub use reverb::*;
+
+pub mod agc;
+pub mod biquad;
+pub mod brickwall;
+pub mod channel;
+pub mod convolution;
+pub mod delay;
+pub mod dynamics;
+pub mod eq;
+pub mod gain;
+pub mod generator;
+pub mod normalize;
+pub mod reverb;
+mod util;
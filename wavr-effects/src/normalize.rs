@@ -0,0 +1,305 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! # Loudness normalization
+//!
+//! Two-pass offline loudness normalization. The first pass measures the integrated loudness of
+//! the program with a [`LoudnessAnalyzer`]; the second pass applies the gain bringing it to the
+//! target loudness with a [`LoudnessNormalizer`], optionally followed by a brickwall true peak
+//! limiter, and measures the result.
+//!
+//! For programs held in memory, [`normalize`] runs both passes at once:
+//!
+//! ```rust
+//! # use wavr_audio_buffer::AudioBuffer;
+//! # use wavr_effects::{normalize, NormalizationTarget};
+//! # let mut program = AudioBuffer::zeroed(2, 48000);
+//! let report = normalize(&mut program, 48000, NormalizationTarget::ebu_r128())?;
+//! println!("{} -> {}", report.before.loudness, report.after.loudness);
+//! # Ok::<(), wavr_meter::Error>(())
+//! ```
+//!
+//! To normalize the output of a rack, render it once through a `LoudnessAnalyzer`, then push a
+//! `LoudnessNormalizer` created from the analysis at the end of the rack and render it again.
+//!
+//! [`LoudnessAnalyzer`]: struct.LoudnessAnalyzer.html
+//! [`LoudnessNormalizer`]: struct.LoudnessNormalizer.html
+//! [`normalize`]: fn.normalize.html
+
+use smallvec::SmallVec;
+use wavr_audio_buffer::AudioBuffer;
+use wavr_engine::{AudioContext, AudioContextState, Effect};
+use wavr_meter::decibel::{Decibel, Gain, Linear};
+use wavr_meter::ebu::modes::Integrated;
+use wavr_meter::{EBUMeter, Result};
+
+use crate::brickwall::TruePeakLimiter;
+
+/// Target of a loudness normalization.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct NormalizationTarget {
+    /// Target integrated loudness, in LUFS.
+    pub loudness: f64,
+    /// Maximum true peak level in dBTP. When set, a brickwall true peak limiter is applied after
+    /// the normalization gain.
+    pub true_peak: Option<f64>,
+}
+
+/// Loudness measurements of a program.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LoudnessStats {
+    /// Integrated loudness, in LUFS.
+    pub loudness: Decibel,
+    /// Loudness range, in LU.
+    pub range: Decibel,
+    /// Highest true peak of all channels, in dBTP.
+    pub true_peak: Decibel,
+}
+
+/// Report of a loudness normalization.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct NormalizationReport {
+    /// Target of the normalization.
+    pub target: NormalizationTarget,
+    /// Gain applied to the program, in decibels.
+    pub gain_db: f64,
+    /// Measurements of the program before normalization.
+    pub before: LoudnessStats,
+    /// Measurements of the program after normalization (and limiting).
+    pub after: LoudnessStats,
+}
+
+/// Measures the integrated loudness, loudness range and true peak of a program.
+#[derive(Debug)]
+pub struct LoudnessAnalyzer {
+    channels: u32,
+    meter: EBUMeter<Integrated>,
+}
+
+/// Second pass of the normalization, applying the gain computed from the first pass, and the
+/// optional true peak limiter. The output is measured to report the result of the normalization.
+#[derive(Debug)]
+pub struct LoudnessNormalizer {
+    target: NormalizationTarget,
+    before: LoudnessStats,
+    gain_db: f64,
+    limiter: Option<TruePeakLimiter>,
+    analyzer: Option<LoudnessAnalyzer>,
+}
+
+impl NormalizationTarget {
+    /// EBU R 128 broadcast delivery: -23 LUFS, -1 dBTP.
+    pub fn ebu_r128() -> Self {
+        Self {
+            loudness: -23.0,
+            true_peak: Some(-1.0),
+        }
+    }
+
+    /// ATSC A/85 broadcast delivery: -24 LKFS, -2 dBTP.
+    pub fn atsc_a85() -> Self {
+        Self {
+            loudness: -24.0,
+            true_peak: Some(-2.0),
+        }
+    }
+
+    /// Common streaming platforms delivery: -14 LUFS, -1 dBTP.
+    pub fn streaming() -> Self {
+        Self {
+            loudness: -14.0,
+            true_peak: Some(-1.0),
+        }
+    }
+
+    /// Custom target loudness in LUFS, without true peak limiting.
+    pub fn loudness(loudness: f64) -> Self {
+        Self {
+            loudness,
+            true_peak: None,
+        }
+    }
+
+    /// Sets the maximum true peak level, in dBTP.
+    pub fn with_true_peak(self, true_peak: Option<f64>) -> Self {
+        Self { true_peak, ..self }
+    }
+}
+
+impl LoudnessStats {
+    /// Returns the gain in decibels bringing the program to the target loudness. Silent programs,
+    /// whose loudness cannot be measured, are left untouched.
+    pub fn gain_to(&self, target: &NormalizationTarget) -> f64 {
+        let Decibel(loudness) = self.loudness;
+        if loudness.is_finite() {
+            target.loudness - loudness
+        } else {
+            0.0
+        }
+    }
+}
+
+impl LoudnessAnalyzer {
+    /// Create a new analyzer for the given channel count and sample rate.
+    pub fn new(channels: u32, sample_rate: u32) -> Result<Self> {
+        Ok(Self {
+            channels,
+            meter: EBUMeter::new(channels, sample_rate)?,
+        })
+    }
+
+    /// Add a buffer to the measured program.
+    pub fn add_buffer(&mut self, buffer: &AudioBuffer) -> Result<()> {
+        self.meter
+            .add_samples(&AudioBuffer::clone(buffer).interleave())
+    }
+
+    /// Add interleaved samples to the measured program.
+    pub fn add_samples(&mut self, interleaved: &[f64]) -> Result<()> {
+        self.meter.add_samples(interleaved)
+    }
+
+    /// Returns the measurements of the program so far.
+    pub fn stats(&self) -> Result<LoudnessStats> {
+        let mut true_peak = 0.0f64;
+        for ch in 0..self.channels {
+            true_peak = true_peak.max(self.meter.get_true_peak(ch)?.0);
+        }
+        Ok(LoudnessStats {
+            loudness: self.meter.get_loudness()?,
+            range: self.meter.get_range()?,
+            true_peak: Linear(true_peak).into(),
+        })
+    }
+}
+
+impl LoudnessNormalizer {
+    /// Create the second pass of a normalization towards the target, from the measurements of the
+    /// first pass.
+    pub fn new(target: NormalizationTarget, before: LoudnessStats) -> Self {
+        Self {
+            target,
+            before,
+            gain_db: before.gain_to(&target),
+            limiter: target.true_peak.map(TruePeakLimiter::with_ceiling),
+            analyzer: None,
+        }
+    }
+
+    /// Returns the gain applied to the program, in decibels.
+    pub fn gain_db(&self) -> f64 {
+        self.gain_db
+    }
+
+    /// Returns the report of the normalization, measured on the processed audio so far.
+    pub fn report(&self) -> NormalizationReport {
+        let after = self
+            .analyzer
+            .as_ref()
+            .and_then(|analyzer| analyzer.stats().ok())
+            .unwrap_or(self.before);
+        NormalizationReport {
+            target: self.target,
+            gain_db: self.gain_db,
+            before: self.before,
+            after,
+        }
+    }
+}
+
+impl Effect for LoudnessNormalizer {
+    fn prepare(&mut self, context: &AudioContext) {
+        if let Some(limiter) = &mut self.limiter {
+            limiter.prepare(context);
+        }
+        self.analyzer =
+            LoudnessAnalyzer::new(context.channel_count as u32, context.sample_rate as u32).ok();
+    }
+
+    fn reset(&mut self) {
+        if let Some(limiter) = &mut self.limiter {
+            limiter.reset();
+        }
+        self.analyzer = None;
+    }
+
+    fn latency(&self) -> usize {
+        self.limiter.as_ref().map(Effect::latency).unwrap_or(0)
+    }
+
+    fn process(&mut self, context: &AudioContext, buffer: &mut AudioBuffer) {
+        buffer.apply_gain(Gain(self.gain_db).to_linear());
+        if let Some(limiter) = &mut self.limiter {
+            limiter.process(context, buffer);
+        }
+        if self.analyzer.is_none() {
+            self.analyzer =
+                LoudnessAnalyzer::new(context.channel_count as u32, context.sample_rate as u32)
+                    .ok();
+        }
+        if let Some(analyzer) = &mut self.analyzer {
+            analyzer.add_buffer(buffer).ok();
+        }
+    }
+}
+
+/// Normalizes a program held in memory towards the target, and returns the report of the
+/// normalization. The latency of the limiter is compensated, so that the program is not shifted
+/// in time. Fails if the program cannot be measured with this channel count or sample rate.
+pub fn normalize(
+    buffer: &mut AudioBuffer,
+    sample_rate: u32,
+    target: NormalizationTarget,
+) -> Result<NormalizationReport> {
+    const BLOCK_SIZE: usize = 4096;
+    let channels = buffer.channels();
+    let length = buffer.buffer_size();
+
+    let mut analyzer = LoudnessAnalyzer::new(channels as u32, sample_rate)?;
+    analyzer.add_buffer(buffer)?;
+
+    let mut context = AudioContext::new(sample_rate as u64, channels as u8);
+    context.state = AudioContextState::Offline;
+    let mut normalizer = LoudnessNormalizer::new(target, analyzer.stats()?);
+    normalizer.prepare(&context);
+    let latency = normalizer.latency();
+
+    // Process the program followed by enough silence to flush the limiter, and write the output
+    // back shifted by the latency
+    let mut output: SmallVec<[Vec<f64>; 16]> = SmallVec::from_elem(vec![0.0; length], channels);
+    let mut position = 0;
+    while position < length + latency {
+        let size = BLOCK_SIZE.min(length + latency - position);
+        let mut block = AudioBuffer::zeroed(channels, size);
+        for ch in 0..channels {
+            for i in 0..size.min(length.saturating_sub(position)) {
+                block[(ch, i)] = buffer[(ch, position + i)];
+            }
+        }
+        normalizer.process(&context, &mut block);
+        for ch in 0..channels {
+            for i in 0..size {
+                let out = position + i;
+                if out >= latency && out - latency < length {
+                    output[ch][out - latency] = block[(ch, i)];
+                }
+            }
+        }
+        position += size;
+    }
+    for (ch, samples) in output.iter().enumerate() {
+        for (i, &sample) in samples.iter().enumerate() {
+            buffer[(ch, i)] = sample;
+        }
+    }
+
+    // Measure the result on the aligned output, without the flushing silence
+    let mut after = LoudnessAnalyzer::new(channels as u32, sample_rate)?;
+    after.add_buffer(buffer)?;
+    Ok(NormalizationReport {
+        after: after.stats()?,
+        ..normalizer.report()
+    })
+}
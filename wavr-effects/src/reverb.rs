@@ -0,0 +1,240 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! # Reverb
+//!
+//! Algorithmic stereo reverb, based on Jezar's public domain "Freeverb": 8 parallel lowpass-feedback
+//! comb filters followed by 4 series all-pass filters per channel. The delay lengths are scaled to
+//! the sample rate of the context.
+
+use smallvec::SmallVec;
+use wavr_audio_buffer::AudioBuffer;
+use wavr_engine::{AudioContext, Effect};
+
+/// Comb filter lengths at 44.1 kHz.
+const COMB_TUNINGS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+/// All-pass filter lengths at 44.1 kHz.
+const ALLPASS_TUNINGS: [usize; 4] = [556, 441, 341, 225];
+/// Length offset of the right channel, decorrelating it from the left channel.
+const STEREO_SPREAD: usize = 23;
+/// Input attenuation, compensating for the summing of the comb filters.
+const FIXED_GAIN: f64 = 0.015;
+const SCALE_ROOM: f64 = 0.28;
+const OFFSET_ROOM: f64 = 0.7;
+const ALLPASS_FEEDBACK: f64 = 0.5;
+
+#[derive(Clone, Debug, Default)]
+struct Comb {
+    buffer: Vec<f64>,
+    pos: usize,
+    store: f64,
+}
+
+#[derive(Clone, Debug, Default)]
+struct AllPass {
+    buffer: Vec<f64>,
+    pos: usize,
+}
+
+/// Comb and all-pass filters of one output channel.
+#[derive(Clone, Debug, Default)]
+struct Tank {
+    combs: Vec<Comb>,
+    allpasses: Vec<AllPass>,
+}
+
+/// Stereo algorithmic reverb. Mono buffers are processed through the left channel only, and
+/// channels past the first two are left untouched.
+#[derive(Clone, Debug)]
+pub struct Reverb {
+    room_size: f64,
+    damping: f64,
+    wet: f64,
+    dry: f64,
+    width: f64,
+    sample_rate: f64,
+    tanks: SmallVec<[Tank; 2]>,
+}
+
+impl Comb {
+    fn new(len: usize) -> Self {
+        Self {
+            buffer: vec![0.0; len.max(1)],
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, input: f64, feedback: f64, damping: f64) -> f64 {
+        let output = self.buffer[self.pos];
+        self.store = output * (1.0 - damping) + self.store * damping;
+        self.buffer[self.pos] = input + self.store * feedback;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        output
+    }
+
+    fn reset(&mut self) {
+        self.buffer.iter_mut().for_each(|s| *s = 0.0);
+        self.store = 0.0;
+    }
+}
+
+impl AllPass {
+    fn new(len: usize) -> Self {
+        Self {
+            buffer: vec![0.0; len.max(1)],
+            pos: 0,
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, input: f64) -> f64 {
+        let delayed = self.buffer[self.pos];
+        self.buffer[self.pos] = input + delayed * ALLPASS_FEEDBACK;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        delayed - input
+    }
+
+    fn reset(&mut self) {
+        self.buffer.iter_mut().for_each(|s| *s = 0.0);
+    }
+}
+
+impl Tank {
+    fn new(sample_rate: f64, spread: usize) -> Self {
+        let scale = |len: usize| ((len + spread) as f64 * sample_rate / 44100.0).round() as usize;
+        Self {
+            combs: COMB_TUNINGS.iter().map(|&l| Comb::new(scale(l))).collect(),
+            allpasses: ALLPASS_TUNINGS
+                .iter()
+                .map(|&l| AllPass::new(scale(l)))
+                .collect(),
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, input: f64, feedback: f64, damping: f64) -> f64 {
+        let mut output = self
+            .combs
+            .iter_mut()
+            .map(|comb| comb.process(input, feedback, damping))
+            .sum();
+        for allpass in &mut self.allpasses {
+            output = allpass.process(output);
+        }
+        output
+    }
+
+    fn reset(&mut self) {
+        self.combs.iter_mut().for_each(Comb::reset);
+        self.allpasses.iter_mut().for_each(AllPass::reset);
+    }
+}
+
+impl Reverb {
+    /// Create a new reverb with the given room size, from 0 to 1. Defaults to a 0.5 damping, a
+    /// fully dry signal with a third of wet signal mixed in, and full stereo width.
+    pub fn new(room_size: f64) -> Self {
+        Self {
+            room_size: room_size.clamp(0.0, 1.0),
+            damping: 0.5,
+            wet: 1.0 / 3.0,
+            dry: 1.0,
+            width: 1.0,
+            sample_rate: 0.0,
+            tanks: SmallVec::new(),
+        }
+    }
+
+    /// Returns the room size, from 0 to 1.
+    pub fn room_size(&self) -> f64 {
+        self.room_size
+    }
+
+    /// Sets the room size, from 0 to 1. Larger rooms have longer decay times.
+    pub fn set_room_size(&mut self, room_size: f64) {
+        self.room_size = room_size.clamp(0.0, 1.0);
+    }
+
+    /// Returns the high frequency damping, from 0 to 1.
+    pub fn damping(&self) -> f64 {
+        self.damping
+    }
+
+    /// Sets the high frequency damping, from 0 to 1.
+    pub fn set_damping(&mut self, damping: f64) {
+        self.damping = damping.clamp(0.0, 1.0);
+    }
+
+    /// Returns the level of the reverberated signal, as a linear gain factor.
+    pub fn wet(&self) -> f64 {
+        self.wet
+    }
+
+    /// Sets the level of the reverberated signal, as a linear gain factor.
+    pub fn set_wet(&mut self, wet: f64) {
+        self.wet = wet.max(0.0);
+    }
+
+    /// Returns the level of the original signal, as a linear gain factor.
+    pub fn dry(&self) -> f64 {
+        self.dry
+    }
+
+    /// Sets the level of the original signal, as a linear gain factor.
+    pub fn set_dry(&mut self, dry: f64) {
+        self.dry = dry.max(0.0);
+    }
+
+    /// Returns the stereo width of the reverberated signal, from 0 (mono) to 1.
+    pub fn width(&self) -> f64 {
+        self.width
+    }
+
+    /// Sets the stereo width of the reverberated signal, from 0 (mono) to 1.
+    pub fn set_width(&mut self, width: f64) {
+        self.width = width.clamp(0.0, 1.0);
+    }
+}
+
+impl Effect for Reverb {
+    fn prepare(&mut self, context: &AudioContext) {
+        self.sample_rate = context.sample_rate as f64;
+        self.tanks = SmallVec::new();
+        self.tanks.push(Tank::new(self.sample_rate, 0));
+        self.tanks.push(Tank::new(self.sample_rate, STEREO_SPREAD));
+    }
+
+    fn reset(&mut self) {
+        self.tanks.iter_mut().for_each(Tank::reset);
+    }
+
+    fn process(&mut self, context: &AudioContext, buffer: &mut AudioBuffer) {
+        if self.sample_rate != context.sample_rate as f64 || self.tanks.is_empty() {
+            self.prepare(context);
+        }
+        let feedback = self.room_size * SCALE_ROOM + OFFSET_ROOM;
+        let damping = self.damping * 0.4;
+        let wet1 = self.wet * (self.width / 2.0 + 0.5);
+        let wet2 = self.wet * (1.0 - self.width) / 2.0;
+
+        if buffer.channels() == 1 {
+            for sample in &mut buffer[0] {
+                let out = self.tanks[0].process(*sample * FIXED_GAIN, feedback, damping);
+                *sample = *sample * self.dry + out * self.wet;
+            }
+            return;
+        }
+
+        for i in 0..buffer.buffer_size() {
+            let (left, right) = (buffer[(0, i)], buffer[(1, i)]);
+            let input = (left + right) * FIXED_GAIN;
+            let out_left = self.tanks[0].process(input, feedback, damping);
+            let out_right = self.tanks[1].process(input, feedback, damping);
+            buffer[(0, i)] = left * self.dry + out_left * wet1 + out_right * wet2;
+            buffer[(1, i)] = right * self.dry + out_right * wet1 + out_left * wet2;
+        }
+    }
+}
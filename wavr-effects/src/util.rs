@@ -0,0 +1,189 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Small helpers shared by the effects.
+
+use std::collections::VecDeque;
+
+/// Returns the coefficient of a one-pole smoothing filter reaching ~63% of a step in `time_ms`.
+#[inline]
+pub(crate) fn time_coefficient(time_ms: f64, sample_rate: f64) -> f64 {
+    if time_ms <= 0.0 {
+        0.0
+    } else {
+        (-1.0 / (time_ms * 1e-3 * sample_rate)).exp()
+    }
+}
+
+/// Linear parameter ramp, used to change parameters without zipper noise.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct Smoothed {
+    current: f64,
+    target: f64,
+    step: f64,
+    ramp_samples: f64,
+}
+
+/// Duration of parameter ramps, in milliseconds.
+const RAMP_MS: f64 = 20.0;
+
+impl Smoothed {
+    pub(crate) fn new(value: f64) -> Self {
+        Self {
+            current: value,
+            target: value,
+            step: 0.0,
+            ramp_samples: RAMP_MS * 48.0,
+        }
+    }
+
+    /// Sets the sample rate used to compute the ramp duration.
+    pub(crate) fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.ramp_samples = (RAMP_MS * 1e-3 * sample_rate).max(1.0);
+    }
+
+    /// Sets the target value, reached after the ramp duration.
+    pub(crate) fn set(&mut self, value: f64) {
+        self.target = value;
+        self.step = (self.target - self.current) / self.ramp_samples;
+    }
+
+    /// Jumps to the target value.
+    pub(crate) fn reset(&mut self) {
+        self.current = self.target;
+        self.step = 0.0;
+    }
+
+    /// Returns the current value, without advancing the ramp.
+    pub(crate) fn current(&self) -> f64 {
+        self.current
+    }
+
+    /// Returns whether the value is still moving towards the target.
+    pub(crate) fn is_ramping(&self) -> bool {
+        self.current != self.target
+    }
+
+    /// Advances the ramp by one sample and returns the new value.
+    #[inline]
+    pub(crate) fn next(&mut self) -> f64 {
+        if self.current != self.target {
+            self.current += self.step;
+            if (self.step > 0.0 && self.current >= self.target)
+                || (self.step <= 0.0 && self.current <= self.target)
+            {
+                self.current = self.target;
+            }
+        }
+        self.current
+    }
+}
+
+/// Minimum of the last `window` values pushed, used to hold gains over a lookahead window.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct SlidingMinimum {
+    /// Candidates for the minimum, as `(index, value)` pairs with increasing values.
+    values: VecDeque<(usize, f64)>,
+    window: usize,
+    index: usize,
+}
+
+impl SlidingMinimum {
+    /// Creates a sliding minimum over the given number of values. The storage is allocated here,
+    /// so that pushing values never allocates.
+    pub(crate) fn new(window: usize) -> Self {
+        let window = window.max(1);
+        Self {
+            values: VecDeque::with_capacity(window + 1),
+            window,
+            index: 0,
+        }
+    }
+
+    /// Sets the number of values over which the minimum is taken, and forgets the pushed values.
+    /// The storage is only reallocated if the window is longer than the one given to `new`.
+    pub(crate) fn set_window(&mut self, window: usize) {
+        self.window = window.max(1);
+        self.reset();
+    }
+
+    /// Forgets the pushed values.
+    pub(crate) fn reset(&mut self) {
+        self.values.clear();
+        self.index = 0;
+    }
+
+    /// Pushes a value, and returns the minimum over the window.
+    #[inline]
+    pub(crate) fn push(&mut self, value: f64) -> f64 {
+        while let Some(&(_, back)) = self.values.back() {
+            if back >= value {
+                self.values.pop_back();
+            } else {
+                break;
+            }
+        }
+        self.values.push_back((self.index, value));
+        while let Some(&(index, _)) = self.values.front() {
+            if index + self.window <= self.index {
+                self.values.pop_front();
+            } else {
+                break;
+            }
+        }
+        self.index += 1;
+        self.values.front().map(|&(_, v)| v).unwrap_or(value)
+    }
+}
+
+/// Mean of the last `window` values pushed, used to ramp held gains over a lookahead window.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct MovingAverage {
+    values: Vec<f64>,
+    window: usize,
+    pos: usize,
+    sum: f64,
+}
+
+impl MovingAverage {
+    /// Creates a moving average over up to `max_window` values, all zero. The storage is
+    /// allocated here, so that changing the window never allocates.
+    pub(crate) fn new(max_window: usize) -> Self {
+        let max_window = max_window.max(1);
+        Self {
+            values: vec![0.0; max_window],
+            window: max_window,
+            pos: 0,
+            sum: 0.0,
+        }
+    }
+
+    /// Sets the number of values averaged, up to the one given to `new`, and resets them to zero.
+    pub(crate) fn set_window(&mut self, window: usize) {
+        self.window = window.clamp(1, self.values.len());
+        self.reset();
+    }
+
+    /// Resets the values to zero.
+    pub(crate) fn reset(&mut self) {
+        self.values.iter_mut().for_each(|v| *v = 0.0);
+        self.pos = 0;
+        self.sum = 0.0;
+    }
+
+    /// Pushes a value, and returns the mean over the window.
+    #[inline]
+    pub(crate) fn push(&mut self, value: f64) -> f64 {
+        self.sum += value - self.values[self.pos];
+        self.values[self.pos] = value;
+        self.pos += 1;
+        if self.pos == self.window {
+            // Recompute the sum once per cycle to avoid accumulating rounding errors
+            self.pos = 0;
+            self.sum = self.values[..self.window].iter().sum();
+        }
+        self.sum / self.window as f64
+    }
+}
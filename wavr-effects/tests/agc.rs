@@ -0,0 +1,169 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Drives the automatic gain control with sines at known loudness levels, checking the gating,
+//! freezing, rate limits and momentary headroom.
+
+use std::f64::consts::PI;
+
+use wavr_audio_buffer::AudioBuffer;
+use wavr_effects::AutomaticGainControl;
+use wavr_engine::{AudioContext, AudioContextState, Effect};
+
+const SAMPLE_RATE: u64 = 48000;
+/// 100 ms blocks.
+const BLOCK_SIZE: usize = 4800;
+const TOLERANCE: f64 = 1e-9;
+
+/// Mono 997 Hz sine generator, at a given loudness.
+struct Sine {
+    position: usize,
+}
+
+impl Sine {
+    /// Returns the next block of a sine at the given loudness. A full scale mono sine measures
+    /// -3.01 LUFS.
+    fn block(&mut self, lufs: f64) -> AudioBuffer {
+        let amplitude = 10f64.powf((lufs + 3.01) / 20.0);
+        let data: Vec<f64> = (self.position..self.position + BLOCK_SIZE)
+            .map(|i| amplitude * (2.0 * PI * 997.0 * i as f64 / SAMPLE_RATE as f64).sin())
+            .collect();
+        self.position += BLOCK_SIZE;
+        AudioBuffer::new(1, &data)
+    }
+}
+
+fn setup() -> (AutomaticGainControl, AudioContext, Sine) {
+    let mut context = AudioContext::new(SAMPLE_RATE, 1);
+    context.state = AudioContextState::Offline;
+    let mut agc = AutomaticGainControl::new(-16.0);
+    agc.prepare(&context);
+    (agc, context, Sine { position: 0 })
+}
+
+/// Runs blocks at the given loudness, returning the gain after each block.
+fn run(
+    agc: &mut AutomaticGainControl,
+    context: &AudioContext,
+    sine: &mut Sine,
+    lufs: f64,
+    blocks: usize,
+) -> Vec<f64> {
+    (0..blocks)
+        .map(|_| {
+            agc.process(context, &mut sine.block(lufs));
+            agc.gain_db()
+        })
+        .collect()
+}
+
+#[test]
+fn boost_is_rate_limited() {
+    let (mut agc, context, mut sine) = setup();
+    let gains = run(&mut agc, &context, &mut sine, -30.0, 100);
+    let mut previous = 0.0;
+    for &gain in &gains {
+        assert!(gain >= previous - TOLERANCE);
+        assert!(gain - previous <= agc.boost_rate_db() * 0.1 + TOLERANCE);
+        previous = gain;
+    }
+    // The desired gain of 14 dB is clamped to the gain range
+    assert_eq!(gains.last(), Some(&agc.max_gain_db()));
+
+    let gains = run(&mut agc, &context, &mut sine, -10.0, 100);
+    let mut previous = agc.max_gain_db();
+    for &gain in &gains {
+        assert!(gain <= previous + TOLERANCE);
+        assert!(previous - gain <= agc.cut_rate_db() * 0.1 + TOLERANCE);
+        previous = gain;
+    }
+    assert!((gains.last().unwrap() + 6.0).abs() < 0.1);
+}
+
+#[test]
+fn gate_and_freeze_hold_gain() {
+    let (mut agc, context, mut sine) = setup();
+    run(&mut agc, &context, &mut sine, -30.0, 10);
+    // Let the momentary loudness fall under the -50 LUFS gate
+    run(&mut agc, &context, &mut sine, -70.0, 5);
+    let held = agc.gain_db();
+    assert!(held > 0.0);
+
+    // Below the gate, and in silence, the gain is held
+    assert!(run(&mut agc, &context, &mut sine, -70.0, 50)
+        .iter()
+        .all(|&gain| gain == held));
+    let mut silence = AudioBuffer::zeroed(1, BLOCK_SIZE);
+    agc.process(&context, &mut silence);
+    assert_eq!(agc.gain_db(), held);
+
+    let freeze = agc.freeze_control();
+    freeze.set_frozen(true);
+    assert!(agc.is_frozen());
+    assert!(run(&mut agc, &context, &mut sine, -10.0, 50)
+        .iter()
+        .all(|&gain| gain == held));
+    freeze.set_frozen(false);
+    assert!(!agc.is_frozen());
+    assert!(run(&mut agc, &context, &mut sine, -10.0, 1)[0] < held);
+}
+
+#[test]
+fn headroom_pulls_gain_down() {
+    for &(headroom_db, pulled_down) in &[(0.0, true), (20.0, false)] {
+        let (mut agc, context, mut sine) = setup();
+        agc.set_headroom_db(headroom_db);
+        run(&mut agc, &context, &mut sine, -30.0, 100);
+        assert_eq!(agc.gain_db(), agc.max_gain_db());
+
+        // The short-term loudness still asks for the full boost half a second after the step, but
+        // the momentary loudness exceeds the target
+        let gains = run(&mut agc, &context, &mut sine, -26.0, 5);
+        let last = *gains.last().unwrap();
+        if pulled_down {
+            assert!(last < agc.max_gain_db() - 0.5, "gain at {} dB", last);
+        } else {
+            assert_eq!(last, agc.max_gain_db());
+        }
+    }
+}
+
+#[test]
+fn gain_is_applied_without_meters() {
+    let (mut agc, context, mut sine) = setup();
+    run(&mut agc, &context, &mut sine, -30.0, 20);
+    let held = agc.gain_db();
+
+    // The meters are prepared for one channel: the stereo block is not measured, but still gets
+    // the held gain
+    let mut buffer = AudioBuffer::new(2, &[0.5; 2 * BLOCK_SIZE]);
+    agc.process(&context, &mut buffer);
+    assert_eq!(agc.gain_db(), held);
+    let expected = 0.5 * 10f64.powf(held / 20.0);
+    assert!(buffer
+        .iter()
+        .flatten()
+        .all(|&s| (s - expected).abs() < TOLERANCE));
+}
+
+#[test]
+fn reset_clears_loudness_history() {
+    let (mut agc, context, mut sine) = setup();
+    let (mut control, _, _) = setup();
+    let loud = run(&mut agc, &context, &mut sine, -6.0, 30);
+    run(&mut control, &context, &mut Sine { position: 0 }, -6.0, 30);
+    let held = *loud.last().unwrap();
+    assert!((held + 10.0).abs() < 0.1, "gain at {} dB", held);
+
+    // The gain is kept, but the loud program is forgotten: a block at the target loudness is
+    // measured against the silence before it, and boosts the gain at the full rate
+    agc.reset();
+    assert_eq!(agc.gain_db(), held);
+    let position = sine.position;
+    let after_reset = run(&mut agc, &context, &mut sine, -16.0, 1)[0];
+    assert!((after_reset - held - agc.boost_rate_db() * 0.1).abs() < TOLERANCE);
+    let without_reset = run(&mut control, &context, &mut Sine { position }, -16.0, 1)[0];
+    assert!(without_reset < after_reset - 0.1, "gain at {} dB", without_reset);
+}
@@ -0,0 +1,147 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Checks the partitioned convolution against a direct time-domain convolution, and the swapping
+//! of impulse responses through a handle.
+
+use wavr_audio_buffer::AudioBuffer;
+use wavr_effects::{Convolution, ImpulseResponse, Partitioning};
+use wavr_engine::{AudioContext, AudioContextState, Effect};
+
+const SAMPLE_RATE: u64 = 48000;
+const TOLERANCE: f64 = 1e-9;
+
+/// Deterministic uniform noise in [-1, 1].
+fn noise(seed: u64, len: usize) -> Vec<f64> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            (state >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+        })
+        .collect()
+}
+
+fn context() -> AudioContext {
+    let mut context = AudioContext::new(SAMPLE_RATE, 1);
+    context.state = AudioContextState::Offline;
+    context
+}
+
+/// Processes the mono signal in blocks of the given size.
+fn process(convolution: &mut Convolution, signal: &[f64], block_size: usize) -> Vec<f64> {
+    let context = context();
+    let mut output = Vec::with_capacity(signal.len());
+    for block in signal.chunks(block_size) {
+        let mut buffer = AudioBuffer::new(1, block);
+        convolution.process(&context, &mut buffer);
+        output.extend_from_slice(&buffer[0]);
+    }
+    output
+}
+
+/// Direct convolution, delayed by the latency.
+fn convolve(signal: &[f64], ir: &[f64], latency: usize) -> Vec<f64> {
+    (0..signal.len())
+        .map(|n| {
+            (0..ir.len())
+                .take_while(|&k| k + latency <= n)
+                .map(|k| ir[k] * signal[n - latency - k])
+                .sum()
+        })
+        .collect()
+}
+
+fn assert_close(actual: &[f64], expected: &[f64]) {
+    for (i, (a, e)) in actual.iter().zip(expected).enumerate() {
+        assert!((a - e).abs() <= TOLERANCE, "{} != {} at {}", a, e, i);
+    }
+}
+
+#[test]
+fn matches_direct_convolution() {
+    let partitionings = [
+        Partitioning::Uniform { block_size: 64 },
+        Partitioning::Uniform { block_size: 256 },
+        Partitioning::NonUniform {
+            block_size: 32,
+            max_block_size: 512,
+        },
+    ];
+    let signal = noise(0x5eed, 8000);
+    for &partitioning in &partitionings {
+        for &length in &[1, 100, 1000, 3000] {
+            let ir = noise(length as u64, length);
+            let mut convolution = Convolution::with_partitioning(
+                ImpulseResponse::new(SAMPLE_RATE as u32, vec![ir.clone()]).unwrap(),
+                partitioning,
+            );
+            convolution.prepare(&context());
+            let expected = convolve(&signal, &ir, partitioning.latency());
+            for &block_size in &[1, 100, 480] {
+                convolution.reset();
+                let output = process(&mut convolution, &signal, block_size);
+                assert_close(&output, &expected);
+            }
+        }
+    }
+}
+
+#[test]
+fn passes_through_until_prepared() {
+    let ir = ImpulseResponse::new(SAMPLE_RATE as u32, vec![vec![0.5]]).unwrap();
+    let mut convolution = Convolution::new(ir);
+    let signal = noise(1, 1000);
+    assert_eq!(process(&mut convolution, &signal, 100), signal);
+}
+
+#[test]
+fn swaps_impulse_response() {
+    let partitioning = Partitioning::Uniform { block_size: 64 };
+    let latency = partitioning.latency();
+    let identity = ImpulseResponse::new(SAMPLE_RATE as u32, vec![vec![1.0]]).unwrap();
+    let echo = ImpulseResponse::new(SAMPLE_RATE as u32, vec![vec![0.0, 0.0, 0.5]]).unwrap();
+    let mut convolution = Convolution::with_partitioning(identity, partitioning);
+    let handle = convolution.handle();
+
+    // Loaded before the effect is prepared: applied when preparing, without crossfade
+    handle.load(echo.clone());
+    convolution.prepare(&context());
+    let signal = noise(2, 4800);
+    let output = process(&mut convolution, &signal, 256);
+    assert_eq!(convolution.impulse_response(), &echo);
+    assert_close(&output, &convolve(&signal, echo.channel(0), latency));
+
+    // Loaded while running: crossfaded over 50 ms from the start of the next block
+    let identity = ImpulseResponse::new(SAMPLE_RATE as u32, vec![vec![1.0]]).unwrap();
+    handle.load(identity.clone());
+    let signal = noise(3, 9600);
+    let output = process(&mut convolution, &signal, 256);
+    assert_eq!(convolution.impulse_response(), &identity);
+    let fade = 2400usize;
+    let from = convolve(&signal, echo.channel(0), latency);
+    let to = convolve(&signal, identity.channel(0), latency);
+    // The echo kernel still holds the tail of the previous signal for a few samples
+    for i in latency + 2..output.len() {
+        let previous = fade.saturating_sub(i) as f64 / fade as f64;
+        let expected = from[i] * previous + to[i] * (1.0 - previous);
+        assert!(
+            (output[i] - expected).abs() <= TOLERANCE,
+            "{} != {} at {}",
+            output[i],
+            expected,
+            i
+        );
+    }
+
+    // Only the latest of several loads is applied
+    for gain in &[0.1, 0.2, 0.3] {
+        handle.load(ImpulseResponse::new(SAMPLE_RATE as u32, vec![vec![*gain]]).unwrap());
+    }
+    process(&mut convolution, &noise(4, 256), 256);
+    assert_eq!(convolution.impulse_response().channel(0), &[0.3]);
+}
@@ -0,0 +1,179 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Checks the static curves of the dynamics processors, and their output levels once the
+//! ballistics have settled.
+
+use wavr_audio_buffer::AudioBuffer;
+use wavr_effects::*;
+use wavr_engine::{AudioContext, AudioContextState, Effect};
+
+const SAMPLE_RATE: u64 = 48000;
+const TOLERANCE: f64 = 1e-9;
+
+fn context(channels: u8) -> AudioContext {
+    let mut context = AudioContext::new(SAMPLE_RATE, channels);
+    context.state = AudioContextState::Offline;
+    context
+}
+
+fn assert_close(lhs: f64, rhs: f64, tolerance: f64) {
+    assert!((lhs - rhs).abs() <= tolerance, "{} != {}", lhs, rhs);
+}
+
+fn to_db(amplitude: f64) -> f64 {
+    20.0 * amplitude.abs().log10()
+}
+
+#[test]
+fn compressor_static_curve() {
+    let mut compressor = Compressor::new(-20.0, 4.0);
+    compressor.set_knee_db(6.0);
+    // Unity below the knee, the ratio above it
+    for &input in &[-60.0, -30.0, -23.0] {
+        assert_close(compressor.curve(input), input, TOLERANCE);
+    }
+    for &input in &[-17.0, -10.0, 0.0, 12.0] {
+        assert_close(
+            compressor.curve(input),
+            -20.0 + (input + 20.0) / 4.0,
+            TOLERANCE,
+        );
+    }
+    // Quadratic knee, 0.75 * 3² / 12 dB under the threshold at its center
+    assert_close(compressor.curve(-20.0), -20.5625, TOLERANCE);
+    let mut previous = compressor.curve(-23.0);
+    for i in 1..=60 {
+        let input = -23.0 + i as f64 * 0.1;
+        let output = compressor.curve(input);
+        assert!(output > previous && output <= input);
+        assert!(output - previous <= 0.1 + TOLERANCE);
+        previous = output;
+    }
+
+    compressor.set_knee_db(0.0);
+    assert_close(compressor.curve(-20.0), -20.0, TOLERANCE);
+    assert_close(compressor.curve(-20.0 + 1e-6), -20.0 + 0.25e-6, TOLERANCE);
+}
+
+#[test]
+fn compressor_settles_on_static_curve() {
+    let context = context(2);
+    for &level in &[-30.0, -20.0, -18.0, -8.0, 0.0] {
+        let mut compressor = Compressor::new(-20.0, 4.0);
+        compressor.prepare(&context);
+        let amplitude = 10f64.powf(level / 20.0);
+        let mut buffer = AudioBuffer::new(2, &vec![amplitude; 2 * SAMPLE_RATE as usize]);
+        compressor.process(&context, &mut buffer);
+        let output = to_db(buffer[0][buffer.buffer_size() - 1]);
+        assert_close(output, compressor.curve(level), 1e-6);
+        assert_close(
+            compressor.core().gain_reduction_db(),
+            level - compressor.curve(level),
+            1e-6,
+        );
+    }
+}
+
+/// Short bursts and single-sample spikes over a quiet noise floor, with a deterministic noise.
+fn transients(len: usize) -> Vec<f64> {
+    let mut state = 0x5eedu64;
+    (0..len)
+        .map(|i| {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            let noise = (state >> 11) as f64 / (1u64 << 52) as f64 - 1.0;
+            match i % 4800 {
+                1000 => 1.0,
+                2000..=2047 => 0.9 * noise.signum(),
+                3000..=3200 => noise,
+                _ => 0.05 * noise,
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn lookahead_limiter_stays_under_ceiling() {
+    let context = context(1);
+    let input = transients(SAMPLE_RATE as usize);
+    for &lookahead_ms in &[0.0, 1.0, 5.0] {
+        let mut limiter = Limiter::new(-6.0);
+        limiter.core_mut().set_lookahead_ms(lookahead_ms);
+        limiter.prepare(&context);
+        let mut buffer = AudioBuffer::new(1, &input);
+        limiter.process(&context, &mut buffer);
+
+        assert_eq!(limiter.latency(), (lookahead_ms * 48.0) as usize);
+        let ceiling = 10f64.powf(-6.0 / 20.0);
+        for (i, &sample) in buffer[0].iter().enumerate() {
+            assert!(
+                sample.abs() <= ceiling + TOLERANCE,
+                "{} over the ceiling at {} with {} ms lookahead",
+                sample,
+                i,
+                lookahead_ms
+            );
+        }
+    }
+}
+
+#[test]
+fn lookahead_compressor_stays_under_curve() {
+    let context = context(1);
+    let input = transients(SAMPLE_RATE as usize);
+    let mut compressor = Compressor::new(-20.0, 8.0);
+    compressor.core_mut().set_attack_ms(0.0);
+    compressor.core_mut().set_lookahead_ms(2.0);
+    compressor.prepare(&context);
+    let mut buffer = AudioBuffer::new(1, &input);
+    compressor.process(&context, &mut buffer);
+
+    let latency = compressor.latency();
+    assert_eq!(latency, 96);
+    for (i, &sample) in buffer[0].iter().enumerate().skip(latency) {
+        let level = to_db(input[i - latency]);
+        assert!(
+            to_db(sample) <= compressor.curve(level) + 1e-6,
+            "{} dB over the curve at {}",
+            to_db(sample) - compressor.curve(level),
+            i
+        );
+    }
+}
+
+#[test]
+fn lookahead_limiter_ramps_gain() {
+    let context = context(1);
+    let mut limiter = Limiter::new(-6.0);
+    limiter.prepare(&context);
+    // Changing the lookahead after preparing takes effect on the next block
+    limiter.core_mut().set_lookahead_ms(1.0);
+    limiter.core_mut().set_lookahead_ms(1000.0);
+    assert_eq!(limiter.core().lookahead_ms(), MAX_LOOKAHEAD_MS);
+    limiter.core_mut().set_lookahead_ms(1.0);
+
+    // A single full-scale spike over a constant level under the ceiling
+    let mut input = vec![0.1; 4800];
+    input[1000] = 1.0;
+    let mut buffer = AudioBuffer::new(1, &input);
+    limiter.process(&context, &mut buffer);
+    let latency = limiter.latency();
+    assert_eq!(latency, 48);
+    assert_close(buffer[(0, 1000 + latency)], 10f64.powf(-6.0 / 20.0), 1e-9);
+
+    // The 6 dB reduction is spread over the 49 samples of the window, on both sides of the spike
+    let gains: Vec<f64> = (latency..input.len())
+        .filter(|&i| i - latency != 1000)
+        .map(|i| to_db(buffer[(0, i)] / input[i - latency]))
+        .collect();
+    let steepest = gains
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).abs())
+        .fold(0.0, f64::max);
+    assert!(steepest < 6.0 / 49.0 + 1e-6, "{} dB step", steepest);
+    assert!(gains.iter().cloned().fold(0.0, f64::min) < -5.0);
+}
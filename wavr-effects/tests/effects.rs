@@ -0,0 +1,171 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Checks the basic effects against their reference values: biquad responses from the RBJ audio
+//! EQ cookbook, gain and pan laws, delay taps, reverb reset and polarity inversion.
+
+use std::f64::consts::{FRAC_1_SQRT_2, PI};
+
+use wavr_audio_buffer::AudioBuffer;
+use wavr_effects::*;
+use wavr_engine::{AudioContext, AudioContextState, Effect};
+
+const SAMPLE_RATE: u64 = 48000;
+const TOLERANCE: f64 = 1e-9;
+
+fn context(channels: u8) -> AudioContext {
+    let mut context = AudioContext::new(SAMPLE_RATE, channels);
+    context.state = AudioContextState::Offline;
+    context
+}
+
+fn assert_close(lhs: f64, rhs: f64, tolerance: f64) {
+    assert!((lhs - rhs).abs() <= tolerance, "{} != {}", lhs, rhs);
+}
+
+#[test]
+fn biquad_response_at_cutoff() {
+    let sr = SAMPLE_RATE as f64;
+    for &fc in &[100.0, 1000.0, 10000.0] {
+        for &q in &[0.5, FRAC_1_SQRT_2, 4.0] {
+            let gain_db = 9.0;
+            let a = 10f64.powf(gain_db / 40.0);
+            let magnitude = |kind| {
+                BiquadCoefficients::new(kind, sr, fc, q, gain_db)
+                    .response(fc, sr)
+                    .0
+            };
+            // Low- and high-pass filters have a gain of Q at the cutoff frequency
+            assert_close(magnitude(BiquadType::LowPass), q, TOLERANCE);
+            assert_close(magnitude(BiquadType::HighPass), q, TOLERANCE);
+            assert_close(magnitude(BiquadType::BandPass), 1.0, TOLERANCE);
+            assert_close(magnitude(BiquadType::Notch), 0.0, TOLERANCE);
+            assert_close(magnitude(BiquadType::AllPass), 1.0, TOLERANCE);
+            // Peak filters reach the full gain at the center, shelves half of it (in decibels)
+            assert_close(magnitude(BiquadType::Peak), a * a, TOLERANCE);
+            assert_close(magnitude(BiquadType::LowShelf), a, TOLERANCE);
+            assert_close(magnitude(BiquadType::HighShelf), a, TOLERANCE);
+        }
+    }
+}
+
+#[test]
+fn biquad_filters_sine() {
+    let (fc, q) = (1000.0, 2.0);
+    let mut filter = BiquadFilter::new(BiquadType::LowPass, fc, q);
+    let context = context(1);
+    filter.prepare(&context);
+
+    let len = SAMPLE_RATE as usize;
+    let sine: Vec<f64> = (0..len)
+        .map(|i| (2.0 * PI * fc * i as f64 / SAMPLE_RATE as f64).sin())
+        .collect();
+    let mut buffer = AudioBuffer::new(1, &sine);
+    filter.process(&context, &mut buffer);
+    let peak = buffer[0][len / 2..]
+        .iter()
+        .fold(0.0f64, |acc, s| acc.max(s.abs()));
+    assert_close(peak, q, 1e-3);
+}
+
+#[test]
+fn gain_and_pan_laws() {
+    let context = context(2);
+    let mut gain = Gain::new(-6.0);
+    gain.prepare(&context);
+    let mut buffer = AudioBuffer::new(2, &[1.0; 64]);
+    gain.process(&context, &mut buffer);
+    let expected = 10f64.powf(-6.0 / 20.0);
+    assert!(buffer
+        .iter()
+        .flatten()
+        .all(|&s| (s - expected).abs() < TOLERANCE));
+
+    let center = |law| Pan::gains(law, 0.0);
+    assert_close(center(PanLaw::ConstantPower).0, FRAC_1_SQRT_2, TOLERANCE);
+    assert_close(center(PanLaw::ConstantPower).1, FRAC_1_SQRT_2, TOLERANCE);
+    assert_eq!(center(PanLaw::Linear), (0.5, 0.5));
+    assert_eq!(center(PanLaw::Balance), (1.0, 1.0));
+    for &law in &[PanLaw::ConstantPower, PanLaw::Linear, PanLaw::Balance] {
+        let (left, right) = Pan::gains(law, -1.0);
+        assert_close(left, 1.0, TOLERANCE);
+        assert_close(right, 0.0, TOLERANCE);
+        let (left, right) = Pan::gains(law, 1.0);
+        assert_close(left, 0.0, TOLERANCE);
+        assert_close(right, 1.0, TOLERANCE);
+    }
+    for i in 0..=20 {
+        let (left, right) = Pan::gains(PanLaw::ConstantPower, i as f64 / 10.0 - 1.0);
+        assert_close(left * left + right * right, 1.0, TOLERANCE);
+    }
+
+    let mut pan = Pan::new(0.5, PanLaw::Linear);
+    pan.prepare(&context);
+    let mut buffer = AudioBuffer::new(2, &[1.0; 64]);
+    pan.process(&context, &mut buffer);
+    assert!(buffer[0].iter().all(|&s| (s - 0.25).abs() < TOLERANCE));
+    assert!(buffer[1].iter().all(|&s| (s - 0.75).abs() < TOLERANCE));
+}
+
+#[test]
+fn delay_impulse_and_feedback() {
+    let context = context(1);
+    let mut delay = Delay::new(10.0);
+    delay.set_feedback(0.5);
+    delay.set_mix(1.0);
+    delay.prepare(&context);
+
+    let delay_samples = 480;
+    let mut impulse = vec![0.0; 4 * delay_samples + 1];
+    impulse[0] = 1.0;
+    let mut buffer = AudioBuffer::new(1, &impulse);
+    delay.process(&context, &mut buffer);
+    for (i, &sample) in buffer[0].iter().enumerate() {
+        let expected = if i > 0 && i % delay_samples == 0 {
+            0.5f64.powi(i as i32 / delay_samples as i32 - 1)
+        } else {
+            0.0
+        };
+        assert_close(sample, expected, TOLERANCE);
+    }
+}
+
+#[test]
+fn reverb_reset_is_silent() {
+    let context = context(2);
+    let mut reverb = Reverb::new(0.9);
+    reverb.prepare(&context);
+    let noise: Vec<f64> = (0..2 * 4800)
+        .map(|i| ((i * 7919) % 1000) as f64 / 500.0 - 1.0)
+        .collect();
+    let mut buffer = AudioBuffer::new(2, &noise);
+    reverb.process(&context, &mut buffer);
+
+    let mut tail = AudioBuffer::zeroed(2, 4800);
+    reverb.process(&context, &mut tail);
+    assert!(tail.iter().flatten().any(|&s| s != 0.0));
+
+    reverb.reset();
+    let mut silence = AudioBuffer::zeroed(2, 4800);
+    reverb.process(&context, &mut silence);
+    assert!(silence.iter().flatten().all(|&s| s == 0.0));
+}
+
+#[test]
+fn polarity_all_keeps_other_channels_inverted() {
+    let context = context(4);
+    let mut polarity = Polarity::all();
+    polarity.set_inverted(0, false);
+    assert!(!polarity.is_inverted(0));
+    assert!((1..8).all(|ch| polarity.is_inverted(ch)));
+
+    let mut buffer = AudioBuffer::new(4, &[1.0; 16]);
+    polarity.process(&context, &mut buffer);
+    assert!(buffer[0].iter().all(|&s| s == 1.0));
+    assert!((1..4).all(|ch| buffer[ch].iter().all(|&s| s == -1.0)));
+
+    let polarity = Polarity::channels(&[2]);
+    assert!((0..8).all(|ch| polarity.is_inverted(ch) == (ch == 2)));
+}
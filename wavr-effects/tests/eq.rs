@@ -0,0 +1,132 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Checks the equalizer frequency response against its processed output, and that parameter
+//! changes and enabling or disabling a band are ramped rather than stepped.
+
+use std::f64::consts::PI;
+
+use wavr_audio_buffer::AudioBuffer;
+use wavr_effects::*;
+use wavr_engine::{AudioContext, AudioContextState, Effect};
+
+const SAMPLE_RATE: u64 = 48000;
+/// One period of the 1 kHz test sine.
+const PERIOD: usize = 48;
+const TOLERANCE: f64 = 1e-9;
+
+fn context() -> AudioContext {
+    let mut context = AudioContext::new(SAMPLE_RATE, 1);
+    context.state = AudioContextState::Offline;
+    context
+}
+
+fn assert_close(lhs: f64, rhs: f64, tolerance: f64) {
+    assert!((lhs - rhs).abs() <= tolerance, "{} != {}", lhs, rhs);
+}
+
+/// Mono 1 kHz sine at half scale, starting at the given sample.
+fn sine(start: usize, len: usize) -> Vec<f64> {
+    (start..start + len)
+        .map(|n| 0.5 * (2.0 * PI * 1000.0 * n as f64 / SAMPLE_RATE as f64).sin())
+        .collect()
+}
+
+/// Equalizer with a single peaking band at 1 kHz, prepared and fed half a second of the sine.
+fn settled_eq(gain_db: f64, enabled: bool) -> ParametricEq {
+    let band = EqBand {
+        enabled,
+        ..EqBand::new(BiquadType::Peak, 1000.0, 1.0, gain_db)
+    };
+    let mut eq = ParametricEq::new().with_band(band);
+    eq.prepare(&context());
+    let mut buffer = AudioBuffer::new(1, &sine(0, SAMPLE_RATE as usize / 2));
+    eq.process(&context(), &mut buffer);
+    eq
+}
+
+/// Processes the following periods of the sine one by one, returning the output.
+fn process_periods(eq: &mut ParametricEq, periods: usize) -> Vec<f64> {
+    let start = SAMPLE_RATE as usize / 2;
+    let mut output = vec![];
+    for period in 0..periods {
+        let mut buffer = AudioBuffer::new(1, &sine(start + period * PERIOD, PERIOD));
+        eq.process(&context(), &mut buffer);
+        output.extend_from_slice(&buffer[0]);
+    }
+    output
+}
+
+/// Returns the peak amplitude of each period.
+fn peaks(output: &[f64]) -> Vec<f64> {
+    output
+        .chunks(PERIOD)
+        .map(|period| period.iter().fold(0.0, |max, s| s.abs().max(max)))
+        .collect()
+}
+
+#[test]
+fn peaking_band_response() {
+    let mut eq = settled_eq(6.0, true);
+    assert_close(eq.magnitude_db(1000.0), 6.0, 1e-6);
+    assert_close(eq.magnitude_db(20.0), 0.0, 0.05);
+    assert_close(eq.magnitude_db(20000.0), 0.0, 0.1);
+    assert!(eq.magnitude_db(500.0) > 0.0 && eq.magnitude_db(500.0) < 6.0);
+    assert_eq!(eq.response_curve(&[20.0, 1000.0]).len(), 2);
+
+    // The processed sine follows the response
+    let peak = peaks(&process_periods(&mut eq, 10))[9];
+    assert_close(20.0 * (peak / 0.5).log10(), 6.0, 0.05);
+
+    // Disabled bands are left out of the response
+    eq.set_band_enabled(0, false);
+    assert_close(eq.magnitude_db(1000.0), 0.0, TOLERANCE);
+    let mut eq = ParametricEq::new();
+    assert_close(eq.magnitude_db(1000.0), 0.0, TOLERANCE);
+    eq.add_band(EqBand::new(BiquadType::Peak, 1000.0, 1.0, 6.0));
+    eq.add_band(EqBand::new(BiquadType::Peak, 1000.0, 1.0, -6.0));
+    assert_close(eq.magnitude_db(1000.0), 0.0, 1e-6);
+}
+
+#[test]
+fn parameter_changes_ramp() {
+    let mut eq = settled_eq(0.0, true);
+    eq.set_band(0, EqBand::new(BiquadType::Peak, 1000.0, 1.0, 12.0));
+    // The 20 ms ramp spreads the 4x gain change over 20 periods
+    let peaks = peaks(&process_periods(&mut eq, 40));
+    assert!(peaks[0] < 0.5 * 1.3, "first period at {}", peaks[0]);
+    for pair in peaks.windows(2) {
+        assert!(pair[1] >= pair[0] - 1e-3, "{:?}", pair);
+        assert!(pair[1] - pair[0] < 0.25, "{:?}", pair);
+    }
+    assert_close(peaks[39], 0.5 * 10f64.powf(12.0 / 20.0), 0.02);
+}
+
+#[test]
+fn enabling_crossfades() {
+    let mut eq = settled_eq(6.0, false);
+    // The sine repeats every period, so each call of `process_periods` is fed the same signal
+    let dry = sine(SAMPLE_RATE as usize / 2, 40 * PERIOD);
+
+    eq.set_band_enabled(0, true);
+    let output = process_periods(&mut eq, 40);
+    // The first period is barely mixed in, the last one is fully filtered
+    for (out, dry) in output.iter().zip(&dry).take(PERIOD) {
+        assert!((out - dry).abs() < 0.05, "{} != {}", out, dry);
+    }
+    assert_close(peaks(&output)[39], 0.5 * 10f64.powf(6.0 / 20.0), 0.01);
+
+    // Disabling fades back to the unprocessed signal
+    eq.set_band_enabled(0, false);
+    let output = process_periods(&mut eq, 40);
+    let peaks = peaks(&output);
+    assert!(peaks[0] > 0.9, "first period at {}", peaks[0]);
+    for pair in peaks.windows(2) {
+        assert!(pair[1] <= pair[0] + 1e-3, "{:?}", pair);
+    }
+    for (out, dry) in output.iter().zip(&dry).skip(30 * PERIOD) {
+        assert_eq!(out, dry);
+    }
+}
@@ -0,0 +1,251 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Checks the generators: harmonics and aliasing of the band-limited oscillators, the noise
+//! spectra and levels, sweep frequencies, alignment tone levels and idents, and impulse periods.
+
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use wavr_audio_buffer::AudioBuffer;
+use wavr_effects::*;
+use wavr_engine::{AudioContext, AudioContextState, Effect};
+
+const SAMPLE_RATE: u64 = 48000;
+
+fn assert_close(lhs: f64, rhs: f64, tolerance: f64) {
+    assert!((lhs - rhs).abs() <= tolerance, "{} != {}", lhs, rhs);
+}
+
+fn to_db(amplitude: f64) -> f64 {
+    20.0 * amplitude.abs().log10()
+}
+
+/// Renders a second of the first channel of the generator.
+fn render(generator: &mut impl Generator) -> Vec<f64> {
+    generator.render(SAMPLE_RATE, 1, SAMPLE_RATE as usize)[0].to_vec()
+}
+
+fn peak(samples: &[f64]) -> f64 {
+    samples.iter().fold(0.0, |max, s| s.abs().max(max))
+}
+
+fn rms(samples: &[f64]) -> f64 {
+    (samples.iter().map(|s| s * s).sum::<f64>() / samples.len() as f64).sqrt()
+}
+
+/// Returns the amplitude of each 1 Hz bin of a second of signal, for a sine of amplitude 1
+/// reading 1.
+fn spectrum(samples: &[f64]) -> Vec<f64> {
+    let mut data: Vec<Complex<f64>> = samples.iter().map(|&s| Complex::new(s, 0.0)).collect();
+    FftPlanner::new()
+        .plan_fft_forward(data.len())
+        .process(&mut data);
+    let scale = 2.0 / data.len() as f64;
+    data[..data.len() / 2]
+        .iter()
+        .map(|c| c.norm() * scale)
+        .collect()
+}
+
+/// Number of sign changes of the signal.
+fn zero_crossings(samples: &[f64]) -> usize {
+    samples
+        .windows(2)
+        .filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0))
+        .count()
+}
+
+#[test]
+fn oscillator_harmonics() {
+    // 1.25 kHz fits a whole number of periods in a second, putting the harmonics on the bins
+    let fundamental = 1250;
+    let harmonic_amplitude = |waveform: Waveform, k: usize| {
+        let k = k as f64;
+        match waveform {
+            Waveform::Sine if k == 1.0 => 1.0,
+            Waveform::Saw => 2.0 / (std::f64::consts::PI * k),
+            Waveform::Square if k % 2.0 == 1.0 => 4.0 / (std::f64::consts::PI * k),
+            Waveform::Triangle if k % 2.0 == 1.0 => 8.0 / (std::f64::consts::PI * k).powi(2),
+            _ => 0.0,
+        }
+    };
+    for &waveform in &[
+        Waveform::Sine,
+        Waveform::Saw,
+        Waveform::Square,
+        Waveform::Triangle,
+    ] {
+        let mut oscillator = Oscillator::new(waveform, fundamental as f64, -6.0);
+        let samples = render(&mut oscillator);
+        let amplitude = 10f64.powf(-6.0 / 20.0);
+        assert!(peak(&samples) < 1.1 * amplitude, "{:?}", waveform);
+
+        let spectrum = spectrum(&samples);
+        // The low harmonics are left untouched by the band-limiting corrections
+        for k in 1..=3 {
+            assert_close(
+                spectrum[k * fundamental] / amplitude,
+                harmonic_amplitude(waveform, k),
+                0.01,
+            );
+        }
+        // Harmonics above the Nyquist frequency fold back between the harmonics. A naive
+        // sawtooth has aliases about 30 dB under the fundamental below 6 kHz; the corrected waveforms
+        // keep them 60 dB under it
+        let aliasing = spectrum[..6000]
+            .iter()
+            .enumerate()
+            .filter(|&(bin, _)| bin % fundamental != 0)
+            .fold(0.0, |max: f64, (_, &a)| max.max(a));
+        assert!(
+            to_db(aliasing / spectrum[fundamental]) < -60.0,
+            "{:?} aliasing at {} dB",
+            waveform,
+            to_db(aliasing / spectrum[fundamental])
+        );
+    }
+}
+
+#[test]
+fn noise_spectra_and_levels() {
+    let octave_powers = |color: NoiseColor| {
+        let mut noise = Noise::new(color, -20.0);
+        let samples = noise.render(SAMPLE_RATE, 1, 4 * SAMPLE_RATE as usize)[0].to_vec();
+        assert_close(to_db(rms(&samples)), -20.0, 0.5);
+        let spectrum = spectrum(&samples);
+        // Bins are 0.25 Hz wide over four seconds
+        (0..5)
+            .map(|octave| {
+                let start = 4 * (400 << octave);
+                let power: f64 = spectrum[start..2 * start].iter().map(|a| a * a).sum();
+                10.0 * power.log10()
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // Pink noise has the same power in each octave, white noise twice as much in each octave as
+    // in the one below, and brown noise half as much
+    for &(color, slope) in &[
+        (NoiseColor::Pink, 0.0),
+        (NoiseColor::White, 3.01),
+        (NoiseColor::Brown, -3.01),
+    ] {
+        let powers = octave_powers(color);
+        for (octave, pair) in powers.windows(2).enumerate() {
+            assert!(
+                (pair[1] - pair[0] - slope).abs() < 1.0,
+                "{:?} octave {}: {:?}",
+                color,
+                octave,
+                powers
+            );
+        }
+    }
+
+    // Channels are independent, and the sequences restart from the seed
+    let mut noise = Noise::new(NoiseColor::White, -20.0);
+    let first = noise.render(SAMPLE_RATE, 2, 1000);
+    assert_ne!(first[0], first[1]);
+    noise.restart();
+    assert_eq!(noise.render(SAMPLE_RATE, 2, 1000)[1], first[1]);
+    noise.set_seed(1);
+    assert_ne!(noise.render(SAMPLE_RATE, 2, 1000)[0], first[0]);
+}
+
+#[test]
+fn sweep_frequencies() {
+    let mut sweep = Sweep::new(SweepKind::Linear, 1000.0, 2000.0, 1.0, -6.0);
+    assert_close(sweep.frequency_at(0.5), 1500.0, 1e-9);
+    assert_close(sweep.frequency_at(2.0), 2000.0, 1e-9);
+    // 1500 cycles in the second, crossing zero twice each
+    let samples = render(&mut sweep);
+    assert!((zero_crossings(&samples) as f64 - 3000.0).abs() <= 2.0);
+    assert_close(peak(&samples), 10f64.powf(-6.0 / 20.0), 1e-3);
+
+    let mut sweep = Sweep::new(SweepKind::Logarithmic, 100.0, 1600.0, 1.0, -6.0);
+    assert_close(sweep.frequency_at(0.5), 400.0, 1e-9);
+    assert_close(sweep.frequency_at(0.25), 200.0, 1e-9);
+    // (1600 - 100) / ln(16) cycles in the second
+    let samples = render(&mut sweep);
+    let cycles = 1500.0 / 16f64.ln();
+    assert!((zero_crossings(&samples) as f64 - 2.0 * cycles).abs() <= 2.0);
+
+    // Silent after the sweep, unless repeating
+    assert!(render(&mut sweep).iter().all(|&s| s == 0.0));
+    sweep.restart();
+    sweep.set_repeat(true);
+    let first = render(&mut sweep);
+    assert_eq!(render(&mut sweep), first);
+}
+
+#[test]
+fn alignment_tones() {
+    for (tone, level) in &mut [
+        (TestTone::ebu_r68(), -18.0),
+        (TestTone::smpte_rp155(), -20.0),
+    ] {
+        let samples = render(tone);
+        assert_close(to_db(peak(&samples)), *level, 1e-3);
+        assert_close(to_db(rms(&samples)), *level - 3.0103, 1e-3);
+        assert_eq!(zero_crossings(&samples), 2000 - 1);
+    }
+
+    let silent = |buffer: &AudioBuffer, channel: usize, start_s: f64| {
+        let start = (start_s * SAMPLE_RATE as f64) as usize;
+        buffer[channel][start + 10..start + 1000]
+            .iter()
+            .all(|&s| s == 0.0)
+    };
+    let ident = TestTone::ebu_stereo_ident().render(SAMPLE_RATE, 2, 7 * SAMPLE_RATE as usize);
+    for &time in &[0.0, 3.0, 6.0] {
+        assert!(silent(&ident, 0, time));
+        assert!(!silent(&ident, 1, time));
+    }
+    assert!(!silent(&ident, 0, 1.5));
+
+    let glits = TestTone::glits().render(SAMPLE_RATE, 2, 5 * SAMPLE_RATE as usize);
+    for &(channel, time) in &[(0, 0.0), (1, 0.5), (1, 1.0), (0, 4.0), (1, 4.5)] {
+        assert!(silent(&glits, channel, time));
+        assert!(!silent(&glits, 1 - channel, time));
+    }
+    assert!(!silent(&glits, 0, 2.0) && !silent(&glits, 1, 2.0));
+}
+
+#[test]
+fn impulse_period() {
+    let positions = |impulse: &mut Impulse| {
+        render(impulse)
+            .iter()
+            .enumerate()
+            .filter(|&(_, &s)| s != 0.0)
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>()
+    };
+
+    let mut single = Impulse::new(-6.0);
+    assert_eq!(positions(&mut single), vec![0]);
+    assert!(positions(&mut single).is_empty());
+    single.restart();
+    assert_eq!(positions(&mut single), vec![0]);
+
+    let mut periodic = Impulse::periodic(0.25, -6.0);
+    assert_eq!(positions(&mut periodic), vec![0, 12000, 24000, 36000]);
+    assert_eq!(positions(&mut periodic), vec![0, 12000, 24000, 36000]);
+    periodic.restart();
+    let samples = render(&mut periodic);
+    assert_close(samples[0], 10f64.powf(-6.0 / 20.0), 1e-12);
+
+    // As an effect, the impulses replace the input
+    let mut context = AudioContext::new(SAMPLE_RATE, 2);
+    context.state = AudioContextState::Offline;
+    let mut buffer = AudioBuffer::new(2, &[0.5; 2 * 12001]);
+    periodic.reset();
+    periodic.process(&context, &mut buffer);
+    for channel in 0..2 {
+        assert_eq!(buffer[(channel, 0)], samples[0]);
+        assert_eq!(buffer[(channel, 1)], 0.0);
+        assert_eq!(buffer[(channel, 12000)], samples[0]);
+    }
+}
@@ -0,0 +1,110 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Normalizes programs of known loudness, checking that the output reaches the target loudness
+//! and stays under the true peak ceiling, as measured independently of the normalizer.
+
+use std::f64::consts::PI;
+
+use wavr_audio_buffer::AudioBuffer;
+use wavr_effects::*;
+use wavr_engine::{AudioContext, AudioContextState, Effect};
+use wavr_meter::decibel::Decibel;
+
+const SAMPLE_RATE: u32 = 48000;
+const LOUDNESS_TOLERANCE: f64 = 0.1;
+
+/// Stereo 997 Hz sine program lasting 5 seconds, at the given loudness. A full scale stereo sine
+/// measures 0 LUFS. A single-sample spike of the given amplitude is added in the middle.
+fn program(lufs: f64, spike: f64) -> AudioBuffer {
+    let amplitude = 10f64.powf(lufs / 20.0);
+    let mut buffer = AudioBuffer::zeroed(2, 5 * SAMPLE_RATE as usize);
+    for ch in 0..2 {
+        for i in 0..buffer.buffer_size() {
+            let sine = amplitude * (2.0 * PI * 997.0 * i as f64 / SAMPLE_RATE as f64).sin();
+            buffer[(ch, i)] = if i == buffer.buffer_size() / 2 {
+                spike
+            } else {
+                sine
+            };
+        }
+    }
+    buffer
+}
+
+fn measure(buffer: &AudioBuffer) -> LoudnessStats {
+    let mut analyzer = LoudnessAnalyzer::new(2, SAMPLE_RATE).unwrap();
+    analyzer.add_buffer(buffer).unwrap();
+    analyzer.stats().unwrap()
+}
+
+fn assert_loudness(actual: Decibel, expected: f64) {
+    assert!(
+        (actual.0 - expected).abs() < LOUDNESS_TOLERANCE,
+        "{} LUFS != {} LUFS",
+        actual.0,
+        expected
+    );
+}
+
+#[test]
+fn reaches_target_loudness() {
+    let input = program(-30.0, 0.0);
+    assert_loudness(measure(&input).loudness, -30.0);
+    let mut buffer = input.clone();
+    let report = normalize(
+        &mut buffer,
+        SAMPLE_RATE,
+        NormalizationTarget::loudness(-23.0),
+    )
+    .unwrap();
+
+    assert_loudness(report.before.loudness, -30.0);
+    assert_loudness(report.after.loudness, -23.0);
+    assert_loudness(measure(&buffer).loudness, -23.0);
+    // Without limiting, the program is only scaled, and not shifted in time
+    let gain = 10f64.powf(report.gain_db / 20.0);
+    assert!((report.gain_db - 7.0).abs() < LOUDNESS_TOLERANCE);
+    for (output, input) in buffer.iter().flatten().zip(input.iter().flatten()) {
+        assert!((output - input * gain).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn true_peak_stays_under_ceiling() {
+    // The 6 dB of gain would bring the spike to +5 dBTP
+    let mut buffer = program(-20.0, 0.9);
+    let report = normalize(&mut buffer, SAMPLE_RATE, NormalizationTarget::streaming()).unwrap();
+    assert!(report.before.true_peak.0 > -1.0);
+
+    let after = measure(&buffer);
+    assert_eq!(report.after, after);
+    assert!(after.true_peak.0 <= -1.0, "{} dBTP", after.true_peak.0);
+    // The limiter only touches the spike, barely changing the loudness
+    assert_loudness(after.loudness, -14.0);
+}
+
+#[test]
+fn rack_normalization() {
+    let input = program(-30.0, 0.0);
+    let mut normalizer = LoudnessNormalizer::new(NormalizationTarget::ebu_r128(), measure(&input));
+    let mut context = AudioContext::new(SAMPLE_RATE as u64, 2);
+    context.state = AudioContextState::Offline;
+    normalizer.prepare(&context);
+
+    // The second pass reports the loudness of its output, including the limiter latency
+    let mut output = input.clone();
+    normalizer.process(&context, &mut output);
+    let report = normalizer.report();
+    assert!((normalizer.gain_db() - 7.0).abs() < LOUDNESS_TOLERANCE);
+    assert_loudness(report.after.loudness, -23.0);
+    assert!(report.after.true_peak.0 <= -1.0);
+
+    // Silent programs cannot be measured, and are left untouched
+    let mut silence = AudioBuffer::zeroed(2, SAMPLE_RATE as usize);
+    let report = normalize(&mut silence, SAMPLE_RATE, NormalizationTarget::ebu_r128()).unwrap();
+    assert_eq!(report.gain_db, 0.0);
+    assert!(silence.iter().flatten().all(|&s| s == 0.0));
+}
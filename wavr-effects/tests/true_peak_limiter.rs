@@ -0,0 +1,226 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Runs hostile signals through the brickwall true peak limiter, and checks its output with the
+//! true peak meters of `wavr_meter`.
+
+use std::f64::consts::PI;
+
+use wavr_audio_buffer::AudioBuffer;
+use wavr_effects::TruePeakLimiter;
+use wavr_engine::{AudioContext, AudioContextState, Effect};
+use wavr_meter::{TruePeak, WavrMeter};
+
+const BLOCK_SIZE: usize = 512;
+const SAMPLE_RATES: [u64; 3] = [44100, 48000, 96000];
+
+/// Deterministic uniform noise in [-1, 1].
+struct Noise(u64);
+
+impl Noise {
+    fn next(&mut self) -> f64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        (self.0 >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+    }
+}
+
+/// Signal generator, returning the sample of a channel at the given index.
+type Signal = Box<dyn FnMut(usize, usize, f64) -> f64>;
+
+fn hostile_signals() -> Vec<(&'static str, Signal)> {
+    let mut noise = Noise(0x5eed);
+    let mut burst = Noise(0xb0b);
+    vec![
+        // Quarter-sample-rate sine with its samples at ±0.707, and its true peak at +3 dB over them
+        (
+            "fs/4 sine at 45°",
+            Box::new(|_, i, _| (PI / 2.0 * i as f64 + PI / 4.0).sin() * 2f64.sqrt()),
+        ),
+        (
+            "full scale square",
+            Box::new(|_, i, sr| {
+                if (i as f64 * 997.0 / sr).fract() < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }),
+        ),
+        (
+            "nyquist",
+            Box::new(|_, i, _| if i % 2 == 0 { 1.0 } else { -1.0 }),
+        ),
+        (
+            "white noise +6 dB",
+            Box::new(move |_, _, _| 2.0 * noise.next()),
+        ),
+        (
+            "impulses",
+            Box::new(|ch, i, _| if (i + ch * 7) % 1000 == 0 { 1.0 } else { 0.0 }),
+        ),
+        (
+            "overdriven sweep",
+            Box::new(|_, i, sr| {
+                // 20 Hz to 20 kHz over the quarter of a second
+                let rate = 4.0 * 1000f64.ln();
+                let phase = 2.0 * PI * 20.0 * ((rate * i as f64 / sr).exp() - 1.0) / rate;
+                2.0 * phase.sin()
+            }),
+        ),
+        (
+            "silence then bursts",
+            Box::new(move |_, i, _| {
+                if (i / 2000) % 2 == 1 {
+                    4.0 * burst.next()
+                } else {
+                    0.0
+                }
+            }),
+        ),
+        (
+            "dc steps",
+            Box::new(|ch, i, _| if (i / 1500 + ch) % 2 == 0 { 1.5 } else { -1.5 }),
+        ),
+    ]
+}
+
+/// Runs a quarter of a second of the signal through a limiter, and returns the highest true peak of the
+/// output as measured by `WavrMeter` and by a `TruePeak` detector.
+fn run(signal: &mut Signal, sample_rate: u64, channels: u8, ceiling_db: f64) -> (f64, f64) {
+    let mut context = AudioContext::new(sample_rate, channels);
+    context.state = AudioContextState::Offline;
+    let mut limiter = TruePeakLimiter::with_ceiling(ceiling_db);
+    limiter.prepare(&context);
+
+    let mut meter = WavrMeter::new(channels as u32, sample_rate as u32).unwrap();
+    let mut detector = TruePeak::new(channels as usize, sample_rate as u32);
+    let (mut meter_peak, mut detector_peak) = (0.0f64, 0.0f64);
+    let blocks = sample_rate as usize / 4 / BLOCK_SIZE + 2;
+    for block in 0..blocks {
+        let mut buffer = AudioBuffer::new(
+            channels as usize,
+            &vec![0.0; BLOCK_SIZE * channels as usize],
+        );
+        // The last blocks are silent, flushing the lookahead and the meters
+        if block < blocks - 2 {
+            for ch in 0..channels as usize {
+                for i in 0..BLOCK_SIZE {
+                    buffer[(ch, i)] = signal(ch, block * BLOCK_SIZE + i, sample_rate as f64);
+                }
+            }
+        }
+        limiter.process(&context, &mut buffer);
+
+        meter.add_samples(&buffer);
+        for peak in meter.get_values().unwrap().peak {
+            meter_peak = meter_peak.max(peak.0);
+        }
+        let mut frame = vec![0.0; channels as usize];
+        for i in 0..BLOCK_SIZE {
+            for (ch, sample) in frame.iter_mut().enumerate() {
+                *sample = buffer[(ch, i)];
+            }
+            detector.process_frame(&frame, |_, p| detector_peak = detector_peak.max(p));
+        }
+    }
+    (20.0 * meter_peak.log10(), 20.0 * detector_peak.log10())
+}
+
+#[test]
+fn hostile_signals_stay_under_the_ceiling() {
+    for &sample_rate in &SAMPLE_RATES {
+        for &channels in &[1u8, 2] {
+            for (name, mut signal) in hostile_signals() {
+                let (meter_db, detector_db) = run(&mut signal, sample_rate, channels, -1.0);
+                assert!(
+                    meter_db <= -1.0 && detector_db <= -1.0,
+                    "{} at {} Hz, {} channels: meter {:.3} dBTP, detector {:.3} dBTP",
+                    name,
+                    sample_rate,
+                    channels,
+                    meter_db,
+                    detector_db
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn other_ceilings_are_respected() {
+    for &ceiling in &[-0.1, -2.0, -6.0, -12.0] {
+        for (name, mut signal) in hostile_signals() {
+            let (meter_db, detector_db) = run(&mut signal, 48000, 2, ceiling);
+            assert!(
+                meter_db <= ceiling && detector_db <= ceiling,
+                "{} with a {} dBTP ceiling: meter {:.3} dBTP, detector {:.3} dBTP",
+                name,
+                ceiling,
+                meter_db,
+                detector_db
+            );
+        }
+    }
+}
+
+#[test]
+fn quiet_signals_are_untouched() {
+    let mut context = AudioContext::new(48000, 1);
+    context.state = AudioContextState::Offline;
+    let mut limiter = TruePeakLimiter::new();
+    limiter.prepare(&context);
+    let latency = limiter.latency();
+
+    let input: Vec<f64> = (0..4096)
+        .map(|i| 0.5 * (2.0 * PI * 440.0 * i as f64 / 48000.0).sin())
+        .collect();
+    let mut buffer = AudioBuffer::new(1, &input);
+    limiter.process(&context, &mut buffer);
+    for i in latency..input.len() {
+        assert!((buffer[(0, i)] - input[i - latency]).abs() < 1e-12);
+    }
+    assert_eq!(limiter.gain_reduction_db(), 0.0);
+}
+
+#[test]
+fn loud_signals_are_not_over_limited() {
+    let mut signal: Signal = Box::new(|_, i, sr| 2.0 * (2.0 * PI * 997.0 * i as f64 / sr).sin());
+    let (meter_db, _) = run(&mut signal, 48000, 2, -1.0);
+    assert!(meter_db > -1.5, "output peak {:.3} dBTP", meter_db);
+}
+
+#[test]
+fn reset_clears_the_lookahead() {
+    let mut context = AudioContext::new(48000, 1);
+    context.state = AudioContextState::Offline;
+    let loud: Vec<f64> = (0..4096)
+        .map(|i| 2.0 * (2.0 * PI * 997.0 * i as f64 / 48000.0).sin())
+        .collect();
+    let mut limiter = TruePeakLimiter::new();
+    limiter.prepare(&context);
+    limiter.process(&context, &mut AudioBuffer::new(1, &loud));
+    assert!(limiter.gain_reduction_db() > 0.0);
+
+    // After a reset, the limiter behaves as a freshly prepared one
+    limiter.reset();
+    assert_eq!(limiter.gain_reduction_db(), 0.0);
+    let mut fresh = TruePeakLimiter::new();
+    fresh.prepare(&context);
+    let mut buffer = AudioBuffer::new(1, &loud);
+    let mut expected = AudioBuffer::new(1, &loud);
+    limiter.process(&context, &mut buffer);
+    fresh.process(&context, &mut expected);
+    assert_eq!(buffer[0], expected[0]);
+
+    // Without channels, there is nothing to process
+    let mut context = AudioContext::new(48000, 0);
+    context.state = AudioContextState::Offline;
+    limiter.prepare(&context);
+    limiter.process(&context, &mut AudioBuffer::zeroed(0, BLOCK_SIZE));
+    limiter.reset();
+}
@@ -4,64 +4,63 @@
  * are licensed under MIT.
  */
 
-use wavr_engine::{AudioContext, AudioEngine, Effect};
+use wavr_audio_buffer::AudioBuffer;
+use wavr_effects::{Oscillator, Waveform};
+use wavr_engine::{AudioContext, AudioContextState, AudioEngine, Effect};
 
-struct SineWaveGenerator {
-    freq: f64,
-    amplitude: f64,
+const SAMPLE_RATE: u64 = 48000;
+const BLOCK_SIZE: usize = 512;
+
+struct Decay {
+    rate: f64,
 }
 
 struct Saturator {
     power: f64,
 }
 
-impl Effect for SineWaveGenerator {
-    fn process(&mut self, context: &AudioContext, data: &mut [f64]) {
-        if !context.is_playing() {
-            return;
-        }
-
-        for s in 0..context.buffer_size {
-            for i in 0..context.channel_count {
-                let idx = s * context.channel_count as usize + i as usize;
-                let decay = (context.timestamp_offset(s).as_secs_f64() * -5.0).exp();
-                let phase =
-                    context.timestamp_offset(s).as_secs_f64() % (2.0 * std::f64::consts::PI);
-                data[idx] = (self.freq * phase).sin() * self.amplitude * decay;
+impl Effect for Decay {
+    fn process(&mut self, context: &AudioContext, buffer: &mut AudioBuffer) {
+        for ch in 0..buffer.channels() {
+            for i in 0..buffer.buffer_size() {
+                let time = context.timestamp_offset(i).as_secs_f64();
+                buffer[(ch, i)] *= (time * -self.rate).exp();
             }
         }
     }
 }
 
 impl Effect for Saturator {
-    fn process(&mut self, _context: &AudioContext, data: &mut [f64]) {
-        for s in data {
-            *s = (*s * self.power).tanh();
+    fn process(&mut self, _context: &AudioContext, buffer: &mut AudioBuffer) {
+        for ch in 0..buffer.channels() {
+            for i in 0..buffer.buffer_size() {
+                buffer[(ch, i)] = (buffer[(ch, i)] * self.power).tanh();
+            }
         }
     }
 }
 
 fn main() {
-    let mut engine = AudioEngine::new(48000, 2, 512);
+    let mut engine = AudioEngine::new(SAMPLE_RATE, 2);
     {
         let rack = engine.get_rack_mut();
-        rack.push_effect(SineWaveGenerator {
-            freq: 80.0,
-            amplitude: 1.0,
-        });
+        rack.push_effect(Oscillator::new(Waveform::Sine, 80.0, 0.0));
+        rack.push_effect(Decay { rate: 5.0 });
         rack.push_effect(Saturator { power: 2.0 });
     }
     let spec = hound::WavSpec {
         channels: 2,
-        sample_rate: 48000,
+        sample_rate: SAMPLE_RATE as u32,
         bits_per_sample: 32,
         sample_format: hound::SampleFormat::Float,
     };
     let mut writer = hound::WavWriter::create("track.wav", spec).unwrap();
-    let chunk_count = (48000f64 / 512.0).ceil() as usize;
-    engine.set_context_state(wavr_engine::AudioContextState::Offline);
+    let chunk_count = (SAMPLE_RATE as f64 / BLOCK_SIZE as f64).ceil() as usize;
+    engine.set_context_state(AudioContextState::Offline);
     for _ in 0..chunk_count {
-        for sample in engine.generate() {
+        let mut data = vec![0.0; BLOCK_SIZE * 2];
+        engine.fill_interleaved(&mut data);
+        for sample in data {
             writer.write_sample(sample as f32).unwrap();
         }
     }
@@ -19,13 +19,39 @@ use wavr_audio_buffer::AudioBuffer;
 /// means that all effects are bypassed and the output is muted. This is useful
 /// for the application to stop processing audio entirely when no sound is
 /// playing (ie. no audio playback and not monitoring the audio input).
+///
+/// The `AudioEngine` fades the output out when switching to `Paused`, and
+/// fades it back in when switching to a processing state, so that transitions
+/// do not click.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum AudioContextState {
+    /// The output is muted and the transport is stopped.
     Paused,
+    /// Effects are processed and the transport advances in real-time.
     Playing,
+    /// Effects are processed but the transport is stopped. Useful to listen to
+    /// the input through the rack (ie. while setting up a recording).
+    Monitoring,
+    /// Effects are processed and the transport advances, without real-time
+    /// constraints (ie. when rendering to a file).
     Offline,
 }
 
+impl AudioContextState {
+    /// Returns whether effects are processed in this state.
+    pub fn is_processing(self) -> bool {
+        self != AudioContextState::Paused
+    }
+
+    /// Returns whether the transport advances in this state.
+    pub fn is_playing(self) -> bool {
+        matches!(
+            self,
+            AudioContextState::Playing | AudioContextState::Offline
+        )
+    }
+}
+
 /// The AudioContext structure holds information about the state of processing
 /// (see [`AudioContextState`](struct.AudioContextState.html)), and timestamp of the
 /// current audio block.
@@ -84,11 +110,15 @@ impl AudioContext {
         self.current_sample += buffer.buffer_size();
     }
 
-    /// Returns whether the `AudioContext` is in a playing state.
+    /// Returns whether the `AudioContext` is in a playing state, that is,
+    /// whether the transport advances.
     pub fn is_playing(&self) -> bool {
-        match self.state {
-            AudioContextState::Playing | AudioContextState::Offline => true,
-            _ => false,
-        }
+        self.state.is_playing()
+    }
+
+    /// Returns whether the `AudioContext` is in a state where effects are
+    /// processed (playing or monitoring).
+    pub fn is_processing(&self) -> bool {
+        self.state.is_processing()
     }
 }
@@ -10,10 +10,35 @@
 
 use wavr_audio_buffer::AudioBuffer;
 
-use crate::context::AudioContext;
+use crate::context::{AudioContext, AudioContextState};
 
 /// Effect trait. Provides an interface for audio processing, parameter information and GUI.
-pub trait Effect {
+///
+/// Effects are required to be `Send` so that the engine holding them can be moved onto the audio
+/// thread of a backend.
+pub trait Effect: Send {
+    /// Prepare the effect for processing with the given context. This is called whenever the
+    /// sample rate or channel count of the engine changes, before any call to `process` with the
+    /// new configuration. The default implementation does nothing.
+    fn prepare(&mut self, _context: &AudioContext) {}
+
+    /// Resets the internal state of the effect (filter memories, delay lines, envelopes...), as if
+    /// it had never processed any audio. Parameters are left untouched. The default implementation
+    /// does nothing.
+    fn reset(&mut self) {}
+
+    /// Returns the processing latency of the effect, in samples. Effects delaying their output
+    /// (ie. lookahead processors) report it so that hosts can compensate. The default
+    /// implementation returns 0.
+    fn latency(&self) -> usize {
+        0
+    }
+
+    /// Notifies the effect that the state of the audio context changed. The context holds the
+    /// new state, and `previous` the state it transitioned from. The default implementation does
+    /// nothing.
+    fn state_changed(&mut self, _context: &AudioContext, _previous: AudioContextState) {}
+
     /// Process an audio frame.
     fn process(&mut self, context: &AudioContext, buffer: &mut AudioBuffer);
 }
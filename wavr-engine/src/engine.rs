@@ -7,27 +7,58 @@
 //!
 //! Core structures for the audio engine. This crate implements the effects rack and monitoring
 //! capabilities.
+//!
+//! ## State transitions
+//!
+//! Switching the engine to `Paused` fades the output out before muting it, and switching it back
+//! to a processing state fades the output in, so that the audio is never cut abruptly. Transitions
+//! from or to `Offline` are applied immediately, as rendering must not be altered by fades.
+
+use std::time::Duration;
 
 use wavr_audio_buffer::AudioBuffer;
 
 use crate::{AudioContext, AudioContextState, Effect, Rack};
 
+/// Default duration of the fades applied on state transitions.
+pub const DEFAULT_FADE_DURATION: Duration = Duration::from_millis(10);
+
 /// Structure holding the audio context and processing rack.
 pub struct AudioEngine {
     context: AudioContext,
     rack: Rack,
+    /// State used for processing. Lags behind the context state while fading out.
+    processing_state: AudioContextState,
+    fade_duration: Duration,
+    fade_gain: f64,
+    fade_target: f64,
 }
 
 impl AudioEngine {
-    /// Creates a new audio engine with the given sample rate and channel count.
+    /// Creates a new audio engine with the given sample rate and channel count, with the rack
+    /// prepared for this format.
     pub fn new(sample_rate: u64, channel_count: u8) -> Self {
         let context = AudioContext::new(sample_rate, channel_count);
+        let mut rack = Rack::new();
+        rack.prepare(&context);
         Self {
+            processing_state: context.state,
             context,
-            rack: Rack::new(),
+            rack,
+            fade_duration: DEFAULT_FADE_DURATION,
+            fade_gain: 0.0,
+            fade_target: 0.0,
         }
     }
 
+    /// Reconfigures the engine for a new sample rate and channel count, and prepares the rack for
+    /// processing with the new format. Backends call this once the device format is known.
+    pub fn configure(&mut self, sample_rate: u64, channel_count: u8) {
+        self.context.sample_rate = sample_rate;
+        self.context.channel_count = channel_count;
+        self.rack.prepare(&self.context);
+    }
+
     /// Fills the given audio buffer with audio data processed from the rack. The buffer's data is
     /// used as input into the rack.
     pub fn fill_interleaved(&mut self, input: &mut [f64]) {
@@ -37,10 +68,21 @@ impl AudioEngine {
     }
 
     /// Fills the `AudioBuffer` with audio data processed from the rack. The buffer's data is used
-    /// as input into the rack.
+    /// as input into the rack. The output is silent while the engine is paused.
     pub fn fill_buffer(&mut self, input: &mut AudioBuffer) {
-        self.rack.process(&self.context, input);
-        self.context.add_sample_cycle(input);
+        let context = AudioContext {
+            state: self.processing_state,
+            ..self.context
+        };
+        self.rack.process(&context, input);
+        self.apply_fade(input);
+        if self.fade_gain == 0.0 && !self.context.is_processing() {
+            // The fade out is over, stop processing the rack
+            self.processing_state = self.context.state;
+        }
+        if context.is_playing() {
+            self.context.add_sample_cycle(input);
+        }
     }
 
     /// Returns a constant reference to the rack.
@@ -58,8 +100,76 @@ impl AudioEngine {
         &self.context
     }
 
-    /// Set the audio context state.
+    /// Set the audio context state. Effects are notified of the change, and the output is faded
+    /// in or out as needed.
     pub fn set_context_state(&mut self, state: AudioContextState) {
+        let previous = self.context.state;
+        if state == previous {
+            return;
+        }
+
         self.context.state = state;
+        self.rack.state_changed(&self.context, previous);
+        let immediate = state == AudioContextState::Offline
+            || previous == AudioContextState::Offline
+            || self.fade_duration == Duration::from_secs(0);
+        if state.is_processing() {
+            self.processing_state = state;
+            self.fade_target = 1.0;
+        } else {
+            self.fade_target = 0.0;
+        }
+        if immediate {
+            self.fade_gain = self.fade_target;
+            self.processing_state = state;
+        }
+    }
+
+    /// Returns the duration of the fades applied on state transitions.
+    pub fn fade_duration(&self) -> Duration {
+        self.fade_duration
+    }
+
+    /// Sets the duration of the fades applied on state transitions. A zero duration disables
+    /// fades.
+    pub fn set_fade_duration(&mut self, duration: Duration) {
+        self.fade_duration = duration;
+    }
+
+    /// Moves the audio context to the given sample position. Used by backends following an
+    /// external transport.
+    pub fn set_position(&mut self, sample: usize) {
+        self.context.current_sample = sample;
+    }
+
+    fn apply_fade(&mut self, buffer: &mut AudioBuffer) {
+        if self.fade_gain == 1.0 && self.fade_target == 1.0 {
+            return;
+        }
+
+        let fade_samples = self.fade_duration.as_secs_f64() * self.context.sample_rate as f64;
+        let step = if fade_samples >= 1.0 {
+            1.0 / fade_samples
+        } else {
+            1.0
+        };
+        let start = self.fade_gain;
+        for ch in 0..buffer.channels() {
+            let mut gain = start;
+            for sample in &mut buffer[ch] {
+                gain = approach(gain, self.fade_target, step);
+                *sample *= gain;
+            }
+            self.fade_gain = gain;
+        }
+    }
+}
+
+/// Moves `value` towards `target` by at most `step`.
+fn approach(value: f64, target: f64, step: f64) -> f64 {
+    if value < target {
+        (value + step).min(target)
+    } else {
+        (value - step).max(target)
     }
 }
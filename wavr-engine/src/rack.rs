@@ -11,23 +11,42 @@
 use std::collections::LinkedList;
 
 use wavr_audio_buffer::AudioBuffer;
-use wavr_meter::{WavrMeter, WavrMeterData};
+use wavr_meter::{Error as MeterError, LoudnessHistory, WavrMeter, WavrMeterData};
 
-use crate::context::AudioContext;
+use crate::context::{AudioContext, AudioContextState};
 use crate::effect::Effect;
 
 /// Wrapping structure over a rack effect. Holds metering data and an `enabled` flag.
 pub struct RackEffect {
     effect: Box<dyn Effect>,
+    context: Option<AudioContext>,
     meter: Option<WavrMeter>,
+    meter_error: Option<MeterError>,
     enabled: bool,
 }
 
-/// Rack structure, holding metering and the list of effects to apply.
+/// Rack structure, holding metering and the list of effects to apply. The meters are created
+/// when preparing the rack, and kept with their history when prepared again with the same format.
+/// Effects added to a prepared rack are prepared right away.
+#[derive(Default)]
 pub struct Rack {
+    context: Option<AudioContext>,
     input_meter: Option<WavrMeter>,
     effects: LinkedList<RackEffect>,
     output_meter: Option<WavrMeter>,
+    meter_error: Option<MeterError>,
+    record_history: bool,
+}
+
+/// Creates a meter for the context, as the meters of the rack are created when preparing it.
+fn create_meter(context: &AudioContext) -> Result<WavrMeter, MeterError> {
+    WavrMeter::new(context.channel_count as u32, context.sample_rate as u32)
+}
+
+/// Returns whether the meters created for the previous context can measure the new one.
+fn same_format(previous: Option<&AudioContext>, context: &AudioContext) -> bool {
+    matches!(previous, Some(previous) if previous.sample_rate == context.sample_rate
+        && previous.channel_count == context.channel_count)
 }
 
 impl RackEffect {
@@ -35,14 +54,22 @@ impl RackEffect {
     pub fn new<E: 'static + Effect>(effect: E) -> Self {
         Self {
             effect: Box::new(effect),
+            context: None,
             meter: None,
+            meter_error: None,
             enabled: true,
         }
     }
 
     /// Returns processed peak and loudness data from `WavrMeter`.
     pub fn get_meter_data(&self) -> Option<WavrMeterData> {
-        self.meter.as_ref().map(|m| m.get_values())
+        self.meter.as_ref().and_then(WavrMeter::get_values)
+    }
+
+    /// Returns the error raised when creating the meter of the effect, if any. The effect is not
+    /// metered until prepared again with a supported format.
+    pub fn meter_error(&self) -> Option<MeterError> {
+        self.meter_error
     }
 
     /// Returns whether the effect is enabled or not.
@@ -67,12 +94,40 @@ impl RackEffect {
 }
 
 impl Effect for RackEffect {
+    fn prepare(&mut self, context: &AudioContext) {
+        if !same_format(self.context.as_ref(), context) {
+            match create_meter(context) {
+                Ok(meter) => {
+                    self.meter = Some(meter);
+                    self.meter_error = None;
+                }
+                Err(err) => {
+                    self.meter = None;
+                    self.meter_error = Some(err);
+                }
+            }
+        }
+        self.context = Some(*context);
+        self.effect.prepare(context);
+    }
+
+    fn reset(&mut self) {
+        self.effect.reset();
+    }
+
+    fn latency(&self) -> usize {
+        self.effect.latency()
+    }
+
+    fn state_changed(&mut self, context: &AudioContext, previous: AudioContextState) {
+        self.effect.state_changed(context, previous);
+    }
+
     fn process(&mut self, context: &AudioContext, data: &mut AudioBuffer) {
         self.effect.process(context, data);
-        let meter = self.meter.get_or_insert_with(|| {
-            WavrMeter::new(context.channel_count as u32, context.sample_rate as u32)
-        });
-        meter.add_samples(data);
+        if let Some(meter) = &mut self.meter {
+            meter.add_samples_at(context.current_sample, data);
+        }
     }
 }
 
@@ -84,13 +139,15 @@ impl Rack {
 
     /// Push an effect onto the rack. It will be placed last.
     pub fn push_effect<E: 'static + Effect>(&mut self, effect: E) {
-        self.effects.push_back(RackEffect::new(effect));
+        let effect = self.wrap_effect(effect);
+        self.effects.push_back(effect);
     }
 
     /// Inserts an effect at the given position.
     pub fn insert_effect<E: 'static + Effect>(&mut self, pos: usize, effect: E) {
+        let effect = self.wrap_effect(effect);
         let mut after = self.effects.split_off(pos);
-        after.push_front(RackEffect::new(effect));
+        after.push_front(effect);
         self.effects.append(&mut after);
     }
 
@@ -114,43 +171,132 @@ impl Rack {
 
     /// Returns the peak and loudness metering data from the rack input.
     pub fn get_input_meter_data(&self) -> Option<WavrMeterData> {
-        self.input_meter.as_ref().map(|m| m.get_values())
+        self.input_meter.as_ref().and_then(WavrMeter::get_values)
     }
 
     /// Returns the peak and loudness metering data from the rack output.
     pub fn get_output_meter_data(&self) -> Option<WavrMeterData> {
-        self.output_meter.as_ref().map(|m| m.get_values())
+        self.output_meter.as_ref().and_then(WavrMeter::get_values)
     }
-}
 
-impl Default for Rack {
-    fn default() -> Self {
-        Self {
-            input_meter: None,
-            effects: LinkedList::new(),
-            output_meter: None,
+    /// Returns the metering data of each effect slot having processed audio, in rack order. Feed
+    /// it to a `MeterGroup` to show a group meter over the slots.
+    pub fn get_effects_meter_data(&self) -> Vec<WavrMeterData> {
+        self.effects
+            .iter()
+            .filter_map(RackEffect::get_meter_data)
+            .collect()
+    }
+
+    /// Returns the error raised when creating the rack input and output meters, if any. The rack
+    /// is not metered until prepared again with a supported format.
+    pub fn meter_error(&self) -> Option<MeterError> {
+        self.meter_error
+    }
+
+    /// Returns whether the rack input and output meters record their loudness history.
+    pub fn is_history_enabled(&self) -> bool {
+        self.record_history
+    }
+
+    /// Starts or stops recording the loudness history of the rack input and output. Stopping the
+    /// recording drops the histories.
+    pub fn set_history_enabled(&mut self, enabled: bool) {
+        self.record_history = enabled;
+        for meter in self.meters_mut() {
+            meter.set_history_enabled(enabled);
         }
     }
+
+    /// Returns the loudness history of the rack input, if recorded.
+    pub fn get_input_history(&self) -> Option<&LoudnessHistory> {
+        self.input_meter.as_ref().and_then(WavrMeter::history)
+    }
+
+    /// Returns the loudness history of the rack output, if recorded.
+    pub fn get_output_history(&self) -> Option<&LoudnessHistory> {
+        self.output_meter.as_ref().and_then(WavrMeter::history)
+    }
+
+    /// Wraps an effect, preparing it when the rack is already prepared.
+    fn wrap_effect<E: 'static + Effect>(&self, effect: E) -> RackEffect {
+        let mut effect = RackEffect::new(effect);
+        if let Some(context) = &self.context {
+            effect.prepare(context);
+        }
+        effect
+    }
+
+    fn meters_mut(&mut self) -> impl Iterator<Item = &mut WavrMeter> {
+        self.input_meter
+            .iter_mut()
+            .chain(self.output_meter.iter_mut())
+    }
 }
 
 impl Effect for Rack {
+    fn prepare(&mut self, context: &AudioContext) {
+        if !same_format(self.context.as_ref(), context) {
+            match create_meter(context).and_then(|input| Ok((input, create_meter(context)?))) {
+                Ok((input, output)) => {
+                    self.input_meter = Some(input);
+                    self.output_meter = Some(output);
+                    self.meter_error = None;
+                }
+                Err(err) => {
+                    self.input_meter = None;
+                    self.output_meter = None;
+                    self.meter_error = Some(err);
+                }
+            }
+            let record_history = self.record_history;
+            for meter in self.meters_mut() {
+                meter.set_history_enabled(record_history);
+            }
+        }
+        self.context = Some(*context);
+        for effect in self.effects.iter_mut() {
+            effect.prepare(context);
+        }
+    }
+
+    fn reset(&mut self) {
+        for effect in self.effects.iter_mut() {
+            effect.reset();
+        }
+    }
+
+    /// Returns the total latency of the enabled effects of the rack, in samples.
+    fn latency(&self) -> usize {
+        self.effects
+            .iter()
+            .filter(|e| e.enabled)
+            .map(|e| e.latency())
+            .sum()
+    }
+
+    fn state_changed(&mut self, context: &AudioContext, previous: AudioContextState) {
+        for effect in self.effects.iter_mut() {
+            effect.state_changed(context, previous);
+        }
+    }
+
     fn process(&mut self, context: &AudioContext, data: &mut AudioBuffer) {
-        if !context.is_playing() {
+        if !context.is_processing() {
+            data.clear();
             return;
         }
 
-        let input_meter = self.input_meter.get_or_insert_with(|| {
-            WavrMeter::new(context.channel_count as u32, context.sample_rate as u32)
-        });
-        let output_meter = self.output_meter.get_or_insert_with(|| {
-            WavrMeter::new(context.channel_count as u32, context.sample_rate as u32)
-        });
-        input_meter.add_samples(data);
+        if let Some(input_meter) = &mut self.input_meter {
+            input_meter.add_samples_at(context.current_sample, data);
+        }
         if !self.effects.is_empty() {
             for effect in self.effects.iter_mut().filter(|e| e.enabled) {
                 effect.process(context, data);
             }
         }
-        output_meter.add_samples(data);
+        if let Some(output_meter) = &mut self.output_meter {
+            output_meter.add_samples_at(context.current_sample, data);
+        }
     }
 }
@@ -0,0 +1,68 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Drives an engine directly, without a backend, checking that the rack meters are available and
+//! survive preparing the rack again.
+
+use std::f64::consts::PI;
+
+use wavr_audio_buffer::AudioBuffer;
+use wavr_effects::Gain;
+use wavr_engine::{AudioContextState, AudioEngine};
+
+const SAMPLE_RATE: u64 = 48000;
+const BLOCK_SIZE: usize = 480;
+
+/// Processes a second of a stereo 1 kHz sine at half scale.
+fn run(engine: &mut AudioEngine) {
+    for block in 0..SAMPLE_RATE as usize / BLOCK_SIZE {
+        let data: Vec<f64> = (0..2 * BLOCK_SIZE)
+            .map(|i| {
+                let n = block * BLOCK_SIZE + i % BLOCK_SIZE;
+                0.5 * (2.0 * PI * 1000.0 * n as f64 / SAMPLE_RATE as f64).sin()
+            })
+            .collect();
+        let mut buffer = AudioBuffer::new(2, &data);
+        engine.fill_buffer(&mut buffer);
+    }
+}
+
+#[test]
+fn new_engine_is_metered() {
+    let mut engine = AudioEngine::new(SAMPLE_RATE, 2);
+    engine.get_rack_mut().push_effect(Gain::new(-6.0));
+    engine.set_context_state(AudioContextState::Offline);
+    run(&mut engine);
+
+    let rack = engine.get_rack();
+    assert_eq!(rack.meter_error(), None);
+    let input = rack.get_input_meter_data().expect("input meter data");
+    let output = rack.get_output_meter_data().expect("output meter data");
+    assert!((input.peak[0].0 - 0.5).abs() < 1e-3);
+    assert!((output.peak[0].0 - 0.5 * 10f64.powf(-6.0 / 20.0)).abs() < 1e-3);
+    assert_eq!(rack.get_effects_meter_data().len(), 1);
+}
+
+#[test]
+fn prepare_keeps_meters_of_same_format() {
+    let mut engine = AudioEngine::new(SAMPLE_RATE, 2);
+    engine.get_rack_mut().set_history_enabled(true);
+    engine.set_context_state(AudioContextState::Offline);
+    run(&mut engine);
+    let recorded = engine.get_rack().get_input_history().unwrap().len();
+    assert!(recorded > 0);
+
+    engine.configure(SAMPLE_RATE, 2);
+    assert_eq!(
+        engine.get_rack().get_input_history().unwrap().len(),
+        recorded
+    );
+    assert!(engine.get_rack().get_input_meter_data().is_some());
+
+    // A new format needs new meters
+    engine.configure(44100, 2);
+    assert!(engine.get_rack().get_input_meter_data().is_none());
+    assert_eq!(engine.get_rack().get_input_history().unwrap().len(), 0);
+}
@@ -21,6 +21,7 @@ mod recipes;
 struct MetersApp {
     meters: Option<WavrMeter>,
     canvases: Vec<(Meter, canvas::layer::Cache<Meter>)>,
+    groups: Vec<Vec<usize>>,
     is_error: bool,
 }
 
@@ -35,6 +36,7 @@ impl Application for MetersApp {
                 meters: None,
                 is_error: false,
                 canvases: vec![],
+                groups: vec![],
             },
             Command::none(),
         )
@@ -48,18 +50,37 @@ impl Application for MetersApp {
         use recipes::cpal::Data;
         match message {
             Data::Format(format) => {
-                self.canvases = (0..format.channels).map(|_| Default::default()).collect();
-                self.meters = Some(WavrMeter::new(format.channels as u32, format.sample_rate.0))
+                self.meters = WavrMeter::new(format.channels as u32, format.sample_rate.0).ok();
+                self.is_error = self.meters.is_none();
+                if let Some(meters) = &self.meters {
+                    let layout = meters.layout();
+                    self.canvases = (0..layout.len())
+                        .map(|i| {
+                            let mut meter = Meter::default();
+                            meter.set_label(Some(layout.label(i)));
+                            (meter, Default::default())
+                        })
+                        .collect();
+                    self.groups = layout
+                        .groups()
+                        .into_iter()
+                        .map(|(_, channels)| channels)
+                        .collect();
+                }
             }
             Data::Data(data) => {
                 if let Some(meters) = self.meters.as_mut() {
                     meters.add_samples(&data);
-                    let value = dbg!(meters.get_values());
+                    let value = match meters.get_values() {
+                        Some(value) => value,
+                        None => return Command::none(),
+                    };
                     self.canvases
                         .iter_mut()
                         .enumerate()
                         .for_each(|(i, (meter, layer))| {
                             meter.set_values(value.peak[i], value.loudness);
+                            meter.set_rms(value.rms[i]);
                             layer.clear();
                         });
                 }
@@ -87,20 +108,32 @@ impl Application for MetersApp {
             .height(Length::Fill)
             .into()
         } else {
+            let canvases = &self.canvases;
             Row::with_children(
-                self.canvases
+                self.groups
                     .iter()
-                    .map(|(meter, layer)| {
-                        Canvas::new()
-                            .width(Length::Fill)
-                            .height(Length::Fill)
-                            .push(layer.with(meter))
-                            .into()
+                    .map(|channels| {
+                        Row::with_children(
+                            channels
+                                .iter()
+                                .map(|&i| {
+                                    let (meter, layer) = &canvases[i];
+                                    Canvas::new()
+                                        .width(Length::Fill)
+                                        .height(Length::Fill)
+                                        .push(layer.with(meter))
+                                        .into()
+                                })
+                                .collect(),
+                        )
+                        .width(Length::FillPortion(channels.len() as u16))
+                        .spacing(4)
+                        .into()
                     })
                     .collect(),
             )
             .padding(10)
-            .spacing(10)
+            .spacing(20)
             .into()
         }
     }
@@ -0,0 +1,265 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+use iced::canvas::{self, Path, Stroke, Text};
+use iced_native::{Color, Point, Size};
+use iced_wgpu::widget::canvas::Frame;
+
+use wavr_meter::decibel::Decibel;
+use wavr_meter::LoudnessHistory;
+
+use crate::core::Range;
+use crate::meter::clamp;
+
+/// Scrolling loudness history graph, with the momentary and short-term loudness over time, a
+/// target line with its tolerance band and a readout of the integrated loudness. The newest
+/// values are on the right edge.
+#[derive(Clone, Debug)]
+pub struct HistoryGraph {
+    level_range: Range<f64>,
+    grid_step: f64,
+    window: f64,
+    target: f64,
+    tolerance: f64,
+    integrated: Option<f64>,
+    /// Points as (seconds before the newest entry, momentary, short-term).
+    points: Vec<(f64, f64, f64)>,
+    momentary_color: Color,
+    short_term_color: Color,
+    target_color: Color,
+    band_color: Color,
+    grid_color: Color,
+    text_color: Color,
+}
+
+impl HistoryGraph {
+    pub fn new() -> Self {
+        Self {
+            level_range: Range {
+                min: -50.0,
+                max: -5.0,
+            },
+            grid_step: 5.0,
+            window: 60.0,
+            target: -23.0,
+            tolerance: 1.0,
+            integrated: None,
+            points: vec![],
+            momentary_color: Color::from([0.1, 1.0, 0.2, 0.5]),
+            short_term_color: Color::from([0.1, 1.0, 0.2, 1.0]),
+            target_color: Color::from([0.2, 0.3, 1.0, 0.8]),
+            band_color: Color::from([0.2, 0.3, 1.0, 0.15]),
+            grid_color: Color::from([0.0, 0.0, 0.0, 0.3]),
+            text_color: Color::from([0.0, 0.0, 0.0, 0.8]),
+        }
+    }
+
+    /// Sets the displayed history, keeping the entries of the visible time window.
+    pub fn set_values(&mut self, history: &LoudnessHistory) {
+        let latest = match history.latest() {
+            Some(entry) => entry.position,
+            None => {
+                self.points.clear();
+                return;
+            }
+        };
+        let window = (self.window * history.sample_rate() as f64).ceil() as usize;
+        let sample_rate = history.sample_rate() as f64;
+        self.points = history
+            .range(latest.saturating_sub(window), latest + 1)
+            .map(|e| {
+                (
+                    (latest - e.position) as f64 / sample_rate,
+                    e.momentary.0,
+                    e.short_term.0,
+                )
+            })
+            .collect();
+    }
+
+    /// Sets the integrated loudness shown in the readout.
+    pub fn set_integrated(&mut self, integrated: Decibel) {
+        self.integrated = Some(integrated.0).filter(|i| i.is_finite());
+    }
+
+    /// Returns the displayed time span, in seconds.
+    pub fn window(&self) -> f64 {
+        self.window
+    }
+
+    /// Sets the displayed time span, in seconds. The next `set_values` call fills the window.
+    pub fn set_window(&mut self, seconds: f64) {
+        self.window = seconds.max(1.0);
+    }
+
+    /// Returns the target loudness, in LUFS.
+    pub fn target(&self) -> f64 {
+        self.target
+    }
+
+    /// Sets the target loudness, in LUFS.
+    pub fn set_target(&mut self, target: f64) {
+        self.target = target;
+    }
+
+    /// Returns the tolerance around the target, in LU.
+    pub fn tolerance(&self) -> f64 {
+        self.tolerance
+    }
+
+    /// Sets the tolerance around the target, in LU.
+    pub fn set_tolerance(&mut self, tolerance: f64) {
+        self.tolerance = tolerance.abs();
+    }
+
+    /// Sets the displayed loudness range, in LUFS.
+    pub fn set_level_range(&mut self, min: f64, max: f64) {
+        self.level_range = Range { min, max };
+    }
+
+    fn x(&self, age: f64, size: Size) -> f32 {
+        (1.0 - age / self.window) as f32 * size.width
+    }
+
+    fn y(&self, level: f64, size: Size) -> f32 {
+        (1.0 - clamp(self.level_range.map(level))) as f32 * size.height
+    }
+
+    fn curve(&self, size: Size, level: impl Fn(&(f64, f64, f64)) -> f64) -> Path {
+        Path::new(|builder| {
+            let mut started = false;
+            for point in &self.points {
+                let level = level(point);
+                if !level.is_finite() {
+                    started = false;
+                    continue;
+                }
+                let point = Point::new(self.x(point.0, size), self.y(level, size));
+                if started {
+                    builder.line_to(point);
+                } else {
+                    builder.move_to(point);
+                    started = true;
+                }
+            }
+        })
+    }
+
+    fn draw_grid(&self, frame: &mut Frame) {
+        let size = frame.size();
+        let stroke = Stroke {
+            color: self.grid_color,
+            width: 1.0,
+            ..Stroke::default()
+        };
+        let first = (self.level_range.min / self.grid_step).ceil() as i32;
+        let last = (self.level_range.max / self.grid_step).floor() as i32;
+        for step in first..=last {
+            let level = step as f64 * self.grid_step;
+            let y = self.y(level, size);
+            frame.stroke(
+                &Path::line(Point::new(0.0, y), Point::new(size.width, y)),
+                stroke,
+            );
+            frame.fill_text(Text {
+                content: format!("{}", level),
+                position: Point::new(2.0, y + 2.0),
+                color: self.text_color,
+                size: 12.0,
+                ..Text::default()
+            });
+        }
+        let time_step = time_grid_step(self.window);
+        let mut age = time_step;
+        while age < self.window {
+            let x = self.x(age, size);
+            frame.stroke(
+                &Path::line(Point::new(x, 0.0), Point::new(x, size.height)),
+                stroke,
+            );
+            frame.fill_text(Text {
+                content: format!("-{}s", age),
+                position: Point::new(x + 2.0, size.height - 14.0),
+                color: self.text_color,
+                size: 12.0,
+                ..Text::default()
+            });
+            age += time_step;
+        }
+    }
+}
+
+impl Default for HistoryGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl canvas::Drawable for HistoryGraph {
+    fn draw(&self, frame: &mut Frame) {
+        let size = frame.size();
+        frame.fill(
+            &Path::rectangle(Point::new(0.0, 0.0), size),
+            Color::from([0.0, 0.0, 0.0, 0.1]),
+        );
+
+        let band_top = self.y(self.target + self.tolerance, size);
+        let band_bottom = self.y(self.target - self.tolerance, size);
+        frame.fill(
+            &Path::rectangle(
+                Point::new(0.0, band_top),
+                Size::new(size.width, band_bottom - band_top),
+            ),
+            self.band_color,
+        );
+        self.draw_grid(frame);
+        let target = self.y(self.target, size);
+        frame.stroke(
+            &Path::line(Point::new(0.0, target), Point::new(size.width, target)),
+            Stroke {
+                color: self.target_color,
+                width: 1.5,
+                ..Stroke::default()
+            },
+        );
+
+        frame.stroke(
+            &self.curve(size, |p| p.1),
+            Stroke {
+                color: self.momentary_color,
+                width: 1.0,
+                ..Stroke::default()
+            },
+        );
+        frame.stroke(
+            &self.curve(size, |p| p.2),
+            Stroke {
+                color: self.short_term_color,
+                width: 2.0,
+                ..Stroke::default()
+            },
+        );
+
+        frame.fill_text(Text {
+            content: match self.integrated {
+                Some(integrated) => format!("I: {:2.1} LUFS", integrated),
+                None => String::from("I: --.- LUFS"),
+            },
+            position: Point::new(size.width - 100.0, 4.0),
+            color: self.text_color,
+            size: 14.0,
+            ..Text::default()
+        });
+    }
+}
+
+/// Returns a time grid step, in seconds, giving between 3 and 12 lines over the window.
+fn time_grid_step(window: f64) -> f64 {
+    [1.0, 5.0, 10.0, 30.0, 60.0, 300.0, 600.0, 1800.0, 3600.0]
+        .iter()
+        .cloned()
+        .find(|step| window / step <= 12.0)
+        .unwrap_or(3600.0)
+}
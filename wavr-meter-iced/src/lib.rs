@@ -4,7 +4,13 @@
  * are licensed under MIT.
  */
 
+pub use history::*;
 pub use meter::*;
+pub use spectrum::*;
+pub use stereo::*;
 
 mod core;
+mod history;
 mod meter;
+mod spectrum;
+mod stereo;
@@ -4,50 +4,107 @@
  * are licensed under MIT.
  */
 use iced::{
-    canvas::{self, Path, Stroke},
+    canvas::{self, Path, Stroke, Text},
     Canvas,
 };
 use iced_native::{
     layout::{Limits, Node},
     window::Backend,
-    Background, Color, Element, Hasher, Layout, Length, MouseCursor, Point, Size, Widget,
+    Background, Color, Element, Hasher, HorizontalAlignment, Layout, Length, MouseCursor, Point,
+    Size, VerticalAlignment, Widget,
 };
 use iced_wgpu::widget::canvas::Frame;
 use iced_wgpu::{Defaults, Primitive, Renderer};
 use num::Float;
 
-use wavr_meter::decibel::{Linear, LUFS};
-use wavr_meter::{decibel::Decibel, WavrMeterData};
+use wavr_meter::decibel::Linear;
+use wavr_meter::{decibel::Decibel, KScale, WavrMeterData};
 
 use crate::core::Range;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct Meter {
     range: Range<f64>,
+    label: Option<String>,
     peak_data: Decibel,
-    loudness_data: LUFS,
+    loudness_data: Decibel,
+    rms_data: Option<Decibel>,
+    scale: Option<KScale>,
     peak_color: Color,
     loudness_color: Color,
+    rms_color: Color,
+    tick_color: Color,
 }
 
 impl Meter {
-    pub fn new<P: Into<Decibel>, L: Into<LUFS>>(peak: P, loudness: L) -> Self {
+    pub fn new<P: Into<Decibel>, L: Into<Decibel>>(peak: P, loudness: L) -> Self {
         Self {
             range: Range {
                 min: -48.0,
                 max: 6.0,
             },
+            label: None,
             peak_data: peak.into(),
             loudness_data: loudness.into(),
+            rms_data: None,
+            scale: None,
             peak_color: Color::from([0.1, 1.0, 0.2, 1.0]),
             loudness_color: Color::from([0.2, 0.3, 1.0, 0.5]),
+            rms_color: Color::from([1.0, 0.6, 0.1, 0.7]),
+            tick_color: Color::from([0.0, 0.0, 0.0, 0.4]),
         }
     }
 
-    pub fn set_values<P: Into<Decibel>, L: Into<LUFS>>(&mut self, peak: P, loudness: L) {
+    pub fn set_values<P: Into<Decibel>, L: Into<Decibel>>(&mut self, peak: P, loudness: L) {
         self.peak_data = peak.into();
         self.loudness_data = loudness.into();
     }
+
+    /// Sets the RMS level, drawn as a narrow bar in the middle of the meter.
+    pub fn set_rms<R: Into<Decibel>>(&mut self, rms: R) {
+        self.rms_data = Some(rms.into());
+    }
+
+    /// Returns the channel label of the meter.
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    /// Sets the channel label drawn at the bottom of the meter, for example from
+    /// `ChannelLayout::label`.
+    pub fn set_label<S: Into<String>>(&mut self, label: Option<S>) {
+        self.label = label.map(Into::into);
+    }
+
+    /// Returns the K-System scale of the meter, or `None` for a dBFS scale.
+    pub fn scale(&self) -> Option<KScale> {
+        self.scale
+    }
+
+    /// Sets the K-System scale of the meter, or `None` for a dBFS scale. The K-System scales
+    /// expect AES-17 calibrated RMS levels.
+    pub fn set_scale(&mut self, scale: Option<KScale>) {
+        self.scale = scale;
+    }
+
+    /// Returns the tick marks as dBFS levels and labels in the units of the scale.
+    fn ticks(&self) -> Vec<(f64, String)> {
+        match self.scale {
+            None => ((self.range.min as i32)..=0)
+                .step_by(6)
+                .map(|db| (db as f64, format!("{}", db)))
+                .collect(),
+            Some(scale) => {
+                let top = scale.headroom() as i32;
+                let bottom = top + self.range.min as i32;
+                ((bottom / 4 * 4)..=top)
+                    .step_by(4)
+                    .filter(|&k| k >= bottom)
+                    .map(|k| (scale.from_scale(Decibel(k as f64)).0, format!("{:+}", k)))
+                    .collect()
+            }
+        }
+    }
 }
 
 impl Default for Meter {
@@ -78,10 +135,52 @@ impl canvas::Drawable for Meter {
         );
         frame.fill(&peak_rect, self.peak_color);
         frame.fill(&loudness_rect, self.loudness_color);
+
+        if let Some(rms) = self.rms_data {
+            let rms_height = clamp(self.range.map(rms.0)) as f32;
+            frame.fill(
+                &Path::rectangle(
+                    Point::new(size.width * 0.35, size.height * (1.0 - rms_height)),
+                    Size::new(size.width * 0.3, size.height * rms_height),
+                ),
+                self.rms_color,
+            );
+        }
+
+        for (level, label) in self.ticks() {
+            let y = size.height * (1.0 - self.range.map(level) as f32);
+            frame.stroke(
+                &Path::line(Point::new(0.0, y), Point::new(size.width, y)),
+                Stroke {
+                    color: self.tick_color,
+                    width: 1.0,
+                    ..Stroke::default()
+                },
+            );
+            frame.fill_text(Text {
+                content: label,
+                position: Point::new(2.0, y + 2.0),
+                color: self.tick_color,
+                size: 10.0,
+                ..Text::default()
+            });
+        }
+
+        if let Some(label) = &self.label {
+            frame.fill_text(Text {
+                content: label.clone(),
+                position: Point::new(size.width / 2.0, size.height - 2.0),
+                color: Color::BLACK,
+                size: 12.0,
+                horizontal_alignment: HorizontalAlignment::Center,
+                vertical_alignment: VerticalAlignment::Bottom,
+                ..Text::default()
+            });
+        }
     }
 }
 
-fn clamp<F: Float>(x: F) -> F {
+pub(crate) fn clamp<F: Float>(x: F) -> F {
     if x < F::zero() {
         F::zero()
     } else if x > F::one() {
@@ -0,0 +1,320 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+use std::hash::Hash;
+use std::sync::Arc;
+
+use iced::canvas::{self, Path, Stroke, Text};
+use iced_native::{
+    layout::{Limits, Node},
+    Color, Element, Hasher, Layout, Length, MouseCursor, Point, Size, Widget,
+};
+use iced_wgpu::widget::canvas::Frame;
+use iced_wgpu::{Defaults, Primitive, Renderer};
+
+use wavr_meter::widget::{format_frequency, grid_frequencies, is_decade_frequency};
+use wavr_meter::SpectrumData;
+
+use crate::core::Range;
+use crate::meter::clamp;
+
+/// Spectrum display, with a logarithmic frequency axis and a decibel grid. Can be drawn on a
+/// canvas layer, or used as a widget to get a readout of the frequency and level under the mouse.
+#[derive(Clone, Debug)]
+pub struct Spectrum {
+    frequency_range: Range<f64>,
+    level_range: Range<f64>,
+    grid_step_db: f64,
+    tilt: f64,
+    frequencies: Vec<f64>,
+    magnitudes: Vec<f64>,
+    peaks: Vec<f64>,
+    width: Length,
+    height: Length,
+    curve_color: Color,
+    peak_color: Color,
+    grid_color: Color,
+    text_color: Color,
+}
+
+impl Spectrum {
+    pub fn new() -> Self {
+        Self {
+            frequency_range: Range {
+                min: 20f64.log10(),
+                max: 20000f64.log10(),
+            },
+            level_range: Range {
+                min: -96.0,
+                max: 0.0,
+            },
+            grid_step_db: 12.0,
+            tilt: 0.0,
+            frequencies: vec![],
+            magnitudes: vec![],
+            peaks: vec![],
+            width: Length::Fill,
+            height: Length::Fill,
+            curve_color: Color::from([0.1, 1.0, 0.2, 1.0]),
+            peak_color: Color::from([1.0, 1.0, 0.2, 0.6]),
+            grid_color: Color::from([0.0, 0.0, 0.0, 0.3]),
+            text_color: Color::from([0.0, 0.0, 0.0, 0.8]),
+        }
+    }
+
+    /// Sets the displayed spectrum, combining the power of all channels.
+    pub fn set_values(&mut self, data: &SpectrumData) {
+        self.frequencies = data.frequencies.clone();
+        self.magnitudes = data.combined().iter().map(|m| m.0).collect();
+        self.peaks = data.combined_peaks().iter().map(|p| p.0).collect();
+    }
+
+    /// Sets the displayed spectrum from a single channel.
+    pub fn set_channel_values(&mut self, data: &SpectrumData, channel: usize) {
+        self.frequencies = data.frequencies.clone();
+        self.magnitudes = data.magnitudes[channel].iter().map(|d| d.0).collect();
+        self.peaks = data.peaks[channel].iter().map(|d| d.0).collect();
+    }
+
+    /// Sets the displayed frequency range, in Hertz.
+    pub fn set_frequency_range(&mut self, min: f64, max: f64) {
+        self.frequency_range = Range {
+            min: min.log10(),
+            max: max.log10(),
+        };
+    }
+
+    /// Sets the displayed level range, in decibels.
+    pub fn set_level_range(&mut self, min: f64, max: f64) {
+        self.level_range = Range { min, max };
+    }
+
+    /// Returns the tilt applied to the spectrum, in decibels per octave around 1 kHz.
+    pub fn tilt(&self) -> f64 {
+        self.tilt
+    }
+
+    /// Sets the tilt applied to the spectrum, in decibels per octave around 1 kHz. A tilt of
+    /// 3 to 4.5 dB/oct shows pink noise and most music as a flat spectrum.
+    pub fn set_tilt(&mut self, db_per_octave: f64) {
+        self.tilt = db_per_octave;
+    }
+
+    pub fn width(mut self, width: Length) -> Self {
+        self.width = width;
+        self
+    }
+
+    pub fn height(mut self, height: Length) -> Self {
+        self.height = height;
+        self
+    }
+
+    /// Returns the frequency in Hertz and the tilted level in decibels of the spectrum at the
+    /// given horizontal position, between 0 and 1.
+    pub fn readout(&self, x: f64) -> Option<(f64, f64)> {
+        let frequency = 10f64.powf(self.frequency_range.unmap(x));
+        let bin = self
+            .frequencies
+            .iter()
+            .position(|&f| f >= frequency)
+            .unwrap_or_else(|| self.frequencies.len().saturating_sub(1));
+        let level = *self.magnitudes.get(bin)?;
+        Some((frequency, level + self.tilt_at(frequency)))
+    }
+
+    fn tilt_at(&self, frequency: f64) -> f64 {
+        self.tilt * (frequency / 1000.0).log2()
+    }
+
+    fn point(&self, frequency: f64, level: f64, size: Size) -> Point {
+        let x = self.frequency_range.map(frequency.log10()) as f32;
+        let y = clamp(self.level_range.map(level + self.tilt_at(frequency))) as f32;
+        Point::new(x * size.width, (1.0 - y) * size.height)
+    }
+
+    fn curve(&self, levels: &[f64], size: Size) -> Path {
+        Path::new(|builder| {
+            let mut started = false;
+            for (&frequency, &level) in self.frequencies.iter().zip(levels) {
+                if frequency <= 0.0 {
+                    continue;
+                }
+                let point = self.point(frequency, level, size);
+                if started {
+                    builder.line_to(point);
+                } else {
+                    builder.move_to(point);
+                    started = true;
+                }
+            }
+        })
+    }
+
+    fn draw_grid(&self, frame: &mut Frame) {
+        let size = frame.size();
+        let stroke = Stroke {
+            color: self.grid_color,
+            width: 1.0,
+            ..Stroke::default()
+        };
+        for frequency in grid_frequencies(
+            10f64.powf(self.frequency_range.min),
+            10f64.powf(self.frequency_range.max),
+        ) {
+            let x = self.frequency_range.map(frequency.log10()) as f32 * size.width;
+            frame.stroke(
+                &Path::line(Point::new(x, 0.0), Point::new(x, size.height)),
+                stroke,
+            );
+            if is_decade_frequency(frequency) {
+                frame.fill_text(Text {
+                    content: format_frequency(frequency),
+                    position: Point::new(x + 2.0, size.height - 14.0),
+                    color: self.text_color,
+                    size: 12.0,
+                    ..Text::default()
+                });
+            }
+        }
+        let first = (self.level_range.min / self.grid_step_db).ceil() as i32;
+        let last = (self.level_range.max / self.grid_step_db).floor() as i32;
+        for step in first..=last {
+            let level = step as f64 * self.grid_step_db;
+            let y = (1.0 - self.level_range.map(level)) as f32 * size.height;
+            frame.stroke(
+                &Path::line(Point::new(0.0, y), Point::new(size.width, y)),
+                stroke,
+            );
+            frame.fill_text(Text {
+                content: format!("{}", level),
+                position: Point::new(2.0, y + 2.0),
+                color: self.text_color,
+                size: 12.0,
+                ..Text::default()
+            });
+        }
+    }
+
+    fn draw_readout(&self, frame: &mut Frame, cursor: Point) {
+        let size = frame.size();
+        if let Some((frequency, level)) = self.readout((cursor.x / size.width) as f64) {
+            frame.stroke(
+                &Path::line(Point::new(cursor.x, 0.0), Point::new(cursor.x, size.height)),
+                Stroke {
+                    color: self.text_color,
+                    width: 1.0,
+                    ..Stroke::default()
+                },
+            );
+            frame.fill_text(Text {
+                content: format!("{}  {:.1} dB", format_frequency(frequency), level),
+                position: Point::new(cursor.x + 4.0, 4.0),
+                color: self.text_color,
+                size: 14.0,
+                ..Text::default()
+            });
+        }
+    }
+
+    fn draw_frame(&self, frame: &mut Frame, cursor: Option<Point>) {
+        let size = frame.size();
+        frame.fill(
+            &Path::rectangle(Point::new(0.0, 0.0), size),
+            Color::from([0.0, 0.0, 0.0, 0.1]),
+        );
+        self.draw_grid(frame);
+        frame.stroke(
+            &self.curve(&self.peaks, size),
+            Stroke {
+                color: self.peak_color,
+                width: 1.0,
+                ..Stroke::default()
+            },
+        );
+        frame.stroke(
+            &self.curve(&self.magnitudes, size),
+            Stroke {
+                color: self.curve_color,
+                width: 1.5,
+                ..Stroke::default()
+            },
+        );
+        if let Some(cursor) = cursor {
+            self.draw_readout(frame, cursor);
+        }
+    }
+}
+
+impl Default for Spectrum {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl canvas::Drawable for Spectrum {
+    fn draw(&self, frame: &mut Frame) {
+        self.draw_frame(frame, None);
+    }
+}
+
+impl<Message> Widget<Message, Renderer> for Spectrum {
+    fn width(&self) -> Length {
+        self.width
+    }
+
+    fn height(&self) -> Length {
+        self.height
+    }
+
+    fn layout(&self, _renderer: &Renderer, limits: &Limits) -> Node {
+        let limits = limits.width(self.width).height(self.height);
+        Node::new(limits.resolve(Size::ZERO))
+    }
+
+    fn draw(
+        &self,
+        _renderer: &mut Renderer,
+        _defaults: &Defaults,
+        layout: Layout<'_>,
+        cursor_position: Point,
+    ) -> (Primitive, MouseCursor) {
+        let bounds = layout.bounds();
+        let origin = Point::new(bounds.x, bounds.y);
+        let cursor = if bounds.contains(cursor_position) {
+            Some(Point::new(
+                cursor_position.x - bounds.x,
+                cursor_position.y - bounds.y,
+            ))
+        } else {
+            None
+        };
+        let mut frame = Frame::new(bounds.width, bounds.height);
+        self.draw_frame(&mut frame, cursor);
+        (
+            Primitive::Cached {
+                origin,
+                cache: Arc::new(frame.into_primitive()),
+            },
+            if cursor.is_some() {
+                MouseCursor::Pointer
+            } else {
+                MouseCursor::Idle
+            },
+        )
+    }
+
+    fn hash_layout(&self, state: &mut Hasher) {
+        std::any::TypeId::of::<Spectrum>().hash(state);
+        self.width.hash(state);
+        self.height.hash(state);
+    }
+}
+
+impl<'a, Message> From<Spectrum> for Element<'a, Message, Renderer> {
+    fn from(spectrum: Spectrum) -> Element<'a, Message, Renderer> {
+        Element::new(spectrum)
+    }
+}
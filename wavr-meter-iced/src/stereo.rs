@@ -0,0 +1,211 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+use std::f32::consts::FRAC_1_SQRT_2;
+
+use iced::canvas::{self, Path, Stroke, Text};
+use iced_native::{Color, Point, Size};
+use iced_wgpu::widget::canvas::Frame;
+
+use wavr_meter::{ScopePoint, StereoData};
+
+use crate::core::Range;
+use crate::meter::clamp;
+
+/// Horizontal phase correlation bar, from -1 on the left to +1 on the right, with a marker for the
+/// stereo balance.
+#[derive(Copy, Clone, Debug)]
+pub struct CorrelationBar {
+    range: Range<f64>,
+    correlation: f64,
+    balance: f64,
+    positive_color: Color,
+    negative_color: Color,
+    balance_color: Color,
+}
+
+impl CorrelationBar {
+    pub fn new() -> Self {
+        Self {
+            range: Range {
+                min: -1.0,
+                max: 1.0,
+            },
+            correlation: 0.0,
+            balance: 0.0,
+            positive_color: Color::from([0.1, 1.0, 0.2, 1.0]),
+            negative_color: Color::from([1.0, 0.2, 0.1, 1.0]),
+            balance_color: Color::from([0.2, 0.3, 1.0, 0.8]),
+        }
+    }
+
+    pub fn set_values(&mut self, data: &StereoData) {
+        self.correlation = data.correlation;
+        self.balance = data.balance;
+    }
+}
+
+impl Default for CorrelationBar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl canvas::Drawable for CorrelationBar {
+    fn draw(&self, frame: &mut Frame) {
+        let size = frame.size();
+        let center = self.range.map(0.0) as f32 * size.width;
+        let value = clamp(self.range.map(self.correlation)) as f32 * size.width;
+        let balance = clamp(self.range.map(self.balance)) as f32 * size.width;
+
+        frame.fill(
+            &Path::rectangle(Point::new(0.0, 0.0), size),
+            Color::from([0.0, 0.0, 0.0, 0.1]),
+        );
+        frame.fill(
+            &Path::rectangle(
+                Point::new(center.min(value), 0.0),
+                Size::new((value - center).abs(), size.height),
+            ),
+            if self.correlation < 0.0 {
+                self.negative_color
+            } else {
+                self.positive_color
+            },
+        );
+        frame.stroke(
+            &Path::line(Point::new(center, 0.0), Point::new(center, size.height)),
+            Stroke {
+                color: Color::from([0.0, 0.0, 0.0, 0.5]),
+                width: 1.0,
+                ..Stroke::default()
+            },
+        );
+        frame.stroke(
+            &Path::line(
+                Point::new(balance, size.height * 0.5),
+                Point::new(balance, size.height),
+            ),
+            Stroke {
+                color: self.balance_color,
+                width: 2.0,
+                ..Stroke::default()
+            },
+        );
+    }
+}
+
+/// Vector scope (goniometer), plotting the mid/side points with mid on the vertical axis and the
+/// left and right channels along the diagonals.
+#[derive(Clone, Debug)]
+pub struct VectorScope {
+    points: Vec<ScopePoint>,
+    zoom: f32,
+    point_color: Color,
+    axis_color: Color,
+    text_color: Color,
+}
+
+impl VectorScope {
+    pub fn new() -> Self {
+        Self {
+            points: vec![],
+            zoom: 1.0,
+            point_color: Color::from([0.1, 1.0, 0.2, 0.6]),
+            axis_color: Color::from([0.0, 0.0, 0.0, 0.3]),
+            text_color: Color::from([0.0, 0.0, 0.0, 0.8]),
+        }
+    }
+
+    pub fn set_values(&mut self, data: &StereoData) {
+        self.points = data.points.clone();
+    }
+
+    /// Returns the display zoom factor. At a zoom of 1, full-scale signals reach the edge.
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    /// Sets the display zoom factor.
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.zoom = zoom;
+    }
+}
+
+impl Default for VectorScope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl canvas::Drawable for VectorScope {
+    fn draw(&self, frame: &mut Frame) {
+        let size = frame.size();
+        let center = Point::new(size.width / 2.0, size.height / 2.0);
+        let radius = size.width.min(size.height) / 2.0;
+        let scale = radius * FRAC_1_SQRT_2 * self.zoom;
+        let diagonal = radius * FRAC_1_SQRT_2;
+        let axis = Stroke {
+            color: self.axis_color,
+            width: 1.0,
+            ..Stroke::default()
+        };
+
+        frame.fill(
+            &Path::rectangle(Point::new(0.0, 0.0), size),
+            Color::from([0.0, 0.0, 0.0, 0.1]),
+        );
+        frame.stroke(
+            &Path::line(
+                Point::new(center.x, center.y - radius),
+                Point::new(center.x, center.y + radius),
+            ),
+            axis,
+        );
+        frame.stroke(
+            &Path::line(
+                Point::new(center.x - radius, center.y),
+                Point::new(center.x + radius, center.y),
+            ),
+            axis,
+        );
+        frame.stroke(
+            &Path::line(
+                Point::new(center.x - diagonal, center.y - diagonal),
+                Point::new(center.x + diagonal, center.y + diagonal),
+            ),
+            axis,
+        );
+        frame.stroke(
+            &Path::line(
+                Point::new(center.x + diagonal, center.y - diagonal),
+                Point::new(center.x - diagonal, center.y + diagonal),
+            ),
+            axis,
+        );
+        for (label, x, y) in &[
+            ("M", center.x + 2.0, center.y - radius),
+            ("L", center.x - diagonal - 10.0, center.y - diagonal - 14.0),
+            ("R", center.x + diagonal + 2.0, center.y - diagonal - 14.0),
+        ] {
+            frame.fill_text(Text {
+                content: label.to_string(),
+                position: Point::new(*x, *y),
+                color: self.text_color,
+                size: 12.0,
+                ..Text::default()
+            });
+        }
+
+        let dots = Path::new(|builder| {
+            for point in &self.points {
+                let x = center.x - point.side as f32 * scale;
+                let y = center.y - point.mid as f32 * scale;
+                builder.rectangle(Point::new(x - 0.75, y - 0.75), Size::new(1.5, 1.5));
+            }
+        });
+        frame.fill(&dots, self.point_color);
+    }
+}
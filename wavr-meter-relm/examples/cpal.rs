@@ -141,7 +141,8 @@ fn build_stream<S: cpal::Sample>(
     config: &cpal::StreamConfig,
     sender: Sender<AppMessages>,
 ) -> Result<Stream, BuildStreamError> {
-    let mut meter = WavrMeter::new(config.channels as u32, config.sample_rate.0);
+    let mut meter = WavrMeter::new(config.channels as u32, config.sample_rate.0)
+        .expect("unsupported input format");
     let channels = config.channels as usize;
     device.build_input_stream::<S, _, _>(
         &config,
@@ -149,11 +150,11 @@ fn build_stream<S: cpal::Sample>(
             let buf = data.iter().map(|v| v.to_f32() as f64).collect::<Vec<_>>();
             let buffer = AudioBuffer::new(channels, &buf);
             meter.add_samples(&buffer);
-            sender
-                .send(AppMessages::MeterMessage(WidgetMessages::Value(
-                    meter.get_values(),
-                )))
-                .unwrap();
+            if let Some(values) = meter.get_values() {
+                sender
+                    .send(AppMessages::MeterMessage(WidgetMessages::Value(values)))
+                    .unwrap();
+            }
         },
         error_fn,
     )
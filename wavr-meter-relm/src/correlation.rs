@@ -0,0 +1,147 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gtk::{Inhibit, StyleContextExt, WidgetExt};
+use relm::{interval, Relm, Update, Widget};
+use relm_derive::Msg;
+
+use wavr_meter::StereoData;
+
+use crate::range::Range;
+
+#[derive(Msg, Clone, Debug)]
+pub enum Messages {
+    Value(StereoData),
+    Redraw,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct CorrelationBarModel {
+    correlation: f64,
+    balance: f64,
+    range: Range<f64>,
+}
+
+/// Horizontal phase correlation bar, from -1 on the left to +1 on the right, with a marker for the
+/// stereo balance.
+pub struct CorrelationBar {
+    root: gtk::DrawingArea,
+    model: Rc<RefCell<CorrelationBarModel>>,
+}
+
+impl CorrelationBarModel {
+    fn position(&self, value: f64, width: f64) -> f64 {
+        self.range.map(value).max(0.0).min(1.0) * width
+    }
+
+    fn draw(&self, cr: &cairo::Context, width: f64, height: f64) {
+        let center = self.position(0.0, width);
+        let value = self.position(self.correlation, width);
+        let balance = self.position(self.balance, width);
+
+        cr.set_source_rgba(0.0, 0.0, 0.0, 0.1);
+        cr.rectangle(0.0, 0.0, width, height);
+        cr.fill();
+
+        if self.correlation < 0.0 {
+            cr.set_source_rgb(1.0, 0.2, 0.1);
+        } else {
+            cr.set_source_rgb(0.1, 1.0, 0.2);
+        }
+        cr.rectangle(center.min(value), 0.0, (value - center).abs(), height);
+        cr.fill();
+
+        cr.set_source_rgba(0.0, 0.0, 0.0, 0.5);
+        cr.set_line_width(1.);
+        cr.move_to(center, 0.0);
+        cr.line_to(center, height);
+        cr.stroke();
+
+        cr.set_source_rgba(0.2, 0.3, 1.0, 0.8);
+        cr.set_line_width(2.);
+        cr.move_to(balance, height * 0.5);
+        cr.line_to(balance, height);
+        cr.stroke();
+
+        cr.set_source_rgb(0.0, 0.0, 0.0);
+        let text = format!("{:+.2}", self.correlation);
+        let extends = cr.text_extents(&text);
+        cr.move_to(width - extends.width - 2.0, extends.height + 2.0);
+        cr.text_path(&text);
+        cr.fill();
+    }
+}
+
+impl Update for CorrelationBar {
+    type Model = CorrelationBarModel;
+    type ModelParam = ();
+    type Msg = Messages;
+
+    fn model(_: &Relm<Self>, _: Self::ModelParam) -> Self::Model {
+        CorrelationBarModel {
+            correlation: 0.0,
+            balance: 0.0,
+            range: Range {
+                min: -1.0,
+                max: 1.0,
+            },
+        }
+    }
+
+    fn subscriptions(&mut self, relm: &Relm<Self>) {
+        interval(relm.stream(), 16, || Messages::Redraw);
+    }
+
+    fn update(&mut self, event: Self::Msg) {
+        match event {
+            Messages::Value(data) => {
+                let mut model = self.model.borrow_mut();
+                model.correlation = data.correlation;
+                model.balance = data.balance;
+            }
+            Messages::Redraw => self.root.queue_draw(),
+        }
+    }
+}
+
+impl Widget for CorrelationBar {
+    type Root = gtk::DrawingArea;
+
+    fn init_view(&mut self) {
+        let model = self.model.clone();
+        self.root.connect_draw(move |da, cr| {
+            let style = da.get_style_context();
+            let alloc = da.get_allocation();
+            let width = alloc.width as f64;
+            let height = alloc.height as f64;
+
+            gtk::render_background(&style, cr, 0.0, 0.0, width, height);
+            model.borrow().draw(cr, width, height);
+
+            Inhibit(false)
+        });
+    }
+
+    fn root(&self) -> Self::Root {
+        self.root.clone()
+    }
+
+    fn view(_relm: &Relm<Self>, model: CorrelationBarModel) -> Self {
+        let root = gtk::DrawingAreaBuilder::new()
+            .hexpand(true)
+            .width_request(100)
+            .height_request(16)
+            .build();
+
+        Self {
+            root,
+            model: Rc::from(RefCell::from(model)),
+        }
+    }
+}
@@ -0,0 +1,291 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gtk::{Inhibit, StyleContextExt, WidgetExt};
+use relm::{interval, Relm, Update, Widget};
+use relm_derive::Msg;
+
+use wavr_meter::decibel::Decibel;
+use wavr_meter::LoudnessHistory;
+
+use crate::range::Range;
+
+#[derive(Msg, Clone, Debug)]
+pub enum Messages {
+    Value(LoudnessHistory),
+    Integrated(Decibel),
+    /// Target loudness in LUFS, and tolerance in LU.
+    Target(f64, f64),
+    /// Displayed time span, in seconds.
+    Window(f64),
+    LevelRange(f64, f64),
+    Redraw,
+}
+
+#[derive(Clone, Debug)]
+pub struct HistoryGraphModel {
+    level_range: Range<f64>,
+    grid_step: f64,
+    window: f64,
+    target: f64,
+    tolerance: f64,
+    integrated: Option<f64>,
+    /// Points as (seconds before the newest entry, momentary, short-term).
+    points: Vec<(f64, f64, f64)>,
+}
+
+/// Scrolling loudness history graph, with the momentary and short-term loudness over time, a
+/// target line with its tolerance band and a readout of the integrated loudness. Scrolling over
+/// the graph zooms the time axis.
+pub struct HistoryGraph {
+    root: gtk::DrawingArea,
+    model: Rc<RefCell<HistoryGraphModel>>,
+}
+
+impl HistoryGraphModel {
+    fn set_values(&mut self, history: &LoudnessHistory) {
+        let latest = match history.latest() {
+            Some(entry) => entry.position,
+            None => {
+                self.points.clear();
+                return;
+            }
+        };
+        let sample_rate = history.sample_rate() as f64;
+        let window = (self.window * sample_rate).ceil() as usize;
+        self.points = history
+            .range(latest.saturating_sub(window), latest + 1)
+            .map(|e| {
+                (
+                    (latest - e.position) as f64 / sample_rate,
+                    e.momentary.0,
+                    e.short_term.0,
+                )
+            })
+            .collect();
+    }
+
+    fn set_window(&mut self, seconds: f64) {
+        self.window = seconds.max(1.0).min(3600.0);
+    }
+
+    fn x(&self, age: f64, width: f64) -> f64 {
+        (1.0 - age / self.window) * width
+    }
+
+    fn y(&self, level: f64, height: f64) -> f64 {
+        (1.0 - self.level_range.map(level).max(0.0).min(1.0)) * height
+    }
+
+    fn draw_grid(&self, cr: &cairo::Context, width: f64, height: f64) {
+        let first = (self.level_range.min / self.grid_step).ceil() as i32;
+        let last = (self.level_range.max / self.grid_step).floor() as i32;
+        let time_step = time_grid_step(self.window);
+        let ages: Vec<f64> = (1..)
+            .map(|i| i as f64 * time_step)
+            .take_while(|&age| age < self.window)
+            .collect();
+
+        cr.set_source_rgba(0.0, 0.0, 0.0, 0.3);
+        cr.set_line_width(1.);
+        for step in first..=last {
+            let y = self.y(step as f64 * self.grid_step, height);
+            cr.move_to(0.0, y);
+            cr.line_to(width, y);
+        }
+        for &age in &ages {
+            let x = self.x(age, width);
+            cr.move_to(x, 0.0);
+            cr.line_to(x, height);
+        }
+        cr.stroke();
+
+        cr.set_source_rgb(0.0, 0.0, 0.0);
+        for step in first..=last {
+            let level = step as f64 * self.grid_step;
+            let text = format!("{}", level);
+            let extends = cr.text_extents(&text);
+            cr.move_to(2.0, extends.height + self.y(level, height) + 2.0);
+            cr.text_path(&text);
+        }
+        for &age in &ages {
+            cr.move_to(self.x(age, width) + 2.0, height - 2.0);
+            cr.text_path(&format!("-{}s", age));
+        }
+        cr.fill();
+    }
+
+    fn draw_curve(
+        &self,
+        cr: &cairo::Context,
+        width: f64,
+        height: f64,
+        level: fn(&(f64, f64, f64)) -> f64,
+    ) {
+        let mut started = false;
+        for point in &self.points {
+            let level = level(point);
+            if !level.is_finite() {
+                started = false;
+                continue;
+            }
+            let (x, y) = (self.x(point.0, width), self.y(level, height));
+            if started {
+                cr.line_to(x, y);
+            } else {
+                cr.move_to(x, y);
+                started = true;
+            }
+        }
+        cr.stroke();
+    }
+
+    fn draw(&self, cr: &cairo::Context, width: f64, height: f64) {
+        cr.set_source_rgba(0.0, 0.0, 0.0, 0.1);
+        cr.rectangle(0.0, 0.0, width, height);
+        cr.fill();
+
+        let band_top = self.y(self.target + self.tolerance, height);
+        let band_bottom = self.y(self.target - self.tolerance, height);
+        cr.set_source_rgba(0.2, 0.3, 1.0, 0.15);
+        cr.rectangle(0.0, band_top, width, band_bottom - band_top);
+        cr.fill();
+
+        self.draw_grid(cr, width, height);
+
+        let target = self.y(self.target, height);
+        cr.set_source_rgba(0.2, 0.3, 1.0, 0.8);
+        cr.set_line_width(1.5);
+        cr.move_to(0.0, target);
+        cr.line_to(width, target);
+        cr.stroke();
+
+        cr.set_source_rgba(0.1, 1.0, 0.2, 0.5);
+        cr.set_line_width(1.);
+        self.draw_curve(cr, width, height, |p| p.1);
+
+        cr.set_source_rgb(0.1, 1.0, 0.2);
+        cr.set_line_width(2.);
+        self.draw_curve(cr, width, height, |p| p.2);
+
+        let text = match self.integrated {
+            Some(integrated) => format!("I: {:2.1} LUFS", integrated),
+            None => String::from("I: --.- LUFS"),
+        };
+        let extends = cr.text_extents(&text);
+        cr.set_source_rgba(0.0, 0.0, 0.0, 0.8);
+        cr.move_to(width - extends.width - 4.0, extends.height + 4.0);
+        cr.text_path(&text);
+        cr.fill();
+    }
+}
+
+impl Update for HistoryGraph {
+    type Model = HistoryGraphModel;
+    type ModelParam = ();
+    type Msg = Messages;
+
+    fn model(_: &Relm<Self>, _: Self::ModelParam) -> Self::Model {
+        HistoryGraphModel {
+            level_range: Range {
+                min: -50.0,
+                max: -5.0,
+            },
+            grid_step: 5.0,
+            window: 60.0,
+            target: -23.0,
+            tolerance: 1.0,
+            integrated: None,
+            points: vec![],
+        }
+    }
+
+    fn subscriptions(&mut self, relm: &Relm<Self>) {
+        interval(relm.stream(), 16, || Messages::Redraw);
+    }
+
+    fn update(&mut self, event: Self::Msg) {
+        match event {
+            Messages::Value(history) => self.model.borrow_mut().set_values(&history),
+            Messages::Integrated(integrated) => {
+                self.model.borrow_mut().integrated = Some(integrated.0).filter(|i| i.is_finite())
+            }
+            Messages::Target(target, tolerance) => {
+                let mut model = self.model.borrow_mut();
+                model.target = target;
+                model.tolerance = tolerance.abs();
+            }
+            Messages::Window(seconds) => self.model.borrow_mut().set_window(seconds),
+            Messages::LevelRange(min, max) => {
+                self.model.borrow_mut().level_range = Range { min, max }
+            }
+            Messages::Redraw => self.root.queue_draw(),
+        }
+    }
+}
+
+impl Widget for HistoryGraph {
+    type Root = gtk::DrawingArea;
+
+    fn init_view(&mut self) {
+        self.root.add_events(gdk::EventMask::SCROLL_MASK);
+
+        let model = self.model.clone();
+        self.root.connect_scroll_event(move |_, event| {
+            let mut model = model.borrow_mut();
+            let window = model.window;
+            match event.get_direction() {
+                gdk::ScrollDirection::Up => model.set_window(window / 1.25),
+                gdk::ScrollDirection::Down => model.set_window(window * 1.25),
+                _ => {}
+            }
+            Inhibit(false)
+        });
+
+        let model = self.model.clone();
+        self.root.connect_draw(move |da, cr| {
+            let style = da.get_style_context();
+            let alloc = da.get_allocation();
+            let width = alloc.width as f64;
+            let height = alloc.height as f64;
+
+            gtk::render_background(&style, cr, 0.0, 0.0, width, height);
+            model.borrow().draw(cr, width, height);
+
+            Inhibit(false)
+        });
+    }
+
+    fn root(&self) -> Self::Root {
+        self.root.clone()
+    }
+
+    fn view(_relm: &Relm<Self>, model: HistoryGraphModel) -> Self {
+        let root = gtk::DrawingAreaBuilder::new()
+            .vexpand(true)
+            .hexpand(true)
+            .width_request(200)
+            .height_request(100)
+            .build();
+
+        Self {
+            root,
+            model: Rc::from(RefCell::from(model)),
+        }
+    }
+}
+
+/// Returns a time grid step, in seconds, giving between 3 and 12 lines over the window.
+fn time_grid_step(window: f64) -> f64 {
+    [1.0, 5.0, 10.0, 30.0, 60.0, 300.0, 600.0, 1800.0, 3600.0]
+        .iter()
+        .cloned()
+        .find(|step| window / step <= 12.0)
+        .unwrap_or(3600.0)
+}
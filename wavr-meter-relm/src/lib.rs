@@ -8,18 +8,27 @@ use gtk::{BoxExt, BuildableExt, ContainerExt, LabelExt, WidgetExt};
 use relm::{Component, ContainerWidget, Relm, Update, Widget};
 use relm_derive::Msg;
 
-use wavr_meter::WavrMeterData;
+use wavr_meter::{ChannelLayout, KScale, WavrMeterData};
 
 use crate::meter::SingleMeter;
 
+pub mod correlation;
+pub mod history;
 mod meter;
 pub mod mini;
 mod range;
+pub mod scope;
+pub mod spectrum;
 
 #[derive(Msg, Clone, Debug)]
 pub enum Messages {
     Setup(u16),
+    /// Channel layout used to label and group the meters. Values with a different layout also
+    /// rebuild the meters.
+    Layout(ChannelLayout),
     Value(WavrMeterData),
+    /// K-System scale of the meters, or `None` for a dBFS scale.
+    Scale(Option<KScale>),
 }
 
 pub struct WavrMeterWidget {
@@ -27,6 +36,43 @@ pub struct WavrMeterWidget {
     loudness_label: gtk::Label,
     meters_box: gtk::Box,
     meters: Vec<Component<SingleMeter>>,
+    layout: ChannelLayout,
+    scale: Option<KScale>,
+}
+
+impl WavrMeterWidget {
+    /// Rebuilds the meters for the given layout: one box per group of channel roles, with each
+    /// meter labelled by its role.
+    fn set_layout(&mut self, layout: ChannelLayout) {
+        for child in self.meters_box.get_children() {
+            self.meters_box.remove(&child);
+        }
+        self.meters.clear();
+        for (_, channels) in layout.groups() {
+            let group_box = gtk::BoxBuilder::new()
+                .orientation(gtk::Orientation::Horizontal)
+                .spacing(2)
+                .build();
+            for channel in channels {
+                let column = gtk::BoxBuilder::new()
+                    .orientation(gtk::Orientation::Vertical)
+                    .spacing(2)
+                    .build();
+                let meter = column.add_widget::<SingleMeter>(());
+                meter.emit(meter::Messages::Scale(self.scale));
+                let label = gtk::LabelBuilder::new()
+                    .halign(gtk::Align::Center)
+                    .label(&layout.label(channel))
+                    .build();
+                column.pack_start(&label, false, true, 0);
+                group_box.pack_start(&column, true, true, 0);
+                self.meters.push(meter);
+            }
+            self.meters_box.pack_start(&group_box, true, true, 0);
+        }
+        self.layout = layout;
+        self.meters_box.show_all();
+    }
 }
 
 impl Update for WavrMeterWidget {
@@ -41,18 +87,21 @@ impl Update for WavrMeterWidget {
     fn update(&mut self, event: Self::Msg) {
         match event {
             Messages::Setup(channelcount) => {
-                for _ in 0..channelcount {
-                    self.meters
-                        .push(self.meters_box.add_widget::<SingleMeter>(()));
-                }
-                self.meters_box.show_all();
+                self.set_layout(ChannelLayout::default_for(channelcount as usize))
             }
+            Messages::Layout(layout) => self.set_layout(layout),
             Messages::Value(data) => {
+                if data.layout != self.layout {
+                    self.set_layout(data.layout.clone());
+                }
                 for (i, meter) in self.meters.iter().enumerate() {
                     meter.emit(meter::Messages::Value(
                         data.peak[i].into(),
                         data.loudness.clone(),
                     ));
+                    if let Some(rms) = data.rms.get(i) {
+                        meter.emit(meter::Messages::Rms(rms.into()));
+                    }
                 }
                 let loudness_text = if data.loudness.0 < -120.0 {
                     format!("--.-- LUFS")
@@ -61,6 +110,12 @@ impl Update for WavrMeterWidget {
                 };
                 self.loudness_label.set_text(&loudness_text);
             }
+            Messages::Scale(scale) => {
+                self.scale = scale;
+                for meter in &self.meters {
+                    meter.emit(meter::Messages::Scale(scale));
+                }
+            }
         }
     }
 }
@@ -81,7 +136,7 @@ impl Widget for WavrMeterWidget {
             .build();
         let meters_box = gtk::BoxBuilder::new()
             .orientation(gtk::Orientation::Horizontal)
-            .spacing(5)
+            .spacing(12)
             .hexpand(true)
             .vexpand(true)
             .build();
@@ -97,6 +152,8 @@ impl Widget for WavrMeterWidget {
             meters_box,
             loudness_label,
             meters: vec![],
+            layout: ChannelLayout::default_for(0),
+            scale: None,
         }
     }
 }
@@ -5,7 +5,7 @@
  */
 
 use std::borrow::Borrow;
-use std::cell::{Ref, RefCell};
+use std::cell::{Cell, Ref, RefCell};
 use std::ops::Deref;
 use std::rc::Rc;
 
@@ -13,25 +13,30 @@ use gtk::{Inhibit, StyleContextExt, WidgetExt};
 use relm::{connect, interval, DrawHandler, Relm, Update, Widget};
 use relm_derive::Msg;
 
-use wavr_meter::decibel::{Decibel, LUFS};
+use wavr_meter::decibel::Decibel;
+use wavr_meter::KScale;
 
 use crate::range::Range;
 
 #[derive(Msg, Clone, Debug)]
 pub enum Messages {
-    Value(Decibel, LUFS),
+    Value(Decibel, Decibel),
+    Rms(Decibel),
+    Scale(Option<KScale>),
     Redraw,
 }
 
 pub struct SingleMeterModel {
     peak: Decibel,
-    loudness: LUFS,
+    loudness: Decibel,
+    rms: Option<Decibel>,
 }
 
 pub struct SingleMeter {
     data: Rc<RefCell<Option<SingleMeterModel>>>,
     root: gtk::DrawingArea,
     range: Range<f64>,
+    scale: Rc<Cell<Option<KScale>>>,
 
     draw_handler: DrawHandler<gtk::DrawingArea>,
 }
@@ -52,8 +57,20 @@ impl Update for SingleMeter {
     fn update(&mut self, event: Self::Msg) {
         match event {
             Messages::Value(peak, loudness) => {
-                *self.data.borrow_mut() = Some(SingleMeterModel { peak, loudness });
+                let mut data = self.data.borrow_mut();
+                let rms = data.as_ref().and_then(|d| d.rms);
+                *data = Some(SingleMeterModel {
+                    peak,
+                    loudness,
+                    rms,
+                });
             }
+            Messages::Rms(rms) => {
+                if let Some(data) = self.data.borrow_mut().as_mut() {
+                    data.rms = Some(rms);
+                }
+            }
+            Messages::Scale(scale) => self.scale.set(scale),
             Messages::Redraw => {
                 self.root.queue_draw();
                 /*let ctx = self.draw_handler.get_context();
@@ -118,16 +135,23 @@ impl Widget for SingleMeter {
     fn init_view(&mut self) {
         let model = self.data.clone();
         let range = self.range;
+        let scale = self.scale.clone();
 
         self.root.connect_draw(move |da, cr| {
+            let scale = scale.get();
             let alloc = da.get_allocation();
             let width = alloc.width as f64;
             let height = alloc.height as f64;
             let model: Ref<_> = model.deref().borrow();
             let style = da.get_style_context();
             let pattern = cairo::LinearGradient::new(0.0, 0.0, 0.0, height);
-            let zero_point = 1.0 - range.map(0.0);
-            let half_point = 1.0 - range.map(-6.0);
+            // K-System meters turn yellow at their 0 mark, and red 4 dB above it
+            let (red, yellow) = match scale {
+                Some(scale) => (4.0 - scale.headroom(), -scale.headroom()),
+                None => (0.0, -6.0),
+            };
+            let zero_point = 1.0 - range.map(red);
+            let half_point = 1.0 - range.map(yellow);
             pattern.set_extend(cairo::Extend::Pad);
             pattern.add_color_stop_rgb(0.0, 1.0, 0.2, 0.1);
             pattern.add_color_stop_rgb(zero_point, 1.0, 0.2, 0.1);
@@ -158,23 +182,32 @@ impl Widget for SingleMeter {
                 cr.rectangle(0.0, loudness_inv, width, loudness);
                 cr.fill();
 
+                if let Some(rms) = data.rms {
+                    let rms_pc = range.map(rms.0).max(0.0).min(1.0);
+                    cr.set_source_rgba(1.0, 0.6, 0.1, 0.7);
+                    cr.rectangle(
+                        width * 0.35,
+                        height * (1.0 - rms_pc),
+                        width * 0.3,
+                        height * rms_pc,
+                    );
+                    cr.fill();
+                }
+
+                let ticks = ticks(&range, scale);
                 cr.set_source_rgba(0.0, 0.0, 0.0, 0.3);
                 cr.set_line_width(1.);
-                for y in ((range.min as i32)..=0)
-                    .step_by(6)
-                    .map(|m| (1.0 - range.map(m as f64)) * height)
-                {
+                for y in ticks.iter().map(|(m, _)| (1.0 - range.map(*m)) * height) {
                     cr.move_to(0.0, y);
                     cr.line_to(width, y);
                 }
                 cr.stroke();
 
                 cr.set_source_rgb(0.0, 0.0, 0.0);
-                for (dB, y) in ((range.min as i32)..=0)
-                    .step_by(6)
-                    .map(|m| (m, height * (1.0 - range.map(m as f64))))
+                for (text, y) in ticks
+                    .iter()
+                    .map(|(m, text)| (text, height * (1.0 - range.map(*m))))
                 {
-                    let text = format!("{}", dB);
                     let extends = cr.text_extents(&text);
                     cr.move_to(2.0, extends.height + y + 2.0);
                     cr.text_path(&text);
@@ -202,8 +235,29 @@ impl Widget for SingleMeter {
                 min: -48.0,
                 max: 6.0,
             },
+            scale: Rc::new(Cell::new(None)),
             draw_handler: DrawHandler::new().unwrap(),
             root,
         }
     }
 }
+
+/// Returns the tick marks as dBFS levels and labels in the units of the scale: every 6 dB on a
+/// dBFS scale, every 4 dB on a K-System scale.
+fn ticks(range: &Range<f64>, scale: Option<KScale>) -> Vec<(f64, String)> {
+    match scale {
+        None => ((range.min as i32)..=0)
+            .step_by(6)
+            .map(|db| (db as f64, format!("{}", db)))
+            .collect(),
+        Some(scale) => {
+            let top = scale.headroom() as i32;
+            let bottom = top + range.min as i32;
+            ((bottom / 4 * 4)..=top)
+                .step_by(4)
+                .filter(|&k| k >= bottom)
+                .map(|k| (scale.from_scale(Decibel(k as f64)).0, format!("{:+}", k)))
+                .collect()
+        }
+    }
+}
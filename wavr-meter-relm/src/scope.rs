@@ -0,0 +1,146 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+
+use std::cell::RefCell;
+use std::f64::consts::FRAC_1_SQRT_2;
+use std::rc::Rc;
+
+use gtk::{Inhibit, StyleContextExt, WidgetExt};
+use relm::{interval, Relm, Update, Widget};
+use relm_derive::Msg;
+
+use wavr_meter::{ScopePoint, StereoData};
+
+#[derive(Msg, Clone, Debug)]
+pub enum Messages {
+    Value(StereoData),
+    Zoom(f64),
+    Redraw,
+}
+
+#[derive(Clone, Debug)]
+pub struct VectorScopeModel {
+    points: Vec<ScopePoint>,
+    zoom: f64,
+}
+
+/// Vector scope (goniometer), plotting the mid/side points with mid on the vertical axis and the
+/// left and right channels along the diagonals. At a zoom of 1, full-scale signals reach the edge.
+pub struct VectorScope {
+    root: gtk::DrawingArea,
+    model: Rc<RefCell<VectorScopeModel>>,
+}
+
+impl VectorScopeModel {
+    fn draw_axes(cr: &cairo::Context, cx: f64, cy: f64, radius: f64) {
+        let diagonal = radius * FRAC_1_SQRT_2;
+
+        cr.set_source_rgba(0.0, 0.0, 0.0, 0.3);
+        cr.set_line_width(1.);
+        cr.move_to(cx, cy - radius);
+        cr.line_to(cx, cy + radius);
+        cr.move_to(cx - radius, cy);
+        cr.line_to(cx + radius, cy);
+        cr.move_to(cx - diagonal, cy - diagonal);
+        cr.line_to(cx + diagonal, cy + diagonal);
+        cr.move_to(cx + diagonal, cy - diagonal);
+        cr.line_to(cx - diagonal, cy + diagonal);
+        cr.stroke();
+
+        cr.set_source_rgb(0.0, 0.0, 0.0);
+        for (label, x, y) in &[
+            ("M", cx + 2.0, cy - radius + 12.0),
+            ("L", cx - diagonal - 10.0, cy - diagonal),
+            ("R", cx + diagonal + 2.0, cy - diagonal),
+        ] {
+            cr.move_to(*x, *y);
+            cr.text_path(label);
+        }
+        cr.fill();
+    }
+
+    fn draw(&self, cr: &cairo::Context, width: f64, height: f64) {
+        let (cx, cy) = (width / 2.0, height / 2.0);
+        let radius = width.min(height) / 2.0;
+        let scale = radius * FRAC_1_SQRT_2 * self.zoom;
+
+        cr.set_source_rgba(0.0, 0.0, 0.0, 0.1);
+        cr.rectangle(0.0, 0.0, width, height);
+        cr.fill();
+
+        Self::draw_axes(cr, cx, cy, radius);
+
+        cr.set_source_rgba(0.1, 1.0, 0.2, 0.6);
+        for point in &self.points {
+            let x = cx - point.side * scale;
+            let y = cy - point.mid * scale;
+            cr.rectangle(x - 0.75, y - 0.75, 1.5, 1.5);
+        }
+        cr.fill();
+    }
+}
+
+impl Update for VectorScope {
+    type Model = VectorScopeModel;
+    type ModelParam = ();
+    type Msg = Messages;
+
+    fn model(_: &Relm<Self>, _: Self::ModelParam) -> Self::Model {
+        VectorScopeModel {
+            points: vec![],
+            zoom: 1.0,
+        }
+    }
+
+    fn subscriptions(&mut self, relm: &Relm<Self>) {
+        interval(relm.stream(), 16, || Messages::Redraw);
+    }
+
+    fn update(&mut self, event: Self::Msg) {
+        match event {
+            Messages::Value(data) => self.model.borrow_mut().points = data.points,
+            Messages::Zoom(zoom) => self.model.borrow_mut().zoom = zoom,
+            Messages::Redraw => self.root.queue_draw(),
+        }
+    }
+}
+
+impl Widget for VectorScope {
+    type Root = gtk::DrawingArea;
+
+    fn init_view(&mut self) {
+        let model = self.model.clone();
+        self.root.connect_draw(move |da, cr| {
+            let style = da.get_style_context();
+            let alloc = da.get_allocation();
+            let width = alloc.width as f64;
+            let height = alloc.height as f64;
+
+            gtk::render_background(&style, cr, 0.0, 0.0, width, height);
+            model.borrow().draw(cr, width, height);
+
+            Inhibit(false)
+        });
+    }
+
+    fn root(&self) -> Self::Root {
+        self.root.clone()
+    }
+
+    fn view(_relm: &Relm<Self>, model: VectorScopeModel) -> Self {
+        let root = gtk::DrawingAreaBuilder::new()
+            .vexpand(true)
+            .hexpand(true)
+            .width_request(100)
+            .height_request(100)
+            .build();
+
+        Self {
+            root,
+            model: Rc::from(RefCell::from(model)),
+        }
+    }
+}
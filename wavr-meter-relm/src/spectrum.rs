@@ -0,0 +1,267 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gtk::{Inhibit, StyleContextExt, WidgetExt};
+use relm::{interval, Relm, Update, Widget};
+use relm_derive::Msg;
+
+use wavr_meter::widget::{format_frequency, grid_frequencies, is_decade_frequency};
+use wavr_meter::SpectrumData;
+
+use crate::range::Range;
+
+#[derive(Msg, Clone, Debug)]
+pub enum Messages {
+    Value(SpectrumData),
+    Tilt(f64),
+    FrequencyRange(f64, f64),
+    LevelRange(f64, f64),
+    Redraw,
+}
+
+#[derive(Clone, Debug)]
+pub struct SpectrumModel {
+    frequency_range: Range<f64>,
+    level_range: Range<f64>,
+    grid_step_db: f64,
+    tilt: f64,
+    frequencies: Vec<f64>,
+    magnitudes: Vec<f64>,
+    peaks: Vec<f64>,
+    cursor: Option<(f64, f64)>,
+}
+
+/// Spectrum display, with a logarithmic frequency axis, a decibel grid, a peak-hold curve and a
+/// readout of the frequency and level under the mouse.
+pub struct Spectrum {
+    root: gtk::DrawingArea,
+    model: Rc<RefCell<SpectrumModel>>,
+}
+
+impl SpectrumModel {
+    fn set_values(&mut self, data: &SpectrumData) {
+        self.frequencies = data.frequencies.clone();
+        self.magnitudes = data.combined().iter().map(|m| m.0).collect();
+        self.peaks = data.combined_peaks().iter().map(|p| p.0).collect();
+    }
+
+    fn tilt_at(&self, frequency: f64) -> f64 {
+        self.tilt * (frequency / 1000.0).log2()
+    }
+
+    fn x(&self, frequency: f64, width: f64) -> f64 {
+        self.frequency_range.map(frequency.log10()) * width
+    }
+
+    fn y(&self, level: f64, height: f64) -> f64 {
+        (1.0 - self.level_range.map(level).clamp(0.0, 1.0)) * height
+    }
+
+    /// Returns the frequency in Hertz and the tilted level in decibels at the given horizontal
+    /// position, between 0 and 1.
+    fn readout(&self, x: f64) -> Option<(f64, f64)> {
+        let frequency = 10f64.powf(self.frequency_range.unmap(x));
+        let bin = self
+            .frequencies
+            .iter()
+            .position(|&f| f >= frequency)
+            .unwrap_or_else(|| self.frequencies.len().saturating_sub(1));
+        let level = *self.magnitudes.get(bin)?;
+        Some((frequency, level + self.tilt_at(frequency)))
+    }
+
+    fn draw_grid(&self, cr: &cairo::Context, width: f64, height: f64) {
+        cr.set_source_rgba(0.0, 0.0, 0.0, 0.3);
+        cr.set_line_width(1.);
+        let grid = grid_frequencies(
+            10f64.powf(self.frequency_range.min),
+            10f64.powf(self.frequency_range.max),
+        );
+        for &frequency in &grid {
+            let x = self.x(frequency, width);
+            cr.move_to(x, 0.0);
+            cr.line_to(x, height);
+        }
+        let first = (self.level_range.min / self.grid_step_db).ceil() as i32;
+        let last = (self.level_range.max / self.grid_step_db).floor() as i32;
+        for step in first..=last {
+            let y = self.y(step as f64 * self.grid_step_db, height);
+            cr.move_to(0.0, y);
+            cr.line_to(width, y);
+        }
+        cr.stroke();
+
+        cr.set_source_rgb(0.0, 0.0, 0.0);
+        for &frequency in grid.iter().filter(|&&f| is_decade_frequency(f)) {
+            cr.move_to(self.x(frequency, width) + 2.0, height - 2.0);
+            cr.text_path(&format_frequency(frequency));
+        }
+        for step in first..=last {
+            let level = step as f64 * self.grid_step_db;
+            let text = format!("{}", level);
+            let extends = cr.text_extents(&text);
+            cr.move_to(2.0, extends.height + self.y(level, height) + 2.0);
+            cr.text_path(&text);
+        }
+        cr.fill();
+    }
+
+    fn draw_curve(&self, cr: &cairo::Context, levels: &[f64], width: f64, height: f64) {
+        let mut started = false;
+        for (&frequency, &level) in self.frequencies.iter().zip(levels) {
+            if frequency <= 0.0 {
+                continue;
+            }
+            let x = self.x(frequency, width);
+            let y = self.y(level + self.tilt_at(frequency), height);
+            if started {
+                cr.line_to(x, y);
+            } else {
+                cr.move_to(x, y);
+                started = true;
+            }
+        }
+        cr.stroke();
+    }
+
+    fn draw_readout(&self, cr: &cairo::Context, width: f64, height: f64) {
+        let (x, _) = match self.cursor {
+            Some(cursor) => cursor,
+            None => return,
+        };
+        if let Some((frequency, level)) = self.readout(x / width) {
+            cr.set_source_rgba(0.0, 0.0, 0.0, 0.8);
+            cr.set_line_width(1.);
+            cr.move_to(x, 0.0);
+            cr.line_to(x, height);
+            cr.stroke();
+
+            let text = format!("{}  {:.1} dB", format_frequency(frequency), level);
+            let extends = cr.text_extents(&text);
+            cr.move_to(x + 4.0, extends.height + 4.0);
+            cr.text_path(&text);
+            cr.fill();
+        }
+    }
+
+    fn draw(&self, cr: &cairo::Context, width: f64, height: f64) {
+        cr.set_source_rgba(0.0, 0.0, 0.0, 0.1);
+        cr.rectangle(0.0, 0.0, width, height);
+        cr.fill();
+
+        self.draw_grid(cr, width, height);
+
+        cr.set_source_rgba(1.0, 1.0, 0.2, 0.6);
+        cr.set_line_width(1.);
+        self.draw_curve(cr, &self.peaks, width, height);
+
+        cr.set_source_rgb(0.1, 1.0, 0.2);
+        cr.set_line_width(1.5);
+        self.draw_curve(cr, &self.magnitudes, width, height);
+
+        self.draw_readout(cr, width, height);
+    }
+}
+
+impl Update for Spectrum {
+    type Model = SpectrumModel;
+    type ModelParam = ();
+    type Msg = Messages;
+
+    fn model(_: &Relm<Self>, _: Self::ModelParam) -> Self::Model {
+        SpectrumModel {
+            frequency_range: Range {
+                min: 20f64.log10(),
+                max: 20000f64.log10(),
+            },
+            level_range: Range {
+                min: -96.0,
+                max: 0.0,
+            },
+            grid_step_db: 12.0,
+            tilt: 0.0,
+            frequencies: vec![],
+            magnitudes: vec![],
+            peaks: vec![],
+            cursor: None,
+        }
+    }
+
+    fn subscriptions(&mut self, relm: &Relm<Self>) {
+        interval(relm.stream(), 16, || Messages::Redraw);
+    }
+
+    fn update(&mut self, event: Self::Msg) {
+        match event {
+            Messages::Value(data) => self.model.borrow_mut().set_values(&data),
+            Messages::Tilt(tilt) => self.model.borrow_mut().tilt = tilt,
+            Messages::FrequencyRange(min, max) => {
+                self.model.borrow_mut().frequency_range = Range {
+                    min: min.log10(),
+                    max: max.log10(),
+                }
+            }
+            Messages::LevelRange(min, max) => {
+                self.model.borrow_mut().level_range = Range { min, max }
+            }
+            Messages::Redraw => self.root.queue_draw(),
+        }
+    }
+}
+
+impl Widget for Spectrum {
+    type Root = gtk::DrawingArea;
+
+    fn init_view(&mut self) {
+        self.root
+            .add_events(gdk::EventMask::POINTER_MOTION_MASK | gdk::EventMask::LEAVE_NOTIFY_MASK);
+
+        let model = self.model.clone();
+        self.root.connect_motion_notify_event(move |_, event| {
+            model.borrow_mut().cursor = Some(event.get_position());
+            Inhibit(false)
+        });
+        let model = self.model.clone();
+        self.root.connect_leave_notify_event(move |_, _| {
+            model.borrow_mut().cursor = None;
+            Inhibit(false)
+        });
+
+        let model = self.model.clone();
+        self.root.connect_draw(move |da, cr| {
+            let style = da.get_style_context();
+            let alloc = da.get_allocation();
+            let width = alloc.width as f64;
+            let height = alloc.height as f64;
+
+            gtk::render_background(&style, cr, 0.0, 0.0, width, height);
+            model.borrow().draw(cr, width, height);
+
+            Inhibit(false)
+        });
+    }
+
+    fn root(&self) -> Self::Root {
+        self.root.clone()
+    }
+
+    fn view(_relm: &Relm<Self>, model: SpectrumModel) -> Self {
+        let root = gtk::DrawingAreaBuilder::new()
+            .vexpand(true)
+            .hexpand(true)
+            .width_request(200)
+            .height_request(100)
+            .build();
+
+        Self {
+            root,
+            model: Rc::from(RefCell::from(model)),
+        }
+    }
+}
@@ -12,12 +12,12 @@ use cpal::{Sample, StreamData, UnknownTypeInputBuffer};
 use pbr::{MultiBar, ProgressBar};
 use smallvec::SmallVec;
 
-use wavr_meter::decibel::{Decibel, LUFS};
+use wavr_meter::decibel::Decibel;
 use wavr_meter::ebu::modes::Momentary;
 use wavr_meter::EBUMeter;
 
 fn main() {
-    let (tx, rx) = sync_channel::<LUFS>(16);
+    let (tx, rx) = sync_channel::<Decibel>(16);
     let host = cpal::default_host();
     let event_loop = host.event_loop();
     let device = host
@@ -28,7 +28,8 @@ fn main() {
         .expect("no device format available");
     std::thread::spawn(move || {
         let stream_id = event_loop.build_input_stream(&device, &format).unwrap();
-        let mut meter = EBUMeter::<Momentary>::new(format.channels as u32, format.sample_rate.0);
+        let mut meter = EBUMeter::<Momentary>::new(format.channels as u32, format.sample_rate.0)
+            .expect("unsupported input format");
         event_loop
             .play_stream(stream_id)
             .expect("failed to start input stream");
@@ -61,8 +62,8 @@ fn main() {
                 } => buffer.iter().cloned().map(|v: f32| v as f64).collect(),
                 _ => unreachable!(),
             };
-            meter.add_samples(&buffer);
-            tx.send(meter.get_loudness()).unwrap();
+            meter.add_samples(&buffer).unwrap();
+            tx.send(meter.get_loudness().unwrap()).unwrap();
         });
     });
 
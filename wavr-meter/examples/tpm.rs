@@ -12,7 +12,7 @@ use cpal::{Sample, StreamData, UnknownTypeInputBuffer};
 use pbr::{MultiBar, ProgressBar};
 use smallvec::SmallVec;
 
-use wavr_meter::decibel::{Decibel, Linear, LUFS};
+use wavr_meter::decibel::{Decibel, Linear};
 use wavr_meter::{EBUMeter, PeakMeter};
 
 fn main() {
@@ -30,7 +30,8 @@ fn main() {
         let stream_id = event_loop.build_input_stream(&device, &format).unwrap();
         let mut meters = (0..format.channels)
             .map(|_| PeakMeter::new(format.sample_rate.0))
-            .collect::<SmallVec<[PeakMeter; 16]>>();
+            .collect::<Result<SmallVec<[PeakMeter; 16]>, _>>()
+            .expect("unsupported sample rate");
         event_loop
             .play_stream(stream_id)
             .expect("failed to start input stream");
@@ -67,7 +68,7 @@ fn main() {
             for (buffer, meter) in buffers.into_iter().zip(&mut meters) {
                 meter.add_samples(&buffer);
             }
-            tx.send(meters.iter_mut().map(|m| m.get_true_peak()).collect())
+            tx.send(meters.iter_mut().map(|m| m.get_level()).collect())
                 .unwrap();
         });
     });
@@ -0,0 +1,144 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Group and bus metering: aggregates the data of several meters into the data of their sum,
+//! without processing the summed audio.
+
+use smallvec::SmallVec;
+
+use crate::decibel::{Decibel, Linear};
+use crate::{ClipStatus, EBUReport, WavrMeter, WavrMeterData};
+
+/// Aggregate meter over several sources, for example all the tracks of a group. The peaks are the
+/// maximum of the sources, and the loudness values are power sums, which is the loudness of the
+/// summed signals when the sources are uncorrelated. The integrated loudness and loudness range
+/// are approximations, as the gating of the summed signal cannot be recovered from the sources.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MeterGroup {
+    max_momentary: Decibel,
+    max_short_term: Decibel,
+}
+
+impl MeterGroup {
+    /// Create a new aggregate meter.
+    pub fn new() -> Self {
+        Self {
+            max_momentary: Decibel(f64::NEG_INFINITY),
+            max_short_term: Decibel(f64::NEG_INFINITY),
+        }
+    }
+
+    /// Resets the maximum momentary and short-term loudness of the group.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Aggregates the values of the given meters, skipping meters without data. See `update`.
+    pub fn update_meters<'a, I: IntoIterator<Item = &'a WavrMeter>>(
+        &mut self,
+        meters: I,
+    ) -> Option<WavrMeterData> {
+        let values: Vec<WavrMeterData> = meters
+            .into_iter()
+            .filter_map(WavrMeter::get_values)
+            .collect();
+        self.update(&values)
+    }
+
+    /// Aggregates the values of the given sources, or returns `None` if there are none. Channels
+    /// are matched by index, and the layout is the one of the source with the most channels.
+    /// The maximum momentary and short-term loudness are tracked by the group from the
+    /// aggregated values, so `update` should be called at the update rate of the sources.
+    pub fn update<'a, I: IntoIterator<Item = &'a WavrMeterData>>(
+        &mut self,
+        sources: I,
+    ) -> Option<WavrMeterData> {
+        let sources: Vec<&WavrMeterData> = sources.into_iter().collect();
+        let layout = sources.iter().max_by_key(|s| s.peak.len())?.layout.clone();
+        let channels = sources.iter().map(|s| s.peak.len()).max().unwrap_or(0);
+
+        let peak = (0..channels)
+            .map(|c| {
+                sources
+                    .iter()
+                    .filter_map(|s| s.peak.get(c))
+                    .fold(Linear(0.0), |a, &b| if b > a { b } else { a })
+            })
+            .collect();
+        let rms = (0..channels)
+            .map(|c| {
+                let power: f64 = sources
+                    .iter()
+                    .filter_map(|s| s.rms.get(c))
+                    .map(|r| r.0 * r.0)
+                    .sum();
+                Linear(power.sqrt())
+            })
+            .collect();
+        let clips = (0..channels)
+            .map(|c| {
+                sources
+                    .iter()
+                    .filter_map(|s| s.clips.get(c))
+                    .fold(ClipStatus::default(), |a, b| ClipStatus {
+                        latched: a.latched || b.latched,
+                        sample_overs: a.sample_overs + b.sample_overs,
+                        true_peak_overs: a.true_peak_overs + b.true_peak_overs,
+                        dropped_events: a.dropped_events + b.dropped_events,
+                    })
+            })
+            .collect::<SmallVec<_>>();
+
+        let momentary = sum_loudness(sources.iter().map(|s| s.ebu.momentary));
+        let short_term = sum_loudness(sources.iter().map(|s| s.ebu.short_term));
+        if momentary > self.max_momentary {
+            self.max_momentary = momentary;
+        }
+        if short_term > self.max_short_term {
+            self.max_short_term = short_term;
+        }
+        let ebu = EBUReport {
+            momentary,
+            short_term,
+            integrated: sum_loudness(sources.iter().map(|s| s.ebu.integrated)),
+            range: sources
+                .iter()
+                .map(|s| s.ebu.range)
+                .fold(Decibel(0.0), |a, b| if b > a { b } else { a }),
+            max_momentary: self.max_momentary,
+            max_short_term: self.max_short_term,
+            max_true_peak: sources
+                .iter()
+                .map(|s| s.ebu.max_true_peak)
+                .fold(Linear(0.0), |a, b| if b > a { b } else { a }),
+        };
+
+        Some(WavrMeterData {
+            layout,
+            peak,
+            rms,
+            clips,
+            loudness: short_term,
+            ebu,
+        })
+    }
+}
+
+impl Default for MeterGroup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the loudness of the sum of uncorrelated signals from their loudness values (power
+/// sum). Silent (-inf) values add nothing, and invalid values are ignored.
+pub fn sum_loudness<I: IntoIterator<Item = Decibel>>(values: I) -> Decibel {
+    let power: f64 = values
+        .into_iter()
+        .filter(|v| !v.0.is_nan())
+        .map(|v| 10f64.powf(v.0 / 10.0))
+        .sum();
+    Decibel(10.0 * power.log10())
+}
@@ -0,0 +1,187 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Level meter ballistics: how the displayed level rises, holds and falls back, defined in
+//! milliseconds and decibels per second so that meters behave the same at any block size and
+//! sample rate.
+
+use std::f64::consts::{FRAC_PI_2, SQRT_2};
+
+/// Level detector feeding the meter ballistics.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Detector {
+    /// Absolute value of the samples.
+    SamplePeak,
+    /// Absolute value of the 4x oversampled signal, as in ITU-R BS.1770.
+    TruePeak,
+    /// Root mean square of the signal, averaged over the integration time.
+    Rms,
+    /// Rectified average of the signal, averaged over the integration time, scaled to read the
+    /// RMS value of a sine (as VU meters do).
+    Average,
+}
+
+/// Time-based meter ballistics.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ballistics {
+    /// Level detector.
+    pub detector: Detector,
+    /// Integration time constant, in milliseconds. Peak detectors rise with this time constant
+    /// (0 reads peaks instantly); averaging detectors average over it.
+    pub integration_ms: f64,
+    /// Time the highest level is held before falling back, in milliseconds.
+    pub hold_ms: f64,
+    /// Fall back rate after the hold time, in dB per second. An infinite rate follows the
+    /// detector directly.
+    pub decay_db_per_s: f64,
+}
+
+impl Ballistics {
+    /// IEC 60268-10 Type I quasi-peak programme meter (DIN PPM): a 5 ms tone burst reads 2 dB
+    /// below the steady state, and the level falls back 20 dB in 1.7 s.
+    pub fn ppm_type_i() -> Self {
+        Self {
+            detector: Detector::SamplePeak,
+            integration_ms: 1.35,
+            hold_ms: 0.0,
+            decay_db_per_s: 20.0 / 1.7,
+        }
+    }
+
+    /// IEC 60268-10 Type II quasi-peak programme meter (BBC and EBU PPM): a 10 ms tone burst
+    /// reads 4 dB below the steady state, and the level falls back 24 dB in 2.8 s.
+    pub fn ppm_type_ii() -> Self {
+        Self {
+            detector: Detector::SamplePeak,
+            integration_ms: 5.2,
+            hold_ms: 0.0,
+            decay_db_per_s: 24.0 / 2.8,
+        }
+    }
+
+    /// Volume unit meter (IEC 60268-17): rectified average reaching 99% of a step in 300 ms,
+    /// rising and falling symmetrically.
+    pub fn vu() -> Self {
+        Self {
+            detector: Detector::Average,
+            integration_ms: 300.0 / 100f64.ln(),
+            hold_ms: 0.0,
+            decay_db_per_s: f64::INFINITY,
+        }
+    }
+
+    /// EBU digital peak meter: true peak read instantly, falling back 20 dB in 1.7 s.
+    pub fn digital_peak() -> Self {
+        Self {
+            detector: Detector::TruePeak,
+            integration_ms: 0.0,
+            hold_ms: 0.0,
+            decay_db_per_s: 20.0 / 1.7,
+        }
+    }
+
+    /// K-System RMS meter: RMS reaching 99% of a step in 600 ms, rising and falling
+    /// symmetrically.
+    pub fn k_system() -> Self {
+        Self {
+            detector: Detector::Rms,
+            integration_ms: 600.0 / 100f64.ln(),
+            hold_ms: 0.0,
+            decay_db_per_s: f64::INFINITY,
+        }
+    }
+
+    /// Returns the ballistics with the given hold time, in milliseconds.
+    pub fn with_hold_ms(mut self, hold_ms: f64) -> Self {
+        self.hold_ms = hold_ms;
+        self
+    }
+}
+
+impl Default for Ballistics {
+    fn default() -> Self {
+        Self::digital_peak()
+    }
+}
+
+/// Single-channel, sample-by-sample processor of meter ballistics. The true peak is computed by
+/// the caller, so that it is shared with the other measurements of the channel.
+#[derive(Clone, Debug)]
+pub(crate) struct BallisticsProcessor {
+    ballistics: Ballistics,
+    integration: f64,
+    hold_samples: usize,
+    decay: f64,
+    average: f64,
+    level: f64,
+    hold: usize,
+}
+
+impl BallisticsProcessor {
+    pub(crate) fn new(ballistics: Ballistics, sample_rate: u32) -> Self {
+        let sample_rate = sample_rate as f64;
+        Self {
+            ballistics,
+            integration: one_pole(ballistics.integration_ms, sample_rate),
+            hold_samples: (ballistics.hold_ms.max(0.0) * sample_rate / 1000.0).round() as usize,
+            decay: 10f64.powf(-ballistics.decay_db_per_s / 20.0 / sample_rate),
+            average: 0.0,
+            level: 0.0,
+            hold: 0,
+        }
+    }
+
+    pub(crate) fn ballistics(&self) -> Ballistics {
+        self.ballistics
+    }
+
+    pub(crate) fn level(&self) -> f64 {
+        self.level
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.average = 0.0;
+        self.level = 0.0;
+        self.hold = 0;
+    }
+
+    /// Processes one sample, with its true peak as returned by `TruePeak`.
+    pub(crate) fn process(&mut self, sample: f64, true_peak: f64) {
+        let (detected, is_peak) = match self.ballistics.detector {
+            Detector::SamplePeak => (sample.abs(), true),
+            Detector::TruePeak => (true_peak, true),
+            Detector::Rms => {
+                self.average += (sample * sample - self.average) * self.integration;
+                (self.average.sqrt(), false)
+            }
+            Detector::Average => {
+                self.average += (sample.abs() - self.average) * self.integration;
+                (self.average * FRAC_PI_2 / SQRT_2, false)
+            }
+        };
+
+        if detected >= self.level {
+            self.level = if is_peak {
+                self.level + (detected - self.level) * self.integration
+            } else {
+                detected
+            };
+            self.hold = self.hold_samples;
+        } else if self.hold > 0 {
+            self.hold -= 1;
+        } else {
+            self.level = (self.level * self.decay).max(detected);
+        }
+    }
+}
+
+/// Returns the coefficient of a one-pole smoothing filter with the given time constant.
+fn one_pole(time_constant_ms: f64, sample_rate: f64) -> f64 {
+    if time_constant_ms <= 0.0 {
+        1.0
+    } else {
+        1.0 - (-1000.0 / (time_constant_ms * sample_rate)).exp()
+    }
+}
@@ -0,0 +1,533 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! # Pure-Rust ITU-R BS.1770 measurement
+//!
+//! A port of the measurement algorithms of `libebur128`, used as the loudness backend when the
+//! `pure-rust` feature is enabled: K-weighting filter, 400 ms gating blocks with 75% overlap,
+//! absolute and relative gating (EBU Tech 3341), loudness range over 3 s blocks (EBU Tech 3342),
+//! and 4x oversampled true peak using the [`TruePeak`](../true_peak/struct.TruePeak.html)
+//! interpolator. `Bs1770Meter` mirrors the API of `libebur128`'s state, so that the meters can use
+//! either implementation.
+
+use std::f64::consts::PI;
+use std::ops::BitOr;
+
+use crate::error::{Error, Result};
+use crate::true_peak::TruePeak;
+
+/// Blocks quieter than -70 LUFS are ignored by the gating, as energy.
+const ABSOLUTE_GATE: f64 = 1.173_494_974_687_901_4e-7;
+/// The relative gate is 10 LU below the ungated loudness, as energy.
+const RELATIVE_GATE_FACTOR: f64 = 0.1;
+/// The loudness range gate is 20 LU below the ungated loudness, as energy.
+const RANGE_GATE_FACTOR: f64 = 0.01;
+
+/// Measurement mode flags. Each mode implies the modes it depends on, as in `libebur128`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Mode(u8);
+
+impl Mode {
+    /// Momentary loudness.
+    pub const M: Mode = Mode(0b00_0001);
+    /// Short-term loudness.
+    pub const S: Mode = Mode(0b00_0011);
+    /// Integrated loudness.
+    pub const I: Mode = Mode(0b00_0101);
+    /// Loudness range.
+    pub const LRA: Mode = Mode(0b00_1011);
+    /// Sample peak.
+    pub const SAMPLE_PEAK: Mode = Mode(0b01_0001);
+    /// True peak.
+    pub const TRUE_PEAK: Mode = Mode(0b11_0001);
+
+    /// Returns whether all the flags of `other` are set.
+    pub fn contains(self, other: Mode) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Mode {
+    type Output = Mode;
+
+    fn bitor(self, rhs: Mode) -> Mode {
+        Mode(self.0 | rhs.0)
+    }
+}
+
+/// Loudness weighting of a channel, with the same positions as `libebur128`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Channel {
+    /// Unused channel (for example LFE channel).
+    Unused,
+    /// Left or ITU M+030.
+    Left,
+    /// Right or ITU M-030.
+    Right,
+    /// Center or ITU M+000.
+    Center,
+    /// Left surround or ITU M+110.
+    LeftSurround,
+    /// Right surround or ITU M-110.
+    RightSurround,
+    /// A channel that is counted twice.
+    DualMono,
+    /// ITU M+SC.
+    MpSC,
+    /// ITU M-SC.
+    MmSC,
+    /// ITU M+060.
+    Mp060,
+    /// ITU M-060.
+    Mm060,
+    /// ITU M+090.
+    Mp090,
+    /// ITU M-090.
+    Mm090,
+    /// ITU M+135.
+    Mp135,
+    /// ITU M-135.
+    Mm135,
+    /// ITU M+180.
+    Mp180,
+    /// ITU U+000.
+    Up000,
+    /// ITU U+030.
+    Up030,
+    /// ITU U-030.
+    Um030,
+    /// ITU U+045.
+    Up045,
+    /// ITU U-045.
+    Um045,
+    /// ITU U+090.
+    Up090,
+    /// ITU U-090.
+    Um090,
+    /// ITU U+110.
+    Up110,
+    /// ITU U-110.
+    Um110,
+    /// ITU U+135.
+    Up135,
+    /// ITU U-135.
+    Um135,
+    /// ITU U+180.
+    Up180,
+    /// ITU T+000.
+    Tp000,
+    /// ITU B+000.
+    Bp000,
+    /// ITU B+045.
+    Bp045,
+    /// ITU B-045.
+    Bm045,
+}
+
+impl Channel {
+    /// Returns the power weighting of the channel in the loudness sum.
+    pub fn weight(self) -> f64 {
+        match self {
+            Channel::Unused => 0.0,
+            Channel::LeftSurround
+            | Channel::RightSurround
+            | Channel::Mp060
+            | Channel::Mm060
+            | Channel::Mp090
+            | Channel::Mm090 => 1.41,
+            Channel::DualMono => 2.0,
+            _ => 1.0,
+        }
+    }
+
+    /// Returns the default weighting of the given channel in a layout of `channels` channels.
+    fn default_for(channel: usize, channels: usize) -> Channel {
+        match (channels, channel) {
+            (4, 0) | (5, 0) => Channel::Left,
+            (4, 1) | (5, 1) => Channel::Right,
+            (4, 2) => Channel::LeftSurround,
+            (4, 3) => Channel::RightSurround,
+            (5, 2) => Channel::Center,
+            (5, 3) => Channel::LeftSurround,
+            (5, 4) => Channel::RightSurround,
+            (_, 0) => Channel::Left,
+            (_, 1) => Channel::Right,
+            (_, 2) => Channel::Center,
+            (_, 4) => Channel::LeftSurround,
+            (_, 5) => Channel::RightSurround,
+            _ => Channel::Unused,
+        }
+    }
+}
+
+/// BS.1770 loudness and true peak measurement state.
+#[derive(Debug)]
+pub struct Bs1770Meter {
+    channels: usize,
+    mode: Mode,
+    channel_map: Vec<Channel>,
+    b: [f64; 5],
+    a: [f64; 5],
+    filter_state: Vec<[f64; 5]>,
+    /// Ring buffer of K-weighted, interleaved samples.
+    audio_data: Vec<f64>,
+    audio_data_frames: usize,
+    /// Write position in `audio_data`, in samples.
+    audio_data_index: usize,
+    needed_frames: usize,
+    samples_in_100ms: usize,
+    block_energies: Vec<f64>,
+    short_term_energies: Vec<f64>,
+    short_term_frame_counter: usize,
+    true_peak_detector: Option<TruePeak>,
+    sample_peak: Vec<f64>,
+    prev_sample_peak: Vec<f64>,
+    true_peak: Vec<f64>,
+    prev_true_peak: Vec<f64>,
+}
+
+impl Bs1770Meter {
+    /// Create a new measurement state with the given configuration.
+    pub fn new(channels: u32, sample_rate: u32, mode: Mode) -> Result<Self> {
+        Error::check_config(channels, sample_rate)?;
+        let channels = channels as usize;
+        let samples_in_100ms = (sample_rate as usize + 5) / 10;
+        let window = if mode.contains(Mode::S) { 3000 } else { 400 };
+        let audio_data_frames =
+            (sample_rate as usize * window / 1000).div_ceil(samples_in_100ms) * samples_in_100ms;
+        let (b, a) = k_weighting(sample_rate as f64);
+
+        Ok(Self {
+            channels,
+            mode,
+            channel_map: (0..channels)
+                .map(|ch| Channel::default_for(ch, channels))
+                .collect(),
+            b,
+            a,
+            filter_state: vec![[0.0; 5]; channels],
+            audio_data: vec![0.0; audio_data_frames * channels],
+            audio_data_frames,
+            audio_data_index: 0,
+            needed_frames: samples_in_100ms * 4,
+            samples_in_100ms,
+            block_energies: vec![],
+            short_term_energies: vec![],
+            short_term_frame_counter: 0,
+            true_peak_detector: if mode.contains(Mode::TRUE_PEAK) {
+                Some(TruePeak::new(channels, sample_rate))
+            } else {
+                None
+            },
+            sample_peak: vec![0.0; channels],
+            prev_sample_peak: vec![0.0; channels],
+            true_peak: vec![0.0; channels],
+            prev_true_peak: vec![0.0; channels],
+        })
+    }
+
+    /// Clears the measurements, keeping the channel weighting and the allocated storage.
+    pub fn reset(&mut self) {
+        self.filter_state
+            .iter_mut()
+            .for_each(|state| *state = [0.0; 5]);
+        self.audio_data.iter_mut().for_each(|s| *s = 0.0);
+        self.audio_data_index = 0;
+        self.needed_frames = self.samples_in_100ms * 4;
+        self.block_energies.clear();
+        self.short_term_energies.clear();
+        self.short_term_frame_counter = 0;
+        if let Some(detector) = &mut self.true_peak_detector {
+            detector.reset();
+        }
+        for peaks in &mut [
+            &mut self.sample_peak,
+            &mut self.prev_sample_peak,
+            &mut self.true_peak,
+            &mut self.prev_true_peak,
+        ] {
+            peaks.iter_mut().for_each(|p| *p = 0.0);
+        }
+    }
+
+    /// Sets the weighting of the given channel. `Channel::DualMono` is only valid for the single
+    /// channel of a mono meter.
+    pub fn set_channel(&mut self, channel: u32, value: Channel) -> Result<()> {
+        let index = channel as usize;
+        if index >= self.channels || (value == Channel::DualMono && self.channels != 1) {
+            return Err(Error::InvalidChannel(channel));
+        }
+        self.channel_map[index] = value;
+        Ok(())
+    }
+
+    /// Add interleaved frames to the measurement.
+    pub fn add_frames_f64(&mut self, frames: &[f64]) -> Result<()> {
+        for c in 0..self.channels {
+            self.prev_sample_peak[c] = 0.0;
+            self.prev_true_peak[c] = 0.0;
+        }
+        let mut remaining = frames.len() / self.channels;
+        let mut src = 0;
+        while remaining > 0 {
+            if remaining >= self.needed_frames {
+                let needed = self.needed_frames;
+                self.filter(&frames[src..src + needed * self.channels]);
+                src += needed * self.channels;
+                remaining -= needed;
+                self.audio_data_index += needed * self.channels;
+                if self.mode.contains(Mode::I) {
+                    let energy = self.gating_block_energy(self.samples_in_100ms * 4);
+                    if energy >= ABSOLUTE_GATE {
+                        self.block_energies.push(energy);
+                    }
+                }
+                if self.mode.contains(Mode::LRA) {
+                    self.short_term_frame_counter += needed;
+                    if self.short_term_frame_counter == self.samples_in_100ms * 30 {
+                        let energy = self.energy_in_interval(self.samples_in_100ms * 30)?;
+                        if energy >= ABSOLUTE_GATE {
+                            self.short_term_energies.push(energy);
+                        }
+                        self.short_term_frame_counter = self.samples_in_100ms * 20;
+                    }
+                }
+                // 100 ms are needed for all blocks besides the first one
+                self.needed_frames = self.samples_in_100ms;
+                if self.audio_data_index == self.audio_data_frames * self.channels {
+                    self.audio_data_index = 0;
+                }
+            } else {
+                self.filter(&frames[src..src + remaining * self.channels]);
+                self.audio_data_index += remaining * self.channels;
+                if self.mode.contains(Mode::LRA) {
+                    self.short_term_frame_counter += remaining;
+                }
+                self.needed_frames -= remaining;
+                remaining = 0;
+            }
+        }
+        for c in 0..self.channels {
+            self.sample_peak[c] = self.sample_peak[c].max(self.prev_sample_peak[c]);
+            self.true_peak[c] = self.true_peak[c].max(self.prev_true_peak[c]);
+        }
+        Ok(())
+    }
+
+    /// Returns the integrated loudness, in LUFS.
+    pub fn loudness_global(&self) -> Result<f64> {
+        if !self.mode.contains(Mode::I) {
+            return Err(Error::InvalidMode);
+        }
+        if self.block_energies.is_empty() {
+            return Ok(f64::NEG_INFINITY);
+        }
+        let relative_threshold = mean(&self.block_energies) * RELATIVE_GATE_FACTOR;
+        let (sum, count) = self
+            .block_energies
+            .iter()
+            .filter(|&&z| z >= relative_threshold)
+            .fold((0.0, 0usize), |(sum, count), &z| (sum + z, count + 1));
+        if count == 0 {
+            return Ok(f64::NEG_INFINITY);
+        }
+        Ok(energy_to_loudness(sum / count as f64))
+    }
+
+    /// Returns the momentary loudness (400 ms), in LUFS.
+    pub fn loudness_momentary(&self) -> Result<f64> {
+        self.energy_in_interval(self.samples_in_100ms * 4)
+            .map(energy_to_loudness)
+    }
+
+    /// Returns the short-term loudness (3 s), in LUFS.
+    pub fn loudness_shortterm(&self) -> Result<f64> {
+        self.energy_in_interval(self.samples_in_100ms * 30)
+            .map(energy_to_loudness)
+    }
+
+    /// Returns the loudness range, in LU.
+    pub fn loudness_range(&self) -> Result<f64> {
+        if !self.mode.contains(Mode::LRA) {
+            return Err(Error::InvalidMode);
+        }
+        if self.short_term_energies.is_empty() {
+            return Ok(0.0);
+        }
+        let mut energies = self.short_term_energies.clone();
+        energies.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let gate = mean(&energies) * RANGE_GATE_FACTOR;
+        let gated = &energies[energies.iter().take_while(|&&z| z < gate).count()..];
+        if gated.is_empty() {
+            return Ok(0.0);
+        }
+        let percentile = |p: f64| gated[((gated.len() - 1) as f64 * p + 0.5) as usize];
+        Ok(energy_to_loudness(percentile(0.95)) - energy_to_loudness(percentile(0.1)))
+    }
+
+    /// Returns the highest true peak of the channel since the meter was created.
+    pub fn true_peak(&self, channel: u32) -> Result<f64> {
+        self.check_true_peak(channel)?;
+        let c = channel as usize;
+        Ok(self.true_peak[c].max(self.sample_peak[c]))
+    }
+
+    /// Returns the highest true peak of the channel in the last added frames.
+    pub fn prev_true_peak(&self, channel: u32) -> Result<f64> {
+        self.check_true_peak(channel)?;
+        let c = channel as usize;
+        Ok(self.prev_true_peak[c].max(self.prev_sample_peak[c]))
+    }
+
+    fn check_true_peak(&self, channel: u32) -> Result<()> {
+        if !self.mode.contains(Mode::TRUE_PEAK) {
+            Err(Error::InvalidMode)
+        } else if channel as usize >= self.channels {
+            Err(Error::InvalidChannel(channel))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Measures the peaks of the frames, and writes them K-weighted into the ring buffer.
+    fn filter(&mut self, src: &[f64]) {
+        let channels = self.channels;
+        let frames = src.len() / channels;
+        if self.mode.contains(Mode::SAMPLE_PEAK) {
+            for c in 0..channels {
+                let max = (0..frames)
+                    .map(|i| src[i * channels + c].abs())
+                    .fold(0.0, f64::max);
+                self.prev_sample_peak[c] = self.prev_sample_peak[c].max(max);
+            }
+        }
+        if let Some(detector) = &mut self.true_peak_detector {
+            let prev_true_peak = &mut self.prev_true_peak;
+            for frame in src.chunks(channels) {
+                detector.process_frame(frame, |c, peak| {
+                    prev_true_peak[c] = prev_true_peak[c].max(peak);
+                });
+            }
+        }
+
+        let (a, b) = (self.a, self.b);
+        let start = self.audio_data_index;
+        for c in 0..channels {
+            if self.channel_map[c] == Channel::Unused {
+                continue;
+            }
+            let v = &mut self.filter_state[c];
+            for i in 0..frames {
+                v[0] =
+                    src[i * channels + c] - a[1] * v[1] - a[2] * v[2] - a[3] * v[3] - a[4] * v[4];
+                self.audio_data[start + i * channels + c] =
+                    b[0] * v[0] + b[1] * v[1] + b[2] * v[2] + b[3] * v[3] + b[4] * v[4];
+                v[4] = v[3];
+                v[3] = v[2];
+                v[2] = v[1];
+                v[1] = v[0];
+            }
+            for s in v.iter_mut().skip(1) {
+                if s.abs() < f64::MIN_POSITIVE {
+                    *s = 0.0;
+                }
+            }
+        }
+    }
+
+    fn energy_in_interval(&self, frames: usize) -> Result<f64> {
+        if frames > self.audio_data_frames {
+            return Err(Error::InvalidMode);
+        }
+        Ok(self.gating_block_energy(frames))
+    }
+
+    /// Returns the weighted mean square of the last `frames_per_block` frames.
+    fn gating_block_energy(&self, frames_per_block: usize) -> f64 {
+        let channels = self.channels;
+        let index = self.audio_data_index / channels;
+        let square = |i: usize, c: usize| {
+            let s = self.audio_data[i * channels + c];
+            s * s
+        };
+        let mut sum = 0.0;
+        for c in 0..channels {
+            let weight = self.channel_map[c].weight();
+            if weight == 0.0 {
+                continue;
+            }
+            let channel_sum: f64 = if index < frames_per_block {
+                (0..index)
+                    .chain(
+                        self.audio_data_frames - (frames_per_block - index)..self.audio_data_frames,
+                    )
+                    .map(|i| square(i, c))
+                    .sum()
+            } else {
+                (index - frames_per_block..index)
+                    .map(|i| square(i, c))
+                    .sum()
+            };
+            sum += channel_sum * weight;
+        }
+        sum / frames_per_block as f64
+    }
+}
+
+/// Returns the coefficients of the K-weighting filter (high shelf followed by high pass), as a
+/// single 4th-order filter, for the given sample rate.
+fn k_weighting(sample_rate: f64) -> ([f64; 5], [f64; 5]) {
+    let f0 = 1_681.974_450_955_533;
+    let gain = 3.999_843_853_973_347;
+    let q = 0.707_175_236_955_419_6;
+    let k = (PI * f0 / sample_rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.499_666_774_154_541_6);
+    let a0 = 1.0 + k / q + k * k;
+    let pb = [
+        (vh + vb * k / q + k * k) / a0,
+        2.0 * (k * k - vh) / a0,
+        (vh - vb * k / q + k * k) / a0,
+    ];
+    let pa = [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0];
+
+    let f0 = 38.135_470_876_024_44;
+    let q = 0.500_327_037_323_877_3;
+    let k = (PI * f0 / sample_rate).tan();
+    let rb = [1.0, -2.0, 1.0];
+    let ra = [
+        1.0,
+        2.0 * (k * k - 1.0) / (1.0 + k / q + k * k),
+        (1.0 - k / q + k * k) / (1.0 + k / q + k * k),
+    ];
+
+    let b = [
+        pb[0] * rb[0],
+        pb[0] * rb[1] + pb[1] * rb[0],
+        pb[0] * rb[2] + pb[1] * rb[1] + pb[2] * rb[0],
+        pb[1] * rb[2] + pb[2] * rb[1],
+        pb[2] * rb[2],
+    ];
+    let a = [
+        pa[0] * ra[0],
+        pa[0] * ra[1] + pa[1] * ra[0],
+        pa[0] * ra[2] + pa[1] * ra[1] + pa[2] * ra[0],
+        pa[1] * ra[2] + pa[2] * ra[1],
+        pa[2] * ra[2],
+    ];
+    (b, a)
+}
+
+fn energy_to_loudness(energy: f64) -> f64 {
+    if energy <= 0.0 {
+        f64::NEG_INFINITY
+    } else {
+        10.0 * energy.log10() - 0.691
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
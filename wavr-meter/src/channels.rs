@@ -0,0 +1,198 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Channel roles and layouts, for labelling surround meters and applying the ITU-R BS.1770
+//! loudness weights of each loudspeaker position.
+
+use std::ops::Deref;
+
+use smallvec::SmallVec;
+
+use crate::ebu::Channel;
+
+/// Role of a channel in a loudspeaker layout, following ITU-R BS.2051 positions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ChannelRole {
+    /// Single channel of a mono layout.
+    Mono,
+    /// Left front (M+030).
+    Left,
+    /// Right front (M-030).
+    Right,
+    /// Center front (M+000).
+    Center,
+    /// Low-frequency effects, excluded from the loudness measurement.
+    Lfe,
+    /// Left surround of a 5.1 layout (M+110).
+    LeftSurround,
+    /// Right surround of a 5.1 layout (M-110).
+    RightSurround,
+    /// Left side surround of a 7.1 layout (M+090).
+    LeftSideSurround,
+    /// Right side surround of a 7.1 layout (M-090).
+    RightSideSurround,
+    /// Left rear surround of a 7.1 layout (M+135).
+    LeftRearSurround,
+    /// Right rear surround of a 7.1 layout (M-135).
+    RightRearSurround,
+    /// Channel without a known role, weighted as a front channel.
+    Unlabeled,
+}
+
+/// Group of channel roles, for grouping meter bars.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ChannelGroup {
+    /// Front channels: mono, left, right and center.
+    Front,
+    /// Low-frequency effects channel.
+    Lfe,
+    /// Surround channels.
+    Surround,
+    /// Channels without a known role.
+    Other,
+}
+
+impl ChannelRole {
+    /// Returns the short label of the role, as printed under meter bars.
+    pub fn label(self) -> &'static str {
+        match self {
+            ChannelRole::Mono => "M",
+            ChannelRole::Left => "L",
+            ChannelRole::Right => "R",
+            ChannelRole::Center => "C",
+            ChannelRole::Lfe => "LFE",
+            ChannelRole::LeftSurround => "Ls",
+            ChannelRole::RightSurround => "Rs",
+            ChannelRole::LeftSideSurround => "Lss",
+            ChannelRole::RightSideSurround => "Rss",
+            ChannelRole::LeftRearSurround => "Lrs",
+            ChannelRole::RightRearSurround => "Rrs",
+            ChannelRole::Unlabeled => "",
+        }
+    }
+
+    /// Returns the group of the role.
+    pub fn group(self) -> ChannelGroup {
+        match self {
+            ChannelRole::Mono | ChannelRole::Left | ChannelRole::Right | ChannelRole::Center => {
+                ChannelGroup::Front
+            }
+            ChannelRole::Lfe => ChannelGroup::Lfe,
+            ChannelRole::LeftSurround
+            | ChannelRole::RightSurround
+            | ChannelRole::LeftSideSurround
+            | ChannelRole::RightSideSurround
+            | ChannelRole::LeftRearSurround
+            | ChannelRole::RightRearSurround => ChannelGroup::Surround,
+            ChannelRole::Unlabeled => ChannelGroup::Other,
+        }
+    }
+
+    /// Returns the BS.1770 loudness weighting of the role: the LFE channel is excluded, and the
+    /// surround channels between 60 and 120 degrees of azimuth are weighted +1.5 dB.
+    pub fn weighting(self) -> Channel {
+        match self {
+            ChannelRole::Mono | ChannelRole::Center | ChannelRole::Unlabeled => Channel::Center,
+            ChannelRole::Left => Channel::Left,
+            ChannelRole::Right => Channel::Right,
+            ChannelRole::Lfe => Channel::Unused,
+            ChannelRole::LeftSurround => Channel::LeftSurround,
+            ChannelRole::RightSurround => Channel::RightSurround,
+            ChannelRole::LeftSideSurround => Channel::Mp090,
+            ChannelRole::RightSideSurround => Channel::Mm090,
+            ChannelRole::LeftRearSurround => Channel::Mp135,
+            ChannelRole::RightRearSurround => Channel::Mm135,
+        }
+    }
+}
+
+/// Roles of the channels of a meter, in channel order.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ChannelLayout(SmallVec<[ChannelRole; 16]>);
+
+impl ChannelLayout {
+    /// Create a layout from the roles of each channel.
+    pub fn new(roles: &[ChannelRole]) -> Self {
+        Self(roles.into())
+    }
+
+    /// Mono layout: M.
+    pub fn mono() -> Self {
+        Self::new(&[ChannelRole::Mono])
+    }
+
+    /// Stereo layout: L, R.
+    pub fn stereo() -> Self {
+        Self::new(&[ChannelRole::Left, ChannelRole::Right])
+    }
+
+    /// 5.1 layout, in SMPTE order: L, R, C, LFE, Ls, Rs.
+    pub fn surround_5_1() -> Self {
+        Self::new(&[
+            ChannelRole::Left,
+            ChannelRole::Right,
+            ChannelRole::Center,
+            ChannelRole::Lfe,
+            ChannelRole::LeftSurround,
+            ChannelRole::RightSurround,
+        ])
+    }
+
+    /// 7.1 layout, in SMPTE order: L, R, C, LFE, Lss, Rss, Lrs, Rrs.
+    pub fn surround_7_1() -> Self {
+        Self::new(&[
+            ChannelRole::Left,
+            ChannelRole::Right,
+            ChannelRole::Center,
+            ChannelRole::Lfe,
+            ChannelRole::LeftSideSurround,
+            ChannelRole::RightSideSurround,
+            ChannelRole::LeftRearSurround,
+            ChannelRole::RightRearSurround,
+        ])
+    }
+
+    /// Returns the usual layout for the given channel count: mono, stereo, 5.1 or 7.1. Other
+    /// channel counts get unlabeled channels.
+    pub fn default_for(channels: usize) -> Self {
+        match channels {
+            1 => Self::mono(),
+            2 => Self::stereo(),
+            6 => Self::surround_5_1(),
+            8 => Self::surround_7_1(),
+            _ => Self(SmallVec::from_elem(ChannelRole::Unlabeled, channels)),
+        }
+    }
+
+    /// Returns the label of the given channel: the label of its role, or its 1-based channel
+    /// number for unlabeled channels.
+    pub fn label(&self, channel: usize) -> String {
+        match self.0.get(channel) {
+            Some(role) if *role != ChannelRole::Unlabeled => role.label().to_string(),
+            _ => (channel + 1).to_string(),
+        }
+    }
+
+    /// Returns the runs of consecutive channels sharing a group, as the group and the channel
+    /// indices, for laying out grouped meter bars.
+    pub fn groups(&self) -> Vec<(ChannelGroup, Vec<usize>)> {
+        let mut groups: Vec<(ChannelGroup, Vec<usize>)> = vec![];
+        for (channel, role) in self.0.iter().enumerate() {
+            match groups.last_mut() {
+                Some((group, channels)) if *group == role.group() => channels.push(channel),
+                _ => groups.push((role.group(), vec![channel])),
+            }
+        }
+        groups
+    }
+}
+
+impl Deref for ChannelLayout {
+    type Target = [ChannelRole];
+
+    fn deref(&self) -> &[ChannelRole] {
+        &self.0
+    }
+}
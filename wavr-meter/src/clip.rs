@@ -0,0 +1,231 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Clip and over detection: runs of consecutive samples at or above a threshold, and true peak
+//! overs above 0 dBTP, with counters, a resettable latch and events tagged with sample positions.
+//!
+//! The events are kept in a list of fixed capacity, so that the detector does not allocate while
+//! processing. Once it is full, new events are dropped and counted until the events are taken.
+
+use crate::decibel::Linear;
+use crate::true_peak::TruePeak;
+
+/// Number of events kept by a detector until they are taken.
+pub const CLIP_EVENT_CAPACITY: usize = 1024;
+
+/// Kind of a detected over.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ClipKind {
+    /// Run of consecutive samples at or above the sample threshold, with its length in samples.
+    Sample { length: usize },
+    /// Excursion of the true peak above the true peak threshold.
+    TruePeak,
+}
+
+/// A detected over.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ClipEvent {
+    /// Channel index of the over; always 0 for single-channel meters.
+    pub channel: u32,
+    /// Sample position of the start of the over.
+    pub position: usize,
+    /// Kind of the over.
+    pub kind: ClipKind,
+    /// Highest level reached during the over.
+    pub level: Linear,
+}
+
+/// Clip status of a channel, for clip indicators.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClipStatus {
+    /// Whether an over happened since the last latch reset.
+    pub latched: bool,
+    /// Number of sample overs since the last reset.
+    pub sample_overs: usize,
+    /// Number of true peak overs since the last reset.
+    pub true_peak_overs: usize,
+    /// Number of events dropped since the last reset, because the event list was full.
+    pub dropped_events: usize,
+}
+
+/// Single-channel over detector. By default, 3 consecutive samples at full scale count as a
+/// sample over, and true peaks above 0 dBTP count as a true peak over.
+#[derive(Clone, Debug)]
+pub struct ClipDetector {
+    threshold: Linear,
+    min_consecutive: usize,
+    true_peak_threshold: Linear,
+    true_peak: TruePeak,
+    latency: usize,
+    run: usize,
+    in_true_peak_over: bool,
+    status: ClipStatus,
+    events: Vec<ClipEvent>,
+    /// Index of the event of the sample over in progress, if recorded.
+    sample_event: Option<usize>,
+    /// Index of the event of the true peak over in progress, if recorded.
+    true_peak_event: Option<usize>,
+}
+
+impl ClipDetector {
+    /// Create a new over detector for the given sample rate.
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            threshold: Linear(1.0),
+            min_consecutive: 3,
+            true_peak_threshold: Linear(1.0),
+            true_peak: TruePeak::new(1, sample_rate),
+            latency: TruePeak::latency_at(sample_rate),
+            run: 0,
+            in_true_peak_over: false,
+            status: ClipStatus::default(),
+            events: Vec::with_capacity(CLIP_EVENT_CAPACITY),
+            sample_event: None,
+            true_peak_event: None,
+        }
+    }
+
+    /// Returns the sample threshold.
+    pub fn threshold(&self) -> Linear {
+        self.threshold
+    }
+
+    /// Sets the sample threshold. Samples at or above it are counted towards a sample over.
+    pub fn set_threshold(&mut self, threshold: Linear) {
+        self.threshold = threshold;
+    }
+
+    /// Returns the number of consecutive samples making a sample over.
+    pub fn min_consecutive(&self) -> usize {
+        self.min_consecutive
+    }
+
+    /// Sets the number of consecutive samples making a sample over.
+    pub fn set_min_consecutive(&mut self, min_consecutive: usize) {
+        self.min_consecutive = min_consecutive.max(1);
+    }
+
+    /// Returns the true peak threshold.
+    pub fn true_peak_threshold(&self) -> Linear {
+        self.true_peak_threshold
+    }
+
+    /// Sets the true peak threshold. True peaks above it are counted as a true peak over.
+    pub fn set_true_peak_threshold(&mut self, threshold: Linear) {
+        self.true_peak_threshold = threshold;
+    }
+
+    /// Returns the clip status: latch and counters.
+    pub fn status(&self) -> ClipStatus {
+        self.status
+    }
+
+    /// Returns whether an over happened since the last latch reset.
+    pub fn is_latched(&self) -> bool {
+        self.status.latched
+    }
+
+    /// Resets the clip latch, keeping the counters and events.
+    pub fn reset_latch(&mut self) {
+        self.status.latched = false;
+    }
+
+    /// Resets the latch, the counters and the events.
+    pub fn reset(&mut self) {
+        self.status = ClipStatus::default();
+        self.events.clear();
+        self.sample_event = None;
+        self.true_peak_event = None;
+    }
+
+    /// Returns the recorded events, from oldest to newest. At most `CLIP_EVENT_CAPACITY` events
+    /// are kept until they are taken.
+    pub fn events(&self) -> &[ClipEvent] {
+        &self.events
+    }
+
+    /// Removes the recorded events, appending them to `events`. Overs still in progress keep
+    /// being tracked, but their event is not updated anymore. The detector keeps its event list,
+    /// so this does not allocate when `events` has room for the events.
+    pub fn take_events(&mut self, events: &mut Vec<ClipEvent>) {
+        self.sample_event = None;
+        self.true_peak_event = None;
+        events.append(&mut self.events);
+    }
+
+    /// Processes one sample at the given sample position.
+    pub fn process(&mut self, position: usize, sample: f64) {
+        let true_peak = self.true_peak.process_sample(sample);
+        self.process_true_peak(position, sample, true_peak);
+    }
+
+    /// Processes one sample at the given sample position, with its true peak as returned by a
+    /// `TruePeak` detector running at the sample rate of this detector. The interpolator of the
+    /// detector is left unused, so that the true peak can be shared with other measurements.
+    pub fn process_true_peak(&mut self, position: usize, sample: f64, true_peak: f64) {
+        let level = sample.abs();
+        if level >= self.threshold.0 {
+            self.run += 1;
+            if self.run == self.min_consecutive {
+                self.sample_event = self.push_event(
+                    position + 1 - self.run,
+                    ClipKind::Sample { length: self.run },
+                    level,
+                );
+                self.status.sample_overs += 1;
+            } else if self.run > self.min_consecutive {
+                let kind = ClipKind::Sample { length: self.run };
+                self.update_event(self.sample_event, kind, level);
+            }
+        } else {
+            self.run = 0;
+            self.sample_event = None;
+        }
+
+        if true_peak > self.true_peak_threshold.0 {
+            if self.in_true_peak_over {
+                self.update_event(self.true_peak_event, ClipKind::TruePeak, true_peak);
+            } else {
+                self.in_true_peak_over = true;
+                self.true_peak_event = self.push_event(
+                    position.saturating_sub(self.latency),
+                    ClipKind::TruePeak,
+                    true_peak,
+                );
+                self.status.true_peak_overs += 1;
+            }
+        } else {
+            self.in_true_peak_over = false;
+            self.true_peak_event = None;
+        }
+    }
+
+    /// Latches and records a new event, returning its index, or `None` when the event list is
+    /// full and the event is dropped.
+    fn push_event(&mut self, position: usize, kind: ClipKind, level: f64) -> Option<usize> {
+        self.status.latched = true;
+        if self.events.len() >= CLIP_EVENT_CAPACITY {
+            self.status.dropped_events += 1;
+            return None;
+        }
+        self.events.push(ClipEvent {
+            channel: 0,
+            position,
+            kind,
+            level: Linear(level),
+        });
+        Some(self.events.len() - 1)
+    }
+
+    /// Updates the event of an over still in progress, if it was recorded.
+    fn update_event(&mut self, index: Option<usize>, kind: ClipKind, level: f64) {
+        if let Some(event) = index.and_then(|index| self.events.get_mut(index)) {
+            event.kind = kind;
+            if level > event.level.0 {
+                event.level = Linear(level);
+            }
+        }
+    }
+}
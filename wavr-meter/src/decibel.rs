@@ -3,48 +3,182 @@
  * This source file, as well as the binaries generated by it,
  * are licensed under MIT.
  */
+//! Level units. `Linear` holds amplitudes, and the decibel types hold their logarithmic values:
+//! `Gain` for amplitude ratios, `DbFs` for sample levels, `Lufs` for loudness and `Lu` for
+//! loudness differences.
+//!
+//! `Decibel` is the legacy untyped decibel value, still returned by the meters. It converts from
+//! and into all the other types, which should be used for arithmetic: adding or scaling a
+//! `Decibel` works on the linear amplitude, and is only kept for compatibility.
+//!
+//! Silence is a linear amplitude of 0, or -inf dB. The decibel types format with their unit
+//! (`-23 LUFS`, `+1.5 dB`), honouring the precision of the format string, and parse back from
+//! the same text, with or without the unit.
 
+use std::error;
 use std::fmt;
 use std::fmt::{Display, Formatter};
-use std::ops::{Add, Mul};
+use std::num::ParseFloatError;
+use std::ops::{Add, Mul, Neg, Sub};
+use std::str::FromStr;
 
 #[derive(Copy, Clone, Debug, PartialOrd, PartialEq)]
 pub struct Linear(pub f64);
 
+/// Legacy untyped decibel value, used by the meters. Prefer the typed levels for arithmetic, and
+/// convert with `into()`.
 #[derive(Copy, Clone, Debug, PartialOrd, PartialEq)]
 pub struct Decibel(pub f64);
 
+/// Legacy alias of `Decibel`, unrelated to the `Lufs` loudness type.
+#[deprecated(note = "use `Decibel`, or `Lufs` for loudness arithmetic")]
 pub type LUFS = Decibel;
 
+/// Amplitude ratio, in decibels. Adding gains multiplies their amplitude ratios.
+#[derive(Copy, Clone, Debug, PartialOrd, PartialEq)]
+pub struct Gain(pub f64);
+
+/// Sample level relative to digital full scale, in decibels.
+#[derive(Copy, Clone, Debug, PartialOrd, PartialEq)]
+pub struct DbFs(pub f64);
+
+/// Loudness relative to digital full scale, as defined by ITU-R BS.1770.
+#[derive(Copy, Clone, Debug, PartialOrd, PartialEq)]
+pub struct Lufs(pub f64);
+
+/// Difference between two loudness values, in loudness units (1 LU is 1 dB).
+#[derive(Copy, Clone, Debug, PartialOrd, PartialEq)]
+pub struct Lu(pub f64);
+
+/// Error returned when parsing a level.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseLevelError {
+    /// The value is not a number.
+    InvalidNumber(ParseFloatError),
+    /// The unit does not belong to the parsed type.
+    InvalidUnit(String),
+}
+
+impl Display for ParseLevelError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseLevelError::InvalidNumber(err) => write!(f, "invalid level: {}", err),
+            ParseLevelError::InvalidUnit(unit) => write!(f, "invalid level unit: {}", unit),
+        }
+    }
+}
+
+impl error::Error for ParseLevelError {}
+
+/// Converts an amplitude into decibels. Silence gives -inf, and the sign is ignored.
+#[inline]
+fn to_db(amplitude: f64) -> f64 {
+    20.0 * amplitude.abs().log10()
+}
+
+/// Converts decibels into an amplitude. -inf gives silence.
+#[inline]
+fn from_db(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
+
+/// Returns the difference of two decibel values, where the difference of two silences is 0 dB.
+fn difference(lhs: f64, rhs: f64) -> f64 {
+    if lhs == rhs {
+        0.0
+    } else {
+        lhs - rhs
+    }
+}
+
+/// Parses a number followed by one of the given units (case-insensitive), or by no unit.
+fn parse_level(s: &str, units: &[&str]) -> Result<f64, ParseLevelError> {
+    let s = s.trim();
+    let number = units
+        .iter()
+        .filter_map(|unit| {
+            let split = s.len().checked_sub(unit.len())?;
+            match (s.get(..split), s.get(split..)) {
+                (Some(number), Some(suffix)) if suffix.eq_ignore_ascii_case(unit) => Some(number),
+                _ => None,
+            }
+        })
+        .next()
+        .unwrap_or(s)
+        .trim();
+    let number = number.replace('∞', "inf");
+    number.parse().map_err(|err| {
+        let unit_start = number
+            .trim_end_matches(|c: char| c.is_alphabetic())
+            .trim_end()
+            .len();
+        let (value, unit) = number.split_at(unit_start);
+        if !unit.is_empty() && value.parse::<f64>().is_ok() {
+            ParseLevelError::InvalidUnit(unit.trim().to_string())
+        } else {
+            ParseLevelError::InvalidNumber(err)
+        }
+    })
+}
+
+/// Writes a value followed by its unit, with the precision of the formatter if any.
+fn write_level(f: &mut Formatter<'_>, value: f64, signed: bool, unit: &str) -> fmt::Result {
+    match (f.precision(), signed) {
+        (Some(precision), false) => write!(f, "{:.*} {}", precision, value, unit),
+        (Some(precision), true) => write!(f, "{:+.*} {}", precision, value, unit),
+        (None, false) => write!(f, "{} {}", value, unit),
+        (None, true) => write!(f, "{:+} {}", value, unit),
+    }
+}
+
+impl Linear {
+    /// Silence.
+    pub const SILENCE: Linear = Linear(0.0);
+
+    /// Returns whether the amplitude is silence.
+    pub fn is_silence(self) -> bool {
+        self.0 == 0.0
+    }
+}
+
 impl From<Linear> for Decibel {
     fn from(lin: Linear) -> Self {
         let Linear(val) = lin;
-        Decibel(val.log10() * 20.0)
+        Decibel(to_db(val))
     }
 }
 
 impl From<&Linear> for Decibel {
     fn from(Linear(val): &Linear) -> Self {
-        Decibel(val.log10() * 20.0)
+        Decibel(to_db(*val))
     }
 }
 
+/// Formats with two decimals unless the format string sets the precision.
 impl Display for Decibel {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let Decibel(val) = self;
-        write!(f, "{:.2} dB", val)
+        write!(f, "{:.*} dB", f.precision().unwrap_or(2), val)
+    }
+}
+
+impl FromStr for Decibel {
+    type Err = ParseLevelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_level(s, &["dB"]).map(Decibel)
     }
 }
 
 impl From<Decibel> for Linear {
     fn from(Decibel(val): Decibel) -> Self {
-        Linear((val / 20.0).powf(10.0))
+        Linear(from_db(val))
     }
 }
 
 impl From<&Decibel> for Linear {
     fn from(Decibel(val): &Decibel) -> Self {
-        Linear((val / 20.0).powf(10.0))
+        Linear(from_db(*val))
     }
 }
 
@@ -89,6 +223,16 @@ impl Mul<f64> for Linear {
     }
 }
 
+impl Mul<Gain> for Linear {
+    type Output = Linear;
+
+    fn mul(self, rhs: Gain) -> Self::Output {
+        Linear(self.0 * rhs.to_linear())
+    }
+}
+
+/// Sums the linear amplitudes. Kept for compatibility; prefer adding a `Gain` to a `DbFs`, or
+/// summing the amplitudes as `Linear`.
 impl Add<Self> for Decibel {
     type Output = Self;
 
@@ -100,6 +244,7 @@ impl Add<Self> for Decibel {
     }
 }
 
+/// Sums the linear amplitudes. Kept for compatibility; prefer summing the amplitudes as `Linear`.
 impl Add<Linear> for Decibel {
     type Output = Self;
 
@@ -110,6 +255,7 @@ impl Add<Linear> for Decibel {
     }
 }
 
+/// Sums the linear amplitudes. Kept for compatibility; prefer summing the amplitudes as `Linear`.
 impl Add<f64> for Decibel {
     type Output = Decibel;
 
@@ -119,11 +265,333 @@ impl Add<f64> for Decibel {
     }
 }
 
+/// Scales the amplitude by a linear factor, as `Mul<f64> for Linear` does. Kept for
+/// compatibility; prefer applying a `Gain`.
 impl Mul<f64> for Decibel {
     type Output = Decibel;
 
     fn mul(self, rhs: f64) -> Self::Output {
         let lhs: Linear = self.into();
-        (lhs + rhs).into()
+        (lhs * rhs).into()
+    }
+}
+
+impl Gain {
+    /// Gain muting the signal (-inf dB).
+    pub const MUTE: Gain = Gain(f64::NEG_INFINITY);
+    /// Gain leaving the signal unchanged (0 dB).
+    pub const UNITY: Gain = Gain(0.0);
+
+    /// Create a gain from an amplitude ratio. The sign of the ratio is ignored.
+    #[inline]
+    pub fn from_linear(ratio: f64) -> Self {
+        Gain(to_db(ratio))
+    }
+
+    /// Returns the amplitude ratio of the gain.
+    #[inline]
+    pub fn to_linear(self) -> f64 {
+        from_db(self.0)
+    }
+
+    /// Returns whether the gain mutes the signal.
+    pub fn is_mute(self) -> bool {
+        self.0 == f64::NEG_INFINITY
+    }
+}
+
+impl Add for Gain {
+    type Output = Gain;
+
+    fn add(self, rhs: Gain) -> Self::Output {
+        Gain(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Gain {
+    type Output = Gain;
+
+    fn sub(self, rhs: Gain) -> Self::Output {
+        Gain(difference(self.0, rhs.0))
+    }
+}
+
+impl Neg for Gain {
+    type Output = Gain;
+
+    fn neg(self) -> Self::Output {
+        Gain(-self.0)
+    }
+}
+
+/// Scales the gain in decibels, which raises the amplitude ratio to the given power.
+impl Mul<f64> for Gain {
+    type Output = Gain;
+
+    fn mul(self, rhs: f64) -> Self::Output {
+        Gain(self.0 * rhs)
+    }
+}
+
+impl Display for Gain {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write_level(f, self.0, true, "dB")
+    }
+}
+
+impl FromStr for Gain {
+    type Err = ParseLevelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_level(s, &["dB"]).map(Gain)
+    }
+}
+
+impl DbFs {
+    /// Digital silence (-inf dBFS).
+    pub const SILENCE: DbFs = DbFs(f64::NEG_INFINITY);
+    /// Digital full scale (0 dBFS).
+    pub const FULL_SCALE: DbFs = DbFs(0.0);
+
+    /// Create a level from a sample amplitude. The sign of the amplitude is ignored.
+    #[inline]
+    pub fn from_amplitude(amplitude: f64) -> Self {
+        DbFs(to_db(amplitude))
+    }
+
+    /// Returns the sample amplitude of the level.
+    #[inline]
+    pub fn to_amplitude(self) -> f64 {
+        from_db(self.0)
+    }
+
+    /// Returns whether the level is digital silence.
+    pub fn is_silence(self) -> bool {
+        self.0 == f64::NEG_INFINITY
+    }
+}
+
+impl Add<Gain> for DbFs {
+    type Output = DbFs;
+
+    fn add(self, rhs: Gain) -> Self::Output {
+        DbFs(self.0 + rhs.0)
+    }
+}
+
+impl Sub<Gain> for DbFs {
+    type Output = DbFs;
+
+    fn sub(self, rhs: Gain) -> Self::Output {
+        DbFs(self.0 - rhs.0)
+    }
+}
+
+impl Sub for DbFs {
+    type Output = Gain;
+
+    fn sub(self, rhs: DbFs) -> Self::Output {
+        Gain(difference(self.0, rhs.0))
+    }
+}
+
+impl Display for DbFs {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write_level(f, self.0, false, "dBFS")
+    }
+}
+
+impl FromStr for DbFs {
+    type Err = ParseLevelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_level(s, &["dBFS"]).map(DbFs)
+    }
+}
+
+impl Lufs {
+    /// Loudness of silence (-inf LUFS).
+    pub const SILENCE: Lufs = Lufs(f64::NEG_INFINITY);
+
+    /// Returns whether the loudness is silence.
+    pub fn is_silence(self) -> bool {
+        self.0 == f64::NEG_INFINITY
+    }
+}
+
+impl Add<Lu> for Lufs {
+    type Output = Lufs;
+
+    fn add(self, rhs: Lu) -> Self::Output {
+        Lufs(self.0 + rhs.0)
+    }
+}
+
+impl Sub<Lu> for Lufs {
+    type Output = Lufs;
+
+    fn sub(self, rhs: Lu) -> Self::Output {
+        Lufs(self.0 - rhs.0)
+    }
+}
+
+/// Applying a gain to a signal changes its loudness by the same amount.
+impl Add<Gain> for Lufs {
+    type Output = Lufs;
+
+    fn add(self, rhs: Gain) -> Self::Output {
+        Lufs(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Lufs {
+    type Output = Lu;
+
+    fn sub(self, rhs: Lufs) -> Self::Output {
+        Lu(difference(self.0, rhs.0))
+    }
+}
+
+impl Display for Lufs {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write_level(f, self.0, false, "LUFS")
+    }
+}
+
+impl FromStr for Lufs {
+    type Err = ParseLevelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_level(s, &["LUFS", "LKFS"]).map(Lufs)
+    }
+}
+
+impl Add for Lu {
+    type Output = Lu;
+
+    fn add(self, rhs: Lu) -> Self::Output {
+        Lu(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Lu {
+    type Output = Lu;
+
+    fn sub(self, rhs: Lu) -> Self::Output {
+        Lu(difference(self.0, rhs.0))
+    }
+}
+
+impl Neg for Lu {
+    type Output = Lu;
+
+    fn neg(self) -> Self::Output {
+        Lu(-self.0)
+    }
+}
+
+impl Mul<f64> for Lu {
+    type Output = Lu;
+
+    fn mul(self, rhs: f64) -> Self::Output {
+        Lu(self.0 * rhs)
+    }
+}
+
+impl Display for Lu {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write_level(f, self.0, true, "LU")
+    }
+}
+
+impl FromStr for Lu {
+    type Err = ParseLevelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_level(s, &["LU"]).map(Lu)
+    }
+}
+
+impl From<Lu> for Gain {
+    fn from(Lu(val): Lu) -> Self {
+        Gain(val)
+    }
+}
+
+impl From<Gain> for Lu {
+    fn from(Gain(val): Gain) -> Self {
+        Lu(val)
+    }
+}
+
+impl From<Linear> for Gain {
+    fn from(Linear(val): Linear) -> Self {
+        Gain::from_linear(val)
+    }
+}
+
+impl From<Gain> for Linear {
+    fn from(gain: Gain) -> Self {
+        Linear(gain.to_linear())
+    }
+}
+
+impl From<Linear> for DbFs {
+    fn from(Linear(val): Linear) -> Self {
+        DbFs::from_amplitude(val)
+    }
+}
+
+impl From<DbFs> for Linear {
+    fn from(level: DbFs) -> Self {
+        Linear(level.to_amplitude())
+    }
+}
+
+impl From<Decibel> for Gain {
+    fn from(Decibel(val): Decibel) -> Self {
+        Gain(val)
+    }
+}
+
+impl From<Gain> for Decibel {
+    fn from(Gain(val): Gain) -> Self {
+        Decibel(val)
+    }
+}
+
+impl From<Decibel> for DbFs {
+    fn from(Decibel(val): Decibel) -> Self {
+        DbFs(val)
+    }
+}
+
+impl From<DbFs> for Decibel {
+    fn from(DbFs(val): DbFs) -> Self {
+        Decibel(val)
+    }
+}
+
+impl From<Decibel> for Lufs {
+    fn from(Decibel(val): Decibel) -> Self {
+        Lufs(val)
+    }
+}
+
+impl From<Lufs> for Decibel {
+    fn from(Lufs(val): Lufs) -> Self {
+        Decibel(val)
+    }
+}
+
+impl From<Decibel> for Lu {
+    fn from(Decibel(val): Decibel) -> Self {
+        Lu(val)
+    }
+}
+
+impl From<Lu> for Decibel {
+    fn from(Lu(val): Lu) -> Self {
+        Decibel(val)
     }
 }
@@ -5,27 +5,37 @@
  */
 //! # The Wavr Meter Audio
 //!
-//! This crates wraps `libebur128`'s EBU meter with a Rust idiomatic-ish implementation.
+//! This crates wraps `libebur128`'s EBU meter with a Rust idiomatic-ish implementation. With the
+//! `pure-rust` feature, the measurements are done by the [`bs1770`](../bs1770/index.html) module
+//! instead.
 
 use std::marker::PhantomData;
 
-use ebur128::EbuR128;
+#[cfg(feature = "pure-rust")]
+pub use crate::bs1770::Channel;
+#[cfg(feature = "pure-rust")]
+pub(crate) use crate::bs1770::{Bs1770Meter as Backend, Mode as BackendMode};
+#[cfg(not(feature = "pure-rust"))]
+pub use ebur128::Channel;
+#[cfg(not(feature = "pure-rust"))]
+pub(crate) use ebur128::{EbuR128 as Backend, Mode as BackendMode};
 
-use crate::decibel::{Decibel, LUFS};
+use crate::decibel::{Decibel, Linear};
+use crate::error::{Error, Result};
 
 /// EBU modes as flag types.
 pub mod modes {
-    use ebur128::{EbuR128, Mode};
+    use super::{Backend, BackendMode as Mode};
+    use crate::decibel::Decibel;
+    use crate::error::Result;
 
-    use crate::decibel::{Decibel, LUFS};
-
-    /// EBU Mode trait. Used by the flag types to place the calls into `ebur128`.
+    /// EBU Mode trait. Used by the flag types to place the calls into the measurement backend.
     pub trait EBUMeterMode {
         // TODO: Change mode to associated const
         /// Return the EBU mode associated with the type.
         fn mode() -> Mode;
         /// Return the loudness from the associated EBU mode.
-        fn get_loudness(meter: &EbuR128) -> LUFS;
+        fn get_loudness(meter: &Backend) -> Result<Decibel>;
     }
 
     /// EBU Momentary (300ms) loudness metering.
@@ -36,17 +46,23 @@ pub mod modes {
     #[derive(Copy, Clone, Debug)]
     pub struct Short;
 
-    /// EBU Integrated (whole program range) metering.
+    /// EBU Integrated (whole program range) metering. Also tracks the loudness range and the true
+    /// peak of the program.
     #[derive(Copy, Clone, Debug)]
     pub struct Integrated;
 
+    /// EBU Integrated metering with the loudness range, for meters which track the true peak
+    /// themselves.
+    #[derive(Copy, Clone, Debug)]
+    pub struct Program;
+
     impl EBUMeterMode for Momentary {
         fn mode() -> Mode {
             Mode::M
         }
 
-        fn get_loudness(meter: &EbuR128) -> LUFS {
-            Decibel(meter.loudness_momentary().unwrap())
+        fn get_loudness(meter: &Backend) -> Result<Decibel> {
+            Ok(Decibel(meter.loudness_momentary()?))
         }
     }
 
@@ -55,18 +71,28 @@ pub mod modes {
             Mode::S
         }
 
-        fn get_loudness(meter: &EbuR128) -> LUFS {
-            Decibel(meter.loudness_shortterm().unwrap())
+        fn get_loudness(meter: &Backend) -> Result<Decibel> {
+            Ok(Decibel(meter.loudness_shortterm()?))
         }
     }
 
     impl EBUMeterMode for Integrated {
         fn mode() -> Mode {
-            Mode::I
+            Mode::I | Mode::LRA | Mode::TRUE_PEAK
         }
 
-        fn get_loudness(meter: &EbuR128) -> LUFS {
-            Decibel(meter.loudness_global().unwrap())
+        fn get_loudness(meter: &Backend) -> Result<Decibel> {
+            Ok(Decibel(meter.loudness_global()?))
+        }
+    }
+
+    impl EBUMeterMode for Program {
+        fn mode() -> Mode {
+            Mode::I | Mode::LRA
+        }
+
+        fn get_loudness(meter: &Backend) -> Result<Decibel> {
+            Ok(Decibel(meter.loudness_global()?))
         }
     }
 }
@@ -74,34 +100,114 @@ pub mod modes {
 /// EBU metering structure.
 #[derive(Debug)]
 pub struct EBUMeter<Mode: modes::EBUMeterMode> {
-    meter: EbuR128,
+    meter: Backend,
+    channels: u32,
+    /// Only needed to allocate the `libebur128` state again on reset.
+    #[cfg_attr(feature = "pure-rust", allow(dead_code))]
+    samplerate: u32,
+    channel_map: Vec<Option<Channel>>,
     mode: PhantomData<Mode>,
 }
 
 impl<Mode: modes::EBUMeterMode> EBUMeter<Mode> {
     /// New ebu meter with the given channels and sample rate.
-    pub fn new(channels: u32, samplerate: u32) -> Self {
-        Self {
+    pub fn new(channels: u32, samplerate: u32) -> Result<Self> {
+        Error::check_config(channels, samplerate)?;
+        Ok(Self {
             mode: PhantomData,
-            meter: EbuR128::new(channels, samplerate, Mode::mode()).unwrap(),
+            meter: Backend::new(channels, samplerate, Mode::mode())?,
+            channels,
+            samplerate,
+            channel_map: vec![None; channels as usize],
+        })
+    }
+
+    /// Sets the loudness weighting of the given channel, following ITU-R BS.1770. By default
+    /// channels are laid out as L, R, C, LFE, Ls, Rs, with the LFE and any further channels left
+    /// out of the measurement; use `Channel::Unused` to exclude a channel.
+    pub fn set_channel(&mut self, channel: u32, value: Channel) -> Result<()> {
+        self.check_channel(channel)?;
+        self.meter.set_channel(channel, value)?;
+        self.channel_map[channel as usize] = Some(value);
+        Ok(())
+    }
+
+    /// Clears all measurements, keeping the channel weighting. The pure-Rust backend is cleared in
+    /// place; `libebur128` has no reset, so its state is allocated again.
+    pub fn reset(&mut self) -> Result<()> {
+        #[cfg(feature = "pure-rust")]
+        self.meter.reset();
+        #[cfg(not(feature = "pure-rust"))]
+        {
+            self.meter = Backend::new(self.channels, self.samplerate, Mode::mode())?;
+            for (channel, value) in self.channel_map.iter().enumerate() {
+                if let Some(value) = value {
+                    self.meter.set_channel(channel as u32, *value)?;
+                }
+            }
         }
+        Ok(())
     }
 
     /// Add a frame to process by the loudness meter.
-    pub fn add_samples(&mut self, buffer: &[f64]) -> bool {
-        self.meter.add_frames_f64(buffer).is_ok()
+    pub fn add_samples(&mut self, buffer: &[f64]) -> Result<()> {
+        self.meter.add_frames_f64(buffer)?;
+        Ok(())
     }
 
     /// Returns the computed loudness.
-    pub fn get_loudness(&self) -> LUFS {
+    pub fn get_loudness(&self) -> Result<Decibel> {
         Mode::get_loudness(&self.meter)
     }
+
+    /// Returns the momentary loudness (available in all modes).
+    pub fn get_momentary(&self) -> Result<Decibel> {
+        Ok(Decibel(self.meter.loudness_momentary()?))
+    }
+
+    fn check_channel(&self, channel: u32) -> Result<()> {
+        if channel < self.channels {
+            Ok(())
+        } else {
+            Err(Error::InvalidChannel(channel))
+        }
+    }
+}
+
+impl EBUMeter<modes::Short> {
+    /// Returns the short-term loudness (available in Short and Integrated modes).
+    pub fn get_short_term(&self) -> Result<Decibel> {
+        Ok(Decibel(self.meter.loudness_shortterm()?))
+    }
 }
 
 impl EBUMeter<modes::Integrated> {
+    /// Returns the short-term loudness (available in Short and Integrated modes).
+    pub fn get_short_term(&self) -> Result<Decibel> {
+        Ok(Decibel(self.meter.loudness_shortterm()?))
+    }
+
     /// Get loudness range (only available in Integrated mode).
-    pub fn get_range(&self) -> LUFS {
-        let raw = self.meter.loudness_range().unwrap();
-        Decibel(raw)
+    pub fn get_range(&self) -> Result<Decibel> {
+        Ok(Decibel(self.meter.loudness_range()?))
+    }
+
+    /// Get the highest true peak of the given channel since the meter was created (only available
+    /// in Integrated mode).
+    pub fn get_true_peak(&self, channel: u32) -> Result<Linear> {
+        self.check_channel(channel)?;
+        Ok(Linear(self.meter.true_peak(channel)?))
+    }
+}
+
+impl EBUMeter<modes::Program> {
+    /// Returns the short-term loudness.
+    pub fn get_short_term(&self) -> Result<Decibel> {
+        Ok(Decibel(self.meter.loudness_shortterm()?))
+    }
+
+    /// Get loudness range.
+    pub fn get_range(&self) -> Result<Decibel> {
+        Ok(Decibel(self.meter.loudness_range()?))
     }
 }
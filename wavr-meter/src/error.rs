@@ -0,0 +1,75 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Errors returned by the meters.
+
+use std::error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// Lowest sample rate supported by the meters.
+pub const MIN_SAMPLE_RATE: u32 = 16;
+/// Highest sample rate supported by the meters.
+pub const MAX_SAMPLE_RATE: u32 = 2_822_400;
+
+/// Error type for the meters.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The meter cannot measure this number of channels.
+    UnsupportedChannelCount(u32),
+    /// The meter cannot run at this sample rate.
+    UnsupportedSampleRate(u32),
+    /// The channel index is out of range.
+    InvalidChannel(u32),
+    /// The measurement is not available in the mode of the meter.
+    InvalidMode,
+    /// `libebur128` returned an error.
+    #[cfg(feature = "libebur128")]
+    Backend(ebur128::Error),
+}
+
+/// Result type for the meters.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Checks that a meter can be created with the given channel count and sample rate.
+    pub(crate) fn check_config(channels: u32, sample_rate: u32) -> Result<()> {
+        if channels == 0 {
+            Err(Error::UnsupportedChannelCount(channels))
+        } else if !(MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&sample_rate) {
+            Err(Error::UnsupportedSampleRate(sample_rate))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "libebur128")]
+impl From<ebur128::Error> for Error {
+    fn from(err: ebur128::Error) -> Self {
+        Error::Backend(err)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnsupportedChannelCount(count) => {
+                write!(f, "unsupported channel count: {}", count)
+            }
+            Error::UnsupportedSampleRate(rate) => write!(
+                f,
+                "unsupported sample rate: {} Hz (expected {} to {} Hz)",
+                rate, MIN_SAMPLE_RATE, MAX_SAMPLE_RATE
+            ),
+            Error::InvalidChannel(channel) => write!(f, "invalid channel index: {}", channel),
+            Error::InvalidMode => write!(f, "measurement not available in this mode"),
+            #[cfg(feature = "libebur128")]
+            Error::Backend(err) => write!(f, "libebur128 error: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {}
@@ -0,0 +1,234 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Loudness and true peak history, recorded by `WavrMeter` at its 10 Hz update rate and tagged
+//! with sample positions, for quality control reports and history graphs.
+
+use std::collections::VecDeque;
+use std::io;
+use std::io::Write;
+use std::time::Duration;
+
+use smallvec::SmallVec;
+
+use crate::decibel::{Decibel, Linear};
+
+/// Number of entries a new history keeps, one hour at the 10 Hz update rate of `WavrMeter`.
+pub const DEFAULT_HISTORY_CAPACITY: usize = 36000;
+
+/// A single point of the loudness history.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistoryEntry {
+    /// Sample position at the end of the measured interval.
+    pub position: usize,
+    /// Momentary loudness at the position.
+    pub momentary: Decibel,
+    /// Short-term loudness at the position.
+    pub short_term: Decibel,
+    /// Highest true peak of each channel since the previous entry.
+    pub true_peak: SmallVec<[Linear; 16]>,
+}
+
+/// Time series of loudness and true peak measurements, ordered by sample position.
+#[derive(Clone, Debug)]
+pub struct LoudnessHistory {
+    sample_rate: u32,
+    channels: u32,
+    capacity: Option<usize>,
+    entries: VecDeque<HistoryEntry>,
+}
+
+impl LoudnessHistory {
+    /// Create a new history for the given sample rate and channel count, keeping up to
+    /// `DEFAULT_HISTORY_CAPACITY` entries. The entries are allocated up front, so that recording
+    /// does not allocate.
+    pub fn new(sample_rate: u32, channels: u32) -> Self {
+        Self {
+            sample_rate,
+            channels,
+            capacity: Some(DEFAULT_HISTORY_CAPACITY),
+            entries: VecDeque::with_capacity(DEFAULT_HISTORY_CAPACITY),
+        }
+    }
+
+    /// Returns the sample rate of the positions.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Returns the number of channels of the true peak values.
+    pub fn channels(&self) -> u32 {
+        self.channels
+    }
+
+    /// Returns the maximum number of entries kept, or `None` if the history is unbounded.
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    /// Sets the maximum number of entries kept, allocating them up front; the oldest entries are
+    /// dropped first. `None` keeps the whole history, which grows as entries are recorded: an
+    /// unbounded history is not real-time safe.
+    pub fn set_capacity(&mut self, capacity: Option<usize>) {
+        self.capacity = capacity;
+        self.trim();
+        if let Some(capacity) = capacity {
+            self.entries
+                .reserve(capacity.saturating_sub(self.entries.len()));
+        }
+    }
+
+    /// Returns the number of entries in the history.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether the history is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Removes all entries.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Appends an entry. Entries positioned before the last one (after seeking back) replace the
+    /// part of the history they overlap.
+    pub fn push(&mut self, entry: HistoryEntry) {
+        let keep = self
+            .entries
+            .partition_point(|e| e.position < entry.position);
+        self.entries.truncate(keep);
+        if let Some(capacity) = self.capacity {
+            // Make room first, so that the allocated entries are not exceeded
+            while !self.entries.is_empty() && self.entries.len() >= capacity {
+                self.entries.pop_front();
+            }
+        }
+        self.entries.push_back(entry);
+        self.trim();
+    }
+
+    /// Iterates over all entries, from oldest to newest.
+    pub fn iter(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.entries.iter()
+    }
+
+    /// Returns the latest entry.
+    pub fn latest(&self) -> Option<&HistoryEntry> {
+        self.entries.back()
+    }
+
+    /// Iterates over the entries positioned within `start..end`, in samples.
+    pub fn range(&self, start: usize, end: usize) -> impl Iterator<Item = &HistoryEntry> {
+        let first = self.entries.partition_point(|e| e.position < start);
+        let last = self
+            .entries
+            .partition_point(|e| e.position < end)
+            .max(first);
+        self.entries.range(first..last)
+    }
+
+    /// Iterates over the entries positioned within `start..end`, as durations from position 0.
+    pub fn range_time(
+        &self,
+        start: Duration,
+        end: Duration,
+    ) -> impl Iterator<Item = &HistoryEntry> {
+        self.range(self.position_of(start), self.position_of(end))
+    }
+
+    /// Returns the sample position as a duration.
+    pub fn time_of(&self, position: usize) -> Duration {
+        Duration::from_secs_f64(position as f64 / self.sample_rate as f64)
+    }
+
+    /// Returns the duration as a sample position.
+    pub fn position_of(&self, time: Duration) -> usize {
+        (time.as_secs_f64() * self.sample_rate as f64).round() as usize
+    }
+
+    /// Writes the history as CSV, with one row per entry. Loudness values are in LUFS, true peaks
+    /// in dBTP, and silence is written as `-inf`.
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write!(writer, "position,time,momentary,short_term")?;
+        for channel in 1..=self.channels {
+            write!(writer, ",true_peak_{}", channel)?;
+        }
+        writeln!(writer)?;
+        for entry in &self.entries {
+            write!(
+                writer,
+                "{},{:.3},{},{}",
+                entry.position,
+                self.time_of(entry.position).as_secs_f64(),
+                csv_level(entry.momentary),
+                csv_level(entry.short_term)
+            )?;
+            for peak in &entry.true_peak {
+                write!(writer, ",{}", csv_level(peak.into()))?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the history as a JSON object holding the sample rate, the channel count and the
+    /// list of entries. Loudness values are in LUFS, true peaks in dBTP, and silence is written
+    /// as `null`.
+    pub fn write_json<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write!(
+            writer,
+            "{{\"sample_rate\":{},\"channels\":{},\"entries\":[",
+            self.sample_rate, self.channels
+        )?;
+        for (i, entry) in self.entries.iter().enumerate() {
+            if i > 0 {
+                write!(writer, ",")?;
+            }
+            write!(
+                writer,
+                "{{\"position\":{},\"time\":{:.3},\"momentary\":{},\"short_term\":{},\"true_peak\":[",
+                entry.position,
+                self.time_of(entry.position).as_secs_f64(),
+                json_level(entry.momentary),
+                json_level(entry.short_term)
+            )?;
+            for (c, peak) in entry.true_peak.iter().enumerate() {
+                if c > 0 {
+                    write!(writer, ",")?;
+                }
+                write!(writer, "{}", json_level(peak.into()))?;
+            }
+            write!(writer, "]}}")?;
+        }
+        writeln!(writer, "]}}")
+    }
+
+    fn trim(&mut self) {
+        if let Some(capacity) = self.capacity {
+            while self.entries.len() > capacity {
+                self.entries.pop_front();
+            }
+        }
+    }
+}
+
+fn csv_level(Decibel(level): Decibel) -> String {
+    if level.is_finite() {
+        format!("{:.2}", level)
+    } else {
+        String::from("-inf")
+    }
+}
+
+fn json_level(Decibel(level): Decibel) -> String {
+    if level.is_finite() {
+        format!("{:.2}", level)
+    } else {
+        String::from("null")
+    }
+}
@@ -6,60 +6,439 @@
 //! # The Wavr Audio Meter
 //!
 //! This crate implements a dual-purpose peak and EBU loudness audio metering, using `libebur128`
-//! as the backing implementation.
+//! as the backing implementation, an FFT spectrum analyzer and stereo correlation metering.
+//!
+//! The loudness and true peak measurements are done by `libebur128` by default. Enabling the
+//! `pure-rust` feature switches them to the pure-Rust implementation of the
+//! [`bs1770`](bs1770/index.html) module.
+
+#[cfg(not(any(feature = "libebur128", feature = "pure-rust")))]
+compile_error!("wavr-meter needs one of the `libebur128` or `pure-rust` features");
 
 use smallvec::{Array, SmallVec};
 
+pub use aggregate::*;
+pub use ballistics::*;
+pub use channels::*;
+pub use clip::*;
 pub use ebu::*;
+pub use error::{Error, Result};
+pub use history::*;
 pub use peak::*;
+pub use rms::*;
+pub use spectrum::*;
+pub use stereo::*;
+pub use true_peak::*;
 use wavr_audio_buffer::AudioBuffer;
 
-use crate::decibel::{Linear, LUFS};
+use crate::decibel::{Decibel, Linear};
 
+pub mod aggregate;
+pub mod ballistics;
+#[cfg(feature = "pure-rust")]
+pub mod bs1770;
+pub mod channels;
+pub mod clip;
 pub mod decibel;
 pub mod ebu;
+pub mod error;
+pub mod history;
 pub mod peak;
+pub mod rms;
+pub mod spectrum;
+pub mod stereo;
+pub mod true_peak;
+pub mod widget;
 
-/// Audio meter structure. Holds a peak meter for each channel, and a single EBU meter for the
-/// whole input.
+/// Audio meter structure. Holds a peak meter for each channel, and EBU meters for the whole
+/// input: a live one for the momentary and short-term loudness, and a program one for the
+/// integrated loudness, loudness range and true peak, which can be paused and reset following
+/// EBU Tech 3341. The meter can also record a loudness and true peak history.
 #[derive(Debug)]
 pub struct WavrMeter {
     channels: u32,
+    sample_rate: u32,
+    layout: ChannelLayout,
     peak_meters: SmallVec<[PeakMeter; 16]>,
+    rms_meter: RmsMeter,
     ebu_meter: EBUMeter<modes::Short>,
+    program_meter: EBUMeter<modes::Program>,
+    max_momentary: Decibel,
+    max_short_term: Decibel,
+    max_true_peak: Linear,
+    paused: bool,
+    has_data: bool,
+    update_period: usize,
+    until_update: usize,
+    position: usize,
+    history: Option<LoudnessHistory>,
+    history_peaks: SmallVec<[Linear; 16]>,
+    true_peak: TruePeak,
+}
+
+/// EBU R 128 measurement report, as specified by EBU Tech 3341.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EBUReport {
+    /// Momentary loudness (400 ms window).
+    pub momentary: Decibel,
+    /// Short-term loudness (3 s window).
+    pub short_term: Decibel,
+    /// Integrated loudness since the last reset.
+    pub integrated: Decibel,
+    /// Loudness range since the last reset, in LU.
+    pub range: Decibel,
+    /// Maximum momentary loudness since the last reset.
+    pub max_momentary: Decibel,
+    /// Maximum short-term loudness since the last reset.
+    pub max_short_term: Decibel,
+    /// Maximum true peak over all channels since the last reset.
+    pub max_true_peak: Linear,
 }
 
 /// Audio meter data, computed from `WavrMeter`.
 #[derive(Clone, Debug, PartialEq)]
 pub struct WavrMeterData {
+    /// Role of each channel, for labelling and grouping the meter bars.
+    pub layout: ChannelLayout,
     pub peak: SmallVec<[Linear; 16]>,
-    pub loudness: LUFS,
+    /// RMS level of each channel, over the RMS window of the meter.
+    pub rms: SmallVec<[Linear; 16]>,
+    /// Clip latch and over counters of each channel.
+    pub clips: SmallVec<[ClipStatus; 16]>,
+    /// Short-term loudness, same as `ebu.short_term`.
+    pub loudness: Decibel,
+    pub ebu: EBUReport,
 }
 
 impl WavrMeter {
     /// Create a new audio meter from the given channel count and sample rate.
-    pub fn new(channels: u32, sample_rate: u32) -> Self {
-        Self {
+    pub fn new(channels: u32, sample_rate: u32) -> Result<Self> {
+        let update_period = (sample_rate / 10).max(1) as usize;
+        let mut meter = Self {
             channels,
-            peak_meters: (0..channels).map(|_| PeakMeter::new(sample_rate)).collect(),
-            ebu_meter: EBUMeter::new(channels, sample_rate),
+            sample_rate,
+            layout: ChannelLayout::default_for(channels as usize),
+            peak_meters: (0..channels)
+                .map(|_| PeakMeter::new(sample_rate))
+                .collect::<Result<_>>()?,
+            rms_meter: RmsMeter::new(channels as usize, sample_rate),
+            ebu_meter: EBUMeter::new(channels, sample_rate)?,
+            program_meter: EBUMeter::new(channels, sample_rate)?,
+            max_momentary: Decibel(f64::NEG_INFINITY),
+            max_short_term: Decibel(f64::NEG_INFINITY),
+            max_true_peak: Linear(0.0),
+            paused: false,
+            has_data: false,
+            update_period,
+            until_update: update_period,
+            position: 0,
+            history: None,
+            history_peaks: SmallVec::from_elem(Linear(0.0), channels as usize),
+            true_peak: TruePeak::new(channels as usize, sample_rate),
+        };
+        meter.set_layout(meter.layout.clone())?;
+        Ok(meter)
+    }
+
+    /// Returns the channel layout of the meter.
+    pub fn layout(&self) -> &ChannelLayout {
+        &self.layout
+    }
+
+    /// Sets the channel layout of the meter, applying the BS.1770 loudness weighting of each
+    /// role. Defaults to `ChannelLayout::default_for` the channel count.
+    pub fn set_layout(&mut self, layout: ChannelLayout) -> Result<()> {
+        if layout.len() != self.channels as usize {
+            return Err(Error::UnsupportedChannelCount(layout.len() as u32));
+        }
+        for (channel, role) in layout.iter().enumerate() {
+            self.set_channel_weighting(channel as u32, role.weighting())?;
+        }
+        self.layout = layout;
+        Ok(())
+    }
+
+    /// Sets the loudness weighting of the given channel, overriding the weighting of its role in
+    /// the channel layout. See `EBUMeter::set_channel`.
+    pub fn set_channel_weighting(&mut self, channel: u32, value: Channel) -> Result<()> {
+        self.ebu_meter.set_channel(channel, value)?;
+        self.program_meter.set_channel(channel, value)
+    }
+
+    /// Returns the ballistics of the per-channel level meters.
+    pub fn ballistics(&self) -> Ballistics {
+        self.peak_meters
+            .first()
+            .map(PeakMeter::ballistics)
+            .unwrap_or_default()
+    }
+
+    /// Sets the ballistics of the per-channel level meters, for example
+    /// `Ballistics::ppm_type_ii()`.
+    pub fn set_ballistics(&mut self, ballistics: Ballistics) {
+        for meter in &mut self.peak_meters {
+            meter.set_ballistics(ballistics);
+        }
+    }
+
+    /// Returns the RMS averaging window, in milliseconds.
+    pub fn rms_window_ms(&self) -> f64 {
+        self.rms_meter.window_ms()
+    }
+
+    /// Sets the RMS averaging window, in milliseconds.
+    pub fn set_rms_window_ms(&mut self, window_ms: f64) {
+        self.rms_meter.set_window_ms(window_ms);
+    }
+
+    /// Returns whether the RMS levels are AES-17 calibrated.
+    pub fn is_rms_aes17(&self) -> bool {
+        self.rms_meter.is_aes17()
+    }
+
+    /// Sets whether the RMS levels are AES-17 calibrated (+3.01 dB), as used by the K-System
+    /// scales.
+    pub fn set_rms_aes17(&mut self, aes17: bool) {
+        self.rms_meter.set_aes17(aes17);
+    }
+
+    /// Sets the sample threshold and the number of consecutive samples making a sample over, on
+    /// all channels. Defaults to 3 samples at full scale.
+    pub fn set_clip_threshold(&mut self, threshold: Linear, min_consecutive: usize) {
+        for meter in &mut self.peak_meters {
+            meter.clips_mut().set_threshold(threshold);
+            meter.clips_mut().set_min_consecutive(min_consecutive);
+        }
+    }
+
+    /// Sets the true peak over threshold on all channels. Defaults to 0 dBTP.
+    pub fn set_true_peak_clip_threshold(&mut self, threshold: Linear) {
+        for meter in &mut self.peak_meters {
+            meter.clips_mut().set_true_peak_threshold(threshold);
+        }
+    }
+
+    /// Returns whether any channel clipped since the last latch reset.
+    pub fn is_clipped(&self) -> bool {
+        self.peak_meters.iter().any(|m| m.clips().is_latched())
+    }
+
+    /// Resets the clip latch of all channels, keeping the over counters and events.
+    pub fn reset_clip_latch(&mut self) {
+        for meter in &mut self.peak_meters {
+            meter.clips_mut().reset_latch();
+        }
+    }
+
+    /// Resets the clip latch, over counters and clip events of all channels.
+    pub fn reset_clips(&mut self) {
+        for meter in &mut self.peak_meters {
+            meter.clips_mut().reset();
+        }
+    }
+
+    /// Removes the clip events of all channels, appending them to `events` ordered by sample
+    /// position. This does not allocate when `events` has room for the events.
+    pub fn take_clip_events(&mut self, events: &mut Vec<ClipEvent>) {
+        let start = events.len();
+        for (channel, meter) in self.peak_meters.iter_mut().enumerate() {
+            let first = events.len();
+            meter.clips_mut().take_events(events);
+            for event in &mut events[first..] {
+                event.channel = channel as u32;
+            }
+        }
+        events[start..].sort_unstable_by_key(|event| (event.position, event.channel));
+    }
+
+    /// Returns whether the program measurement is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pauses or continues the program measurement (integrated loudness, loudness range and
+    /// maximum values). The momentary and short-term loudness keep following the input.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Resets the program measurement (integrated loudness, loudness range and maximum values).
+    pub fn reset(&mut self) -> Result<()> {
+        self.max_momentary = Decibel(f64::NEG_INFINITY);
+        self.max_short_term = Decibel(f64::NEG_INFINITY);
+        self.max_true_peak = Linear(0.0);
+        self.program_meter.reset()
+    }
+
+    /// Returns whether the loudness history is recorded.
+    pub fn is_history_enabled(&self) -> bool {
+        self.history.is_some()
+    }
+
+    /// Starts or stops recording the loudness history. Stopping the recording drops the history.
+    pub fn set_history_enabled(&mut self, enabled: bool) {
+        if !enabled {
+            self.history = None;
+        } else if self.history.is_none() {
+            self.history = Some(LoudnessHistory::new(self.sample_rate, self.channels));
+            self.history_peaks.iter_mut().for_each(|p| *p = Linear(0.0));
         }
     }
 
-    /// Add an audio frame to process by the audio meter.
+    /// Returns the recorded loudness history, if enabled.
+    pub fn history(&self) -> Option<&LoudnessHistory> {
+        self.history.as_ref()
+    }
+
+    /// Returns the recorded loudness history mutably, if enabled.
+    pub fn history_mut(&mut self) -> Option<&mut LoudnessHistory> {
+        self.history.as_mut()
+    }
+
+    /// Returns the sample position of the next processed sample.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Sets the sample position of the next processed sample, used to tag the history entries.
+    pub fn set_position(&mut self, position: usize) {
+        self.position = position;
+    }
+
+    /// Add an audio frame starting at the given sample position (for example the
+    /// `current_sample` of the audio context) to process by the audio meter.
+    pub fn add_samples_at(&mut self, position: usize, buffer: &AudioBuffer) {
+        self.position = position;
+        self.add_samples(buffer);
+    }
+
+    /// Add an audio frame to process by the audio meter. Errors from the underlying meters are not
+    /// reported; the affected samples are left out of the measurement instead.
     pub fn add_samples(&mut self, buffer: &AudioBuffer) {
+        let position = self.position;
+        for meter in &mut self.peak_meters {
+            meter.set_position(position);
+        }
         let interleaved = AudioBuffer::clone(buffer).interleave();
-        self.ebu_meter.add_samples(&interleaved);
-        for (buffer, meter) in buffer.iter().zip(&mut self.peak_meters) {
-            meter.add_samples(&buffer);
+        let channels = self.channels as usize;
+        let frames = interleaved.len() / channels.max(1);
+        let mut start = 0;
+        // Feed the EBU meters in chunks ending on the 10 Hz update boundaries, so that the
+        // maximum momentary and short-term values do not depend on the block size.
+        while start < frames {
+            let len = self.until_update.min(frames - start);
+            let chunk = &interleaved[start * channels..(start + len) * channels];
+            self.has_data |= self.ebu_meter.add_samples(chunk).is_ok();
+            if !self.paused {
+                self.program_meter.add_samples(chunk).ok();
+            }
+            self.track_peaks(chunk);
+            start += len;
+            self.until_update -= len;
+            if self.until_update == 0 {
+                self.until_update = self.update_period;
+                self.update_maxima();
+                self.record_history(position + start);
+            }
+        }
+        self.position += frames;
+        self.rms_meter.add_samples(buffer);
+    }
+
+    /// Get the processed audio meter values, or `None` if no samples have been processed yet.
+    pub fn get_values(&self) -> Option<WavrMeterData> {
+        let ebu = self.get_report()?;
+        Some(WavrMeterData {
+            layout: self.layout.clone(),
+            peak: self.peak_meters.iter().map(|m| m.get_level()).collect(),
+            rms: self.rms_meter.get_values(),
+            clips: self
+                .peak_meters
+                .iter()
+                .map(|m| m.clips().status())
+                .collect(),
+            loudness: ebu.short_term,
+            ebu,
+        })
+    }
+
+    /// Get the full EBU R 128 measurement report, or `None` if no samples have been processed yet.
+    pub fn get_report(&self) -> Option<EBUReport> {
+        if self.has_data {
+            self.report().ok()
+        } else {
+            None
         }
     }
 
-    /// Get the processed audio meter values.
-    pub fn get_values(&self) -> WavrMeterData {
-        WavrMeterData {
-            peak: self.peak_meters.iter().map(|m| m.get_true_peak()).collect(),
-            loudness: self.ebu_meter.get_loudness(),
+    fn report(&self) -> Result<EBUReport> {
+        Ok(EBUReport {
+            momentary: self.ebu_meter.get_momentary()?,
+            short_term: self.ebu_meter.get_short_term()?,
+            integrated: self.program_meter.get_loudness()?,
+            range: self.program_meter.get_range()?,
+            max_momentary: self.max_momentary,
+            max_short_term: self.max_short_term,
+            max_true_peak: self.max_true_peak,
+        })
+    }
+
+    /// Computes the true peak of each sample once, for the level meters, the over detection, the
+    /// history and the program maximum.
+    fn track_peaks(&mut self, chunk: &[f64]) {
+        let Self {
+            true_peak,
+            peak_meters,
+            history_peaks,
+            max_true_peak,
+            paused,
+            ..
+        } = self;
+        for frame in chunk.chunks(self.channels as usize) {
+            true_peak.process_frame(frame, |channel, peak| {
+                peak_meters[channel].process(frame[channel], peak);
+                if peak > history_peaks[channel].0 {
+                    history_peaks[channel] = Linear(peak);
+                }
+                if !*paused && peak > max_true_peak.0 {
+                    *max_true_peak = Linear(peak);
+                }
+            });
+        }
+    }
+
+    fn record_history(&mut self, position: usize) {
+        let history = match &mut self.history {
+            Some(history) => history,
+            None => return,
+        };
+        if let (Ok(momentary), Ok(short_term)) = (
+            self.ebu_meter.get_momentary(),
+            self.ebu_meter.get_short_term(),
+        ) {
+            history.push(HistoryEntry {
+                position,
+                momentary,
+                short_term,
+                true_peak: self.history_peaks.clone(),
+            });
+        }
+        self.history_peaks.iter_mut().for_each(|p| *p = Linear(0.0));
+    }
+
+    fn update_maxima(&mut self) {
+        if self.paused {
+            return;
+        }
+        if let Ok(momentary) = self.program_meter.get_momentary() {
+            if momentary > self.max_momentary {
+                self.max_momentary = momentary;
+            }
+        }
+        if let Ok(short_term) = self.program_meter.get_short_term() {
+            if short_term > self.max_short_term {
+                self.max_short_term = short_term;
+            }
         }
     }
 }
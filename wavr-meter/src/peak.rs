@@ -5,71 +5,126 @@
  */
 //! # The Wavr Audio Meter
 //!
-//! This crates implements a single-channel true peak meter.
-
-use circular_queue::CircularQueue;
-use ebur128::EbuR128;
+//! This crates implements a single-channel true peak meter, with configurable ballistics.
 
+use crate::ballistics::{Ballistics, BallisticsProcessor};
+use crate::clip::ClipDetector;
 use crate::decibel::Linear;
+use crate::error::{Error, Result};
+use crate::true_peak::TruePeak;
+
+/// Rate at which the deprecated decay coefficient is applied, the update rate of `WavrMeter`.
+const DECAY_UPDATE_RATE: f64 = 10.0;
 
-/// A single-channel peak meter, implemented by `libebur128`'s True Peak mode. The meter also
-/// features peak-holding and value decay to stabilize the peak display while staying accurate.
+/// A single-channel level meter. By default the meter reads the true peak with the ballistics of
+/// an EBU digital peak meter; other ballistics (PPM, VU, K-System) can be set, and behave the same
+/// regardless of the block size. The meter also detects overs, see `ClipDetector`. The true peak
+/// is computed once per sample, for both the ballistics and the over detection.
 #[derive(Debug)]
 pub struct PeakMeter {
-    meter: EbuR128,
-    decay: f64,
-    peaks: CircularQueue<Linear>,
-    last_peak: Linear,
+    sample_rate: u32,
+    true_peak: TruePeak,
+    processor: BallisticsProcessor,
+    clips: ClipDetector,
+    position: usize,
 }
 
 impl PeakMeter {
     /// Create a new peak meter using the given sample rate.
-    pub fn new(sample_rate: u32) -> Self {
-        Self {
-            meter: EbuR128::new(1, sample_rate, ebur128::Mode::TRUE_PEAK).unwrap(),
-            peaks: CircularQueue::with_capacity(10),
-            last_peak: Linear(0.0),
-            decay: 0.98,
-        }
+    pub fn new(sample_rate: u32) -> Result<Self> {
+        Self::with_ballistics(sample_rate, Ballistics::default())
+    }
+
+    /// Create a new level meter using the given sample rate and ballistics.
+    pub fn with_ballistics(sample_rate: u32, ballistics: Ballistics) -> Result<Self> {
+        Error::check_config(1, sample_rate)?;
+        Ok(Self {
+            sample_rate,
+            true_peak: TruePeak::new(1, sample_rate),
+            processor: BallisticsProcessor::new(ballistics, sample_rate),
+            clips: ClipDetector::new(sample_rate),
+            position: 0,
+        })
     }
 
     /// Add a frame to process by the peak meter.
     pub fn add_samples(&mut self, buffer: &[f64]) {
-        if let Some(peak) = self.peaks.iter().cloned().next() {
-            let peak = self.get_held_peak(peak);
-            self.last_peak = if peak >= self.last_peak {
-                peak
-            } else {
-                self.last_peak * self.decay
-            };
+        for &sample in buffer {
+            let true_peak = self.true_peak.process_sample(sample);
+            self.process(sample, true_peak);
         }
+    }
 
-        self.meter.add_frames_f64(buffer).unwrap();
-        self.peaks
-            .push(Linear(self.meter.prev_true_peak(0).unwrap()));
+    /// Add a frame starting at the given sample position to process by the peak meter. The
+    /// position tags the clip events.
+    pub fn add_samples_at(&mut self, position: usize, buffer: &[f64]) {
+        self.position = position;
+        self.add_samples(buffer)
     }
 
-    /// Returns the level decay coefficient.
-    pub fn decay(&self) -> f64 {
-        self.decay
+    /// Returns the over detector of the meter.
+    pub fn clips(&self) -> &ClipDetector {
+        &self.clips
     }
 
-    /// Sets the level decay coefficient.
-    pub fn set_decay(&mut self, decay: f64) {
-        self.decay = decay;
+    /// Returns the over detector of the meter mutably, to configure it or reset it.
+    pub fn clips_mut(&mut self) -> &mut ClipDetector {
+        &mut self.clips
+    }
+
+    /// Returns the meter ballistics.
+    pub fn ballistics(&self) -> Ballistics {
+        self.processor.ballistics()
+    }
+
+    /// Sets the meter ballistics. This resets the meter.
+    pub fn set_ballistics(&mut self, ballistics: Ballistics) {
+        self.processor = BallisticsProcessor::new(ballistics, self.sample_rate);
+    }
+
+    /// Clears the meter level.
+    pub fn reset(&mut self) {
+        self.true_peak.reset();
+        self.processor.reset();
+    }
+
+    /// Gets the meter level, taking into account the ballistics.
+    pub fn get_level(&self) -> Linear {
+        Linear(self.processor.level())
+    }
+
+    /// Sets the sample position of the next processed sample.
+    pub(crate) fn set_position(&mut self, position: usize) {
+        self.position = position;
+    }
+
+    /// Processes one sample with its true peak, computed by the caller with a `TruePeak`
+    /// detector, leaving the interpolator of the meter unused.
+    pub(crate) fn process(&mut self, sample: f64, true_peak: f64) {
+        self.processor.process(sample, true_peak);
+        self.clips
+            .process_true_peak(self.position, sample, true_peak);
+        self.position += 1;
     }
 
     /// Gets the true peak value, taking into account peak-holding and level decay.
+    #[deprecated(note = "use `get_level`, which reads the level with the meter ballistics")]
     pub fn get_true_peak(&self) -> Linear {
-        self.last_peak
+        self.get_level()
+    }
+
+    /// Returns the level decay coefficient, applied every 100 ms.
+    #[deprecated(note = "use the `decay_db_per_s` of the meter `ballistics`")]
+    pub fn decay(&self) -> f64 {
+        10f64.powf(-self.ballistics().decay_db_per_s / DECAY_UPDATE_RATE / 20.0)
     }
 
-    fn get_held_peak(&self, val: Linear) -> Linear {
-        *self
-            .peaks
-            .iter()
-            .filter(|a| a.0.is_finite())
-            .max_by(|a, b| a.partial_cmp(b).unwrap())
-            .unwrap_or(&val)
+    /// Sets the level decay coefficient, applied every 100 ms.
+    #[deprecated(note = "use `set_ballistics` with the fall back rate in `decay_db_per_s`")]
+    pub fn set_decay(&mut self, decay: f64) {
+        self.set_ballistics(Ballistics {
+            decay_db_per_s: -20.0 * decay.log10() * DECAY_UPDATE_RATE,
+            ..self.ballistics()
+        });
     }
 }
@@ -0,0 +1,159 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! RMS metering over a sliding window, with the AES-17 sine calibration and the K-System scales.
+
+use smallvec::SmallVec;
+use wavr_audio_buffer::AudioBuffer;
+
+use crate::decibel::{Decibel, Linear};
+
+/// Scale factor of the AES-17 RMS calibration (+3.01 dB), so that a sine reads its peak level.
+const AES17_GAIN: f64 = std::f64::consts::SQRT_2;
+
+/// K-System meter scales. The 0 mark of each scale sits at the given headroom below full scale,
+/// using AES-17 calibrated RMS levels.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum KScale {
+    /// 12 dB headroom, for broadcast.
+    K12,
+    /// 14 dB headroom, for pop music mastering.
+    K14,
+    /// 20 dB headroom, for wide dynamic range material.
+    K20,
+}
+
+impl KScale {
+    /// Returns the headroom of the scale, in dB.
+    pub fn headroom(self) -> f64 {
+        match self {
+            KScale::K12 => 12.0,
+            KScale::K14 => 14.0,
+            KScale::K20 => 20.0,
+        }
+    }
+
+    /// Converts a level in dBFS into the scale.
+    pub fn to_scale(self, Decibel(level): Decibel) -> Decibel {
+        Decibel(level + self.headroom())
+    }
+
+    /// Converts a level of the scale into dBFS.
+    pub fn from_scale(self, Decibel(level): Decibel) -> Decibel {
+        Decibel(level - self.headroom())
+    }
+}
+
+/// Per-channel RMS window.
+#[derive(Clone, Debug)]
+struct Window {
+    squares: Vec<f64>,
+    index: usize,
+    sum: f64,
+}
+
+impl Window {
+    fn new(len: usize) -> Self {
+        Self {
+            squares: vec![0.0; len],
+            index: 0,
+            sum: 0.0,
+        }
+    }
+
+    fn push(&mut self, sample: f64) {
+        let square = sample * sample;
+        self.sum += square - self.squares[self.index];
+        self.squares[self.index] = square;
+        self.index += 1;
+        if self.index == self.squares.len() {
+            self.index = 0;
+            // Recompute the sum once per window, so that rounding errors do not accumulate
+            self.sum = self.squares.iter().sum();
+        }
+    }
+
+    fn mean_square(&self) -> f64 {
+        (self.sum / self.squares.len() as f64).max(0.0)
+    }
+}
+
+/// Multi-channel RMS meter, averaging the signal power over a sliding window. By default the
+/// window is 300 ms long and the levels are not AES-17 calibrated, so that a full-scale sine
+/// reads -3.01 dBFS.
+#[derive(Clone, Debug)]
+pub struct RmsMeter {
+    sample_rate: u32,
+    window_ms: f64,
+    aes17: bool,
+    windows: SmallVec<[Window; 16]>,
+}
+
+impl RmsMeter {
+    /// Create a new RMS meter for the given channel count and sample rate.
+    pub fn new(channels: usize, sample_rate: u32) -> Self {
+        let window_ms = 300.0;
+        Self {
+            sample_rate,
+            window_ms,
+            aes17: false,
+            windows: SmallVec::from_elem(Window::new(window_len(window_ms, sample_rate)), channels),
+        }
+    }
+
+    /// Returns the length of the averaging window, in milliseconds.
+    pub fn window_ms(&self) -> f64 {
+        self.window_ms
+    }
+
+    /// Sets the length of the averaging window, in milliseconds. This resets the meter.
+    pub fn set_window_ms(&mut self, window_ms: f64) {
+        self.window_ms = window_ms;
+        self.reset();
+    }
+
+    /// Returns whether the levels are AES-17 calibrated.
+    pub fn is_aes17(&self) -> bool {
+        self.aes17
+    }
+
+    /// Sets whether the levels are AES-17 calibrated (+3.01 dB), so that a sine reads its peak
+    /// level.
+    pub fn set_aes17(&mut self, aes17: bool) {
+        self.aes17 = aes17;
+    }
+
+    /// Clears the averaging windows.
+    pub fn reset(&mut self) {
+        let len = window_len(self.window_ms, self.sample_rate);
+        for window in &mut self.windows {
+            *window = Window::new(len);
+        }
+    }
+
+    /// Add an audio block to process by the RMS meter.
+    pub fn add_samples(&mut self, buffer: &AudioBuffer) {
+        for (samples, window) in buffer.iter().zip(&mut self.windows) {
+            for &sample in samples.iter() {
+                window.push(sample);
+            }
+        }
+    }
+
+    /// Returns the RMS level of the given channel.
+    pub fn get_rms(&self, channel: usize) -> Linear {
+        let rms = self.windows[channel].mean_square().sqrt();
+        Linear(if self.aes17 { rms * AES17_GAIN } else { rms })
+    }
+
+    /// Returns the RMS level of all channels.
+    pub fn get_values(&self) -> SmallVec<[Linear; 16]> {
+        (0..self.windows.len()).map(|c| self.get_rms(c)).collect()
+    }
+}
+
+fn window_len(window_ms: f64, sample_rate: u32) -> usize {
+    ((window_ms * sample_rate as f64 / 1000.0).round() as usize).max(1)
+}
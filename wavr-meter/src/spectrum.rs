@@ -0,0 +1,493 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! # The Wavr Audio Meter
+//!
+//! This crate implements an FFT spectrum analyzer, computing the magnitude spectra of each channel
+//! with a windowed short-time Fourier transform.
+//!
+//! Each frame goes through the following steps:
+//!
+//! 1. the last `fft_size` samples of each channel are windowed and transformed,
+//! 2. the power spectrum is averaged with the previous frames,
+//! 3. the averaged spectrum is smoothed over fractional octaves,
+//! 4. the result is converted to decibels, calibrated so that a full-scale sine reads 0 dB, and
+//!    the peak-hold curve is updated.
+
+use std::f64::consts::PI;
+use std::fmt::{self, Formatter};
+use std::sync::Arc;
+
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use smallvec::SmallVec;
+use wavr_audio_buffer::AudioBuffer;
+
+use crate::decibel::Decibel;
+
+/// Lowest level reported by the spectrum meter, in decibels.
+const FLOOR_DB: f64 = -200.0;
+/// Decay of the peak-hold curve once the hold time has elapsed, in decibels per second.
+const PEAK_DECAY_DB_PER_S: f64 = 20.0;
+
+/// Window applied to each frame before the transform.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Window {
+    /// Hann window, a good general-purpose compromise between resolution and leakage.
+    Hann,
+    /// 4-term Blackman-Harris window, with very low leakage (-92 dB side lobes).
+    BlackmanHarris,
+    /// Flat-top window, with accurate amplitudes at the cost of resolution.
+    FlatTop,
+}
+
+/// Averaging of successive frames, applied to the power spectrum.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Averaging {
+    /// Only the last frame is shown.
+    None,
+    /// Exponential averaging with the given time constant, in milliseconds.
+    Exponential(f64),
+    /// Mean of the given number of last frames.
+    Linear(usize),
+}
+
+/// Fractional-octave smoothing of the spectrum.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Smoothing {
+    /// Every bin is shown as is.
+    None,
+    /// Every bin is averaged with its neighbours within an octave.
+    Octave,
+    /// Every bin is averaged with its neighbours within a third of an octave.
+    ThirdOctave,
+}
+
+/// Spectrum meter data, computed from `SpectrumMeter`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpectrumData {
+    /// Center frequency of each bin, in Hertz.
+    pub frequencies: Vec<f64>,
+    /// Magnitude of each bin, for each channel.
+    pub magnitudes: SmallVec<[Vec<Decibel>; 16]>,
+    /// Peak-hold magnitude of each bin, for each channel.
+    pub peaks: SmallVec<[Vec<Decibel>; 16]>,
+}
+
+/// Per-channel state of the spectrum meter.
+#[derive(Clone, Debug)]
+struct ChannelState {
+    /// Ring buffer of the last `fft_size` samples.
+    input: Vec<f64>,
+    power: Vec<f64>,
+    /// Last frames for linear averaging, allocated for the averaged frame count.
+    history: Vec<Vec<f64>>,
+    history_pos: usize,
+    history_len: usize,
+    magnitudes: Vec<f64>,
+    peaks: Vec<f64>,
+    peak_ages: Vec<f64>,
+}
+
+/// Multi-channel FFT spectrum meter.
+pub struct SpectrumMeter {
+    sample_rate: u32,
+    fft_size: usize,
+    window: Window,
+    overlap: f64,
+    averaging: Averaging,
+    smoothing: Smoothing,
+    peak_hold_ms: Option<f64>,
+    fft: Arc<dyn Fft<f64>>,
+    coefficients: Vec<f64>,
+    input_pos: usize,
+    until_frame: usize,
+    channels: SmallVec<[ChannelState; 16]>,
+    buffer: Vec<Complex<f64>>,
+    scratch: Vec<Complex<f64>>,
+    smoothed: Vec<f64>,
+    prefix: Vec<f64>,
+}
+
+impl fmt::Debug for SpectrumMeter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpectrumMeter")
+            .field("sample_rate", &self.sample_rate)
+            .field("fft_size", &self.fft_size)
+            .field("window", &self.window)
+            .field("overlap", &self.overlap)
+            .field("averaging", &self.averaging)
+            .field("smoothing", &self.smoothing)
+            .field("peak_hold_ms", &self.peak_hold_ms)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Window {
+    /// Returns the coefficients of the window for the given frame size. The windows are
+    /// periodic, as suited for spectral analysis.
+    pub fn coefficients(self, size: usize) -> Vec<f64> {
+        let terms: &[f64] = match self {
+            Window::Hann => &[0.5, 0.5],
+            Window::BlackmanHarris => &[0.35875, 0.48829, 0.14128, 0.01168],
+            Window::FlatTop => &[
+                0.215_578_95,
+                0.416_631_58,
+                0.277_263_158,
+                0.083_578_947,
+                0.006_947_368,
+            ],
+        };
+        (0..size)
+            .map(|n| {
+                let phase = 2.0 * PI * n as f64 / size as f64;
+                terms
+                    .iter()
+                    .enumerate()
+                    .map(|(k, a)| {
+                        let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                        sign * a * (k as f64 * phase).cos()
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+}
+
+impl SpectrumData {
+    /// Returns the magnitude of each bin, combined over the channels by averaging their power.
+    pub fn combined(&self) -> Vec<Decibel> {
+        combine(&self.magnitudes)
+    }
+
+    /// Returns the peak-hold magnitude of each bin, combined over the channels by averaging their
+    /// power.
+    pub fn combined_peaks(&self) -> Vec<Decibel> {
+        combine(&self.peaks)
+    }
+}
+
+impl Smoothing {
+    /// Returns the width of the smoothing band, in octaves.
+    pub fn bandwidth(self) -> Option<f64> {
+        match self {
+            Smoothing::None => None,
+            Smoothing::Octave => Some(1.0),
+            Smoothing::ThirdOctave => Some(1.0 / 3.0),
+        }
+    }
+}
+
+impl ChannelState {
+    fn new(fft_size: usize, averaging: Averaging) -> Self {
+        let bins = fft_size / 2 + 1;
+        Self {
+            input: vec![0.0; fft_size],
+            power: vec![0.0; bins],
+            history: Self::history(bins, averaging),
+            history_pos: 0,
+            history_len: 0,
+            magnitudes: vec![FLOOR_DB; bins],
+            peaks: vec![FLOOR_DB; bins],
+            peak_ages: vec![0.0; bins],
+        }
+    }
+
+    /// Allocates the frames needed by the averaging.
+    fn history(bins: usize, averaging: Averaging) -> Vec<Vec<f64>> {
+        match averaging {
+            Averaging::Linear(count) => vec![vec![0.0; bins]; count.max(1)],
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl SpectrumMeter {
+    /// Create a new spectrum meter for the given channel count and sample rate, with frames of
+    /// `fft_size` samples. The meter uses a Hann window with 50% overlap, no averaging, no
+    /// smoothing, and holds peaks until reset.
+    pub fn new(channels: u32, sample_rate: u32, fft_size: usize) -> Self {
+        let fft_size = fft_size.max(16);
+        let fft = FftPlanner::new().plan_fft_forward(fft_size);
+        let scratch = vec![Complex::default(); fft.get_inplace_scratch_len()];
+        let bins = fft_size / 2 + 1;
+        let mut meter = Self {
+            sample_rate,
+            fft_size,
+            window: Window::Hann,
+            overlap: 0.5,
+            averaging: Averaging::None,
+            smoothing: Smoothing::None,
+            peak_hold_ms: None,
+            fft,
+            coefficients: Vec::new(),
+            input_pos: 0,
+            until_frame: fft_size,
+            channels: (0..channels)
+                .map(|_| ChannelState::new(fft_size, Averaging::None))
+                .collect(),
+            buffer: vec![Complex::default(); fft_size],
+            scratch,
+            smoothed: vec![0.0; bins],
+            prefix: vec![0.0; bins + 1],
+        };
+        meter.set_window(Window::Hann);
+        meter
+    }
+
+    /// Returns the size of the frames, in samples.
+    pub fn fft_size(&self) -> usize {
+        self.fft_size
+    }
+
+    /// Returns the number of frequency bins, from DC to Nyquist.
+    pub fn bin_count(&self) -> usize {
+        self.fft_size / 2 + 1
+    }
+
+    /// Returns the center frequency of a bin, in Hertz.
+    pub fn bin_frequency(&self, bin: usize) -> f64 {
+        bin as f64 * self.sample_rate as f64 / self.fft_size as f64
+    }
+
+    /// Returns the center frequencies of all bins, in Hertz.
+    pub fn frequencies(&self) -> Vec<f64> {
+        (0..self.bin_count())
+            .map(|k| self.bin_frequency(k))
+            .collect()
+    }
+
+    /// Returns the window.
+    pub fn window(&self) -> Window {
+        self.window
+    }
+
+    /// Sets the window.
+    pub fn set_window(&mut self, window: Window) {
+        self.window = window;
+        let coefficients = window.coefficients(self.fft_size);
+        // Scale the window so that a full-scale sine centered on a bin reads 0 dB
+        let coherent_gain: f64 = coefficients.iter().sum::<f64>() / 2.0;
+        self.coefficients = coefficients.iter().map(|c| c / coherent_gain).collect();
+    }
+
+    /// Returns the overlap between successive frames, as a fraction of the frame size.
+    pub fn overlap(&self) -> f64 {
+        self.overlap
+    }
+
+    /// Sets the overlap between successive frames, as a fraction of the frame size between 0 and
+    /// 0.95.
+    pub fn set_overlap(&mut self, overlap: f64) {
+        self.overlap = overlap.clamp(0.0, 0.95);
+    }
+
+    /// Returns the averaging of successive frames.
+    pub fn averaging(&self) -> Averaging {
+        self.averaging
+    }
+
+    /// Sets the averaging of successive frames. The average restarts from the next frame.
+    pub fn set_averaging(&mut self, averaging: Averaging) {
+        self.averaging = averaging;
+        let bins = self.bin_count();
+        for channel in &mut self.channels {
+            channel.history = ChannelState::history(bins, averaging);
+            channel.history_pos = 0;
+            channel.history_len = 0;
+        }
+    }
+
+    /// Returns the fractional-octave smoothing.
+    pub fn smoothing(&self) -> Smoothing {
+        self.smoothing
+    }
+
+    /// Sets the fractional-octave smoothing.
+    pub fn set_smoothing(&mut self, smoothing: Smoothing) {
+        self.smoothing = smoothing;
+    }
+
+    /// Returns the time peaks are held before decaying, in milliseconds. Peaks are held until
+    /// reset when `None`.
+    pub fn peak_hold_ms(&self) -> Option<f64> {
+        self.peak_hold_ms
+    }
+
+    /// Sets the time peaks are held before decaying, in milliseconds, or `None` to hold them
+    /// until reset.
+    pub fn set_peak_hold_ms(&mut self, peak_hold_ms: Option<f64>) {
+        self.peak_hold_ms = peak_hold_ms;
+    }
+
+    /// Clears the peak-hold curve.
+    pub fn reset_peaks(&mut self) {
+        for channel in &mut self.channels {
+            channel.peaks.iter_mut().for_each(|p| *p = FLOOR_DB);
+            channel.peak_ages.iter_mut().for_each(|a| *a = 0.0);
+        }
+    }
+
+    /// Clears the input, the averages and the peak-hold curve.
+    pub fn reset(&mut self) {
+        let (fft_size, averaging) = (self.fft_size, self.averaging);
+        self.channels
+            .iter_mut()
+            .for_each(|c| *c = ChannelState::new(fft_size, averaging));
+        self.input_pos = 0;
+        self.until_frame = fft_size;
+    }
+
+    /// Add an audio frame to process by the spectrum meter.
+    pub fn add_samples(&mut self, buffer: &AudioBuffer) {
+        for i in 0..buffer.buffer_size() {
+            for (ch, channel) in self.channels.iter_mut().enumerate() {
+                channel.input[self.input_pos] = if ch < buffer.channels() {
+                    buffer[(ch, i)]
+                } else {
+                    0.0
+                };
+            }
+            self.input_pos = (self.input_pos + 1) % self.fft_size;
+            if self.until_frame <= 1 {
+                self.process_frame();
+                self.until_frame = self.hop_size();
+            } else {
+                self.until_frame -= 1;
+            }
+        }
+    }
+
+    /// Get the processed spectrum meter values.
+    pub fn get_values(&self) -> SpectrumData {
+        let to_decibels = |values: &Vec<f64>| values.iter().map(|&v| Decibel(v)).collect();
+        SpectrumData {
+            frequencies: self.frequencies(),
+            magnitudes: self
+                .channels
+                .iter()
+                .map(|c| to_decibels(&c.magnitudes))
+                .collect(),
+            peaks: self
+                .channels
+                .iter()
+                .map(|c| to_decibels(&c.peaks))
+                .collect(),
+        }
+    }
+
+    /// Returns the number of samples between successive frames.
+    fn hop_size(&self) -> usize {
+        ((self.fft_size as f64 * (1.0 - self.overlap)).round() as usize).max(1)
+    }
+
+    fn process_frame(&mut self) {
+        let bins = self.bin_count();
+        let hop_ms = self.hop_size() as f64 * 1e3 / self.sample_rate as f64;
+        let bandwidth = self.smoothing.bandwidth();
+        for channel in &mut self.channels {
+            // Unroll the ring buffer, oldest sample first
+            for (n, bin) in self.buffer.iter_mut().enumerate() {
+                let sample = channel.input[(self.input_pos + n) % self.fft_size];
+                *bin = Complex::new(sample * self.coefficients[n], 0.0);
+            }
+            self.fft
+                .process_with_scratch(&mut self.buffer, &mut self.scratch);
+            let frame_power = self.buffer[..bins].iter().map(|c| c.norm_sqr());
+
+            match self.averaging {
+                Averaging::None => {
+                    for (p, f) in channel.power.iter_mut().zip(frame_power) {
+                        *p = f;
+                    }
+                }
+                Averaging::Exponential(time_ms) => {
+                    let coefficient = if time_ms > 0.0 {
+                        (-hop_ms / time_ms).exp()
+                    } else {
+                        0.0
+                    };
+                    for (p, f) in channel.power.iter_mut().zip(frame_power) {
+                        *p = f + coefficient * (*p - f);
+                    }
+                }
+                Averaging::Linear(_) => {
+                    let count = channel.history.len();
+                    for (h, f) in channel.history[channel.history_pos]
+                        .iter_mut()
+                        .zip(frame_power)
+                    {
+                        *h = f;
+                    }
+                    channel.history_pos = (channel.history_pos + 1) % count;
+                    channel.history_len = (channel.history_len + 1).min(count);
+                    // The frames are filled in order, so the first ones are the recorded ones
+                    let frames = &channel.history[..channel.history_len];
+                    for (k, p) in channel.power.iter_mut().enumerate() {
+                        *p = frames.iter().map(|f| f[k]).sum::<f64>() / frames.len() as f64;
+                    }
+                }
+            }
+
+            match bandwidth {
+                Some(octaves) => smooth(
+                    &channel.power,
+                    octaves,
+                    &mut self.prefix,
+                    &mut self.smoothed,
+                ),
+                None => self.smoothed.copy_from_slice(&channel.power),
+            }
+
+            for (k, &power) in self.smoothed.iter().enumerate() {
+                let db = (10.0 * power.log10()).max(FLOOR_DB);
+                channel.magnitudes[k] = db;
+                if db >= channel.peaks[k] {
+                    channel.peaks[k] = db;
+                    channel.peak_ages[k] = 0.0;
+                } else if let Some(hold_ms) = self.peak_hold_ms {
+                    channel.peak_ages[k] += hop_ms;
+                    if channel.peak_ages[k] > hold_ms {
+                        let decayed = channel.peaks[k] - PEAK_DECAY_DB_PER_S * hop_ms * 1e-3;
+                        channel.peaks[k] = decayed.max(db);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Averages each bin of the power spectrum with its neighbours within the bandwidth, in octaves,
+/// centered on the bin. `prefix` holds the running sums of the power, one more than the bins.
+fn smooth(power: &[f64], octaves: f64, prefix: &mut [f64], output: &mut [f64]) {
+    prefix[0] = 0.0;
+    for (k, &p) in power.iter().enumerate() {
+        prefix[k + 1] = prefix[k] + p;
+    }
+    let half = 2f64.powf(octaves / 2.0);
+    let last = power.len() - 1;
+    output[0] = power[0];
+    for (k, out) in output.iter_mut().enumerate().skip(1) {
+        let low = ((k as f64 / half).floor() as usize).max(1);
+        let high = ((k as f64 * half).ceil() as usize).min(last);
+        *out = (prefix[high + 1] - prefix[low]) / (high + 1 - low) as f64;
+    }
+}
+
+/// Combines the spectra of several channels by averaging their power. Only the bins present in
+/// every channel are combined.
+fn combine(channels: &[Vec<Decibel>]) -> Vec<Decibel> {
+    let bins = channels.iter().map(Vec::len).min().unwrap_or(0);
+    (0..bins)
+        .map(|k| {
+            let power: f64 = channels
+                .iter()
+                .map(|c| 10f64.powf(c[k].0 / 10.0))
+                .sum::<f64>()
+                / channels.len() as f64;
+            Decibel(10.0 * power.log10())
+        })
+        .collect()
+}
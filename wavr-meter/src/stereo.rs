@@ -0,0 +1,222 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Stereo image metering: phase correlation, balance and a decimated mid/side point cloud for
+//! goniometer displays.
+
+use std::collections::VecDeque;
+use std::f64::consts::FRAC_1_SQRT_2;
+
+use circular_queue::CircularQueue;
+use wavr_audio_buffer::AudioBuffer;
+
+/// A single point of the vector scope, in mid/side coordinates. Both axes are scaled so that a
+/// full-scale signal on a single channel lies at a distance of 1 from the origin.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ScopePoint {
+    /// Mid component, `(L + R) / sqrt(2)`.
+    pub mid: f64,
+    /// Side component, `(L - R) / sqrt(2)`.
+    pub side: f64,
+}
+
+impl ScopePoint {
+    /// Create a scope point from a left and right sample.
+    pub fn from_stereo(left: f64, right: f64) -> Self {
+        Self {
+            mid: (left + right) * FRAC_1_SQRT_2,
+            side: (left - right) * FRAC_1_SQRT_2,
+        }
+    }
+}
+
+/// Stereo meter data, computed from `StereoMeter`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StereoData {
+    /// Phase correlation, between -1 (out of phase) and +1 (mono). Silence reads 0.
+    pub correlation: f64,
+    /// Energy balance, between -1 (left only) and +1 (right only). Silence reads 0.
+    pub balance: f64,
+    /// Decimated mid/side points, from oldest to newest.
+    pub points: Vec<ScopePoint>,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+struct BlockSums {
+    frames: usize,
+    left_right: f64,
+    left: f64,
+    right: f64,
+}
+
+impl BlockSums {
+    fn add(&mut self, other: &BlockSums) {
+        self.frames += other.frames;
+        self.left_right += other.left_right;
+        self.left += other.left;
+        self.right += other.right;
+    }
+}
+
+/// Stereo image meter, measuring the correlation and balance of a channel pair over a sliding
+/// window, and collecting mid/side points for a vector scope.
+#[derive(Debug)]
+pub struct StereoMeter {
+    sample_rate: u32,
+    channels: (usize, usize),
+    window_ms: f64,
+    blocks: VecDeque<BlockSums>,
+    total: BlockSums,
+    decimation: usize,
+    decimation_counter: usize,
+    points: CircularQueue<ScopePoint>,
+}
+
+impl StereoMeter {
+    /// Create a new stereo meter using the given sample rate, measuring the first two channels
+    /// over a 300 ms window.
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            channels: (0, 1),
+            window_ms: 300.0,
+            blocks: VecDeque::new(),
+            total: BlockSums::default(),
+            decimation: 4,
+            decimation_counter: 0,
+            points: CircularQueue::with_capacity(2048),
+        }
+    }
+
+    /// Returns the indices of the left and right channels being measured.
+    pub fn channels(&self) -> (usize, usize) {
+        self.channels
+    }
+
+    /// Sets the indices of the left and right channels being measured, and resets the meter.
+    pub fn set_channels(&mut self, left: usize, right: usize) {
+        self.channels = (left, right);
+        self.reset();
+    }
+
+    /// Returns the integration window, in milliseconds.
+    pub fn window_ms(&self) -> f64 {
+        self.window_ms
+    }
+
+    /// Sets the integration window, in milliseconds.
+    pub fn set_window_ms(&mut self, window_ms: f64) {
+        self.window_ms = window_ms.max(0.0);
+        self.trim();
+    }
+
+    /// Returns the decimation factor of the scope points; one point is kept every `decimation`
+    /// frames.
+    pub fn decimation(&self) -> usize {
+        self.decimation
+    }
+
+    /// Sets the decimation factor of the scope points.
+    pub fn set_decimation(&mut self, decimation: usize) {
+        self.decimation = decimation.max(1);
+        self.decimation_counter = 0;
+    }
+
+    /// Returns the maximum number of scope points kept by the meter.
+    pub fn point_capacity(&self) -> usize {
+        self.points.capacity()
+    }
+
+    /// Sets the maximum number of scope points kept by the meter. This clears the current points.
+    pub fn set_point_capacity(&mut self, capacity: usize) {
+        self.points = CircularQueue::with_capacity(capacity.max(1));
+    }
+
+    /// Clears the measurement window and the scope points.
+    pub fn reset(&mut self) {
+        self.blocks.clear();
+        self.total = BlockSums::default();
+        self.decimation_counter = 0;
+        self.points.clear();
+    }
+
+    /// Add an audio block to process by the stereo meter. A single-channel buffer is measured as
+    /// dual mono.
+    pub fn add_samples(&mut self, buffer: &AudioBuffer) {
+        if buffer.channels() == 0 {
+            return;
+        }
+        let last = buffer.channels() - 1;
+        let left = &buffer[self.channels.0.min(last)];
+        let right = &buffer[self.channels.1.min(last)];
+
+        let mut block = BlockSums::default();
+        for (&l, &r) in left.iter().zip(right.iter()) {
+            block.frames += 1;
+            block.left_right += l * r;
+            block.left += l * l;
+            block.right += r * r;
+
+            if self.decimation_counter == 0 {
+                self.points.push(ScopePoint::from_stereo(l, r));
+            }
+            self.decimation_counter = (self.decimation_counter + 1) % self.decimation;
+        }
+
+        self.blocks.push_back(block);
+        self.trim();
+    }
+
+    /// Returns the phase correlation over the window, between -1 and +1.
+    pub fn correlation(&self) -> f64 {
+        let energy = (self.total.left * self.total.right).sqrt();
+        if energy <= f64::EPSILON {
+            0.0
+        } else {
+            (self.total.left_right / energy).clamp(-1.0, 1.0)
+        }
+    }
+
+    /// Returns the energy balance over the window, between -1 (left) and +1 (right).
+    pub fn balance(&self) -> f64 {
+        let energy = self.total.left + self.total.right;
+        if energy <= f64::EPSILON {
+            0.0
+        } else {
+            (self.total.right - self.total.left) / energy
+        }
+    }
+
+    /// Get the processed stereo meter values.
+    pub fn get_values(&self) -> StereoData {
+        StereoData {
+            correlation: self.correlation(),
+            balance: self.balance(),
+            points: self.points.asc_iter().cloned().collect(),
+        }
+    }
+
+    fn window_frames(&self) -> usize {
+        (self.window_ms * self.sample_rate as f64 / 1000.0).round() as usize
+    }
+
+    /// Drops the oldest blocks as long as the remaining ones still cover the window, and sums
+    /// the remaining blocks.
+    fn trim(&mut self) {
+        let window = self.window_frames().max(1);
+        let mut frames: usize = self.blocks.iter().map(|b| b.frames).sum();
+        while let Some(oldest) = self.blocks.front() {
+            if frames - oldest.frames < window {
+                break;
+            }
+            frames -= oldest.frames;
+            self.blocks.pop_front();
+        }
+        self.total = BlockSums::default();
+        for block in &self.blocks {
+            self.total.add(block);
+        }
+    }
+}
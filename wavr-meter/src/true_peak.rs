@@ -0,0 +1,154 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! # True peak detection
+//!
+//! Sample-by-sample inter-sample peak detection, using the same oversampling interpolator as
+//! `libebur128`'s True Peak mode (and therefore as [`PeakMeter`](../peak/struct.PeakMeter.html)):
+//! a 49-tap Hanning-windowed sinc polyphase filter, oversampling 4 times below 96 kHz, 2 times
+//! below 192 kHz, and not at all above. The interpolator memory is kept in single precision, as
+//! `libebur128` does, so that both implementations measure the same values.
+
+use std::f64::consts::PI;
+
+use smallvec::SmallVec;
+
+/// Number of taps of the interpolation filter.
+const TAPS: usize = 49;
+/// Coefficients smaller than this are dropped from the filter.
+const ALMOST_ZERO: f64 = 0.000_001;
+
+/// One phase of the polyphase interpolation filter.
+#[derive(Clone, Debug, Default)]
+struct SubFilter {
+    index: Vec<usize>,
+    coeff: Vec<f64>,
+}
+
+/// Multi-channel true peak detector, processing one sample at a time.
+#[derive(Clone, Debug)]
+pub struct TruePeak {
+    factor: usize,
+    delay: usize,
+    filters: Vec<SubFilter>,
+    z: SmallVec<[Vec<f32>; 16]>,
+    zi: usize,
+}
+
+impl TruePeak {
+    /// Create a new true peak detector for the given channel count and sample rate.
+    pub fn new(channels: usize, sample_rate: u32) -> Self {
+        let factor = Self::oversampling_factor(sample_rate);
+        let delay = TAPS.div_ceil(factor);
+        let mut filters = vec![SubFilter::default(); factor];
+        if factor > 1 {
+            for j in 0..TAPS {
+                let m = j as f64 - (TAPS - 1) as f64 / 2.0;
+                let mut c = 1.0;
+                if m.abs() > ALMOST_ZERO {
+                    c = (m * PI / factor as f64).sin() / (m * PI / factor as f64);
+                }
+                c *= 0.5 * (1.0 - (2.0 * PI * j as f64 / (TAPS - 1) as f64).cos());
+                if c.abs() > ALMOST_ZERO {
+                    let filter = &mut filters[j % factor];
+                    filter.index.push(j / factor);
+                    filter.coeff.push(c);
+                }
+            }
+        }
+        Self {
+            factor,
+            delay,
+            filters,
+            z: SmallVec::from_elem(vec![0.0; delay], channels),
+            zi: 0,
+        }
+    }
+
+    /// Returns the oversampling factor used for the given sample rate.
+    pub fn oversampling_factor(sample_rate: u32) -> usize {
+        if sample_rate < 96000 {
+            4
+        } else if sample_rate < 192_000 {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Returns the number of channels of the detector.
+    pub fn channels(&self) -> usize {
+        self.z.len()
+    }
+
+    /// Returns the delay of the interpolated signal relative to the input, in samples. Peaks are
+    /// reported this many samples after the input sample which produced them.
+    pub fn latency(&self) -> usize {
+        Self::latency_for_factor(self.factor)
+    }
+
+    /// Returns the latency of a detector running at the given sample rate, in samples.
+    pub fn latency_at(sample_rate: u32) -> usize {
+        Self::latency_for_factor(Self::oversampling_factor(sample_rate))
+    }
+
+    fn latency_for_factor(factor: usize) -> usize {
+        if factor > 1 {
+            (TAPS - 1) / 2 / factor
+        } else {
+            0
+        }
+    }
+
+    /// Processes one frame, with one sample per channel, and calls `peak` with each channel and
+    /// the absolute value of the highest interpolated sample since the previous frame.
+    pub fn process_frame<F: FnMut(usize, f64)>(&mut self, frame: &[f64], mut peak: F) {
+        for (ch, &sample) in frame.iter().enumerate().take(self.z.len()) {
+            peak(ch, self.interpolate(ch, sample).max(sample.abs()));
+        }
+        self.advance();
+    }
+
+    /// Processes one sample of a single-channel detector, returning the absolute value of the
+    /// highest interpolated sample since the previous one.
+    pub fn process_sample(&mut self, sample: f64) -> f64 {
+        let peak = self.interpolate(0, sample).max(sample.abs());
+        self.advance();
+        peak
+    }
+
+    /// Clears the interpolator memory.
+    pub fn reset(&mut self) {
+        for z in &mut self.z {
+            z.iter_mut().for_each(|s| *s = 0.0);
+        }
+        self.zi = 0;
+    }
+
+    fn interpolate(&mut self, channel: usize, sample: f64) -> f64 {
+        if self.factor == 1 {
+            return sample.abs();
+        }
+        let z = &mut self.z[channel];
+        z[self.zi] = sample as f32;
+        let mut max = 0.0f64;
+        for filter in &self.filters {
+            let mut acc = 0.0;
+            for (&index, &coeff) in filter.index.iter().zip(&filter.coeff) {
+                let i = (self.zi + self.delay - index) % self.delay;
+                acc += z[i] as f64 * coeff;
+            }
+            max = max.max((acc as f32).abs() as f64);
+        }
+        max
+    }
+
+    fn advance(&mut self) {
+        self.zi += 1;
+        if self.zi == self.delay {
+            self.zi = 0;
+        }
+    }
+}
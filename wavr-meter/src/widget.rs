@@ -0,0 +1,39 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Display helpers shared by the meter widgets of the UI crates, kept out of the measurement API.
+
+/// Returns the 1-2-5 frequencies of each decade within `min..=max`, in Hertz, for the grid of a
+/// logarithmic frequency axis. The range must be positive.
+pub fn grid_frequencies(min: f64, max: f64) -> Vec<f64> {
+    if !(min > 0.0 && min <= max && max.is_finite()) {
+        return Vec::new();
+    }
+    (min.log10().floor() as i32..=max.log10().ceil() as i32)
+        .flat_map(|decade| [1.0, 2.0, 5.0].iter().map(move |m| m * 10f64.powi(decade)))
+        .filter(|&f| f >= min && f <= max)
+        .collect()
+}
+
+/// Returns whether the frequency starts a decade (1 Hz, 10 Hz, 100 Hz...), as labelled on the
+/// grid of a logarithmic frequency axis.
+pub fn is_decade_frequency(frequency: f64) -> bool {
+    let mantissa = frequency / 10f64.powf(frequency.log10().floor());
+    (mantissa - 1.0).abs() < 1e-6
+}
+
+/// Formats a frequency for display, in Hertz under 1 kHz and in kilohertz above.
+pub fn format_frequency(frequency: f64) -> String {
+    if frequency >= 1000.0 {
+        let khz = frequency / 1000.0;
+        if (khz - khz.round()).abs() < 1e-6 {
+            format!("{} kHz", khz.round())
+        } else {
+            format!("{:.2} kHz", khz)
+        }
+    } else {
+        format!("{:.0} Hz", frequency)
+    }
+}
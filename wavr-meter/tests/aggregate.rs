@@ -0,0 +1,132 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Checks the group metering against the metering of the summed signals: loudness power sums,
+//! maximum peaks and added clip counters.
+
+use std::f64::consts::PI;
+
+use wavr_audio_buffer::AudioBuffer;
+use wavr_meter::decibel::{Decibel, Linear};
+use wavr_meter::{sum_loudness, ClipStatus, MeterGroup, WavrMeter, WavrMeterData};
+
+const SAMPLE_RATE: u32 = 48000;
+const LOUDNESS_TOLERANCE: f64 = 0.05;
+
+/// Mono 997 Hz sine at the given loudness and phase, lasting 4 seconds. A full scale mono sine
+/// measures -3.01 LUFS.
+fn sine(lufs: f64, phase: f64) -> Vec<f64> {
+    let amplitude = 10f64.powf((lufs + 3.01) / 20.0);
+    (0..4 * SAMPLE_RATE as usize)
+        .map(|n| amplitude * (2.0 * PI * 997.0 * n as f64 / SAMPLE_RATE as f64 + phase).sin())
+        .collect()
+}
+
+fn measure(channels: usize, samples: &[f64]) -> (WavrMeter, WavrMeterData) {
+    let mut meter = WavrMeter::new(channels as u32, SAMPLE_RATE).unwrap();
+    meter.add_samples(&AudioBuffer::new(channels, samples));
+    let data = meter.get_values().unwrap();
+    (meter, data)
+}
+
+fn assert_loudness(actual: Decibel, expected: f64) {
+    assert!(
+        (actual.0 - expected).abs() < LOUDNESS_TOLERANCE,
+        "{} LUFS != {} LUFS",
+        actual.0,
+        expected
+    );
+}
+
+#[test]
+fn uncorrelated_sources_add_power() {
+    assert_loudness(sum_loudness(vec![Decibel(-23.0), Decibel(-23.0)]), -19.99);
+    assert_loudness(
+        sum_loudness(vec![Decibel(-23.0), Decibel(f64::NEG_INFINITY)]),
+        -23.0,
+    );
+    assert_eq!(sum_loudness(vec![]).0, f64::NEG_INFINITY);
+
+    // A sine and a cosine are uncorrelated: their sum is 3 dB louder than each of them
+    let sine = sine(-23.0, 0.0);
+    let cosine = self::sine(-23.0, PI / 2.0);
+    let (sine_meter, sine_data) = measure(1, &sine);
+    let (cosine_meter, _) = measure(1, &cosine);
+    assert_loudness(sine_data.ebu.short_term, -23.0);
+
+    let sum: Vec<f64> = sine.iter().zip(&cosine).map(|(a, b)| a + b).collect();
+    let (_, sum_data) = measure(1, &sum);
+    let group = MeterGroup::new()
+        .update_meters(vec![&sine_meter, &cosine_meter])
+        .unwrap();
+    for (aggregated, summed) in &[
+        (group.ebu.momentary, sum_data.ebu.momentary),
+        (group.ebu.short_term, sum_data.ebu.short_term),
+        (group.ebu.integrated, sum_data.ebu.integrated),
+        (group.loudness, sum_data.loudness),
+    ] {
+        assert_loudness(*aggregated, -19.99);
+        assert_loudness(*aggregated, summed.0);
+    }
+    assert_loudness(group.ebu.max_short_term, -19.99);
+}
+
+#[test]
+fn peaks_take_maximum() {
+    let (_, mut stereo) = measure(2, &vec![0.0; 2 * SAMPLE_RATE as usize]);
+    let (_, mut mono) = measure(1, &vec![0.0; SAMPLE_RATE as usize]);
+    stereo.peak[0] = Linear(0.5);
+    stereo.peak[1] = Linear(0.25);
+    stereo.ebu.max_true_peak = Linear(0.6);
+    mono.peak[0] = Linear(0.8);
+    mono.ebu.max_true_peak = Linear(0.9);
+
+    let group = MeterGroup::new().update(&[stereo.clone(), mono]).unwrap();
+    // Channels are matched by index, with the layout of the widest source
+    assert_eq!(group.peak.as_slice(), &[Linear(0.8), Linear(0.25)]);
+    assert_eq!(group.layout, stereo.layout);
+    assert_eq!(group.ebu.max_true_peak, Linear(0.9));
+
+    assert!(MeterGroup::new().update(&[]).is_none());
+}
+
+#[test]
+fn clip_counters_add() {
+    let (_, mut first) = measure(2, &vec![0.0; 2 * SAMPLE_RATE as usize]);
+    let (_, mut second) = measure(2, &vec![0.0; 2 * SAMPLE_RATE as usize]);
+    first.clips[0] = ClipStatus {
+        latched: true,
+        sample_overs: 2,
+        true_peak_overs: 1,
+        dropped_events: 0,
+    };
+    second.clips[0] = ClipStatus {
+        latched: false,
+        sample_overs: 3,
+        true_peak_overs: 4,
+        dropped_events: 5,
+    };
+    second.clips[1].sample_overs = 1;
+
+    let group = MeterGroup::new().update(&[first, second]).unwrap();
+    assert_eq!(
+        group.clips[0],
+        ClipStatus {
+            latched: true,
+            sample_overs: 5,
+            true_peak_overs: 5,
+            dropped_events: 5,
+        }
+    );
+    assert_eq!(
+        group.clips[1],
+        ClipStatus {
+            latched: false,
+            sample_overs: 1,
+            true_peak_overs: 0,
+            dropped_events: 0,
+        }
+    );
+}
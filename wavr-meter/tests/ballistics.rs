@@ -0,0 +1,146 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Checks the meter ballistics presets against their documented readings: PPM tone bursts, fall
+//! back rates, hold times and the rise time of the averaging meters.
+
+use std::f64::consts::PI;
+
+use wavr_meter::{Ballistics, PeakMeter};
+
+const SAMPLE_RATE: u32 = 48000;
+
+/// Full scale 5 kHz sine, the tone of the IEC 60268-10 burst tests, lasting the given time.
+fn tone(ms: f64) -> Vec<f64> {
+    (0..samples(ms))
+        .map(|n| (2.0 * PI * 5000.0 * n as f64 / SAMPLE_RATE as f64).sin())
+        .collect()
+}
+
+fn samples(ms: f64) -> usize {
+    (ms * SAMPLE_RATE as f64 / 1000.0).round() as usize
+}
+
+fn db(level: f64) -> f64 {
+    20.0 * level.log10()
+}
+
+/// Returns the highest level read while processing the samples, then 50 ms of silence.
+fn highest_reading(ballistics: Ballistics, samples: &[f64]) -> f64 {
+    let mut meter = PeakMeter::with_ballistics(SAMPLE_RATE, ballistics).unwrap();
+    let silence = vec![0.0; self::samples(50.0)];
+    samples
+        .iter()
+        .chain(&silence)
+        .map(|&sample| {
+            meter.add_samples(&[sample]);
+            meter.get_level().0
+        })
+        .fold(0.0, f64::max)
+}
+
+/// Returns the level read the given time after a second of tone stops, in dB relative to the
+/// level read when it stops.
+fn fall_back(ballistics: Ballistics, ms: f64) -> f64 {
+    let mut meter = PeakMeter::with_ballistics(SAMPLE_RATE, ballistics).unwrap();
+    meter.add_samples(&tone(1000.0));
+    let steady = meter.get_level().0;
+    meter.add_samples(&vec![0.0; samples(ms)]);
+    db(meter.get_level().0 / steady)
+}
+
+#[test]
+fn ppm_tone_bursts() {
+    for &(ballistics, burst_ms, reading) in &[
+        (Ballistics::ppm_type_i(), 5.0, -2.0),
+        (Ballistics::ppm_type_ii(), 10.0, -4.0),
+    ] {
+        let steady = highest_reading(ballistics, &tone(1000.0));
+        let burst = highest_reading(ballistics, &tone(burst_ms));
+        let relative = db(burst / steady);
+        assert!(
+            (relative - reading).abs() < 0.5,
+            "{} ms burst reads {} dB with {:?}",
+            burst_ms,
+            relative,
+            ballistics
+        );
+    }
+}
+
+#[test]
+fn fall_back_rates() {
+    for &(ballistics, ms, fall) in &[
+        (Ballistics::ppm_type_i(), 1700.0, -20.0),
+        (Ballistics::ppm_type_ii(), 2800.0, -24.0),
+        (Ballistics::digital_peak(), 1700.0, -20.0),
+    ] {
+        let relative = fall_back(ballistics, ms);
+        assert!(
+            (relative - fall).abs() < 0.1,
+            "{} dB after {} ms with {:?}",
+            relative,
+            ms,
+            ballistics
+        );
+    }
+}
+
+#[test]
+fn hold_time() {
+    let ballistics = Ballistics::digital_peak().with_hold_ms(500.0);
+    // Held for 500 ms, then falling back at 20 dB in 1.7 s
+    assert!(fall_back(ballistics, 490.0).abs() < 1e-9);
+    let relative = fall_back(ballistics, 500.0 + 850.0);
+    assert!((relative + 10.0).abs() < 0.1, "{} dB", relative);
+}
+
+#[test]
+fn averaging_rise_time() {
+    // VU and K-System meters read the RMS value of a sine, reaching 99% of it in 300 ms and
+    // 600 ms respectively
+    for &(ballistics, rise_ms) in &[(Ballistics::vu(), 300.0), (Ballistics::k_system(), 600.0)] {
+        let mut meter = PeakMeter::with_ballistics(SAMPLE_RATE, ballistics).unwrap();
+        meter.add_samples(&tone(rise_ms));
+        let risen = meter.get_level().0;
+        meter.add_samples(&tone(2000.0));
+        let steady = meter.get_level().0;
+        assert!(
+            (steady - 0.5f64.sqrt()).abs() < 5e-3,
+            "steady at {}",
+            steady
+        );
+        assert!((risen / steady - 0.99).abs() < 5e-3, "risen to {}", risen);
+    }
+}
+
+#[test]
+fn block_size_independent() {
+    let samples = tone(100.0);
+    for &ballistics in &[
+        Ballistics::ppm_type_ii(),
+        Ballistics::vu(),
+        Ballistics::digital_peak(),
+    ] {
+        let mut whole = PeakMeter::with_ballistics(SAMPLE_RATE, ballistics).unwrap();
+        whole.add_samples(&samples);
+        let mut blocks = PeakMeter::with_ballistics(SAMPLE_RATE, ballistics).unwrap();
+        for block in samples.chunks(37) {
+            blocks.add_samples(block);
+        }
+        assert_eq!(whole.get_level(), blocks.get_level());
+    }
+}
+
+#[test]
+#[allow(deprecated)]
+fn deprecated_decay() {
+    let mut meter = PeakMeter::new(SAMPLE_RATE).unwrap();
+    assert_eq!(meter.get_true_peak(), meter.get_level());
+    // 0.98 every 100 ms is about 1.75 dB/s
+    meter.set_decay(0.98);
+    assert!((meter.ballistics().decay_db_per_s - 1.755).abs() < 1e-3);
+    assert!((meter.decay() - 0.98).abs() < 1e-12);
+}
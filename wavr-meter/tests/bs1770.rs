@@ -0,0 +1,228 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Runs the pure-Rust BS.1770 implementation through the synthetic EBU Tech 3341 (loudness) and
+//! Tech 3342 (loudness range) conformance signals, and through hostile true peak signals. With the
+//! `libebur128` feature also enabled, the results are compared to the C implementation.
+#![cfg(feature = "pure-rust")]
+
+use std::f64::consts::PI;
+
+use wavr_meter::bs1770::{Bs1770Meter, Channel, Mode};
+
+const SAMPLE_RATE: u32 = 48000;
+const BLOCK_SIZE: usize = 4800;
+const LOUDNESS_TOLERANCE: f64 = 0.1;
+const RANGE_TOLERANCE: f64 = 1.0;
+
+/// Stereo 1 kHz sine segments, as `(level in dBFS, duration in seconds)`.
+fn sine_segments(segments: &[(f64, f64)]) -> Vec<f64> {
+    let mut samples = vec![];
+    let mut index = 0usize;
+    for &(level, duration) in segments {
+        let amplitude = 10f64.powf(level / 20.0);
+        let frames = (duration * SAMPLE_RATE as f64).round() as usize;
+        for _ in 0..frames {
+            let s = amplitude * (2.0 * PI * 1000.0 * index as f64 / SAMPLE_RATE as f64).sin();
+            samples.push(s);
+            samples.push(s);
+            index += 1;
+        }
+    }
+    samples
+}
+
+fn measure(samples: &[f64], mode: Mode) -> Bs1770Meter {
+    let mut meter = Bs1770Meter::new(2, SAMPLE_RATE, mode).unwrap();
+    for block in samples.chunks(BLOCK_SIZE * 2) {
+        meter.add_frames_f64(block).unwrap();
+    }
+    meter
+}
+
+fn assert_close(value: f64, expected: f64, tolerance: f64, name: &str) {
+    assert!(
+        (value - expected).abs() <= tolerance,
+        "{}: expected {} ± {}, got {}",
+        name,
+        expected,
+        tolerance,
+        value
+    );
+}
+
+#[cfg(feature = "libebur128")]
+fn compare_with_libebur128(samples: &[f64]) {
+    let ours = measure(samples, Mode::I | Mode::LRA | Mode::TRUE_PEAK);
+    let mut theirs = ebur128::EbuR128::new(
+        2,
+        SAMPLE_RATE,
+        ebur128::Mode::I | ebur128::Mode::LRA | ebur128::Mode::TRUE_PEAK,
+    )
+    .unwrap();
+    for block in samples.chunks(BLOCK_SIZE * 2) {
+        theirs.add_frames_f64(block).unwrap();
+    }
+    let pairs = [
+        (
+            ours.loudness_momentary().unwrap(),
+            theirs.loudness_momentary().unwrap(),
+            "M",
+        ),
+        (
+            ours.loudness_shortterm().unwrap(),
+            theirs.loudness_shortterm().unwrap(),
+            "S",
+        ),
+        (
+            ours.loudness_global().unwrap(),
+            theirs.loudness_global().unwrap(),
+            "I",
+        ),
+        (
+            ours.loudness_range().unwrap(),
+            theirs.loudness_range().unwrap(),
+            "LRA",
+        ),
+    ];
+    for &(ours, theirs, name) in &pairs {
+        if ours.is_finite() || theirs.is_finite() {
+            assert_close(ours, theirs, 1e-6, name);
+        }
+    }
+    for ch in 0..2 {
+        assert_close(
+            ours.true_peak(ch).unwrap(),
+            theirs.true_peak(ch).unwrap(),
+            1e-6,
+            "true peak",
+        );
+    }
+}
+
+#[cfg(not(feature = "libebur128"))]
+fn compare_with_libebur128(_samples: &[f64]) {}
+
+/// Tech 3341 cases 1 and 2: constant sines read the same momentary, short-term and integrated
+/// loudness.
+#[test]
+fn constant_loudness() {
+    for &level in &[-23.0, -33.0] {
+        let samples = sine_segments(&[(level, 20.0)]);
+        let meter = measure(&samples, Mode::I | Mode::S);
+        assert_close(
+            meter.loudness_momentary().unwrap(),
+            level,
+            LOUDNESS_TOLERANCE,
+            "M",
+        );
+        assert_close(
+            meter.loudness_shortterm().unwrap(),
+            level,
+            LOUDNESS_TOLERANCE,
+            "S",
+        );
+        assert_close(
+            meter.loudness_global().unwrap(),
+            level,
+            LOUDNESS_TOLERANCE,
+            "I",
+        );
+        compare_with_libebur128(&samples);
+    }
+}
+
+/// Tech 3341 cases 3 to 5: the relative and absolute gates leave out the quiet segments.
+#[test]
+fn gated_loudness() {
+    let cases: &[&[(f64, f64)]] = &[
+        &[(-36.0, 10.0), (-23.0, 60.0), (-36.0, 10.0)],
+        &[
+            (-72.0, 10.0),
+            (-36.0, 10.0),
+            (-23.0, 60.0),
+            (-36.0, 10.0),
+            (-72.0, 10.0),
+        ],
+        &[(-26.0, 20.0), (-20.0, 20.1), (-26.0, 20.0)],
+    ];
+    for segments in cases {
+        let samples = sine_segments(segments);
+        let meter = measure(&samples, Mode::I);
+        assert_close(
+            meter.loudness_global().unwrap(),
+            -23.0,
+            LOUDNESS_TOLERANCE,
+            "I",
+        );
+        compare_with_libebur128(&samples);
+    }
+}
+
+/// Tech 3342 cases 1 to 4.
+#[test]
+fn loudness_range() {
+    let cases: &[(&[(f64, f64)], f64)] = &[
+        (&[(-20.0, 20.0), (-30.0, 20.0)], 10.0),
+        (&[(-20.0, 20.0), (-15.0, 20.0)], 5.0),
+        (&[(-40.0, 20.0), (-20.0, 20.0)], 20.0),
+        (
+            &[
+                (-50.0, 20.0),
+                (-35.0, 20.0),
+                (-20.0, 20.0),
+                (-35.0, 20.0),
+                (-50.0, 20.0),
+            ],
+            15.0,
+        ),
+    ];
+    for &(segments, expected) in cases {
+        let samples = sine_segments(segments);
+        let meter = measure(&samples, Mode::LRA);
+        assert_close(
+            meter.loudness_range().unwrap(),
+            expected,
+            RANGE_TOLERANCE,
+            "LRA",
+        );
+        compare_with_libebur128(&samples);
+    }
+}
+
+/// Quarter-sample-rate sine with its samples at ±0.354, and its true peak at 0.5 (-6.02 dBTP).
+#[test]
+fn true_peak() {
+    let samples: Vec<f64> = (0..SAMPLE_RATE as usize)
+        .flat_map(|i| {
+            let s = 0.5 * (PI / 2.0 * i as f64 + PI / 4.0).sin();
+            vec![s, s]
+        })
+        .collect();
+    let meter = measure(&samples, Mode::TRUE_PEAK);
+    for ch in 0..2 {
+        let peak = 20.0 * meter.true_peak(ch).unwrap().log10();
+        assert!(
+            peak > -6.02 - 0.4 && peak < -6.02 + 0.2,
+            "expected -6.02 dBTP, got {}",
+            peak
+        );
+    }
+    compare_with_libebur128(&samples);
+}
+
+#[test]
+fn unavailable_measurements() {
+    let meter = Bs1770Meter::new(2, SAMPLE_RATE, Mode::M).unwrap();
+    assert!(meter.loudness_shortterm().is_err());
+    assert!(meter.loudness_global().is_err());
+    assert!(meter.loudness_range().is_err());
+    assert!(meter.true_peak(0).is_err());
+
+    let mut meter = Bs1770Meter::new(2, SAMPLE_RATE, Mode::TRUE_PEAK).unwrap();
+    assert!(meter.true_peak(2).is_err());
+    assert!(meter.set_channel(2, Channel::Left).is_err());
+    assert!(meter.set_channel(0, Channel::DualMono).is_err());
+}
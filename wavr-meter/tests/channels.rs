@@ -0,0 +1,102 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Checks the BS.1770 loudness weights of the 5.1 and 7.1 layouts by measuring a sine on each
+//! channel in turn, and the labels and groups of the layouts.
+
+use std::f64::consts::PI;
+
+use wavr_audio_buffer::AudioBuffer;
+use wavr_meter::{ChannelGroup, ChannelLayout, ChannelRole, WavrMeter};
+
+const SAMPLE_RATE: u32 = 48000;
+const LOUDNESS_TOLERANCE: f64 = 0.05;
+/// Weight of the side surround channels, +1.5 dB.
+const SURROUND_DB: f64 = 1.4922;
+
+/// Returns the momentary loudness of half a second of sine played on a single channel of the layout.
+fn loudness_of_channel(layout: &ChannelLayout, channel: usize) -> f64 {
+    let channels = layout.len();
+    let mut meter = WavrMeter::new(channels as u32, SAMPLE_RATE).unwrap();
+    assert_eq!(meter.layout(), layout);
+    let data: Vec<f64> = (0..SAMPLE_RATE as usize / 2 * channels)
+        .map(|i| {
+            if i % channels == channel {
+                let n = i / channels;
+                0.1 * (2.0 * PI * 997.0 * n as f64 / SAMPLE_RATE as f64).sin()
+            } else {
+                0.0
+            }
+        })
+        .collect();
+    meter.add_samples(&AudioBuffer::new(channels, &data));
+    meter.get_report().unwrap().momentary.0
+}
+
+/// Checks the loudness of each channel relative to the left channel.
+fn assert_weights(layout: ChannelLayout, weights_db: &[f64]) {
+    let reference = loudness_of_channel(&layout, 0);
+    for (channel, &weight_db) in weights_db.iter().enumerate() {
+        let loudness = loudness_of_channel(&layout, channel);
+        if weight_db == f64::NEG_INFINITY {
+            assert_eq!(loudness, f64::NEG_INFINITY, "{:?}", layout[channel]);
+        } else {
+            assert!(
+                (loudness - reference - weight_db).abs() < LOUDNESS_TOLERANCE,
+                "{:?} at {} LU",
+                layout[channel],
+                loudness - reference
+            );
+        }
+    }
+}
+
+#[test]
+fn surround_5_1_weights() {
+    // L, R, C, LFE, Ls, Rs
+    assert_weights(
+        ChannelLayout::surround_5_1(),
+        &[0.0, 0.0, 0.0, f64::NEG_INFINITY, SURROUND_DB, SURROUND_DB],
+    );
+}
+
+#[test]
+fn surround_7_1_weights() {
+    // L, R, C, LFE, Lss, Rss, Lrs, Rrs: the rear surrounds at 135° are weighted as front channels
+    assert_weights(
+        ChannelLayout::surround_7_1(),
+        &[
+            0.0,
+            0.0,
+            0.0,
+            f64::NEG_INFINITY,
+            SURROUND_DB,
+            SURROUND_DB,
+            0.0,
+            0.0,
+        ],
+    );
+}
+
+#[test]
+fn layout_labels_and_groups() {
+    let layout = ChannelLayout::surround_5_1();
+    assert_eq!(ChannelLayout::default_for(6), layout);
+    let labels: Vec<String> = (0..6).map(|channel| layout.label(channel)).collect();
+    assert_eq!(labels, vec!["L", "R", "C", "LFE", "Ls", "Rs"]);
+    assert_eq!(
+        layout.groups(),
+        vec![
+            (ChannelGroup::Front, vec![0, 1, 2]),
+            (ChannelGroup::Lfe, vec![3]),
+            (ChannelGroup::Surround, vec![4, 5]),
+        ]
+    );
+
+    let layout = ChannelLayout::default_for(3);
+    assert!(layout.iter().all(|&role| role == ChannelRole::Unlabeled));
+    assert_eq!(layout.label(2), "3");
+    assert_eq!(layout.groups(), vec![(ChannelGroup::Other, vec![0, 1, 2])]);
+}
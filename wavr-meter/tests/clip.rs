@@ -0,0 +1,193 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Checks the over detection: runs of full-scale samples, inter-sample overs, the clip latch and
+//! the capacity of the event list.
+
+use std::f64::consts::PI;
+
+use wavr_audio_buffer::AudioBuffer;
+use wavr_meter::decibel::Linear;
+use wavr_meter::{ClipDetector, ClipKind, WavrMeter, CLIP_EVENT_CAPACITY};
+
+const SAMPLE_RATE: u32 = 48000;
+
+/// Feeds the samples to the detector, starting at the given position.
+fn process(detector: &mut ClipDetector, position: usize, samples: &[f64]) {
+    for (i, &sample) in samples.iter().enumerate() {
+        detector.process(position + i, sample);
+    }
+}
+
+fn sample_overs(detector: &ClipDetector) -> Vec<(usize, ClipKind)> {
+    detector
+        .events()
+        .iter()
+        .filter(|event| event.kind != ClipKind::TruePeak)
+        .map(|event| (event.position, event.kind))
+        .collect()
+}
+
+#[test]
+fn consecutive_full_scale_samples() {
+    let mut detector = ClipDetector::new(SAMPLE_RATE);
+    // Two full-scale samples are under the default of three
+    process(&mut detector, 0, &[0.0, 1.0, -1.0, 0.0]);
+    assert!(sample_overs(&detector).is_empty());
+    assert_eq!(detector.status().sample_overs, 0);
+
+    process(&mut detector, 100, &[0.0, 1.0, -1.0, 1.0, 0.0]);
+    assert_eq!(
+        sample_overs(&detector),
+        vec![(101, ClipKind::Sample { length: 3 })]
+    );
+    assert_eq!(detector.status().sample_overs, 1);
+
+    // Longer runs make a single event, updated with the run length
+    detector.set_min_consecutive(2);
+    process(&mut detector, 200, &[0.5, 1.0, 1.0, -1.0, 1.0, 1.0, 0.5]);
+    assert_eq!(
+        sample_overs(&detector),
+        vec![
+            (101, ClipKind::Sample { length: 3 }),
+            (201, ClipKind::Sample { length: 5 })
+        ]
+    );
+    assert_eq!(detector.status().sample_overs, 2);
+
+    detector.set_threshold(Linear(0.5));
+    process(&mut detector, 300, &[0.4, 0.5, -0.5, 0.4]);
+    assert_eq!(detector.status().sample_overs, 3);
+    assert_eq!(
+        sample_overs(&detector)[2],
+        (301, ClipKind::Sample { length: 2 })
+    );
+}
+
+#[test]
+fn latch_resets() {
+    let mut detector = ClipDetector::new(SAMPLE_RATE);
+    detector.set_true_peak_threshold(Linear(f64::INFINITY));
+    assert!(!detector.is_latched());
+    process(&mut detector, 0, &[1.0; 3]);
+    assert!(detector.is_latched());
+
+    detector.reset_latch();
+    assert!(!detector.is_latched());
+    assert_eq!(detector.status().sample_overs, 1);
+    assert!(!detector.events().is_empty());
+
+    // Quiet samples leave the latch reset
+    process(&mut detector, 3, &[0.5; 1000]);
+    assert!(!detector.is_latched());
+
+    process(&mut detector, 1003, &[1.0; 3]);
+    assert!(detector.is_latched());
+    detector.reset();
+    assert!(!detector.is_latched());
+    assert_eq!(detector.status().sample_overs, 0);
+    assert_eq!(detector.status().true_peak_overs, 0);
+    assert!(detector.events().is_empty());
+}
+
+/// Sine at a quarter of the sample rate with a 45° phase, sampled at 0.707 of its peak.
+fn quarter_rate_sine(amplitude: f64, len: usize) -> Vec<f64> {
+    (0..len)
+        .map(|n| amplitude * (PI / 2.0 * n as f64 + PI / 4.0).sin())
+        .collect()
+}
+
+#[test]
+fn quarter_rate_sine_raises_true_peak_over() {
+    let amplitude = 1.2;
+    let sine = quarter_rate_sine(amplitude, 480);
+    assert!(sine.iter().all(|s| s.abs() < 0.85));
+
+    let mut detector = ClipDetector::new(SAMPLE_RATE);
+    process(&mut detector, 0, &sine);
+    let status = detector.status();
+    assert_eq!(status.sample_overs, 0);
+    assert!(status.true_peak_overs > 0);
+    assert!(status.latched);
+
+    let events = detector.events();
+    assert_eq!(events.len(), status.true_peak_overs);
+    assert!(events.iter().all(|event| event.kind == ClipKind::TruePeak));
+    // The oversampling filter settles after its latency
+    let settled = events.last().unwrap().level.0;
+    assert!(
+        (settled - amplitude).abs() < 0.05,
+        "true peak at {}",
+        settled
+    );
+}
+
+#[test]
+fn events_are_capped() {
+    let mut detector = ClipDetector::new(SAMPLE_RATE);
+    detector.set_true_peak_threshold(Linear(f64::INFINITY));
+    let overs = CLIP_EVENT_CAPACITY + 10;
+    for i in 0..overs {
+        process(&mut detector, i * 10, &[0.0, 1.0, 1.0, 1.0, 0.0]);
+    }
+    let status = detector.status();
+    assert_eq!(status.sample_overs, overs);
+    assert_eq!(status.dropped_events, 10);
+    assert_eq!(detector.events().len(), CLIP_EVENT_CAPACITY);
+    assert_eq!(
+        detector.events().last().unwrap().position,
+        (CLIP_EVENT_CAPACITY - 1) * 10 + 1
+    );
+
+    // A dropped over in progress does not update a recorded one
+    process(&mut detector, overs * 10, &[1.0; 10]);
+    assert_eq!(
+        detector.events()[CLIP_EVENT_CAPACITY - 1].kind,
+        ClipKind::Sample { length: 3 }
+    );
+
+    let mut events = Vec::with_capacity(CLIP_EVENT_CAPACITY);
+    detector.take_events(&mut events);
+    assert_eq!(events.len(), CLIP_EVENT_CAPACITY);
+    process(&mut detector, overs * 10 + 20, &[0.0, 1.0, 1.0, 1.0]);
+    assert_eq!(detector.events().len(), 1);
+    assert_eq!(detector.status().dropped_events, 11);
+}
+
+#[test]
+fn meter_shares_true_peak() {
+    // The level, the overs, and the program maximum all read the same true peak
+    let amplitude = 1.2;
+    let interleaved: Vec<f64> = quarter_rate_sine(amplitude, 480)
+        .into_iter()
+        .flat_map(|sample| vec![sample, 0.0])
+        .collect();
+    let mut meter = WavrMeter::new(2, SAMPLE_RATE).unwrap();
+    meter.add_samples(&AudioBuffer::new(2, &interleaved));
+
+    let data = meter.get_values().unwrap();
+    let max_true_peak = data.ebu.max_true_peak.0;
+    assert!((max_true_peak - amplitude).abs() < 0.05);
+    assert!(data.peak[0].0 <= max_true_peak && data.peak[0].0 > 0.9 * max_true_peak);
+    assert_eq!(data.peak[1].0, 0.0);
+    assert!(data.clips[0].true_peak_overs > 0);
+    assert_eq!(data.clips[1], Default::default());
+
+    let mut events = vec![];
+    meter.take_clip_events(&mut events);
+    assert_eq!(events.len(), data.clips[0].true_peak_overs);
+    assert!(events.iter().all(|event| event.channel == 0));
+    assert!(events
+        .iter()
+        .all(|event| event.level.0 <= max_true_peak + 1e-12));
+    assert!(events.windows(2).all(|w| w[0].position <= w[1].position));
+
+    // The program maximum is reset, and does not follow the input while paused
+    meter.reset().unwrap();
+    meter.set_paused(true);
+    meter.add_samples(&AudioBuffer::new(2, &interleaved));
+    assert_eq!(meter.get_report().unwrap().max_true_peak, Linear(0.0));
+    assert!(meter.get_values().unwrap().peak[0].0 > 0.9 * max_true_peak);
+}
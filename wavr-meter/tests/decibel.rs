@@ -0,0 +1,179 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Property tests of the level units: conversion and arithmetic round trips over random values,
+//! silence handling, and formatting and parsing with units.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use wavr_meter::decibel::*;
+
+const CASES: usize = 10_000;
+const TOLERANCE: f64 = 1e-9;
+
+/// Runs the property over random values in the given range, with a fixed seed.
+fn check(min: f64, max: f64, property: impl Fn(f64)) {
+    let mut rng = StdRng::seed_from_u64(0x5741_5652);
+    for _ in 0..CASES {
+        property(rng.gen_range(min, max));
+    }
+}
+
+fn assert_close(lhs: f64, rhs: f64) {
+    assert!(
+        (lhs - rhs).abs() <= TOLERANCE * lhs.abs().max(rhs.abs()).max(1.0),
+        "{} != {}",
+        lhs,
+        rhs
+    );
+}
+
+#[test]
+fn linear_decibel_round_trip() {
+    check(1e-6, 1e3, |amplitude| {
+        let db: Decibel = Linear(amplitude).into();
+        assert_close(Linear::from(db).0, amplitude);
+    });
+    check(-200.0, 60.0, |db| {
+        let amplitude: Linear = Decibel(db).into();
+        assert_close(Decibel::from(amplitude).0, db);
+    });
+}
+
+#[test]
+fn known_conversions() {
+    assert_close(Linear::from(Decibel(-20.0)).0, 0.1);
+    assert_close(Linear::from(Decibel(-6.020_599_913_279_624)).0, 0.5);
+    assert_close(Decibel::from(Linear(10.0)).0, 20.0);
+    assert_close(Gain(6.020_599_913_279_624).to_linear(), 2.0);
+    assert_close(DbFs::from_amplitude(-0.5).0, -6.020_599_913_279_624);
+}
+
+#[test]
+fn silence() {
+    assert_eq!(Decibel::from(Linear::SILENCE).0, f64::NEG_INFINITY);
+    assert_eq!(Linear::from(Decibel(f64::NEG_INFINITY)), Linear::SILENCE);
+    assert!(DbFs::from(Linear::SILENCE).is_silence());
+    assert!(Linear::from(DbFs::SILENCE).is_silence());
+    assert!(Gain::from_linear(0.0).is_mute());
+    assert_eq!(DbFs::SILENCE - DbFs::SILENCE, Gain::UNITY);
+    assert_eq!(Lufs::SILENCE - Lufs::SILENCE, Lu(0.0));
+    assert!((DbFs::SILENCE + Gain(12.0)).is_silence());
+    assert!((DbFs::FULL_SCALE + Gain::MUTE).is_silence());
+    assert!((Lufs::SILENCE + Lu(3.0)).is_silence());
+    assert_eq!(Linear(0.5) * Gain::MUTE, Linear::SILENCE);
+    check(-100.0, 0.0, |level| {
+        assert_eq!(DbFs(level) - DbFs::SILENCE, Gain(f64::INFINITY));
+    });
+}
+
+#[test]
+fn adding_gains_multiplies_ratios() {
+    check(-60.0, 24.0, |a| {
+        let b = a * 0.37 - 5.0;
+        assert_close(
+            (Gain(a) + Gain(b)).to_linear(),
+            Gain(a).to_linear() * Gain(b).to_linear(),
+        );
+        assert_close((Linear(0.25) * Gain(a)).0, 0.25 * Gain(a).to_linear());
+        assert_close((Gain(a) - Gain(b) + Gain(b)).0, a);
+        assert_close((-Gain(a)).to_linear(), 1.0 / Gain(a).to_linear());
+        assert_close((Gain(a) * 2.0).to_linear(), Gain(a).to_linear().powi(2));
+    });
+}
+
+#[test]
+fn decibel_mul_scales_amplitude() {
+    check(-60.0, 24.0, |db| {
+        let factor = (db + 61.0) / 10.0;
+        assert_close(
+            Linear::from(Decibel(db) * factor).0,
+            Linear::from(Decibel(db)).0 * factor,
+        );
+    });
+}
+
+#[test]
+fn level_arithmetic() {
+    check(-120.0, 0.0, |a| {
+        let b = a * 0.5 - 3.0;
+        let gain = DbFs(a) - DbFs(b);
+        assert_close((DbFs(b) + gain).0, a);
+        assert_close((DbFs(a) - gain).0, b);
+        assert_close(
+            (DbFs(a) + gain).to_amplitude(),
+            DbFs(a).to_amplitude() * gain.to_linear(),
+        );
+
+        let difference = Lufs(a) - Lufs(b);
+        assert_close((Lufs(b) + difference).0, a);
+        assert_close((Lufs(a) - difference).0, b);
+        assert_close((Lufs(b) + Gain::from(difference)).0, a);
+        assert_close((difference - difference).0, 0.0);
+        assert_close((difference + -difference).0, 0.0);
+    });
+}
+
+#[test]
+fn format_parse_round_trip() {
+    check(-200.0, 30.0, |value| {
+        assert_eq!(Gain(value).to_string().parse(), Ok(Gain(value)));
+        assert_eq!(DbFs(value).to_string().parse(), Ok(DbFs(value)));
+        assert_eq!(Lufs(value).to_string().parse(), Ok(Lufs(value)));
+        assert_eq!(Lu(value).to_string().parse(), Ok(Lu(value)));
+        assert_eq!(value.to_string().parse(), Ok(Lufs(value)));
+
+        let rounded: Lufs = format!("{:.1}", Lufs(value)).parse().unwrap();
+        assert!((rounded.0 - value).abs() <= 0.05 + TOLERANCE);
+        let rounded: Decibel = Decibel(value).to_string().parse().unwrap();
+        assert!((rounded.0 - value).abs() <= 0.005 + TOLERANCE);
+    });
+    for level in &[f64::NEG_INFINITY, 0.0] {
+        assert_eq!(Gain(*level).to_string().parse(), Ok(Gain(*level)));
+        assert_eq!(DbFs(*level).to_string().parse(), Ok(DbFs(*level)));
+        assert_eq!(Lufs(*level).to_string().parse(), Ok(Lufs(*level)));
+        assert_eq!(Lu(*level).to_string().parse(), Ok(Lu(*level)));
+    }
+}
+
+#[test]
+fn formatting() {
+    assert_eq!(format!("{:.1}", Lufs(-23.04)), "-23.0 LUFS");
+    assert_eq!(format!("{:.1}", Lu(1.5)), "+1.5 LU");
+    assert_eq!(format!("{:.2}", Gain(-3.0)), "-3.00 dB");
+    assert_eq!(format!("{}", Gain(6.0)), "+6 dB");
+    assert_eq!(format!("{}", DbFs::SILENCE), "-inf dBFS");
+    assert_eq!(format!("{}", Decibel(-6.0)), "-6.00 dB");
+    assert_eq!(format!("{:.1}", Decibel(-6.04)), "-6.0 dB");
+    assert_eq!(format!("{:.0}", Decibel(f64::NEG_INFINITY)), "-inf dB");
+}
+
+#[test]
+fn parsing() {
+    assert_eq!("-23 LUFS".parse(), Ok(Lufs(-23.0)));
+    assert_eq!("-23lkfs".parse(), Ok(Lufs(-23.0)));
+    assert_eq!("  -1.5   dBFS ".parse(), Ok(DbFs(-1.5)));
+    assert_eq!("+3 dB".parse(), Ok(Gain(3.0)));
+    assert_eq!("-inf dB".parse(), Ok(Gain::MUTE));
+    assert_eq!("-∞ dBFS".parse(), Ok(DbFs::SILENCE));
+    assert_eq!("7 LU".parse(), Ok(Lu(7.0)));
+    assert_eq!(
+        "-23 LU".parse::<Lufs>(),
+        Err(ParseLevelError::InvalidUnit(String::from("LU")))
+    );
+    assert_eq!(
+        "-1 dBFS".parse::<Gain>(),
+        Err(ParseLevelError::InvalidUnit(String::from("dBFS")))
+    );
+    assert!(matches!(
+        "loud LUFS".parse::<Lufs>(),
+        Err(ParseLevelError::InvalidNumber(_))
+    ));
+    assert!(matches!(
+        "".parse::<Lu>(),
+        Err(ParseLevelError::InvalidNumber(_))
+    ));
+}
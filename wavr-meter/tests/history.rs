@@ -0,0 +1,150 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Checks the loudness history: range queries, overwriting after seeking back, capacity trimming
+//! and the CSV and JSON exports.
+
+use std::time::Duration;
+
+use smallvec::smallvec;
+use wavr_meter::decibel::{Decibel, Linear};
+use wavr_meter::{HistoryEntry, LoudnessHistory, DEFAULT_HISTORY_CAPACITY};
+
+const SAMPLE_RATE: u32 = 48000;
+
+fn entry(position: usize, loudness: f64) -> HistoryEntry {
+    HistoryEntry {
+        position,
+        momentary: Decibel(loudness),
+        short_term: Decibel(loudness - 1.0),
+        true_peak: smallvec![Linear(0.5), Linear(0.0)],
+    }
+}
+
+/// History with an entry every 100 ms, from 100 ms to 1 s.
+fn history() -> LoudnessHistory {
+    let mut history = LoudnessHistory::new(SAMPLE_RATE, 2);
+    for i in 1..=10 {
+        history.push(entry(i * 4800, -20.0 - i as f64));
+    }
+    history
+}
+
+fn positions<'a>(entries: impl Iterator<Item = &'a HistoryEntry>) -> Vec<usize> {
+    entries.map(|e| e.position).collect()
+}
+
+#[test]
+fn range_boundaries() {
+    let history = history();
+    // The start is included, the end excluded
+    assert_eq!(positions(history.range(4800, 14400)), vec![4800, 9600]);
+    assert_eq!(positions(history.range(4801, 14401)), vec![9600, 14400]);
+    assert_eq!(positions(history.range(0, 4800)), Vec::<usize>::new());
+    assert_eq!(positions(history.range(48000, usize::MAX)), vec![48000]);
+    assert_eq!(positions(history.range(0, usize::MAX)).len(), 10);
+    // Empty and reversed ranges
+    assert_eq!(positions(history.range(9600, 9600)), Vec::<usize>::new());
+    assert_eq!(positions(history.range(20000, 10000)), Vec::<usize>::new());
+
+    let ms = Duration::from_millis;
+    assert_eq!(
+        positions(history.range_time(ms(100), ms(300))),
+        vec![4800, 9600]
+    );
+    assert_eq!(
+        positions(history.range_time(ms(950), ms(2000))),
+        vec![48000]
+    );
+    assert_eq!(history.position_of(ms(250)), 12000);
+    assert_eq!(history.time_of(12000), ms(250));
+}
+
+#[test]
+fn push_after_seek_overwrites() {
+    let mut history = history();
+    // Seeking back to 300 ms drops the entries from there on
+    history.push(entry(14400, -50.0));
+    assert_eq!(history.len(), 3);
+    assert_eq!(history.latest(), Some(&entry(14400, -50.0)));
+    history.push(entry(19200, -51.0));
+    assert_eq!(positions(history.iter()), vec![4800, 9600, 14400, 19200]);
+
+    // Between two entries
+    history.push(entry(7000, -60.0));
+    assert_eq!(positions(history.iter()), vec![4800, 7000]);
+
+    // Before the first entry
+    history.push(entry(0, -70.0));
+    assert_eq!(positions(history.iter()), vec![0]);
+}
+
+#[test]
+fn capacity_trims_oldest() {
+    let mut history = history();
+    assert_eq!(history.capacity(), Some(DEFAULT_HISTORY_CAPACITY));
+    history.set_capacity(Some(4));
+    assert_eq!(positions(history.iter()), vec![33600, 38400, 43200, 48000]);
+    history.push(entry(52800, -31.0));
+    assert_eq!(history.len(), 4);
+    assert_eq!(positions(history.iter()).first(), Some(&38400));
+
+    history.set_capacity(None);
+    history.push(entry(57600, -32.0));
+    assert_eq!(history.len(), 5);
+
+    history.clear();
+    assert!(history.is_empty());
+    assert_eq!(history.latest(), None);
+
+    // The default history is bounded too
+    let mut history = LoudnessHistory::new(SAMPLE_RATE, 2);
+    for i in 1..=DEFAULT_HISTORY_CAPACITY + 2 {
+        history.push(entry(i * 4800, -23.0));
+    }
+    assert_eq!(history.len(), DEFAULT_HISTORY_CAPACITY);
+    assert_eq!(history.iter().next().unwrap().position, 3 * 4800);
+}
+
+#[test]
+fn csv_output() {
+    let mut history = LoudnessHistory::new(SAMPLE_RATE, 2);
+    history.push(entry(4800, -23.456));
+    history.push(entry(9600, f64::NEG_INFINITY));
+    let mut csv = vec![];
+    history.write_csv(&mut csv).unwrap();
+    assert_eq!(
+        String::from_utf8(csv).unwrap(),
+        "position,time,momentary,short_term,true_peak_1,true_peak_2\n\
+         4800,0.100,-23.46,-24.46,-6.02,-inf\n\
+         9600,0.200,-inf,-inf,-6.02,-inf\n"
+    );
+}
+
+#[test]
+fn json_output() {
+    let mut history = LoudnessHistory::new(SAMPLE_RATE, 2);
+    history.push(entry(4800, -23.456));
+    history.push(entry(9600, f64::NEG_INFINITY));
+    let mut json = vec![];
+    history.write_json(&mut json).unwrap();
+    assert_eq!(
+        String::from_utf8(json).unwrap(),
+        "{\"sample_rate\":48000,\"channels\":2,\"entries\":[\
+         {\"position\":4800,\"time\":0.100,\"momentary\":-23.46,\"short_term\":-24.46,\
+         \"true_peak\":[-6.02,null]},\
+         {\"position\":9600,\"time\":0.200,\"momentary\":null,\"short_term\":null,\
+         \"true_peak\":[-6.02,null]}]}\n"
+    );
+
+    let mut json = vec![];
+    LoudnessHistory::new(SAMPLE_RATE, 1)
+        .write_json(&mut json)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(json).unwrap(),
+        "{\"sample_rate\":48000,\"channels\":1,\"entries\":[]}\n"
+    );
+}
@@ -0,0 +1,149 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Checks the contents of the `WavrMeter` report with sines at known levels, and that pausing
+//! and resetting act on the program measurement only.
+
+use std::f64::consts::PI;
+
+use wavr_audio_buffer::AudioBuffer;
+use wavr_meter::decibel::{Decibel, Linear};
+use wavr_meter::WavrMeter;
+
+const SAMPLE_RATE: u32 = 48000;
+const LOUDNESS_TOLERANCE: f64 = 0.05;
+
+/// Mono 997 Hz sine at the given loudness, lasting the given number of seconds. A full scale
+/// mono sine measures -3.01 LUFS.
+fn sine(lufs: f64, seconds: usize) -> AudioBuffer {
+    let amplitude = amplitude(lufs);
+    let data: Vec<f64> = (0..seconds * SAMPLE_RATE as usize)
+        .map(|n| amplitude * (2.0 * PI * 997.0 * n as f64 / SAMPLE_RATE as f64).sin())
+        .collect();
+    AudioBuffer::new(1, &data)
+}
+
+fn amplitude(lufs: f64) -> f64 {
+    10f64.powf((lufs + 3.01) / 20.0)
+}
+
+fn assert_loudness(actual: Decibel, expected: f64) {
+    assert!(
+        (actual.0 - expected).abs() < LOUDNESS_TOLERANCE,
+        "{} LUFS != {} LUFS",
+        actual.0,
+        expected
+    );
+}
+
+fn assert_peak(actual: Linear, expected: f64) {
+    assert!(
+        (actual.0 - expected).abs() < 1e-3 * expected,
+        "{} != {}",
+        actual.0,
+        expected
+    );
+}
+
+#[test]
+fn report_contents() {
+    let mut meter = WavrMeter::new(1, SAMPLE_RATE).unwrap();
+    assert!(meter.get_values().is_none());
+    assert!(meter.get_report().is_none());
+
+    meter.add_samples(&sine(-23.0, 4));
+    let data = meter.get_values().unwrap();
+    let report = data.ebu;
+    assert_eq!(meter.get_report(), Some(report));
+    assert_eq!(data.loudness, report.short_term);
+    for &loudness in &[
+        report.momentary,
+        report.short_term,
+        report.integrated,
+        report.max_momentary,
+        report.max_short_term,
+    ] {
+        assert_loudness(loudness, -23.0);
+    }
+    assert!(
+        report.range.0.abs() < LOUDNESS_TOLERANCE,
+        "{:?}",
+        report.range
+    );
+    assert_peak(report.max_true_peak, amplitude(-23.0));
+    assert_peak(data.peak[0], amplitude(-23.0));
+    assert_peak(data.rms[0], amplitude(-23.0) / 2f64.sqrt());
+
+    // A louder passage raises the maxima and widens the range
+    meter.add_samples(&sine(-13.0, 4));
+    let report = meter.get_report().unwrap();
+    assert_loudness(report.max_momentary, -13.0);
+    assert_loudness(report.max_short_term, -13.0);
+    assert_peak(report.max_true_peak, amplitude(-13.0));
+    assert!(report.integrated.0 > -23.0 && report.integrated.0 < -13.0);
+    assert!(report.range.0 > 5.0, "{:?}", report.range);
+}
+
+#[test]
+fn pausing_stops_integration() {
+    let mut meter = WavrMeter::new(1, SAMPLE_RATE).unwrap();
+    meter.add_samples(&sine(-23.0, 4));
+    meter.set_paused(true);
+    assert!(meter.is_paused());
+    meter.add_samples(&sine(-13.0, 4));
+
+    // The live values follow the input, the program values ignore it
+    let report = meter.get_report().unwrap();
+    assert_loudness(report.momentary, -13.0);
+    assert_loudness(report.short_term, -13.0);
+    assert_loudness(report.integrated, -23.0);
+    assert_loudness(report.max_momentary, -23.0);
+    assert_loudness(report.max_short_term, -23.0);
+    assert_peak(report.max_true_peak, amplitude(-23.0));
+    assert!(
+        report.range.0.abs() < LOUDNESS_TOLERANCE,
+        "{:?}",
+        report.range
+    );
+
+    meter.set_paused(false);
+    meter.add_samples(&sine(-13.0, 4));
+    let report = meter.get_report().unwrap();
+    assert!(report.integrated.0 > -23.0 + 1.0);
+    assert_loudness(report.max_momentary, -13.0);
+    assert_peak(report.max_true_peak, amplitude(-13.0));
+}
+
+#[test]
+fn reset_clears_program_values() {
+    let mut meter = WavrMeter::new(1, SAMPLE_RATE).unwrap();
+    meter.add_samples(&sine(-23.0, 4));
+    meter.add_samples(&sine(-13.0, 4));
+    meter.reset().unwrap();
+
+    let report = meter.get_report().unwrap();
+    assert_eq!(report.integrated, Decibel(f64::NEG_INFINITY));
+    assert_eq!(report.range, Decibel(0.0));
+    assert_eq!(report.max_momentary, Decibel(f64::NEG_INFINITY));
+    assert_eq!(report.max_short_term, Decibel(f64::NEG_INFINITY));
+    assert_eq!(report.max_true_peak, Linear(0.0));
+    // The live values are kept
+    assert_loudness(report.momentary, -13.0);
+    assert_loudness(report.short_term, -13.0);
+
+    // The program is measured again from the reset
+    meter.add_samples(&sine(-23.0, 4));
+    let report = meter.get_report().unwrap();
+    assert_loudness(report.integrated, -23.0);
+    assert!(
+        report.range.0.abs() < LOUDNESS_TOLERANCE,
+        "{:?}",
+        report.range
+    );
+    // The interpolation of the step from the louder sine overshoots the quieter one, but stays
+    // under the peak measured before the reset
+    let max_true_peak = report.max_true_peak.0;
+    assert!(max_true_peak >= amplitude(-23.0) && max_true_peak < amplitude(-13.0));
+}
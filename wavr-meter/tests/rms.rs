@@ -0,0 +1,102 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Checks the RMS meter readings of sines with and without the AES-17 calibration, the averaging
+//! window, and the K-System scale offsets.
+
+use std::f64::consts::PI;
+
+use wavr_audio_buffer::AudioBuffer;
+use wavr_meter::decibel::Decibel;
+use wavr_meter::{KScale, RmsMeter, WavrMeter};
+
+const SAMPLE_RATE: u32 = 48000;
+const TOLERANCE: f64 = 1e-3;
+
+/// Sine at 1 kHz with the given peak level in dBFS, on each channel, lasting the given time.
+fn sine(channels: usize, level_db: f64, ms: usize) -> AudioBuffer {
+    let amplitude = 10f64.powf(level_db / 20.0);
+    let len = ms * SAMPLE_RATE as usize / 1000;
+    let data: Vec<f64> = (0..len * channels)
+        .map(|i| {
+            let n = i / channels;
+            amplitude * (2.0 * PI * 1000.0 * n as f64 / SAMPLE_RATE as f64).sin()
+        })
+        .collect();
+    AudioBuffer::new(channels, &data)
+}
+
+fn level_db(meter: &RmsMeter, channel: usize) -> f64 {
+    Decibel::from(meter.get_rms(channel)).0
+}
+
+fn assert_close(lhs: f64, rhs: f64) {
+    assert!((lhs - rhs).abs() <= TOLERANCE, "{} != {}", lhs, rhs);
+}
+
+#[test]
+fn sine_calibration() {
+    let mut meter = RmsMeter::new(2, SAMPLE_RATE);
+    assert!(!meter.is_aes17());
+    meter.add_samples(&sine(2, 0.0, 500));
+    // A full scale sine reads -3.01 dB, or 0 dB with the AES-17 calibration
+    for channel in 0..2 {
+        assert_close(level_db(&meter, channel), -3.0103);
+    }
+    meter.set_aes17(true);
+    for channel in 0..2 {
+        assert_close(level_db(&meter, channel), 0.0);
+    }
+    meter.add_samples(&sine(2, -20.0, 500));
+    assert_close(level_db(&meter, 0), -20.0);
+    assert_eq!(meter.get_values().len(), 2);
+
+    // The calibration is forwarded by the full meter
+    let mut meter = WavrMeter::new(1, SAMPLE_RATE).unwrap();
+    meter.set_rms_aes17(true);
+    assert!(meter.is_rms_aes17());
+    meter.add_samples(&sine(1, -6.0, 500));
+    assert_close(Decibel::from(meter.get_values().unwrap().rms[0]).0, -6.0);
+}
+
+#[test]
+fn averaging_window() {
+    let mut meter = RmsMeter::new(1, SAMPLE_RATE);
+    assert_eq!(meter.window_ms(), 300.0);
+    meter.set_window_ms(100.0);
+    // Half of the window filled with the sine reads 3 dB under it
+    meter.add_samples(&sine(1, 0.0, 50));
+    assert_close(level_db(&meter, 0), -6.0206);
+    meter.add_samples(&sine(1, 0.0, 50));
+    assert_close(level_db(&meter, 0), -3.0103);
+    // Silence flushes the window
+    meter.add_samples(&AudioBuffer::zeroed(1, SAMPLE_RATE as usize / 10));
+    assert_eq!(meter.get_rms(0).0, 0.0);
+
+    meter.add_samples(&sine(1, 0.0, 100));
+    meter.reset();
+    assert_eq!(meter.get_rms(0).0, 0.0);
+}
+
+#[test]
+fn k_system_offsets() {
+    for &(scale, headroom) in &[
+        (KScale::K12, 12.0),
+        (KScale::K14, 14.0),
+        (KScale::K20, 20.0),
+    ] {
+        assert_eq!(scale.headroom(), headroom);
+        assert_eq!(scale.to_scale(Decibel(-headroom)), Decibel(0.0));
+        assert_eq!(scale.to_scale(Decibel(0.0)), Decibel(headroom));
+        assert_eq!(scale.from_scale(Decibel(4.0)), Decibel(4.0 - headroom));
+
+        // A sine at the reference level reads 0 on the scale of AES-17 levels
+        let mut meter = RmsMeter::new(1, SAMPLE_RATE);
+        meter.set_aes17(true);
+        meter.add_samples(&sine(1, -headroom, 500));
+        let reading = scale.to_scale(meter.get_rms(0).into());
+        assert_close(reading.0, 0.0);
+    }
+}
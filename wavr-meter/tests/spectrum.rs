@@ -0,0 +1,149 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Checks the spectrum meter calibration with bin-centred sines, the frame averaging and the
+//! peak-hold curve, and the helpers shared by the spectrum widgets.
+
+use std::f64::consts::PI;
+
+use smallvec::smallvec;
+use wavr_audio_buffer::AudioBuffer;
+use wavr_meter::decibel::Decibel;
+use wavr_meter::widget::{format_frequency, grid_frequencies, is_decade_frequency};
+use wavr_meter::{Averaging, SpectrumData, SpectrumMeter, Window};
+
+const SAMPLE_RATE: u32 = 48000;
+const FFT_SIZE: usize = 1024;
+/// Bin of the test sine, at 3 kHz.
+const BIN: usize = 64;
+const TOLERANCE: f64 = 1e-6;
+
+/// Mono full-scale sine centred on the test bin, starting at the given sample.
+fn sine(start: usize, len: usize) -> AudioBuffer {
+    let data: Vec<f64> = (start..start + len)
+        .map(|n| (2.0 * PI * (BIN * n) as f64 / FFT_SIZE as f64).sin())
+        .collect();
+    AudioBuffer::new(1, &data)
+}
+
+fn magnitude(meter: &SpectrumMeter) -> f64 {
+    meter.get_values().magnitudes[0][BIN].0
+}
+
+fn peak(meter: &SpectrumMeter) -> f64 {
+    meter.get_values().peaks[0][BIN].0
+}
+
+#[test]
+fn full_scale_sine_reads_0_db() {
+    for &window in &[Window::Hann, Window::BlackmanHarris, Window::FlatTop] {
+        let mut meter = SpectrumMeter::new(1, SAMPLE_RATE, FFT_SIZE);
+        meter.set_window(window);
+        meter.add_samples(&sine(0, FFT_SIZE));
+        assert!(
+            magnitude(&meter).abs() < TOLERANCE,
+            "{} dB with {:?}",
+            magnitude(&meter),
+            window
+        );
+        assert_eq!(meter.bin_frequency(BIN), 3000.0);
+    }
+}
+
+#[test]
+fn averaging_converges() {
+    for &averaging in &[Averaging::Exponential(100.0), Averaging::Linear(8)] {
+        let mut meter = SpectrumMeter::new(1, SAMPLE_RATE, FFT_SIZE);
+        meter.set_averaging(averaging);
+        meter.add_samples(&AudioBuffer::zeroed(1, 4 * FFT_SIZE));
+
+        // One hop of the sine after silence is averaged with the silent frames
+        meter.add_samples(&sine(0, FFT_SIZE / 2));
+        let mut previous = magnitude(&meter);
+        assert!(previous < -3.0, "{} dB with {:?}", previous, averaging);
+
+        // The average rises towards the level of the sine, and settles on it
+        let hop = FFT_SIZE / 2;
+        for frame in 1..100 {
+            meter.add_samples(&sine(frame * hop, hop));
+            let current = magnitude(&meter);
+            assert!(current >= previous - TOLERANCE);
+            previous = current;
+        }
+        assert!(
+            previous.abs() < 1e-3,
+            "{} dB with {:?}",
+            previous,
+            averaging
+        );
+    }
+}
+
+#[test]
+fn peak_hold_and_decay() {
+    let mut meter = SpectrumMeter::new(1, SAMPLE_RATE, FFT_SIZE);
+    meter.add_samples(&sine(0, 4 * FFT_SIZE));
+    meter.add_samples(&AudioBuffer::zeroed(1, SAMPLE_RATE as usize));
+    // Held until reset by default
+    assert!(magnitude(&meter) < -100.0);
+    assert!(peak(&meter).abs() < TOLERANCE);
+    meter.reset_peaks();
+    assert_eq!(peak(&meter), -200.0);
+
+    let mut meter = SpectrumMeter::new(1, SAMPLE_RATE, FFT_SIZE);
+    meter.set_peak_hold_ms(Some(500.0));
+    meter.add_samples(&sine(0, 4 * FFT_SIZE));
+    let hop_ms = (FFT_SIZE / 2) as f64 * 1e3 / SAMPLE_RATE as f64;
+    // Held for 500 ms, then decaying at 20 dB/s
+    meter.add_samples(&AudioBuffer::zeroed(1, SAMPLE_RATE as usize / 4));
+    assert!(peak(&meter).abs() < TOLERANCE);
+    meter.add_samples(&AudioBuffer::zeroed(1, 3 * SAMPLE_RATE as usize / 4));
+    let decay = -20.0 * 0.5;
+    assert!(
+        (peak(&meter) - decay).abs() <= 20.0 * 4.0 * hop_ms * 1e-3,
+        "peak at {} dB",
+        peak(&meter)
+    );
+    // The peak does not decay under the current level
+    meter.add_samples(&sine(0, 4 * FFT_SIZE));
+    assert!(peak(&meter).abs() < TOLERANCE);
+}
+
+#[test]
+fn combined_channels_average_power() {
+    let data = SpectrumData {
+        frequencies: vec![0.0, 24000.0],
+        magnitudes: smallvec![
+            vec![Decibel(0.0), Decibel(-200.0)],
+            vec![Decibel(f64::NEG_INFINITY), Decibel(-200.0)],
+        ],
+        peaks: smallvec![vec![Decibel(-10.0), Decibel(-20.0)]],
+    };
+    let combined = data.combined();
+    assert!((combined[0].0 + 3.0103).abs() < 1e-4);
+    assert!((combined[1].0 + 200.0).abs() < 1e-9);
+    assert_eq!(data.combined_peaks(), vec![Decibel(-10.0), Decibel(-20.0)]);
+}
+
+#[test]
+fn frequency_grid() {
+    assert_eq!(
+        grid_frequencies(20.0, 20000.0),
+        vec![20.0, 50.0, 100.0, 200.0, 500.0, 1000.0, 2000.0, 5000.0, 10000.0, 20000.0]
+    );
+    assert!(grid_frequencies(0.0, 1000.0).is_empty());
+    assert!(grid_frequencies(1000.0, 100.0).is_empty());
+
+    let decades: Vec<f64> = grid_frequencies(20.0, 20000.0)
+        .into_iter()
+        .filter(|&f| is_decade_frequency(f))
+        .collect();
+    assert_eq!(decades, vec![100.0, 1000.0, 10000.0]);
+
+    assert_eq!(format_frequency(50.0), "50 Hz");
+    assert_eq!(format_frequency(999.4), "999 Hz");
+    assert_eq!(format_frequency(2000.0), "2 kHz");
+    assert_eq!(format_frequency(2500.0), "2.50 kHz");
+}
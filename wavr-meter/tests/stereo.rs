@@ -0,0 +1,120 @@
+/*
+ * Copyright (c) 2020 the Wavr Audio project.
+ * This source file, as well as the binaries generated by it,
+ * are licensed under MIT.
+ */
+//! Checks the stereo meter correlation and balance with identical, inverted and uncorrelated
+//! channels, the sliding window, and the decimated scope points.
+
+use std::f64::consts::{FRAC_1_SQRT_2, PI};
+
+use wavr_audio_buffer::AudioBuffer;
+use wavr_meter::{ScopePoint, StereoMeter};
+
+const SAMPLE_RATE: u32 = 48000;
+/// 10 ms blocks.
+const BLOCK_SIZE: usize = 480;
+const TOLERANCE: f64 = 1e-9;
+
+/// Deterministic uniform noise in [-1, 1].
+struct Noise(u64);
+
+impl Noise {
+    fn next(&mut self) -> f64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        (self.0 >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+    }
+}
+
+/// Feeds half a second of stereo blocks, with the left and right samples returned by `frame` for
+/// each sample index.
+fn feed(meter: &mut StereoMeter, mut frame: impl FnMut(usize) -> (f64, f64)) {
+    for block in 0..SAMPLE_RATE as usize / 2 / BLOCK_SIZE {
+        let mut data = Vec::with_capacity(2 * BLOCK_SIZE);
+        for i in 0..BLOCK_SIZE {
+            let (left, right) = frame(block * BLOCK_SIZE + i);
+            data.push(left);
+            data.push(right);
+        }
+        meter.add_samples(&AudioBuffer::new(2, &data));
+    }
+}
+
+fn sine(i: usize) -> f64 {
+    0.5 * (2.0 * PI * 997.0 * i as f64 / SAMPLE_RATE as f64).sin()
+}
+
+fn assert_close(lhs: f64, rhs: f64, tolerance: f64) {
+    assert!((lhs - rhs).abs() <= tolerance, "{} != {}", lhs, rhs);
+}
+
+#[test]
+fn correlation() {
+    let mut meter = StereoMeter::new(SAMPLE_RATE);
+    assert_eq!(meter.correlation(), 0.0);
+
+    feed(&mut meter, |i| (sine(i), sine(i)));
+    assert_close(meter.correlation(), 1.0, TOLERANCE);
+    // Once the 300 ms window has passed, the inverted channels replace the identical ones
+    feed(&mut meter, |i| (sine(i), -sine(i)));
+    assert_close(meter.correlation(), -1.0, TOLERANCE);
+
+    let (mut left, mut right) = (Noise(0x5eed), Noise(0xb0b));
+    feed(&mut meter, |_| (left.next(), right.next()));
+    assert!(meter.correlation().abs() < 0.05, "{}", meter.correlation());
+
+    // Between phase-shifted sines, the correlation is the cosine of the phase shift: about a
+    // quarter of a period here, nearly uncorrelated
+    feed(&mut meter, |i| (sine(i), sine(i + 12)));
+    let phase_shift = 2.0 * PI * 997.0 * 12.0 / SAMPLE_RATE as f64;
+    assert_close(meter.correlation(), phase_shift.cos(), 1e-3);
+
+    feed(&mut meter, |_| (0.0, 0.0));
+    assert_eq!(meter.get_values().correlation, 0.0);
+}
+
+#[test]
+fn balance() {
+    let mut meter = StereoMeter::new(SAMPLE_RATE);
+    for &(gain_left, gain_right, balance) in &[(1.0, 0.0, -1.0), (0.0, 1.0, 1.0), (1.0, 1.0, 0.0)] {
+        feed(&mut meter, |i| (gain_left * sine(i), gain_right * sine(i)));
+        assert_close(meter.balance(), balance, TOLERANCE);
+    }
+    // Half the amplitude is a quarter of the energy
+    feed(&mut meter, |i| (sine(i), 0.5 * sine(i)));
+    assert_close(meter.get_values().balance, (0.25 - 1.0) / 1.25, 1e-3);
+
+    meter.reset();
+    assert_eq!(meter.balance(), 0.0);
+}
+
+#[test]
+fn scope_points() {
+    let mut meter = StereoMeter::new(SAMPLE_RATE);
+    meter.set_decimation(10);
+    meter.set_point_capacity(100);
+    meter.add_samples(&AudioBuffer::new(2, &[1.0, 0.0, 0.5, 0.5, 0.0, 0.0]));
+    // One point every 10 frames: only the first frame is kept
+    assert_eq!(
+        meter.get_values().points,
+        vec![ScopePoint {
+            mid: FRAC_1_SQRT_2,
+            side: FRAC_1_SQRT_2
+        }]
+    );
+    assert_eq!(ScopePoint::from_stereo(0.5, 0.5).side, 0.0);
+
+    feed(&mut meter, |i| (sine(i), -sine(i)));
+    let points = meter.get_values().points;
+    assert_eq!(points.len(), meter.point_capacity());
+    assert!(points.iter().all(|p| p.mid.abs() < TOLERANCE));
+
+    // Mono buffers are measured as dual mono
+    meter.reset();
+    assert!(meter.get_values().points.is_empty());
+    meter.add_samples(&AudioBuffer::new(1, &[0.5; BLOCK_SIZE]));
+    assert_close(meter.correlation(), 1.0, TOLERANCE);
+}